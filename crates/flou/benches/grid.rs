@@ -0,0 +1,62 @@
+use std::convert::TryFrom;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use flou::{Flou, RenderConfig, Renderer, SvgRenderer};
+
+/// Generates a `size`×`size` grid where only every `spacing`th cell on
+/// the diagonal bands holds a node, each connected to the next node
+/// below and to the right of it where there is one.
+fn sparse_source(size: usize, spacing: usize) -> String {
+    let mut rows = Vec::with_capacity(size);
+
+    for y in 0..size {
+        let row = (0..size)
+            .map(|x| {
+                if (x + y) % spacing != 0 {
+                    return "_";
+                }
+
+                match (y + spacing < size, x + spacing < size) {
+                    (true, true) => "n",
+                    (true, false) => "s",
+                    (false, true) => "e",
+                    (false, false) => "t",
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        rows.push(format!("{};", row));
+    }
+
+    format!(
+        concat!(
+            "grid {{\n{}\n}}\n",
+            "define {{\n",
+            "  n(connect: {{s:n@s; e:w@e}});\n",
+            "  s(connect: s:n@s);\n",
+            "  e(connect: e:w@e);\n",
+            "}}\n",
+        ),
+        rows.join("\n")
+    )
+}
+
+fn bench_sparse_grid(c: &mut Criterion) {
+    // 150×150 = 22,500 cells, of which only ~1,100 are occupied.
+    let source = sparse_source(150, 20);
+
+    c.bench_function("build sparse 150x150", |b| {
+        b.iter(|| Flou::try_from(source.as_str()).unwrap())
+    });
+
+    let flou = Flou::try_from(source.as_str()).unwrap();
+    let config = RenderConfig::default();
+    let renderer = SvgRenderer::new();
+
+    c.bench_function("render sparse 150x150", |b| {
+        b.iter(|| renderer.render(&flou, &config).to_string())
+    });
+}
+
+criterion_group!(benches, bench_sparse_grid);
+criterion_main!(benches);
@@ -1,12 +1,46 @@
 #[cfg(test)]
 mod test;
 
+pub mod lint;
+#[cfg(feature = "render")]
+pub mod markdown;
 mod parse;
 pub mod parts;
 mod pos;
 
+#[cfg(feature = "render")]
+mod render_html;
+#[cfg(feature = "render")]
+mod render_json;
+#[cfg(feature = "render")]
 mod render_svg;
-mod svg;
+#[cfg(feature = "render")]
+pub mod svg;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "render")]
+pub mod text_metrics;
 
-pub use parts::{Flou, FlouError, LogicError, RenderConfig, Renderer, ResolutionError};
-pub use render_svg::SvgRenderer;
+pub use parse::ast::{self, Direction, Placement};
+#[cfg(feature = "cst")]
+pub use parse::cst;
+#[cfg(feature = "render")]
+pub use parts::{
+    Background, BackgroundGrid, ConnectionStyle, LegendPosition, RenderConfig, RenderLimits,
+    Renderer, Stats, Unit,
+};
+pub use parts::{
+    ConvertOptions, Flou, FlouError, GridView, Limit, Limits, LogicError, ResolutionError,
+    Strictness, TransformError,
+};
+pub use pos::{CellPosition, GridCoord, PixelPos};
+#[cfg(feature = "render")]
+pub use render_html::HtmlRenderer;
+#[cfg(feature = "render")]
+pub use render_json::{json_array, json_string, JsonObject, JsonRenderer};
+#[cfg(feature = "render")]
+pub use render_svg::{
+    render_svg, ChannelRouter, ConnectionContext, ConnectionLayout, ConnectionVisit, CornerRouter,
+    EmbeddedFont, IncrementalRenderer, Lane, Layout, MaybeSync, NodeContext, NodeLayout, NodeVisit,
+    PaddedPos, PaddedSpace, PathRouter, PathSegment, Rect, RenderVisitor, Route, SvgRenderer,
+};
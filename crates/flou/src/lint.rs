@@ -0,0 +1,460 @@
+//! Style checks for flowcharts that are valid but could be clearer.
+//!
+//! Each [`Rule`] can be turned off or have its [`Severity`] changed through
+//! a [`LintConfig`]. Unlike errors from parsing, diagnostics don't prevent
+//! a flowchart from being rendered.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    fmt,
+};
+
+use crate::{
+    parse::ast::{ArrowheadType, Document, Identifier, NodeShape},
+    parts::{Flou, FlouError, NodeAttributes},
+    pos::{pos, GridCoord, IndexPos},
+};
+
+/// A single style check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Rule {
+    /// A node has no text, neither in the grid nor in its definition.
+    NodeWithoutText,
+
+    /// A connection leaving a diamond has no text, so it's unclear which
+    /// answer to the decision it stands for.
+    UnlabeledDecision,
+
+    /// A definition is given for an identifier that no node in the grid uses.
+    UnusedDefinition,
+
+    /// The grid has more columns than [`LintConfig::max_grid_width`].
+    GridTooWide,
+
+    /// A row of the grid is shorter than the longest row. Cells are
+    /// easily left out of rows by mistake, which shifts the cells after
+    /// them and makes connections lead to the wrong nodes. Grids whose rows
+    /// are meant to be of different lengths can say so with `ragged: true;`.
+    RaggedGrid,
+
+    /// A node can't be reached by following connections from the start.
+    /// The start is every node with `start: true`, or the node labeled
+    /// `start` if there are none. Without a start, nothing is reported.
+    UnreachableNode,
+
+    /// No connection leads away from a node, but the node isn't an end.
+    /// Ends are nodes with `end: true` or the node labeled `end`.
+    DeadEnd,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::NodeWithoutText,
+        Rule::UnlabeledDecision,
+        Rule::UnusedDefinition,
+        Rule::GridTooWide,
+        Rule::RaggedGrid,
+        Rule::UnreachableNode,
+        Rule::DeadEnd,
+    ];
+
+    /// The name the rule is referred to by in configuration files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::NodeWithoutText => "node-without-text",
+            Rule::UnlabeledDecision => "unlabeled-decision",
+            Rule::UnusedDefinition => "unused-definition",
+            Rule::GridTooWide => "grid-too-wide",
+            Rule::RaggedGrid => "ragged-grid",
+            Rule::UnreachableNode => "unreachable-node",
+            Rule::DeadEnd => "dead-end",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|rule| rule.name() == name)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a rule's diagnostics are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    /// The rule is turned off.
+    Allow,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// The name the severity is referred to by in configuration files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Allow => "allow",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Severity::Allow, Severity::Warning, Severity::Error]
+            .iter()
+            .copied()
+            .find(|severity| severity.name() == name)
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which rules to check and how to report them. By default, every rule is
+/// reported as a warning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    severities: HashMap<Rule, Severity>,
+
+    /// The number of columns above which [`Rule::GridTooWide`] is reported.
+    pub max_grid_width: usize,
+}
+
+impl LintConfig {
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.severities
+            .get(&rule)
+            .copied()
+            .unwrap_or(Severity::Warning)
+    }
+
+    pub fn set_severity(&mut self, rule: Rule, severity: Severity) {
+        self.severities.insert(rule, severity);
+    }
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            severities: HashMap::new(),
+            max_grid_width: 10,
+        }
+    }
+}
+
+/// A problem found by a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    /// The position of the node the problem is about, if there is one.
+    pub pos: Option<GridCoord>,
+}
+
+/// Checks `source` against the rules enabled in `config`. Fails if `source`
+/// isn't a valid flowchart.
+///
+/// Diagnostics are ordered by rule, then by position in row-major order.
+pub fn lint<'i>(source: &'i str, config: &LintConfig) -> Result<Vec<Diagnostic>, FlouError<'i>> {
+    let document = Document::parse(source).map_err(FlouError::Parse)?;
+    let grid_width = document.grid.size().col as usize;
+    let unused_definitions = document
+        .definitions
+        .iter()
+        .map(|(id, _)| *id)
+        .filter(|id| document.grid.nodes().all(|(_, node)| node.id != *id))
+        .collect::<Vec<_>>();
+    let row_lengths = match document.ragged {
+        true => Vec::new(),
+        false => document.grid.0.iter().map(Vec::len).collect(),
+    };
+
+    let flou = Flou::try_from(document).map_err(FlouError::Logic)?;
+
+    // Spacers only take up room in the grid, so the node rules don't apply
+    // to them.
+    let is_spacer =
+        |pos| flou.node_attributes.get(&pos).and_then(|attrs| attrs.shape) == Some(NodeShape::None);
+    let mut positions = flou
+        .grid
+        .positions()
+        .filter(|&pos| !is_spacer(pos))
+        .collect::<Vec<_>>();
+    positions.sort();
+
+    let mut connections = flou.connections.iter().collect::<Vec<_>>();
+    connections.sort_by_key(|c| c.from.0);
+
+    let mut diagnostics = Vec::new();
+    let mut report = |rule, pos: Option<IndexPos>, message| {
+        let severity = config.severity(rule);
+        if severity != Severity::Allow {
+            diagnostics.push(Diagnostic {
+                rule,
+                severity,
+                message,
+                pos: pos.map(GridCoord::from),
+            });
+        }
+    };
+
+    for &pos in &positions {
+        let has_text = flou
+            .node_attributes
+            .get(&pos)
+            .and_then(|attrs| attrs.text.as_ref())
+            .is_some();
+
+        if !has_text {
+            let id = flou.grid[pos];
+            let message = format!("Node \"{}\" has no text.", id);
+            report(Rule::NodeWithoutText, Some(pos), message);
+        }
+    }
+
+    for connection in connections {
+        let pos = connection.from.0;
+        let shape = flou.node_attributes.get(&pos).and_then(|attrs| attrs.shape);
+
+        if shape == Some(NodeShape::Diamond) && connection.attrs.text.is_none() {
+            let message = "Connection from a diamond has no text.".to_string();
+            report(Rule::UnlabeledDecision, Some(pos), message);
+        }
+    }
+
+    for id in unused_definitions {
+        let message = format!("Definition of \"{}\" isn't used by any node.", id);
+        report(Rule::UnusedDefinition, None, message);
+    }
+
+    if grid_width > config.max_grid_width {
+        let message = format!(
+            "Grid has {} columns, more than the maximum of {}.",
+            grid_width, config.max_grid_width
+        );
+        report(Rule::GridTooWide, None, message);
+    }
+
+    for (y, &len) in row_lengths.iter().enumerate() {
+        if len < grid_width {
+            let message = format!(
+                "Row has {} cells, fewer than the {} of the longest row.",
+                len, grid_width
+            );
+            report(Rule::RaggedGrid, Some(pos(len, y).into()), message);
+        }
+    }
+
+    let flag = |pos, get: fn(&NodeAttributes) -> Option<bool>| {
+        flou.node_attributes.get(&pos).and_then(get) == Some(true)
+    };
+    let labeled = |label| flou.labels.get(&Identifier(label)).copied();
+
+    let mut starts = positions
+        .iter()
+        .copied()
+        .filter(|&pos| flag(pos, |attrs| attrs.start))
+        .collect::<Vec<_>>();
+    starts.extend(labeled("start").filter(|_| starts.is_empty()));
+
+    let edges = edges(&flou);
+
+    if !starts.is_empty() {
+        let reachable = reachable(&edges, starts);
+
+        for &pos in positions.iter().filter(|pos| !reachable.contains(pos)) {
+            let id = flou.grid[pos];
+            let message = format!("Node \"{}\" can't be reached from the start.", id);
+            report(Rule::UnreachableNode, Some(pos), message);
+        }
+    }
+
+    let end_label = labeled("end");
+    for &pos in &positions {
+        let is_end = flag(pos, |attrs| attrs.end) || end_label == Some(pos);
+
+        if !is_end && !edges.contains_key(&pos) {
+            let id = flou.grid[pos];
+            let message = format!("Node \"{}\" leads nowhere but isn't an end.", id);
+            report(Rule::DeadEnd, Some(pos), message);
+        }
+    }
+
+    Ok(diagnostics)
+}
+
+/// The nodes each node leads to. Connections lead in the direction of their
+/// arrowheads, or both ways if they have both or none.
+fn edges(flou: &Flou) -> HashMap<IndexPos, Vec<IndexPos>> {
+    let mut edges: HashMap<_, Vec<_>> = HashMap::new();
+
+    for connection in &flou.connections {
+        let (from, to) = (connection.from.0, connection.to.0);
+        let (forward, backward) = match connection.attrs.arrowheads.unwrap_or_default() {
+            ArrowheadType::End => (true, false),
+            ArrowheadType::Start => (false, true),
+            ArrowheadType::Both | ArrowheadType::None => (true, true),
+        };
+
+        if forward {
+            edges.entry(from).or_default().push(to);
+        }
+        if backward {
+            edges.entry(to).or_default().push(from);
+        }
+    }
+
+    edges
+}
+
+fn reachable(edges: &HashMap<IndexPos, Vec<IndexPos>>, starts: Vec<IndexPos>) -> HashSet<IndexPos> {
+    let mut visited = starts.iter().copied().collect::<HashSet<_>>();
+    let mut queue = starts.into_iter().collect::<VecDeque<_>>();
+
+    while let Some(pos) = queue.pop_front() {
+        for &next in edges.get(&pos).into_iter().flatten() {
+            if visited.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    visited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{assert_eq, coord};
+
+    const SOURCE: &str = r#"
+grid {
+    question("Ready?", connect: {s:n@s("Yes"); e:w@e}), wait;
+    block;
+}
+
+define {
+    question(shape: diamond);
+    block("Go");
+    unused("Unused");
+}
+"#;
+
+    fn rules(diagnostics: &[Diagnostic]) -> Vec<(Rule, Option<GridCoord>)> {
+        diagnostics.iter().map(|d| (d.rule, d.pos)).collect()
+    }
+
+    #[test]
+    fn reports_diagnostics() {
+        let mut config = LintConfig {
+            max_grid_width: 1,
+            ..LintConfig::default()
+        };
+        config.set_severity(Rule::DeadEnd, Severity::Allow);
+
+        let diagnostics = lint(SOURCE, &config).unwrap();
+        assert_eq!(
+            rules(&diagnostics),
+            vec![
+                (Rule::NodeWithoutText, Some(coord(0, 1))),
+                (Rule::UnlabeledDecision, Some(coord(0, 0))),
+                (Rule::UnusedDefinition, None),
+                (Rule::GridTooWide, None),
+                (Rule::RaggedGrid, Some(coord(1, 1))),
+            ]
+        );
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn configures_severities() {
+        let mut config = LintConfig::default();
+        config.set_severity(Rule::NodeWithoutText, Severity::Allow);
+        config.set_severity(Rule::UnusedDefinition, Severity::Error);
+        config.set_severity(Rule::DeadEnd, Severity::Allow);
+
+        let diagnostics = lint(SOURCE, &config).unwrap();
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.rule, d.severity))
+                .collect::<Vec<_>>(),
+            vec![
+                (Rule::UnlabeledDecision, Severity::Warning),
+                (Rule::UnusedDefinition, Severity::Error),
+                (Rule::RaggedGrid, Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unreachable_nodes_and_dead_ends() {
+        let source = r#"
+grid {
+    a#start(connect: e:w@e), b(connect: e:w@e("", arrowheads: start)), c;
+    d(connect: n:s@n("", arrowheads: both)), e(end: true), f#end;
+}
+"#;
+
+        let diagnostics = lint(source, &LintConfig::default()).unwrap();
+        let diagnostics = diagnostics
+            .into_iter()
+            .filter(|d| d.rule == Rule::UnreachableNode || d.rule == Rule::DeadEnd)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            rules(&diagnostics),
+            vec![
+                (Rule::UnreachableNode, Some(coord(0, 2))),
+                (Rule::UnreachableNode, Some(coord(1, 1))),
+                (Rule::UnreachableNode, Some(coord(1, 2))),
+                (Rule::DeadEnd, Some(coord(0, 1))),
+            ]
+        );
+
+        let source = "grid { a(start: true, connect: e:w@e), b; }";
+        let diagnostics = lint(source, &LintConfig::default()).unwrap();
+        assert!(diagnostics.iter().all(|d| d.rule != Rule::UnreachableNode));
+    }
+
+    #[test]
+    fn reports_short_rows_unless_ragged() {
+        let source = "grid { a, b, c; d; e*3; fill f, g; }";
+        let diagnostics = lint(source, &LintConfig::default()).unwrap();
+        let diagnostics = diagnostics
+            .into_iter()
+            .filter(|d| d.rule == Rule::RaggedGrid)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules(&diagnostics),
+            vec![(Rule::RaggedGrid, Some(coord(1, 1)))]
+        );
+
+        let source = "grid { ragged: true; a, b, c; d; }";
+        let diagnostics = lint(source, &LintConfig::default()).unwrap();
+        assert!(diagnostics.iter().all(|d| d.rule != Rule::RaggedGrid));
+    }
+
+    #[test]
+    fn ignores_spacers() {
+        let source = r#"grid { a("A", start: true, connect: e:w@e), gap(shape: none, connect: e:w@e), b#end("B"); }"#;
+        let diagnostics = lint(source, &LintConfig::default()).unwrap();
+        assert_eq!(diagnostics, vec![]);
+    }
+
+    #[test]
+    fn names_rules() {
+        for &rule in &Rule::ALL {
+            assert_eq!(Rule::from_name(rule.name()), Some(rule));
+        }
+        assert_eq!(Severity::from_name("error"), Some(Severity::Error));
+        assert_eq!(Rule::from_name("unknown"), None);
+    }
+}
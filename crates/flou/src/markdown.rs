@@ -0,0 +1,235 @@
+//! Rendering of flowcharts embedded in Markdown, for static site generators
+//! and other Markdown pipelines.
+//!
+//! Flowcharts are written in fenced code blocks whose info string starts
+//! with `flou`, and each is replaced with its SVG:
+//!
+//! ````text
+//! ```flou
+//! grid { a("Hello"); }
+//! ```
+//! ````
+//!
+//! ```
+//! use flou::markdown::{replace_fenced_blocks, MarkdownConfig};
+//!
+//! let markdown = "# Example\n\n```flou\ngrid { a(\"Hello\"); }\n```\n";
+//! let output = replace_fenced_blocks(markdown, &MarkdownConfig::default()).unwrap();
+//! assert!(output.starts_with("# Example\n\n<svg"));
+//! ```
+
+use std::fmt::Write;
+
+use crate::{render_svg::render_svg, svg::escape, RenderConfig};
+
+/// How flowcharts in Markdown are rendered.
+pub struct MarkdownConfig {
+    pub render: RenderConfig,
+
+    /// The info string that marks a code block as a flowchart.
+    pub language: String,
+
+    /// Whether flowcharts that fail to render are replaced with an error
+    /// message, so that the rest of the document is still rendered. The
+    /// message is in a `<pre class="flou-error">` element.
+    pub error_placeholders: bool,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        Self {
+            render: RenderConfig::default(),
+            language: String::from("flou"),
+            error_placeholders: false,
+        }
+    }
+}
+
+/// A flowchart in a Markdown document that couldn't be rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The line of the document the flowchart's code block starts on,
+    /// starting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Replaces every flowchart code block in `markdown` with the rendered SVG.
+///
+/// Fails with a diagnostic for each flowchart that couldn't be rendered,
+/// unless [`MarkdownConfig::error_placeholders`] is set.
+pub fn replace_fenced_blocks(
+    markdown: &str,
+    config: &MarkdownConfig,
+) -> Result<String, Vec<Diagnostic>> {
+    let mut output = String::with_capacity(markdown.len());
+    let mut diagnostics = Vec::new();
+    let mut lines = markdown.split_inclusive('\n').enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let fence = match Fence::open(line) {
+            Some(fence) => fence,
+            None => {
+                output.push_str(line);
+                continue;
+            }
+        };
+
+        // Code blocks that aren't flowcharts are copied as they are, so
+        // that fences inside of them aren't mistaken for flowcharts.
+        let is_flowchart = fence.info.split_whitespace().next() == Some(&config.language);
+        let mut contents = String::new();
+        let mut block = String::from(line);
+        for (_, line) in lines.by_ref() {
+            block.push_str(line);
+            if fence.is_closed_by(line) {
+                break;
+            }
+            contents.push_str(fence.unindent(line));
+        }
+
+        if !is_flowchart {
+            output.push_str(&block);
+            continue;
+        }
+
+        match render_svg(&contents, &config.render) {
+            Ok(svg) => push_svg(&mut output, &svg),
+            Err(e) => {
+                let message = e.to_string();
+                if config.error_placeholders {
+                    let _ = writeln!(
+                        output,
+                        "<pre class=\"flou-error\">{}</pre>",
+                        escape::text(message.as_str())
+                    );
+                }
+
+                diagnostics.push(Diagnostic {
+                    line: index + 1,
+                    message,
+                });
+            }
+        }
+    }
+
+    match diagnostics.is_empty() || config.error_placeholders {
+        true => Ok(output),
+        false => Err(diagnostics),
+    }
+}
+
+/// Appends an SVG as a single HTML block. A blank line would end the block
+/// and have the rest of the SVG parsed as Markdown, so blank lines, which
+/// the SVG only has for readability, are left out.
+fn push_svg(output: &mut String, svg: &str) {
+    for line in svg.lines().filter(|line| !line.trim().is_empty()) {
+        output.push_str(line);
+        output.push('\n');
+    }
+}
+
+/// The opening fence of a fenced code block, as described by CommonMark.
+struct Fence<'a> {
+    marker: char,
+    len: usize,
+    indent: usize,
+    info: &'a str,
+}
+
+impl<'a> Fence<'a> {
+    fn open(line: &'a str) -> Option<Self> {
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        let marker = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+        let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+        let info = trimmed[len..].trim();
+
+        // Backticks can't be in the info string of a backtick fence, since
+        // the line would be inline code instead.
+        let valid = indent <= 3 && len >= 3 && !(marker == '`' && info.contains('`'));
+        valid.then_some(Self {
+            marker,
+            len,
+            indent,
+            info,
+        })
+    }
+
+    fn is_closed_by(&self, line: &str) -> bool {
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        let rest = trimmed.trim_start_matches(self.marker);
+        let len = trimmed.len() - rest.len();
+
+        indent <= 3 && len >= self.len && rest.trim().is_empty()
+    }
+
+    /// Removes as much of the fence's indentation from a line of the
+    /// block's contents as the line has.
+    fn unindent<'l>(&self, line: &'l str) -> &'l str {
+        let spaces = line.len() - line.trim_start_matches(' ').len();
+        &line[spaces.min(self.indent)..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::assert_eq;
+
+    #[test]
+    fn replaces_every_flowchart() {
+        let markdown = "\
+# Title
+
+```flou
+grid { a; }
+```
+
+```rust
+let x = 1;
+```
+
+  ~~~~ flou extra
+  grid { b; }
+  ~~~~
+";
+        let output = replace_fenced_blocks(markdown, &MarkdownConfig::default()).unwrap();
+
+        assert_eq!(output.matches("<svg").count(), 2);
+        assert!(output.contains("```rust\nlet x = 1;\n```\n"));
+        assert!(!output.contains("flou extra"));
+
+        // The SVGs have to stay single HTML blocks.
+        for svg in output.split("<svg").skip(1) {
+            let svg = &svg[..svg.find("</svg>").unwrap()];
+            assert!(svg.lines().all(|line| !line.trim().is_empty()));
+        }
+    }
+
+    #[test]
+    fn fences_inside_other_blocks_are_not_flowcharts() {
+        let markdown = "````md\n```flou\ngrid { a; }\n```\n````\n";
+        let output = replace_fenced_blocks(markdown, &MarkdownConfig::default()).unwrap();
+        assert_eq!(output, markdown);
+    }
+
+    #[test]
+    fn reports_flowcharts_that_fail_to_render() {
+        let markdown = "Intro\n\n```flou\ngrid { a(connect: s:n#x); }\n```\n\n```flou\ngrid { b; }\n```\n\n```flou\ngrid {\n";
+
+        let diagnostics = replace_fenced_blocks(markdown, &MarkdownConfig::default()).unwrap_err();
+        let lines = diagnostics.iter().map(|d| d.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 11]);
+        assert!(diagnostics[0].message.starts_with("Error in Flou logic"));
+
+        let config = MarkdownConfig {
+            error_placeholders: true,
+            ..MarkdownConfig::default()
+        };
+        let output = replace_fenced_blocks(markdown, &config).unwrap();
+        assert_eq!(output.matches("<pre class=\"flou-error\">").count(), 2);
+        assert_eq!(output.matches("<svg").count(), 1);
+    }
+}
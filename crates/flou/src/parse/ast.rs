@@ -1,39 +1,56 @@
+//! The syntax tree of a Flou document, as written in the source.
+//!
+//! The tree is produced by [`Document::parse`] and only checked for
+//! syntax errors. Checks that need the whole document, such as whether
+//! labels are unique or destinations exist, happen when it is turned into
+//! a [`Flou`](crate::Flou), so a tree can be inspected or modified by
+//! tools like formatters and linters even if it doesn't describe a valid
+//! flowchart.
+
 #![allow(dead_code)]
 
-use std::fmt;
+use std::{
+    borrow::Borrow,
+    fmt,
+    hash::{Hash, Hasher},
+};
 
 use nom::{
     branch::{alt, permutation},
     bytes::complete::take_while,
     character::{
-        complete::{anychar, char},
+        complete::{anychar, char, i32, multispace1, u32},
         is_alphabetic, is_alphanumeric,
     },
     combinator::{map, opt, recognize, value, verify},
-    multi::many1,
-    sequence::{pair, preceded, separated_pair, terminated, tuple},
+    error::{ContextError, ErrorKind, ParseError},
+    multi::{many0, many1},
+    number::complete::double,
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     Parser,
 };
-use nom_supreme::{final_parser::final_parser, tag::complete::tag, ParserExt};
-
-use crate::{
-    parse::combinators::enclosed_list0,
-    pos::{pos, IndexPos},
+use nom_supreme::{
+    final_parser::final_parser,
+    tag::complete::{tag, tag_no_case},
+    ParserExt,
 };
 
+use crate::{parse::combinators::enclosed_list0, pos::GridCoord};
+
 use super::{
-    combinators::{attribute, block, list1, space, ws},
+    combinators::{attribute, block, list1, optional_block, space, ws},
     constants::*,
-    parts::quoted_string,
+    parts::{boolean, class_list, quoted_string},
     types::{Input, Result},
     Error,
 };
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub struct Identifier<'i>(pub(crate) &'i str);
+/// A node identifier or label, borrowed from the source.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Identifier<'i>(pub &'i str);
 
 impl<'i> Identifier<'i> {
-    pub(crate) fn parse(i: Input<'i>) -> Result<Self> {
+    pub(crate) fn parse(i: Input<'i>) -> Result<'i, Self> {
         let wchar = take_while(|x: char| x == '_' || is_alphanumeric(x as u8));
         map(
             recognize(pair(
@@ -43,6 +60,19 @@ impl<'i> Identifier<'i> {
             Self,
         )(i)
     }
+
+    /// The identifier as written in the source, without any sigil.
+    pub fn as_str(&self) -> &'i str {
+        self.0
+    }
+}
+
+// Lets maps keyed by identifiers be looked up with a plain string. The
+// derived `Hash` hashes the same way as the string itself.
+impl Borrow<str> for Identifier<'_> {
+    fn borrow(&self) -> &str {
+        self.0
+    }
 }
 
 impl fmt::Display for Identifier<'_> {
@@ -51,30 +81,279 @@ impl fmt::Display for Identifier<'_> {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum NodeShape {
+/// The value of an `opacity` attribute, between 0 and 1.
+#[derive(Debug, Clone, Copy)]
+pub struct Opacity(f64);
+
+impl Opacity {
+    pub(crate) fn parse(i: Input) -> Result<Self> {
+        map(verify(double, |x| (0.0..=1.0).contains(x)), Self)(i)
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+// Opacities are never NaN, so comparing their bits is enough.
+impl PartialEq for Opacity {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Opacity {}
+
+impl Hash for Opacity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// A non-negative size in pixels, such as the value of a node's `radius`
+/// or `padding` attribute.
+#[derive(Debug, Clone, Copy)]
+pub struct Length(f64);
+
+impl Length {
+    pub(crate) fn parse(i: Input) -> Result<Self> {
+        map(verify(double, |x| x.is_finite() && *x >= 0.0), Self)(i)
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+// Like opacities, lengths are never NaN.
+impl PartialEq for Length {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Length {}
+
+impl Hash for Length {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// Where connections attach to a side of a node, as a fraction of the
+/// side's length from its top or left end. Written as a percentage, such
+/// as `30%`.
+#[derive(Debug, Clone, Copy)]
+pub struct Anchor(f64);
+
+impl Anchor {
+    pub(crate) fn parse(i: Input) -> Result<Self> {
+        map(
+            verify(double.terminated(char('%')), |x| (0.0..=100.0).contains(x)),
+            |x| Self(x / 100.0),
+        )(i)
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+// Like opacities, anchors are never NaN.
+impl PartialEq for Anchor {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Anchor {}
+
+impl Hash for Anchor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// The value of a node's `anchors` attribute, such as `{n: 30%; s: 70%}`.
+/// Sides without an anchor have their connections attached in the middle.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Anchors {
+    pub north: Option<Anchor>,
+    pub south: Option<Anchor>,
+    pub west: Option<Anchor>,
+    pub east: Option<Anchor>,
+}
+
+impl Anchors {
+    pub(crate) fn parse(i: Input) -> Result<Self> {
+        let anchor = separated_pair(Direction::parse, ws(char(SIDES_SIGIL)), Anchor::parse);
+
+        // Each side can only be given once.
+        let unique = |anchors: &Vec<(Direction, Anchor)>| {
+            anchors
+                .iter()
+                .enumerate()
+                .all(|(i, (side, _))| anchors[..i].iter().all(|(other, _)| other != side))
+        };
+
+        map(
+            verify(enclosed_list0(BLOCK_DELIMITERS, anchor, TERMINATOR), unique),
+            |anchors| {
+                let mut res = Self::default();
+                for (side, anchor) in anchors {
+                    *res.get_mut(side) = Some(anchor);
+                }
+                res
+            },
+        )(i)
+    }
+
+    pub fn get(&self, side: Direction) -> Option<Anchor> {
+        match side {
+            Direction::North => self.north,
+            Direction::South => self.south,
+            Direction::West => self.west,
+            Direction::East => self.east,
+        }
+    }
+
+    fn get_mut(&mut self, side: Direction) -> &mut Option<Anchor> {
+        match side {
+            Direction::North => &mut self.north,
+            Direction::South => &mut self.south,
+            Direction::West => &mut self.west,
+            Direction::East => &mut self.east,
+        }
+    }
+}
+
+/// The value of a node's `shape` attribute.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeShape {
+    #[default]
     Rectangle,
     Square,
     Ellipse,
     Circle,
     Diamond,
     AngledSquare,
+    /// Takes up its place in the grid and can be connected to, but isn't
+    /// drawn. Connections to it meet at its center.
+    None,
 }
 
 impl NodeShape {
     pub(crate) fn parse(i: Input) -> Result<Self> {
         alt((
-            value(Self::Rectangle, tag("rect")),
-            value(Self::Square, tag("square")),
-            value(Self::Ellipse, tag("ellipse")),
-            value(Self::Circle, tag("circle")),
-            value(Self::Diamond, tag("diamond")),
-            value(Self::AngledSquare, tag("angled_square")),
+            value(Self::Rectangle, tag_no_case("rect")),
+            value(Self::Square, tag_no_case("square")),
+            value(Self::Ellipse, tag_no_case("ellipse")),
+            value(Self::Circle, tag_no_case("circle")),
+            value(Self::Diamond, tag_no_case("diamond")),
+            value(Self::AngledSquare, tag_no_case("angled_square")),
+            value(Self::None, tag_no_case("none")),
         ))(i)
     }
+
+    /// The name the shape is referred to by in the DSL.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rectangle => "rect",
+            Self::Square => "square",
+            Self::Ellipse => "ellipse",
+            Self::Circle => "circle",
+            Self::Diamond => "diamond",
+            Self::AngledSquare => "angled_square",
+            Self::None => "none",
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// The value of a node's `palette` attribute: a set of matching colors for
+/// the node's fill, outline and text from the default CSS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Palette {
+    Blue,
+    Green,
+    Red,
+    Gray,
+}
+
+impl Palette {
+    pub(crate) fn parse(i: Input) -> Result<Self> {
+        alt((
+            value(Self::Blue, tag_no_case("blue")),
+            value(Self::Green, tag_no_case("green")),
+            value(Self::Red, tag_no_case("red")),
+            value(Self::Gray, tag_no_case("gray")),
+        ))(i)
+    }
+
+    /// The name the palette is referred to by in the DSL.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Blue => "blue",
+            Self::Green => "green",
+            Self::Red => "red",
+            Self::Gray => "gray",
+        }
+    }
+}
+
+/// The value of a node's `fit` attribute: how the shape is sized to fit its
+/// cell when their aspect ratios differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fit {
+    /// The largest size that fits inside the cell.
+    Contain,
+    /// The smallest size that covers the whole cell.
+    Cover,
+    /// Fills the cell, ignoring the aspect ratio.
+    Stretch,
+}
+
+impl Fit {
+    pub(crate) fn parse(i: Input) -> Result<Self> {
+        alt((
+            value(Self::Contain, tag_no_case("contain")),
+            value(Self::Cover, tag_no_case("cover")),
+            value(Self::Stretch, tag_no_case("stretch")),
+        ))(i)
+    }
+}
+
+/// The value of a node's `aspect` attribute: the ratio of the shape's width
+/// to its height.
+#[derive(Debug, Clone, Copy)]
+pub struct Aspect(f64);
+
+impl Aspect {
+    pub(crate) fn parse(i: Input) -> Result<Self> {
+        map(verify(double, |x| x.is_finite() && *x > 0.0), Self)(i)
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+// Like opacities, aspect ratios are never NaN.
+impl PartialEq for Aspect {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Aspect {}
+
+impl Hash for Aspect {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// A side of a node, or a direction in the grid.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Direction {
     North,
     South,
@@ -85,10 +364,10 @@ pub enum Direction {
 impl Direction {
     pub(crate) fn parse(i: Input) -> Result<Self> {
         alt((
-            value(Self::North, tag("n")),
-            value(Self::South, tag("s")),
-            value(Self::West, tag("w")),
-            value(Self::East, tag("e")),
+            value(Self::North, tag_no_case("n")),
+            value(Self::South, tag_no_case("s")),
+            value(Self::West, tag_no_case("w")),
+            value(Self::East, tag_no_case("e")),
         ))(i)
     }
 }
@@ -106,52 +385,130 @@ impl fmt::Display for Direction {
     }
 }
 
+/// Where a connection leads to.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub(crate) enum Destination<'i> {
+pub enum Destination<'i> {
+    /// `@`: The node the connection starts from.
     Itself,
+    /// `@n`, `@s`, `@w` or `@e`: The closest node in the given direction.
     Relative(Direction),
+    /// `#label`: The node with the given label.
     Label(Identifier<'i>),
+    /// `#label%nearest`: The closest of the nodes with the given label, for
+    /// labels that are used by more than one node.
+    NearestLabel(Identifier<'i>),
+    /// `@(x, y)`: The cell in column `x` and row `y`, counting from 0,
+    /// which doesn't need to have a node in it.
+    Position(GridCoord),
 }
 
 impl<'i> Destination<'i> {
-    pub(crate) fn parse(i: Input<'i>) -> Result<Self> {
+    pub(crate) fn parse(i: Input<'i>) -> Result<'i, Self> {
+        // `@("text")` is a connection to the node itself with attributes,
+        // so positions are only tried before falling back to that.
+        let position = delimited(
+            char(LIST_DELIMITERS.0),
+            separated_pair(ws(u32), char(LIST_SEPARATOR), ws(u32)),
+            char(LIST_DELIMITERS.1),
+        );
+
         alt((
             preceded(
                 char(RELATIVE_SIGIL),
-                map(opt(Direction::parse), |dir| match dir {
-                    Some(dir) => Self::Relative(dir),
-                    None => Self::Itself,
-                }),
+                alt((
+                    map(position, |(x, y)| {
+                        Self::Position(GridCoord::new(y as isize, x as isize))
+                    }),
+                    map(opt(Direction::parse), |dir| match dir {
+                        Some(dir) => Self::Relative(dir),
+                        None => Self::Itself,
+                    }),
+                )),
+            ),
+            map(
+                preceded(
+                    char(LABEL_SIGIL),
+                    pair(Identifier::parse, opt(tag_no_case(NEAREST_SUFFIX))),
+                ),
+                |(label, nearest)| match nearest {
+                    Some(_) => Self::NearestLabel(label),
+                    None => Self::Label(label),
+                },
             ),
-            map(preceded(char(LABEL_SIGIL), Identifier::parse), Self::Label),
         ))(i)
     }
 }
 
+/// An attribute of a node, either in the grid or in a definition.
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub(crate) enum NodeAttribute<'i> {
+#[non_exhaustive]
+pub enum NodeAttribute<'i> {
     Text(String),
     Class(String),
     Shape(NodeShape),
     Connect(Vec<ConnectionDescriptor<'i>>),
+    /// `connect+`, whose connections are added to the ones from the node's
+    /// definition instead of replacing them.
+    ConnectAppend(Vec<ConnectionDescriptor<'i>>),
+    Start(bool),
+    End(bool),
+    Opacity(Opacity),
+    /// Hides the node while keeping its place in the grid.
+    Hidden(bool),
+    /// The step of a progressive reveal that the node first appears in.
+    Step(u32),
+    /// Where on each side of the node connections attach.
+    Anchors(Anchors),
+    /// Corner radius of rectangles and squares.
+    Radius(Length),
+    /// Space between the shape's outline and its text.
+    Padding(Length),
+    /// How the shape is sized to fit its cell.
+    Fit(Fit),
+    /// Aspect ratio of the shape.
+    Aspect(Aspect),
+    /// Colors of the node.
+    Palette(Palette),
 }
 
 impl<'i> NodeAttribute<'i> {
-    fn parse(i: Input<'i>) -> Result<Self> {
-        let connection_descriptors = alt((
-            map(ConnectionDescriptor::parse, |x| vec![x]),
-            enclosed_list0(BLOCK_DELIMITERS, ConnectionDescriptor::parse, TERMINATOR),
-        ));
+    pub(crate) fn parse(i: Input<'i>) -> Result<'i, Self> {
+        let connection_descriptors = || {
+            alt((
+                map(ConnectionDescriptor::parse, |x| vec![x]),
+                enclosed_list0(BLOCK_DELIMITERS, ConnectionDescriptor::parse, TERMINATOR),
+            ))
+        };
 
         alt((
             map(attribute("text", quoted_string), Self::Text),
-            map(attribute("class", quoted_string), Self::Class),
+            map(attribute("class", class_list), Self::Class),
             map(attribute("shape", NodeShape::parse), Self::Shape),
-            map(attribute("connect", connection_descriptors), Self::Connect),
+            // Tried first, since `connect` would commit to its own value
+            // once it has matched the start of `connect+`.
+            map(
+                attribute("connect+", connection_descriptors()),
+                Self::ConnectAppend,
+            ),
+            map(
+                attribute("connect", connection_descriptors()),
+                Self::Connect,
+            ),
+            map(attribute("start", boolean), Self::Start),
+            map(attribute("end", boolean), Self::End),
+            map(attribute("opacity", Opacity::parse), Self::Opacity),
+            map(attribute("hidden", boolean), Self::Hidden),
+            map(attribute("step", u32), Self::Step),
+            map(attribute("anchors", Anchors::parse), Self::Anchors),
+            map(attribute("radius", Length::parse), Self::Radius),
+            map(attribute("padding", Length::parse), Self::Padding),
+            map(attribute("fit", Fit::parse), Self::Fit),
+            map(attribute("aspect", Aspect::parse), Self::Aspect),
+            map(attribute("palette", Palette::parse), Self::Palette),
         ))(i)
     }
 
-    fn parse_vec(i: Input<'i>) -> Result<Vec<Self>> {
+    fn parse_vec(i: Input<'i>) -> Result<'i, Vec<Self>> {
         alt((
             map(quoted_string.terminated(opt(char(LIST_SEPARATOR))), |x| {
                 vec![Self::Text(x)]
@@ -173,25 +530,41 @@ impl<'i> NodeAttribute<'i> {
         .parse(i)
     }
 
-    pub(crate) fn as_key(&self) -> &'static str {
+    /// The name of the attribute as written in the source.
+    pub fn as_key(&self) -> &'static str {
         match self {
             NodeAttribute::Text(_) => "text",
             NodeAttribute::Class(_) => "class",
             NodeAttribute::Shape(_) => "shape",
             NodeAttribute::Connect(_) => "connect",
+            NodeAttribute::ConnectAppend(_) => "connect+",
+            NodeAttribute::Start(_) => "start",
+            NodeAttribute::End(_) => "end",
+            NodeAttribute::Opacity(_) => "opacity",
+            NodeAttribute::Hidden(_) => "hidden",
+            NodeAttribute::Step(_) => "step",
+            NodeAttribute::Anchors(_) => "anchors",
+            NodeAttribute::Radius(_) => "radius",
+            NodeAttribute::Padding(_) => "padding",
+            NodeAttribute::Fit(_) => "fit",
+            NodeAttribute::Aspect(_) => "aspect",
+            NodeAttribute::Palette(_) => "palette",
         }
     }
 }
 
+/// A single connection inside a node's `connect` attribute, such as
+/// `s:n@s("Yes")`.
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub(crate) struct ConnectionDescriptor<'i> {
-    pub(crate) to: Destination<'i>,
-    pub(crate) sides: (Direction, Direction),
-    pub(crate) attrs: Vec<ConnectionAttribute>,
+pub struct ConnectionDescriptor<'i> {
+    pub to: Destination<'i>,
+    /// The sides of the source and destination nodes the connection is attached to.
+    pub sides: (Direction, Direction),
+    pub attrs: Vec<ConnectionAttribute>,
 }
 
 impl<'i> ConnectionDescriptor<'i> {
-    pub(crate) fn parse(i: Input<'i>) -> Result<Self> {
+    pub(crate) fn parse(i: Input<'i>) -> Result<'i, Self> {
         let sides = separated_pair(Direction::parse, char(SIDES_SIGIL), Direction::parse);
 
         map(
@@ -209,40 +582,101 @@ impl<'i> ConnectionDescriptor<'i> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub(crate) enum ArrowheadType {
+/// The value of a connection's `arrowheads` attribute.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ArrowheadType {
     None,
     Start,
+    #[default]
     End,
     Both,
 }
 
-impl Default for ArrowheadType {
-    fn default() -> Self {
-        Self::End
+/// The value of a connection's `weight` attribute.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ConnectionWeight {
+    Thin,
+    #[default]
+    Normal,
+    Thick,
+}
+
+impl ConnectionWeight {
+    /// How much wider than a normal connection the connection's line is.
+    pub fn factor(self) -> f64 {
+        match self {
+            Self::Thin => 0.5,
+            Self::Normal => 1.0,
+            Self::Thick => 2.0,
+        }
     }
 }
 
+/// The value of a connection's `route` attribute, which overrides how the
+/// connection is drawn.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ConnectionRoute {
+    /// A direct line between the two nodes, ignoring the router.
+    Straight,
+    /// Straight lines with right-angled turns, along the router's route.
+    Elbow,
+    /// A smooth curve along the router's route.
+    Curve,
+}
+
+/// An attribute of a connection.
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub(crate) enum ConnectionAttribute {
+#[non_exhaustive]
+pub enum ConnectionAttribute {
     Text(String),
     Class(String),
     Arrowheads(ArrowheadType),
+    /// A name for the connection, used as the `id` of its SVG element.
+    Id(String),
+    /// Where the connection is drawn relative to the others. Connections
+    /// with a higher order are drawn on top.
+    Order(i32),
+    Weight(ConnectionWeight),
+    Opacity(Opacity),
+    /// Hides the connection while keeping the route it takes.
+    Hidden(bool),
+    /// The step of a progressive reveal that the connection first appears in.
+    Step(u32),
+    Route(ConnectionRoute),
 }
 
 impl ConnectionAttribute {
     pub(crate) fn parse(i: Input) -> Result<Self> {
         let arrowheads = alt((
-            value(ArrowheadType::None, tag("none")),
-            value(ArrowheadType::Start, tag("start")),
-            value(ArrowheadType::End, tag("end")),
-            value(ArrowheadType::Both, tag("both")),
+            value(ArrowheadType::None, tag_no_case("none")),
+            value(ArrowheadType::Start, tag_no_case("start")),
+            value(ArrowheadType::End, tag_no_case("end")),
+            value(ArrowheadType::Both, tag_no_case("both")),
+        ));
+
+        let weight = alt((
+            value(ConnectionWeight::Thin, tag_no_case("thin")),
+            value(ConnectionWeight::Normal, tag_no_case("normal")),
+            value(ConnectionWeight::Thick, tag_no_case("thick")),
+        ));
+
+        let route = alt((
+            value(ConnectionRoute::Straight, tag_no_case("straight")),
+            value(ConnectionRoute::Elbow, tag_no_case("elbow")),
+            value(ConnectionRoute::Curve, tag_no_case("curve")),
         ));
 
         alt((
             map(attribute("text", quoted_string), Self::Text),
-            map(attribute("class", quoted_string), Self::Class),
+            map(attribute("class", class_list), Self::Class),
             map(attribute("arrowheads", arrowheads), Self::Arrowheads),
+            map(attribute("id", quoted_string), Self::Id),
+            map(attribute("order", i32), Self::Order),
+            map(attribute("weight", weight), Self::Weight),
+            map(attribute("opacity", Opacity::parse), Self::Opacity),
+            map(attribute("hidden", boolean), Self::Hidden),
+            map(attribute("step", u32), Self::Step),
+            map(attribute("route", route), Self::Route),
         ))(i)
     }
 
@@ -265,24 +699,33 @@ impl ConnectionAttribute {
         .parse(i)
     }
 
-    pub(crate) fn as_key(&self) -> &'static str {
+    /// The name of the attribute as written in the source.
+    pub fn as_key(&self) -> &'static str {
         match self {
             Self::Text(_) => "text",
             Self::Class(_) => "class",
             Self::Arrowheads(_) => "arrowheads",
+            Self::Id(_) => "id",
+            Self::Order(_) => "order",
+            Self::Weight(_) => "weight",
+            Self::Opacity(_) => "opacity",
+            Self::Hidden(_) => "hidden",
+            Self::Step(_) => "step",
+            Self::Route(_) => "route",
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Node<'i> {
-    pub(crate) id: Identifier<'i>,
-    pub(crate) label: Option<Identifier<'i>>,
-    pub(crate) attrs: Vec<NodeAttribute<'i>>,
+/// A node in the grid, such as `block#start("Start", class: "first")`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node<'i> {
+    pub id: Identifier<'i>,
+    pub label: Option<Identifier<'i>>,
+    pub attrs: Vec<NodeAttribute<'i>>,
 }
 
 impl<'i> Node<'i> {
-    pub(crate) fn parse(i: Input<'i>) -> Result<Self> {
+    pub(crate) fn parse(i: Input<'i>) -> Result<'i, Self> {
         map(
             tuple((
                 Identifier::parse,
@@ -298,62 +741,464 @@ impl<'i> Node<'i> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Grid<'i>(Vec<Vec<Option<Node<'i>>>>);
+/// The names of the grid's rows and columns, given at the start of the
+/// `grid` block with `rows: "A", "B";` and `cols: "C", "D";`. Empty names
+/// mean that a row or column has no header.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Headers {
+    pub rows: Vec<String>,
+    pub cols: Vec<String>,
+}
 
-impl<'i> Grid<'i> {
-    pub(crate) fn parse(i: Input<'i>) -> Result<Self> {
-        let empty = tag(EMPTY);
-        let opt_node = alt((map(empty, |_| None), map(Node::parse, Some)));
-        let row = list1(opt_node, LIST_SEPARATOR, TERMINATOR);
-        let grid = map(many1(ws(row)), Self);
+impl Headers {
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty() && self.cols.is_empty()
+    }
+}
+
+/// A line at the start of the `grid` block, before its rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GridSetting {
+    Rows(Vec<String>),
+    Cols(Vec<String>),
+    /// `ragged: true;`, for grids whose rows are meant to be of different
+    /// lengths.
+    Ragged(bool),
+}
+
+impl GridSetting {
+    fn parse(i: Input) -> Result<Self> {
+        // Not `attribute`, since a node can have the same name as the key.
+        let key = |key| pair(tag_no_case(key), ws(char(':')));
+        let names = || list1(quoted_string, LIST_SEPARATOR, TERMINATOR);
+
+        alt((
+            map(preceded(key("rows"), names()), Self::Rows),
+            map(preceded(key("cols"), names()), Self::Cols),
+            map(
+                preceded(key("ragged"), boolean).terminated(ws(char(TERMINATOR))),
+                Self::Ragged,
+            ),
+        ))(i)
+    }
+
+    fn same_kind(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// Parses the headers and the other settings at the start of the `grid`
+/// block, which can be given in any order but only once each.
+fn parse_grid_settings(i: Input) -> Result<(Headers, bool)> {
+    let unique = |settings: &Vec<GridSetting>| {
+        settings
+            .iter()
+            .enumerate()
+            .all(|(i, setting)| settings[..i].iter().all(|other| !other.same_kind(setting)))
+    };
+
+    map(verify(many0(ws(GridSetting::parse)), unique), |settings| {
+        let mut headers = Headers::default();
+        let mut ragged = false;
+        for setting in settings {
+            match setting {
+                GridSetting::Rows(rows) => headers.rows = rows,
+                GridSetting::Cols(cols) => headers.cols = cols,
+                GridSetting::Ragged(value) => ragged = value,
+            }
+        }
+        (headers, ragged)
+    })(i)
+}
+
+/// A cell of a grid row as written in the source, before it's expanded
+/// into the cells it stands for.
+#[derive(Debug, Clone)]
+enum Cell<'i> {
+    /// A cell repeated the given number of times, such as `step*3` or
+    /// `repeat(3, step)`. Cells written once are repeated once.
+    Repeat(Option<Node<'i>>, u32),
+    /// `fill _`: As many copies of the cell as it takes for the row to be
+    /// as long as the grid's declared width, or the longest row of the grid
+    /// if it doesn't declare one.
+    Fill(Option<Node<'i>>),
+}
+
+impl<'i> Cell<'i> {
+    fn parse(i: Input<'i>) -> Result<'i, Self> {
+        let opt_node = || alt((map(tag(EMPTY), |_| None), map(Node::parse, Some)));
+        let count = || verify(u32, |&n| n > 0);
+
+        // Tried before a single cell, since `repeat` and `fill` are also
+        // valid identifiers.
+        let repeat = preceded(
+            pair(tag_no_case("repeat"), char(LIST_DELIMITERS.0)),
+            separated_pair(ws(count()), char(LIST_SEPARATOR), ws(opt_node())),
+        )
+        .terminated(char(LIST_DELIMITERS.1));
+        let fill = preceded(pair(tag_no_case("fill"), multispace1), opt_node());
+        let single = pair(opt_node(), opt(preceded(ws(char(REPEAT_SIGIL)), count())));
+
+        alt((
+            map(repeat, |(count, node)| Self::Repeat(node, count)),
+            map(fill, Self::Fill),
+            map(single, |(node, count)| {
+                Self::Repeat(node, count.unwrap_or(1))
+            }),
+        ))(i)
+    }
 
-        preceded(terminated(tag("grid"), space), block(grid))(i)
+    /// The number of cells the cell stands for, not counting fills.
+    fn len(&self) -> usize {
+        match self {
+            Self::Repeat(_, count) => *count as usize,
+            Self::Fill(_) => 0,
+        }
     }
+}
+
+/// The most cells a grid may expand into. Repeated and filled cells can
+/// stand for far more cells than it takes to write them, so a short source
+/// could otherwise expand into more cells than fit in memory, before any
+/// [`Limits`](crate::Limits) are checked.
+const MAX_EXPANDED_CELLS: usize = 1 << 22;
+
+/// The `grid` block along with everything that is given about the grid
+/// besides its rows.
+pub(crate) struct GridBlock<'i> {
+    pub(crate) headers: Headers,
+    pub(crate) ragged: bool,
+    pub(crate) width: Option<usize>,
+    pub(crate) grid: Grid<'i>,
+}
 
-    pub(crate) fn nodes(&self) -> impl Iterator<Item = (IndexPos, &Node<'i>)> {
+/// Parses the `grid` block, along with its declared width and the headers
+/// and settings at its start.
+pub(crate) fn parse_grid(i: Input) -> Result<GridBlock> {
+    // A row can only be filled once, since otherwise it isn't clear how
+    // the fill should be split between them.
+    let row = verify(
+        list1(Cell::parse, LIST_SEPARATOR, TERMINATOR),
+        |row: &Vec<_>| {
+            row.iter()
+                .filter(|cell| matches!(cell, Cell::Fill(_)))
+                .count()
+                <= 1
+        },
+    );
+    let width = delimited(
+        char(LIST_DELIMITERS.0),
+        ws(attribute("width", verify(u32, |&n| n > 0))),
+        char(LIST_DELIMITERS.1),
+    );
+    let body = pair(parse_grid_settings, many1(ws(row)));
+
+    let (rest, (width, ((headers, ragged), rows))) = preceded(
+        terminated(tag_no_case("grid"), space),
+        pair(opt(width.terminated(space)), block(body)),
+    )(i)?;
+
+    let width = width.map(|width| width as usize);
+    match expand_rows(rows, width) {
+        Some(grid) => Ok((
+            rest,
+            GridBlock {
+                headers,
+                ragged,
+                width,
+                grid,
+            },
+        )),
+        None => {
+            let error = Error::from_error_kind(i, ErrorKind::TooLarge);
+            let error = Error::add_context(i, "grid expands into too many cells", error);
+            Err(nom::Err::Failure(error))
+        }
+    }
+}
+
+/// Expands repeated and filled cells into the cells they stand for, or
+/// returns `None` if there would be more than [`MAX_EXPANDED_CELLS`].
+fn expand_rows(rows: Vec<Vec<Cell>>, width: Option<usize>) -> Option<Grid> {
+    let lens = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .try_fold(0usize, |len, cell| len.checked_add(cell.len()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let width = width
+        .or_else(|| lens.iter().copied().max())
+        .unwrap_or_default();
+
+    // Counted before anything is expanded, so that nothing is allocated
+    // for grids that are too large.
+    let cells = rows
+        .iter()
+        .zip(&lens)
+        .try_fold(0usize, |cells, (row, &len)| {
+            let is_filled = row.iter().any(|cell| matches!(cell, Cell::Fill(_)));
+            cells.checked_add(if is_filled { len.max(width) } else { len })
+        })?;
+    if cells > MAX_EXPANDED_CELLS {
+        return None;
+    }
+
+    let rows = rows.into_iter().zip(lens).map(|(row, len)| {
+        row.into_iter()
+            .flat_map(|cell| {
+                let (node, count) = match cell {
+                    Cell::Repeat(node, count) => (node, count as usize),
+                    Cell::Fill(node) => (node, width.saturating_sub(len)),
+                };
+                std::iter::repeat_n(node, count)
+            })
+            .collect()
+    });
+
+    Some(Grid(rows.collect()))
+}
+
+/// The `grid` block, as rows of cells that are either a node or empty (`_`).
+/// Rows don't have to be the same length.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Grid<'i>(pub Vec<Vec<Option<Node<'i>>>>);
+
+impl<'i> Grid<'i> {
+    pub(crate) fn parse(i: Input<'i>) -> Result<'i, Self> {
+        map(parse_grid, |block| block.grid)(i)
+    }
+
+    /// Iterates over the nodes in the grid along with their positions.
+    pub fn nodes(&self) -> impl Iterator<Item = (GridCoord, &Node<'i>)> {
         self.0.iter().enumerate().flat_map(|(y, row)| {
             row.iter()
                 .enumerate()
-                .filter_map(move |(x, node)| node.as_ref().map(|node| (pos(x, y).into(), node)))
+                .filter_map(move |(x, node)| node.as_ref().map(|node| (coord(x, y), node)))
         })
     }
 
-    pub(crate) fn size(&self) -> IndexPos {
+    /// Like [`Grid::nodes`], but consumes the grid.
+    pub fn into_nodes(self) -> impl Iterator<Item = (GridCoord, Node<'i>)> {
+        self.0.into_iter().enumerate().flat_map(|(y, row)| {
+            row.into_iter()
+                .enumerate()
+                .filter_map(move |(x, node)| node.map(|node| (coord(x, y), node)))
+        })
+    }
+
+    /// The number of rows and the number of columns in the longest row.
+    pub fn size(&self) -> GridCoord {
         let height = self.0.len();
         let width = self.0.iter().map(|v| v.len()).max().unwrap_or_default();
 
-        pos(width, height).into()
+        coord(width, height)
     }
 }
 
-pub(crate) type Definitions<'i> = Vec<(Identifier<'i>, Vec<NodeAttribute<'i>>)>;
+fn coord(x: usize, y: usize) -> GridCoord {
+    GridCoord::new(y as isize, x as isize)
+}
+
+/// The contents of the `define` block: identifiers along with the
+/// attributes every node with that identifier gets.
+pub type Definitions<'i> = Vec<(Identifier<'i>, Vec<NodeAttribute<'i>>)>;
 
 pub(crate) fn parse_definitions(i: Input) -> Result<Definitions> {
     let definition = pair(Identifier::parse, NodeAttribute::parse_vec).terminated(char(TERMINATOR));
     let definitions = many1(ws(definition));
 
-    preceded(terminated(tag("define"), space), block(definitions))(i)
+    preceded(terminated(tag_no_case("define"), space), block(definitions))(i)
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Document<'i> {
-    pub(crate) grid: Grid<'i>,
-    pub(crate) definitions: Definitions<'i>,
+/// An entry of the `legend` block: a sample of connections with the class
+/// `class`, along with the text explaining what they mean.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LegendEntry {
+    pub class: String,
+    pub text: String,
+}
+
+impl LegendEntry {
+    pub(crate) fn parse(i: Input) -> Result<Self> {
+        map(
+            separated_pair(quoted_string, ws(tag(LEGEND_ARROW)), quoted_string),
+            |(class, text)| Self { class, text },
+        )(i)
+    }
+}
+
+/// The contents of the `legend` block.
+pub type Legend = Vec<LegendEntry>;
+
+pub(crate) fn parse_legend(i: Input) -> Result<Legend> {
+    let entries = many1(ws(LegendEntry::parse.terminated(char(TERMINATOR))));
+
+    preceded(terminated(tag_no_case("legend"), space), block(entries))(i)
+}
+
+/// A note attached to the node with the given label, such as
+/// `note #validate "Retries up to 3 times" (side: e)`. Notes are drawn
+/// next to their node and don't take up a place in the grid.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Note<'i> {
+    pub label: Identifier<'i>,
+    pub text: String,
+    pub side: Option<Direction>,
+}
+
+impl<'i> Note<'i> {
+    pub(crate) fn parse(i: Input<'i>) -> Result<'i, Self> {
+        let side = delimited(
+            char(LIST_DELIMITERS.0),
+            ws(attribute("side", Direction::parse)),
+            char(LIST_DELIMITERS.1),
+        );
+
+        map(
+            tuple((
+                preceded(terminated(tag_no_case("note"), space), char(LABEL_SIGIL)),
+                Identifier::parse,
+                ws(quoted_string),
+                opt(side),
+            )),
+            |(_, label, text, side)| Self { label, text, side },
+        )(i)
+    }
+}
+
+/// The notes of a document. They are written one after another, each
+/// optionally followed by a terminator.
+pub(crate) fn parse_notes(i: Input) -> Result<Vec<Note>> {
+    many1(ws(Note::parse.terminated(opt(ws(char(TERMINATOR))))))(i)
+}
+
+/// Where [`Flou::compose`](crate::Flou::compose) places each document's
+/// grid relative to the grid of the documents before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Below the previous grid, shifted right by `offset` columns.
+    Below { offset: usize },
+    /// To the right of the previous grid, shifted down by `offset` rows.
+    Right { offset: usize },
+}
+
+/// A whole Flou document.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Document<'i> {
+    pub grid: Grid<'i>,
+    pub headers: Headers,
+    /// Whether the grid's rows are meant to be of different lengths, set
+    /// with `ragged: true;` at the start of the `grid` block.
+    pub ragged: bool,
+    /// The number of columns every row has to have, declared with
+    /// `grid(width: 5)`.
+    pub width: Option<usize>,
+    pub definitions: Definitions<'i>,
+    pub legend: Legend,
+    pub notes: Vec<Note<'i>>,
 }
 
 impl<'i> Document<'i> {
-    pub(crate) fn parse(i: Input<'i>) -> std::result::Result<Self, Error<'i>> {
+    /// Parses a document, failing only on syntax errors.
+    pub fn parse(i: Input<'i>) -> std::result::Result<Self, Error<'i>> {
         let document = map(
-            permutation((ws(Grid::parse), opt(ws(parse_definitions)))),
-            |(grid, definitions)| Self {
-                grid,
+            permutation((
+                ws(parse_grid),
+                ws(optional_block(parse_definitions)),
+                ws(optional_block(parse_legend)),
+                ws(optional_block(parse_notes)),
+            )),
+            |(grid, definitions, legend, notes)| Self {
+                grid: grid.grid,
+                headers: grid.headers,
+                ragged: grid.ragged,
+                width: grid.width,
                 definitions: definitions.unwrap_or_default(),
+                legend: legend.unwrap_or_default(),
+                notes: notes.unwrap_or_default(),
             },
         );
 
         final_parser(document)(i)
     }
+
+    /// Appends the grid of `other` to this document's grid and adds its
+    /// definitions to this document's definitions. Definitions that appear
+    /// in both documents with the same attributes are only kept once, and
+    /// so are legend entries. Row and column headers move along with the
+    /// grid they belong to, and every note is kept. The merged document has
+    /// no declared width, since its rows no longer match either one.
+    pub(crate) fn merge(mut self, other: Self, placement: Placement) -> Self {
+        let padding = |n| std::iter::repeat_with(|| None).take(n);
+        let size = self.grid.size();
+        let (width, height) = (size.col as usize, size.row as usize);
+        let rows = &mut self.grid.0;
+
+        match placement {
+            Placement::Below { offset } => {
+                place_headers(&mut self.headers.rows, height, other.headers.rows);
+                place_headers(&mut self.headers.cols, offset, other.headers.cols);
+                rows.extend(
+                    other
+                        .grid
+                        .0
+                        .into_iter()
+                        .map(|row| padding(offset).chain(row).collect()),
+                );
+            }
+            Placement::Right { offset } => {
+                place_headers(&mut self.headers.cols, width, other.headers.cols);
+                place_headers(&mut self.headers.rows, offset, other.headers.rows);
+                for (y, row) in other.grid.0.into_iter().enumerate() {
+                    if rows.len() <= offset + y {
+                        rows.resize_with(offset + y + 1, Vec::new);
+                    }
+
+                    let target = &mut rows[offset + y];
+                    target.extend(padding(width - target.len()));
+                    target.extend(row);
+                }
+            }
+        }
+
+        self.ragged |= other.ragged;
+        self.width = None;
+
+        for definition in other.definitions {
+            if !self.definitions.contains(&definition) {
+                self.definitions.push(definition);
+            }
+        }
+
+        self.notes.extend(other.notes);
+
+        for entry in other.legend {
+            if !self.legend.contains(&entry) {
+                self.legend.push(entry);
+            }
+        }
+
+        self
+    }
+}
+
+/// Places `other` headers starting at index `at` of `headers`, without
+/// replacing any names that are already there.
+fn place_headers(headers: &mut Vec<String>, at: usize, other: Vec<String>) {
+    if other.is_empty() {
+        return;
+    }
+
+    if headers.len() < at + other.len() {
+        headers.resize(at + other.len(), String::new());
+    }
+
+    for (header, name) in headers[at..].iter_mut().zip(other) {
+        if header.is_empty() {
+            *header = name;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -414,6 +1259,27 @@ mod tests {
         );
 
         assert_parsed_eq(Destination::parse, "@", Destination::Itself);
+
+        assert_parsed_eq(
+            Destination::parse,
+            "#end%nearest",
+            Destination::NearestLabel(Identifier("end")),
+        );
+
+        assert_parsed_eq(
+            Destination::parse,
+            "@(3, 2)",
+            Destination::Position(GridCoord::new(2, 3)),
+        );
+        assert_parsed_eq(
+            ConnectionDescriptor::parse,
+            r#"n:s@("foo")"#,
+            ConnectionDescriptor {
+                to: Destination::Itself,
+                sides: (Direction::North, Direction::South),
+                attrs: vec![ConnectionAttribute::Text(String::from("foo"))],
+            },
+        );
     }
 
     #[test]
@@ -435,6 +1301,61 @@ mod tests {
             r#"shape: diamond"#,
             NodeAttribute::Shape(NodeShape::Diamond),
         );
+
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "start: true",
+            NodeAttribute::Start(true),
+        );
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "end: false",
+            NodeAttribute::End(false),
+        );
+
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "anchors: {n: 30%; S: 100%;}",
+            NodeAttribute::Anchors(Anchors {
+                north: Some(Anchor(0.3)),
+                south: Some(Anchor(1.0)),
+                ..Default::default()
+            }),
+        );
+        assert_not_parsed(NodeAttribute::parse, "anchors: {n: 30%; n: 70%}");
+        assert_not_parsed(NodeAttribute::parse, "anchors: {e: 120%}");
+        assert_not_parsed(NodeAttribute::parse, "anchors: {e: 0.5}");
+
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "radius: 8",
+            NodeAttribute::Radius(Length(8.0)),
+        );
+        assert_not_parsed(NodeAttribute::parse, "radius: -1");
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "padding: 12.5",
+            NodeAttribute::Padding(Length(12.5)),
+        );
+
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "fit: Cover",
+            NodeAttribute::Fit(Fit::Cover),
+        );
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "aspect: 1.5",
+            NodeAttribute::Aspect(Aspect(1.5)),
+        );
+        assert_not_parsed(NodeAttribute::parse, "aspect: 0");
+
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "palette: red",
+            NodeAttribute::Palette(Palette::Red),
+        );
+        assert_not_parsed(NodeAttribute::parse, "palette: purple");
     }
 
     #[test]
@@ -456,6 +1377,44 @@ mod tests {
             "arrowheads: none",
             ConnectionAttribute::Arrowheads(ArrowheadType::None),
         );
+
+        assert_parsed_eq(
+            ConnectionAttribute::parse,
+            r#"id: "approve-edge""#,
+            ConnectionAttribute::Id(String::from("approve-edge")),
+        );
+
+        assert_parsed_eq(
+            ConnectionAttribute::parse,
+            "order: -2",
+            ConnectionAttribute::Order(-2),
+        );
+
+        assert_parsed_eq(
+            ConnectionAttribute::parse,
+            "weight: thick",
+            ConnectionAttribute::Weight(ConnectionWeight::Thick),
+        );
+
+        assert_parsed_eq(
+            ConnectionAttribute::parse,
+            "opacity: 0.5",
+            ConnectionAttribute::Opacity(Opacity(0.5)),
+        );
+        assert_not_parsed(ConnectionAttribute::parse, "opacity: 1.5");
+
+        assert_parsed_eq(
+            ConnectionAttribute::parse,
+            "hidden: true",
+            ConnectionAttribute::Hidden(true),
+        );
+
+        assert_parsed_eq(
+            ConnectionAttribute::parse,
+            "route: straight",
+            ConnectionAttribute::Route(ConnectionRoute::Straight),
+        );
+        assert_not_parsed(ConnectionAttribute::parse, "route: diagonal");
     }
 
     #[test]
@@ -521,7 +1480,17 @@ mod tests {
                     attrs: vec![],
                 },
             ]),
-        )
+        );
+
+        assert_parsed_eq(
+            NodeAttribute::parse,
+            "connect+: n:e@n",
+            NodeAttribute::ConnectAppend(vec![ConnectionDescriptor {
+                to: Destination::Relative(Direction::North),
+                sides: (Direction::North, Direction::East),
+                attrs: vec![],
+            }]),
+        );
     }
 
     #[test]
@@ -634,6 +1603,60 @@ mod tests {
         assert_not_parsed(Grid::parse, "grid { missing_terminator }");
         assert_not_parsed(Grid::parse, "grid { missing separator; }");
         assert_not_parsed(Grid::parse, "grid { foo; ; }");
+        assert_not_parsed(Grid::parse, "grid { foo*0; }");
+        assert_not_parsed(Grid::parse, "grid { repeat(0, foo); }");
+        assert_not_parsed(Grid::parse, "grid { fill _, fill foo; }");
+
+        // Grids that would expand into too many cells.
+        assert_not_parsed(Grid::parse, "grid { a*4000000000; }");
+        assert_not_parsed(Grid::parse, "grid { a*4000000000, b*4000000000; }");
+        assert_not_parsed(Grid::parse, "grid(width: 4000000000) { fill _; }");
+        assert_not_parsed(Grid::parse, "grid { a*3000000; b*3000000; }");
+    }
+
+    #[test]
+    fn grid_repetition() {
+        let input = r#"
+            grid {
+                a*3, repeat(2, _);
+                fill _, b("Fill");
+                repeat(2, b("Repeat"));
+                fill, repeat;
+                repeat_me, fill x;
+            }
+        "#
+        .trim();
+
+        let node = |id, text: Option<&str>| {
+            Some(Node {
+                id: Identifier(id),
+                label: None,
+                attrs: text
+                    .map(|text| vec![NodeAttribute::Text(text.to_string())])
+                    .unwrap_or_default(),
+            })
+        };
+        let a = node("a", None);
+        let b = node("b", Some("Fill"));
+        let repeat = node("b", Some("Repeat"));
+
+        assert_parsed_eq(
+            Grid::parse,
+            input,
+            Grid(vec![
+                vec![a.clone(), a.clone(), a, None, None],
+                vec![None, None, None, None, b],
+                vec![repeat.clone(), repeat],
+                vec![node("fill", None), node("repeat", None)],
+                vec![
+                    node("repeat_me", None),
+                    node("x", None),
+                    node("x", None),
+                    node("x", None),
+                    node("x", None),
+                ],
+            ]),
+        );
     }
 
     #[test]
@@ -669,4 +1692,186 @@ mod tests {
         assert_not_parsed(parse_definitions, "define { no_terminator(shape: rect) }");
         assert_not_parsed(parse_definitions, "define { ; }");
     }
+
+    #[test]
+    fn valid_headers() {
+        let document = Document::parse(
+            r#"grid { rows: "Step 1", "Step 2"; cols: "Happy path", "Error path"; a, b; c; }"#,
+        )
+        .unwrap();
+        assert_eq!(document.headers.rows, vec!["Step 1", "Step 2"]);
+        assert_eq!(document.headers.cols, vec!["Happy path", "Error path"]);
+        assert_eq!(document.grid.size(), GridCoord::new(2, 2));
+
+        let document =
+            Document::parse(r#"grid { cols: "A"; rows(connect: s:n@s); rows; }"#).unwrap();
+        assert_eq!(document.headers.cols, vec!["A"]);
+        assert!(document.headers.rows.is_empty());
+        assert_eq!(document.grid.size(), GridCoord::new(2, 1));
+
+        assert!(Document::parse(r#"grid { rows: ; a; }"#).is_err());
+        assert!(Document::parse(r#"grid { a; rows: "A"; }"#).is_err());
+        assert!(Document::parse(r#"grid { rows: "A"; rows: "B"; a; }"#).is_err());
+    }
+
+    #[test]
+    fn valid_width() {
+        let document = Document::parse("grid(width: 4) { a, fill _; fill b; }").unwrap();
+        assert_eq!(document.width, Some(4));
+        assert_eq!(
+            document.grid.0.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![4, 4]
+        );
+
+        assert_eq!(Document::parse("grid { a; }").unwrap().width, None);
+        assert!(Document::parse("grid(width: 0) { a; }").is_err());
+        assert!(Document::parse("grid() { a; }").is_err());
+        assert!(Document::parse("grid(height: 2) { a; }").is_err());
+    }
+
+    #[test]
+    fn valid_ragged() {
+        let document =
+            Document::parse(r#"grid { cols: "A"; ragged: true; rows: "B"; a; }"#).unwrap();
+        assert!(document.ragged);
+        assert_eq!(document.headers.rows, vec!["B"]);
+        assert_eq!(document.headers.cols, vec!["A"]);
+
+        assert!(
+            !Document::parse("grid { ragged: false; a; }")
+                .unwrap()
+                .ragged
+        );
+        assert!(!Document::parse("grid { ragged; }").unwrap().ragged);
+        assert!(Document::parse("grid { ragged: yes; a; }").is_err());
+        assert!(Document::parse("grid { ragged: true; ragged: true; a; }").is_err());
+    }
+
+    #[test]
+    fn valid_note() {
+        assert_parsed_eq(
+            Note::parse,
+            r#"note #validate "Retries up to 3 times" (side: e)"#,
+            Note {
+                label: Identifier("validate"),
+                text: "Retries up to 3 times".into(),
+                side: Some(Direction::East),
+            },
+        );
+        assert_parsed_eq(
+            Note::parse,
+            r#"note#x"Text""#,
+            Note {
+                label: Identifier("x"),
+                text: "Text".into(),
+                side: None,
+            },
+        );
+
+        let document = Document::parse(
+            r#"grid { a#a; } note #a "A"; note #a "B" (side: n) legend { "x" -> "y"; }"#,
+        )
+        .unwrap();
+        assert_eq!(document.notes.len(), 2);
+        assert_eq!(document.legend.len(), 1);
+    }
+
+    #[test]
+    fn invalid_note() {
+        assert_not_parsed(Note::parse, r#"note validate "Text""#);
+        assert_not_parsed(Note::parse, r#"note #validate"#);
+        assert_not_parsed(Note::parse, r#"note #validate "Text" (side: x)"#);
+    }
+
+    #[test]
+    fn valid_legend() {
+        let entry = |class: &str, text: &str| LegendEntry {
+            class: class.into(),
+            text: text.into(),
+        };
+
+        assert_parsed_eq(
+            parse_legend,
+            r#"legend { "solid" -> "sync call"; "dashed"->"async"; }"#,
+            vec![entry("solid", "sync call"), entry("dashed", "async")],
+        );
+
+        let document =
+            Document::parse(r#"legend { "solid" -> "sync"; } grid { a; } define { a("A"); }"#)
+                .unwrap();
+        assert_eq!(document.legend, vec![entry("solid", "sync")]);
+        assert_eq!(document.definitions.len(), 1);
+    }
+
+    #[test]
+    fn invalid_legend() {
+        assert_not_parsed(parse_legend, "legend { }");
+        assert_not_parsed(parse_legend, r#"legend { "solid" "sync"; }"#);
+        assert_not_parsed(parse_legend, r#"legend { "solid" -> "sync" }"#);
+        assert!(
+            Document::parse(r#"grid { a; } legend { "a" -> "b"; } legend { "c" -> "d"; }"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        let lower = Document::parse(
+            r#"grid { a(shape: diamond, start: true, connect: s:n@s(arrowheads: both)); b; }
+            define { b(text: "B"); }"#,
+        );
+        let mixed = Document::parse(
+            r#"Grid { a(Shape: Diamond, START: True, connect: S:N@S(ARROWHEADS: BOTH)); b; }
+            DEFINE { b(Text: "B"); }"#,
+        );
+
+        assert_eq!(mixed.unwrap(), lower.unwrap());
+    }
+
+    #[test]
+    fn merges_documents() {
+        let a = || Document::parse(r#"grid { a, b; c; } define { a(shape: rect); }"#).unwrap();
+        let b = || {
+            Document::parse(r#"grid { d; _, e; } define { a(shape: rect); d(text: "d"); }"#)
+                .unwrap()
+        };
+        let node = |id| {
+            Some(Node {
+                id: Identifier(id),
+                label: None,
+                attrs: vec![],
+            })
+        };
+
+        let below = a().merge(b(), Placement::Below { offset: 1 });
+        assert_eq!(
+            below.grid,
+            Grid(vec![
+                vec![node("a"), node("b")],
+                vec![node("c")],
+                vec![None, node("d")],
+                vec![None, None, node("e")],
+            ])
+        );
+        assert_eq!(below.definitions.len(), 2);
+
+        let right = a().merge(b(), Placement::Right { offset: 1 });
+        assert_eq!(
+            right.grid,
+            Grid(vec![
+                vec![node("a"), node("b")],
+                vec![node("c"), None, node("d")],
+                vec![None, None, None, node("e")],
+            ])
+        );
+
+        let a = || Document::parse(r#"grid { rows: "R1"; cols: "C1", "C2"; a, b; c; }"#).unwrap();
+        let b = || Document::parse(r#"grid { rows: "R3"; cols: "C3"; d; }"#).unwrap();
+        let headers = a().merge(b(), Placement::Below { offset: 2 }).headers;
+        assert_eq!(headers.rows, vec!["R1", "", "R3"]);
+        assert_eq!(headers.cols, vec!["C1", "C2", "C3"]);
+        let headers = a().merge(b(), Placement::Right { offset: 0 }).headers;
+        assert_eq!(headers.rows, vec!["R1"]);
+        assert_eq!(headers.cols, vec!["C1", "C2", "C3"]);
+    }
 }
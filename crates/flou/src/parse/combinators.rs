@@ -1,10 +1,14 @@
 use nom::{
     branch::alt,
     character::complete::{char, line_ending, multispace0, not_line_ending},
-    combinator::{cut, map, opt, recognize, value},
+    combinator::{cut, eof, map, opt, recognize, value},
     sequence::{delimited, pair, preceded},
 };
-use nom_supreme::{multi::collect_separated_terminated, tag::complete::tag, ParserExt};
+use nom_supreme::{
+    multi::collect_separated_terminated,
+    tag::complete::{tag, tag_no_case},
+    ParserExt,
+};
 
 use super::{constants::BLOCK_DELIMITERS, Input, Parser, Result};
 
@@ -25,7 +29,7 @@ pub(super) fn attribute<'i, O, V: Parser<'i, O>>(
     key: &'static str,
     value: V,
 ) -> impl Parser<'i, O> {
-    preceded(pair(tag(key), cut(ws(char(':')))), cut(value))
+    preceded(pair(tag_no_case(key), cut(ws(char(':')))), cut(value))
 }
 
 pub(super) fn enclosed_list0<'i, Item>(
@@ -61,3 +65,10 @@ pub(super) fn list1<'i, Item>(
 pub(super) fn block<'i, O, P: Parser<'i, O>>(item: P) -> impl Parser<'i, O> {
     delimited(char(BLOCK_DELIMITERS.0), ws(item), char(BLOCK_DELIMITERS.1))
 }
+
+/// Like `opt`, but only parses nothing at the end of the input. Unlike `opt`,
+/// this fails if something else comes next, so that `permutation` goes on to
+/// try the other blocks before coming back to this one.
+pub(super) fn optional_block<'i, O, P: Parser<'i, O>>(item: P) -> impl Parser<'i, Option<O>> {
+    alt((map(item, Some), map(eof, |_| None)))
+}
@@ -1,10 +1,15 @@
 pub(super) const RELATIVE_SIGIL: char = '@';
 pub(super) const LABEL_SIGIL: char = '#';
 pub(super) const SIDES_SIGIL: char = ':';
+pub(super) const REPEAT_SIGIL: char = '*';
 
 pub(super) const LIST_SEPARATOR: char = ',';
 pub(super) const TERMINATOR: char = ';';
 pub(super) const LIST_DELIMITERS: (char, char) = ('(', ')');
 pub(super) const BLOCK_DELIMITERS: (char, char) = ('{', '}');
+pub(super) const ARRAY_DELIMITERS: (char, char) = ('[', ']');
+
+pub(super) const LEGEND_ARROW: &str = "->";
+pub(super) const NEAREST_SUFFIX: &str = "%nearest";
 
 pub(super) const EMPTY: &str = "_";
@@ -0,0 +1,1026 @@
+//! A lossless syntax tree of a Flou document.
+//!
+//! Unlike the [`ast`](super::ast), the tree keeps every character of the
+//! source, including whitespace, comments and anything that doesn't parse,
+//! so that the source can be recreated from it exactly. This makes it
+//! suitable for tools that rewrite documents, such as formatters.
+//!
+//! Parsing never fails. Tokens that don't fit where they appear are wrapped
+//! in [`NodeKind::Error`] nodes and parsing resumes after them. Whitespace
+//! and comments are attached to the innermost node that is open when they
+//! appear, so a node's range starts at its first significant token.
+//!
+//! [`Editor`] builds on the tree to make changes to a document that only
+//! touch the text they are about.
+
+use std::{convert::TryFrom, ops::Range};
+
+use super::{ast::Document, Error};
+
+mod edit;
+
+pub use self::edit::{EditError, Editor};
+
+/// The kind of a [`Token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Whitespace,
+    /// A `//` comment, not including the line ending.
+    Comment,
+    /// An identifier or keyword, including `_` for an empty cell.
+    Identifier,
+    /// A quoted string, including the quotes.
+    String,
+    /// A number, such as `3`, `-1` or `0.5`.
+    Number,
+    /// One of `{ } ( ) [ ] , ; : @ # + % * ->`.
+    Punctuation,
+    /// A character that can't start any other token, or a string
+    /// without a closing quote.
+    Error,
+}
+
+impl TokenKind {
+    /// Whether the token carries no meaning and is skipped by the parser.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment)
+    }
+}
+
+/// A token, referring to the part of the source it covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    kind: TokenKind,
+    range: Range<usize>,
+}
+
+impl Token {
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    /// The byte range of the token in the source.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+/// The kind of a [`SyntaxNode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    /// The whole document.
+    Document,
+    /// The `grid` block, including its declared width.
+    Grid,
+    /// A `rows:` or `cols:` line naming the grid's rows or columns, or a
+    /// `ragged:` line.
+    Header,
+    /// A row of the grid, including its terminator.
+    Row,
+    /// An empty cell (`_`).
+    Empty,
+    /// A cell repeated a number of times, such as `step*3` or
+    /// `repeat(3, step)`.
+    Repeat,
+    /// A cell that pads its row to the width of the grid, such as `fill _`.
+    Fill,
+    /// A node in the grid.
+    Node,
+    /// A node's `#label`.
+    Label,
+    /// A parenthesized list of node or connection attributes.
+    AttributeList,
+    /// A single attribute, or the text shorthand at the start of a list.
+    Attribute,
+    /// A bracketed list of values, such as `["warning", "db"]`.
+    List,
+    /// A braced list of connections in a `connect` attribute.
+    Connections,
+    /// A single connection, such as `s:n@s("Yes")`.
+    Connection,
+    /// A braced list of link point offsets in an `anchors` attribute,
+    /// such as `{n: 30%; s: 70%}`.
+    Anchors,
+    /// A connection's destination, such as `@s` or `#label`, or the
+    /// `#label` of the node a note is attached to.
+    Destination,
+    /// The `define` block.
+    Define,
+    /// A single definition in the `define` block.
+    Definition,
+    /// The `legend` block.
+    Legend,
+    /// A single entry in the `legend` block, such as `"dashed" -> "async";`.
+    LegendEntry,
+    /// A note, such as `note #validate "Retries" (side: e)`.
+    Note,
+    /// A token that doesn't belong where it appears.
+    Error,
+}
+
+/// A child of a [`SyntaxNode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+impl Element {
+    /// The byte range of the element in the source.
+    pub fn range(&self) -> Range<usize> {
+        match self {
+            Element::Node(node) => node.range(),
+            Element::Token(token) => token.range(),
+        }
+    }
+}
+
+/// A node of the syntax tree, covering a contiguous part of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxNode {
+    kind: NodeKind,
+    range: Range<usize>,
+    children: Vec<Element>,
+}
+
+impl SyntaxNode {
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    /// The byte range of the node in the source.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    pub fn children(&self) -> &[Element] {
+        &self.children
+    }
+
+    /// Iterates over the child nodes, skipping tokens.
+    pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    /// Iterates over all tokens inside the node, in source order.
+    pub fn tokens(&self) -> Box<dyn Iterator<Item = &Token> + '_> {
+        Box::new(self.children.iter().flat_map(|child| match child {
+            Element::Node(node) => node.tokens(),
+            Element::Token(token) => Box::new(std::iter::once(token)),
+        }))
+    }
+}
+
+/// A lossless syntax tree along with the source it was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree<'i> {
+    source: &'i str,
+    root: SyntaxNode,
+}
+
+impl<'i> SyntaxTree<'i> {
+    pub fn parse(source: &'i str) -> Self {
+        let root = Parser::new(source).document();
+        Self { source, root }
+    }
+
+    pub fn source(&self) -> &'i str {
+        self.source
+    }
+
+    /// The [`NodeKind::Document`] node containing the rest of the tree.
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    /// The line, counting from 1, that each row of the grid starts on, in
+    /// the order of the rows.
+    pub fn row_lines(&self) -> Vec<usize> {
+        let line = |offset| self.source[..offset].matches('\n').count() + 1;
+
+        self.root
+            .child_nodes()
+            .filter(|node| node.kind() == NodeKind::Grid)
+            .take(1)
+            .flat_map(|grid| grid.child_nodes())
+            .filter(|node| node.kind() == NodeKind::Row)
+            .map(|row| line(row.range().start))
+            .collect()
+    }
+
+    /// The part of the source covered by `range`, e.g. a node's or token's range.
+    pub fn text(&self, range: Range<usize>) -> &'i str {
+        &self.source[range]
+    }
+}
+
+/// Parses the tree's source into a [`Document`], failing the same way
+/// [`Document::parse`] would. A tree with [`NodeKind::Error`] nodes never
+/// converts successfully.
+impl<'i> TryFrom<&SyntaxTree<'i>> for Document<'i> {
+    type Error = Error<'i>;
+
+    fn try_from(tree: &SyntaxTree<'i>) -> Result<Self, Self::Error> {
+        Document::parse(tree.source)
+    }
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    fn is_identifier(c: char) -> bool {
+        c == '_' || c.is_ascii_alphanumeric()
+    }
+
+    fn string_len(rest: &str) -> Option<usize> {
+        let mut escaped = false;
+        for (i, c) in rest.char_indices().skip(1) {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => return Some(i + 1),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+
+    while let Some(c) = source[offset..].chars().next() {
+        let rest = &source[offset..];
+        let end_of = |f: fn(char) -> bool| rest.find(f).unwrap_or(rest.len());
+
+        let (kind, len) = if c.is_whitespace() {
+            (TokenKind::Whitespace, end_of(|c| !c.is_whitespace()))
+        } else if rest.starts_with("//") {
+            (TokenKind::Comment, end_of(|c| c == '\n' || c == '\r'))
+        } else if c == '_' || c.is_ascii_alphabetic() {
+            (TokenKind::Identifier, end_of(|c| !is_identifier(c)))
+        } else if c == '"' {
+            match string_len(rest) {
+                Some(len) => (TokenKind::String, len),
+                None => (TokenKind::Error, rest.len()),
+            }
+        } else if rest.starts_with("->") {
+            (TokenKind::Punctuation, 2)
+        } else if c.is_ascii_digit()
+            || (c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let len = rest[1..].find(|c: char| !c.is_ascii_digit() && c != '.');
+            (TokenKind::Number, len.map_or(rest.len(), |len| len + 1))
+        } else if "{}()[],;:@#+%*".contains(c) {
+            (TokenKind::Punctuation, 1)
+        } else {
+            (TokenKind::Error, c.len_utf8())
+        };
+
+        tokens.push(Token {
+            kind,
+            range: offset..offset + len,
+        });
+        offset += len;
+    }
+
+    tokens
+}
+
+struct Parser<'i> {
+    source: &'i str,
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    offset: usize,
+    stack: Vec<(NodeKind, Vec<Element>)>,
+}
+
+impl<'i> Parser<'i> {
+    fn new(source: &'i str) -> Self {
+        Self {
+            source,
+            tokens: tokenize(source).into_iter().peekable(),
+            offset: 0,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Moves leading trivia into the currently open node.
+    fn flush_trivia(&mut self) {
+        while let Some(token) = self.tokens.next_if(|token| token.kind.is_trivia()) {
+            self.push_token(token);
+        }
+    }
+
+    fn push_token(&mut self, token: Token) {
+        self.offset = token.range.end;
+        let (_, children) = self.stack.last_mut().expect("no open node");
+        children.push(Element::Token(token));
+    }
+
+    fn peek(&mut self) -> Option<(TokenKind, &'i str)> {
+        self.flush_trivia();
+        let source = self.source;
+        self.tokens
+            .peek()
+            .map(|token| (token.kind, &source[token.range.clone()]))
+    }
+
+    /// The token `n` tokens after the next one, skipping trivia.
+    fn ahead(&mut self, n: usize) -> Option<Token> {
+        self.flush_trivia();
+        self.tokens
+            .clone()
+            .filter(|token| !token.kind.is_trivia())
+            .nth(n)
+    }
+
+    /// The token after the next one.
+    fn second(&mut self) -> Option<Token> {
+        self.ahead(1)
+    }
+
+    /// The text of the token after the next one.
+    fn peek_second(&mut self) -> Option<&'i str> {
+        let source = self.source;
+        self.second().map(|token| &source[token.range])
+    }
+
+    fn peek_second_kind(&mut self) -> Option<TokenKind> {
+        self.second().map(|token| token.kind)
+    }
+
+    fn peek_third_kind(&mut self) -> Option<TokenKind> {
+        self.ahead(2).map(|token| token.kind)
+    }
+
+    fn at(&mut self, text: &str) -> bool {
+        matches!(self.peek(), Some((_, t)) if t == text)
+    }
+
+    fn at_kind(&mut self, kind: TokenKind) -> bool {
+        matches!(self.peek(), Some((k, _)) if k == kind)
+    }
+
+    fn bump(&mut self) {
+        self.flush_trivia();
+        if let Some(token) = self.tokens.next() {
+            self.push_token(token);
+        }
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let found = self.at(text);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn start(&mut self, kind: NodeKind) {
+        if !self.stack.is_empty() {
+            self.flush_trivia();
+        }
+        self.stack.push((kind, Vec::new()));
+    }
+
+    fn finish(&mut self) -> SyntaxNode {
+        let (kind, children) = self.stack.pop().expect("no open node");
+        let range = match (children.first(), children.last()) {
+            (Some(first), Some(last)) => first.range().start..last.range().end,
+            _ => self.offset..self.offset,
+        };
+
+        SyntaxNode {
+            kind,
+            range,
+            children,
+        }
+    }
+
+    fn finish_child(&mut self) {
+        let node = self.finish();
+        let (_, children) = self.stack.last_mut().expect("no open node");
+        children.push(Element::Node(node));
+    }
+
+    /// Opens a node that starts with the last node finished in the
+    /// currently open node, along with anything that came after it.
+    fn wrap_last(&mut self, kind: NodeKind) {
+        let (_, children) = self.stack.last_mut().expect("no open node");
+        let start = children
+            .iter()
+            .rposition(|element| matches!(element, Element::Node(_)))
+            .unwrap_or(children.len());
+        let wrapped = children.split_off(start);
+        self.stack.push((kind, wrapped));
+    }
+
+    fn error(&mut self) {
+        self.start(NodeKind::Error);
+        self.bump();
+        self.finish_child();
+    }
+
+    fn document(mut self) -> SyntaxNode {
+        self.start(NodeKind::Document);
+
+        while let Some((kind, text)) = self.peek() {
+            match (kind, text) {
+                (TokenKind::Identifier, text) if text.eq_ignore_ascii_case("grid") => self.grid(),
+                (TokenKind::Identifier, text) if text.eq_ignore_ascii_case("define") => {
+                    self.define()
+                }
+                (TokenKind::Identifier, text) if text.eq_ignore_ascii_case("legend") => {
+                    self.legend()
+                }
+                (TokenKind::Identifier, text) if text.eq_ignore_ascii_case("note") => self.note(),
+                _ => self.error(),
+            }
+        }
+
+        self.flush_trivia();
+        self.finish()
+    }
+
+    fn grid(&mut self) {
+        self.start(NodeKind::Grid);
+        self.bump();
+
+        if self.at("(") {
+            self.attribute_list();
+        }
+
+        if self.eat("{") {
+            while let Some((_, text)) = self.peek() {
+                if text == "}" {
+                    self.bump();
+                    break;
+                }
+
+                let is_header = ["rows", "cols", "ragged"]
+                    .iter()
+                    .any(|key| text.eq_ignore_ascii_case(key));
+                if is_header && self.peek_second() == Some(":") {
+                    self.header();
+                } else {
+                    self.row();
+                }
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn header(&mut self) {
+        self.start(NodeKind::Header);
+        let is_ragged = matches!(self.peek(), Some((_, key)) if key.eq_ignore_ascii_case("ragged"));
+        self.bump();
+        self.bump();
+
+        while let Some((kind, text)) = self.peek() {
+            match (kind, text) {
+                (_, "}") => break,
+                (_, ";") => {
+                    self.bump();
+                    break;
+                }
+                (TokenKind::Identifier, _) if is_ragged => self.bump(),
+                (_, ",") | (TokenKind::String, _) if !is_ragged => self.bump(),
+                _ => self.error(),
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn row(&mut self) {
+        self.start(NodeKind::Row);
+
+        while let Some((kind, text)) = self.peek() {
+            match (kind, text) {
+                (_, "}") => break,
+                (_, ";") => {
+                    self.bump();
+                    break;
+                }
+                (_, ",") => self.bump(),
+                (TokenKind::Identifier, text)
+                    if text.eq_ignore_ascii_case("repeat")
+                        && self.peek_second() == Some("(")
+                        && self.peek_third_kind() == Some(TokenKind::Number) =>
+                {
+                    self.repeat()
+                }
+                (TokenKind::Identifier, text)
+                    if text.eq_ignore_ascii_case("fill")
+                        && self.peek_second_kind() == Some(TokenKind::Identifier) =>
+                {
+                    self.start(NodeKind::Fill);
+                    self.bump();
+                    self.cell();
+                    self.finish_child();
+                }
+                (TokenKind::Identifier, _) => {
+                    self.cell();
+                    if self.at("*") {
+                        self.wrap_last(NodeKind::Repeat);
+                        self.bump();
+                        if self.at_kind(TokenKind::Number) {
+                            self.bump();
+                        }
+                        self.finish_child();
+                    }
+                }
+                _ => self.error(),
+            }
+        }
+
+        self.finish_child();
+    }
+
+    /// An empty cell or a node.
+    fn cell(&mut self) {
+        match self.peek() {
+            Some((TokenKind::Identifier, "_")) => {
+                self.start(NodeKind::Empty);
+                self.bump();
+                self.finish_child();
+            }
+            Some((TokenKind::Identifier, _)) => self.node(),
+            _ => {}
+        }
+    }
+
+    fn repeat(&mut self) {
+        self.start(NodeKind::Repeat);
+        self.bump();
+        self.bump();
+        self.bump();
+        self.eat(",");
+        self.cell();
+
+        while let Some((_, text)) = self.peek() {
+            match text {
+                ")" => {
+                    self.bump();
+                    break;
+                }
+                "}" | ";" => break,
+                _ => self.error(),
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn node(&mut self) {
+        self.start(NodeKind::Node);
+        self.bump();
+
+        if self.at("#") {
+            self.start(NodeKind::Label);
+            self.bump();
+            if self.at_kind(TokenKind::Identifier) {
+                self.bump();
+            }
+            self.finish_child();
+        }
+
+        if self.at("(") {
+            self.attribute_list();
+        }
+
+        self.finish_child();
+    }
+
+    fn attribute_list(&mut self) {
+        self.start(NodeKind::AttributeList);
+        self.bump();
+
+        while let Some((kind, text)) = self.peek() {
+            match (kind, text) {
+                (_, ")") => {
+                    self.bump();
+                    break;
+                }
+                // An unclosed list; leave the rest to the enclosing node.
+                (_, ";") | (_, "}") => break,
+                (_, ",") => self.bump(),
+                (TokenKind::String, _) => {
+                    self.start(NodeKind::Attribute);
+                    self.bump();
+                    self.finish_child();
+                }
+                (TokenKind::Identifier, _) => self.attribute(),
+                _ => self.error(),
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn attribute(&mut self) {
+        self.start(NodeKind::Attribute);
+        let key = self.peek().map(|(_, key)| key.to_ascii_lowercase());
+        let is_connect = key.as_deref() == Some("connect");
+        let is_anchors = key.as_deref() == Some("anchors");
+        self.bump();
+        // The `+` of `connect+`.
+        self.eat("+");
+
+        if self.eat(":") {
+            match self.peek() {
+                Some((_, "{")) if is_anchors => self.anchors(),
+                Some((_, "{")) => self.connections(),
+                Some((_, "[")) => self.list(),
+                Some((TokenKind::Identifier, _)) if is_connect => self.connection(),
+                Some((TokenKind::Identifier, _))
+                | Some((TokenKind::String, _))
+                | Some((TokenKind::Number, _)) => self.bump(),
+                _ => {}
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn list(&mut self) {
+        self.start(NodeKind::List);
+        self.bump();
+
+        while let Some((kind, text)) = self.peek() {
+            match (kind, text) {
+                (_, "]") => {
+                    self.bump();
+                    break;
+                }
+                // An unclosed list; leave the rest to the enclosing node.
+                (_, ")") | (_, ";") | (_, "}") => break,
+                (_, ",") | (TokenKind::String, _) => self.bump(),
+                _ => self.error(),
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn connections(&mut self) {
+        self.start(NodeKind::Connections);
+        self.bump();
+
+        while let Some((kind, text)) = self.peek() {
+            match (kind, text) {
+                (_, "}") => {
+                    self.bump();
+                    break;
+                }
+                (_, ")") => break,
+                (_, ";") => self.bump(),
+                (TokenKind::Identifier, _) => self.connection(),
+                _ => self.error(),
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn anchors(&mut self) {
+        self.start(NodeKind::Anchors);
+        self.bump();
+
+        while let Some((kind, text)) = self.peek() {
+            match (kind, text) {
+                (_, "}") => {
+                    self.bump();
+                    break;
+                }
+                (_, ")") => break,
+                (_, ";") | (_, ":") | (_, "%") => self.bump(),
+                (TokenKind::Identifier, _) | (TokenKind::Number, _) => self.bump(),
+                _ => self.error(),
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn connection(&mut self) {
+        self.start(NodeKind::Connection);
+        self.bump();
+
+        if self.eat(":") && self.at_kind(TokenKind::Identifier) {
+            self.bump();
+        }
+
+        if self.at("@") || self.at("#") {
+            self.start(NodeKind::Destination);
+            self.bump();
+            if self.at_kind(TokenKind::Identifier) {
+                self.bump();
+                // The `%nearest` of `#label%nearest`.
+                if self.eat("%") && self.at_kind(TokenKind::Identifier) {
+                    self.bump();
+                }
+            } else if self.at("(") && self.peek_second_kind() == Some(TokenKind::Number) {
+                // A position such as `@(3, 2)`, rather than an attribute list.
+                while let Some((kind, text)) = self.peek() {
+                    match (kind, text) {
+                        (_, ")") => {
+                            self.bump();
+                            break;
+                        }
+                        (_, ";") | (_, "}") => break,
+                        (_, "(") | (_, ",") | (TokenKind::Number, _) => self.bump(),
+                        _ => self.error(),
+                    }
+                }
+            }
+            self.finish_child();
+        }
+
+        if self.at("(") {
+            self.attribute_list();
+        }
+
+        self.finish_child();
+    }
+
+    fn define(&mut self) {
+        self.start(NodeKind::Define);
+        self.bump();
+
+        if self.eat("{") {
+            while let Some((kind, text)) = self.peek() {
+                match (kind, text) {
+                    (_, "}") => {
+                        self.bump();
+                        break;
+                    }
+                    (TokenKind::Identifier, _) => self.definition(),
+                    _ => self.error(),
+                }
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn definition(&mut self) {
+        self.start(NodeKind::Definition);
+        self.bump();
+
+        if self.at("(") {
+            self.attribute_list();
+        }
+        self.eat(";");
+
+        self.finish_child();
+    }
+
+    fn note(&mut self) {
+        self.start(NodeKind::Note);
+        self.bump();
+
+        if self.at("#") {
+            self.start(NodeKind::Destination);
+            self.bump();
+            if self.at_kind(TokenKind::Identifier) {
+                self.bump();
+            }
+            self.finish_child();
+        }
+
+        if self.at_kind(TokenKind::String) {
+            self.bump();
+        }
+
+        if self.at("(") {
+            self.attribute_list();
+        }
+        self.eat(";");
+
+        self.finish_child();
+    }
+
+    fn legend(&mut self) {
+        self.start(NodeKind::Legend);
+        self.bump();
+
+        if self.eat("{") {
+            while let Some((kind, text)) = self.peek() {
+                match (kind, text) {
+                    (_, "}") => {
+                        self.bump();
+                        break;
+                    }
+                    (TokenKind::String, _) => self.legend_entry(),
+                    _ => self.error(),
+                }
+            }
+        }
+
+        self.finish_child();
+    }
+
+    fn legend_entry(&mut self) {
+        self.start(NodeKind::LegendEntry);
+        self.bump();
+
+        if self.eat("->") && self.at_kind(TokenKind::String) {
+            self.bump();
+        }
+        self.eat(";");
+
+        self.finish_child();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::test::assert_eq;
+
+    const SOURCE: &str = r#"
+// A comment before the grid.
+grid {
+    cols: "Main", "Side";
+    ragged: true;
+    block#start("Start", connect: s:n@s);  // Trailing comment
+    _, question("Continue?", connect: {e:w@e("Yes", order: -1); s:n@(0, 0)});
+}
+
+define {
+    block(class: ["block", "step"]);
+}
+
+legend {
+    "dashed" -> "async";
+}
+
+note #start "Where it all begins" (side: w)
+"#;
+
+    fn kinds(node: &SyntaxNode) -> Vec<NodeKind> {
+        node.child_nodes().map(SyntaxNode::kind).collect()
+    }
+
+    fn has_errors(node: &SyntaxNode) -> bool {
+        node.kind() == NodeKind::Error || node.child_nodes().any(has_errors)
+    }
+
+    fn unparse(tree: &SyntaxTree) -> String {
+        tree.root()
+            .tokens()
+            .map(|token| tree.text(token.range()))
+            .collect()
+    }
+
+    #[test]
+    fn is_lossless() {
+        for source in [
+            SOURCE,
+            "",
+            "  \n",
+            "grid { a(\"unterminated",
+            "grid { a; } $ define",
+        ] {
+            let tree = SyntaxTree::parse(source);
+            assert_eq!(unparse(&tree), source);
+            assert_eq!(tree.root().range(), 0..source.len());
+        }
+    }
+
+    #[test]
+    fn builds_tree() {
+        let tree = SyntaxTree::parse(SOURCE);
+        let root = tree.root();
+        assert_eq!(
+            kinds(root),
+            vec![
+                NodeKind::Grid,
+                NodeKind::Define,
+                NodeKind::Legend,
+                NodeKind::Note
+            ]
+        );
+
+        let grid = root.child_nodes().next().unwrap();
+        assert_eq!(
+            kinds(grid),
+            vec![
+                NodeKind::Header,
+                NodeKind::Header,
+                NodeKind::Row,
+                NodeKind::Row
+            ]
+        );
+
+        let rows = grid.child_nodes().skip(2).collect::<Vec<_>>();
+        assert_eq!(kinds(rows[1]), vec![NodeKind::Empty, NodeKind::Node]);
+
+        let node = rows[0].child_nodes().next().unwrap();
+        assert_eq!(
+            tree.text(node.range()),
+            r#"block#start("Start", connect: s:n@s)"#
+        );
+        assert_eq!(kinds(node), vec![NodeKind::Label, NodeKind::AttributeList]);
+
+        let connections = rows[1]
+            .child_nodes()
+            .flat_map(|node| node.child_nodes())
+            .flat_map(|list| list.child_nodes())
+            .flat_map(|attribute| attribute.child_nodes())
+            .find(|node| node.kind() == NodeKind::Connections)
+            .unwrap();
+        assert_eq!(
+            kinds(connections),
+            vec![NodeKind::Connection, NodeKind::Connection]
+        );
+        let destination = connections
+            .child_nodes()
+            .last()
+            .and_then(|connection| connection.child_nodes().next())
+            .unwrap();
+        assert_eq!(tree.text(destination.range()), "@(0, 0)");
+        assert!(!has_errors(root));
+        let classes = root
+            .child_nodes()
+            .filter(|node| node.kind() == NodeKind::Define)
+            .flat_map(|define| define.child_nodes())
+            .flat_map(|definition| definition.child_nodes())
+            .flat_map(|list| list.child_nodes())
+            .flat_map(|attribute| attribute.child_nodes())
+            .find(|node| node.kind() == NodeKind::List)
+            .unwrap();
+        assert_eq!(tree.text(classes.range()), r#"["block", "step"]"#);
+
+        let legend = root.child_nodes().nth(2).unwrap();
+        assert_eq!(kinds(legend), vec![NodeKind::LegendEntry]);
+    }
+
+    #[test]
+    fn builds_repeated_cells() {
+        let source = "grid(width: 6) { a * 3, repeat(2, b(\"B\")), fill _; fill, repeat; }";
+        let tree = SyntaxTree::parse(source);
+        let grid = tree.root().child_nodes().next().unwrap();
+        let rows = grid.child_nodes().skip(1).collect::<Vec<_>>();
+
+        let width = grid.child_nodes().next().unwrap();
+        assert_eq!(width.kind(), NodeKind::AttributeList);
+        assert_eq!(tree.text(width.range()), "(width: 6)");
+
+        assert_eq!(
+            kinds(rows[0]),
+            vec![NodeKind::Repeat, NodeKind::Repeat, NodeKind::Fill]
+        );
+        assert_eq!(kinds(rows[1]), vec![NodeKind::Node, NodeKind::Node]);
+
+        let cells = rows[0].child_nodes().collect::<Vec<_>>();
+        assert_eq!(tree.text(cells[0].range()), "a * 3");
+        assert_eq!(kinds(cells[0]), vec![NodeKind::Node]);
+        assert_eq!(tree.text(cells[1].range()), "repeat(2, b(\"B\"))");
+        assert_eq!(kinds(cells[1]), vec![NodeKind::Node]);
+        assert_eq!(kinds(cells[2]), vec![NodeKind::Empty]);
+
+        assert!(!has_errors(tree.root()));
+        assert_eq!(unparse(&tree), source);
+        assert!(Document::try_from(&tree).is_ok());
+    }
+
+    #[test]
+    fn finds_row_lines() {
+        let tree = SyntaxTree::parse(SOURCE);
+        assert_eq!(tree.row_lines(), vec![6, 7]);
+
+        let tree = SyntaxTree::parse("grid { a; b;\n  c; }");
+        assert_eq!(tree.row_lines(), vec![1, 1, 2]);
+    }
+
+    #[test]
+    fn wraps_unexpected_tokens() {
+        let tree = SyntaxTree::parse("grid { a, $; }");
+        let row = tree
+            .root()
+            .child_nodes()
+            .next()
+            .unwrap()
+            .child_nodes()
+            .next()
+            .unwrap();
+
+        assert_eq!(kinds(row), vec![NodeKind::Node, NodeKind::Error]);
+        assert!(Document::try_from(&tree).is_err());
+    }
+
+    #[test]
+    fn converts_to_document() {
+        let tree = SyntaxTree::parse(SOURCE);
+        assert_eq!(
+            Document::try_from(&tree).unwrap(),
+            Document::parse(SOURCE).unwrap()
+        );
+    }
+}
@@ -0,0 +1,565 @@
+use std::ops::Range;
+
+use nom::combinator::all_consuming;
+
+use crate::{
+    parse::ast::{Document, Identifier, Node, NodeAttribute},
+    pos::GridCoord,
+};
+
+use super::{Element, NodeKind, SyntaxNode, SyntaxTree, Token, TokenKind};
+
+/// Error returned when an [`Editor`] can't make the requested edit.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The text to insert isn't valid in the place it would be inserted.
+    InvalidSyntax(String),
+
+    /// No node has the given label.
+    UnknownLabel(String),
+
+    /// A node already has the given label.
+    DuplicateLabel(String),
+
+    /// The document has no grid to insert a node into.
+    NoGrid,
+
+    /// The position has negative coordinates.
+    OutOfBounds(GridCoord),
+
+    /// The position is covered by a repeated or filled cell other than at
+    /// its start, such as the second cell of `a*3`.
+    InsideRepetition(GridCoord),
+}
+
+type TextEdit = (Range<usize>, String);
+
+/// Edits a document's source in place, leaving everything the edit doesn't
+/// touch as it was, including formatting and comments.
+///
+/// Edits are applied to the source one by one, so each edit sees the
+/// result of the ones before it.
+#[derive(Debug, Clone)]
+pub struct Editor {
+    source: String,
+}
+
+impl Editor {
+    pub fn new<S: Into<String>>(source: S) -> Self {
+        Self {
+            source: source.into(),
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn into_source(self) -> String {
+        self.source
+    }
+
+    /// Renames the label `old` to `new`, along with every `#old` destination
+    /// and note referring to it.
+    pub fn rename_label(&mut self, old: &str, new: &str) -> Result<(), EditError> {
+        ensure_valid(Identifier::parse, new)?;
+
+        let tree = SyntaxTree::parse(&self.source);
+        let mut labels = Vec::new();
+        let mut references = Vec::new();
+
+        for node in descendants(tree.root()) {
+            match node.kind() {
+                NodeKind::Label => labels.extend(identifier(node)),
+                NodeKind::Destination if is_label_destination(&tree, node) => {
+                    references.extend(identifier(node))
+                }
+                _ => {}
+            }
+        }
+
+        let text = |token: &&Token| tree.text(token.range());
+
+        if old != new && labels.iter().any(|token| text(token) == new) {
+            return Err(EditError::DuplicateLabel(new.to_string()));
+        }
+
+        if !labels.iter().any(|token| text(token) == old) {
+            return Err(EditError::UnknownLabel(old.to_string()));
+        }
+
+        let edits = labels
+            .iter()
+            .chain(&references)
+            .filter(|token| text(token) == old)
+            .map(|token| (token.range(), new.to_string()))
+            .collect();
+
+        self.apply(edits);
+        Ok(())
+    }
+
+    /// Adds `attribute` to the definition of `id`, replacing the attribute
+    /// with the same key if there is one. The definition, and the `define`
+    /// block, are created if they don't exist yet.
+    pub fn add_attribute(&mut self, id: &str, attribute: &str) -> Result<(), EditError> {
+        ensure_valid(Identifier::parse, id)?;
+        let key = ensure_valid(NodeAttribute::parse, attribute)?.as_key();
+
+        let tree = SyntaxTree::parse(&self.source);
+        let source = tree.source();
+        let define = tree
+            .root()
+            .child_nodes()
+            .find(|node| node.kind() == NodeKind::Define);
+
+        let edit = match define {
+            Some(define) => {
+                let definitions = define
+                    .child_nodes()
+                    .filter(|node| node.kind() == NodeKind::Definition)
+                    .collect::<Vec<_>>();
+
+                let definition = definitions.iter().find(|definition| {
+                    identifier(definition).map(|token| tree.text(token.range())) == Some(id)
+                });
+
+                match (definition, definitions.last()) {
+                    (Some(definition), _) => add_to_definition(&tree, definition, key, attribute),
+                    (None, Some(last)) => {
+                        let indent = indentation(source, last.range().start);
+                        let text = format!("\n{}{}({});", indent, id, attribute);
+                        (last.range().end..last.range().end, text)
+                    }
+                    (None, None) => {
+                        let end = closing_brace(define).unwrap_or(define.range().end);
+                        let indent = block_indentation(&tree);
+                        let text = format!("{}{}({});\n", indent, id, attribute);
+                        (end..end, text)
+                    }
+                }
+            }
+            None => {
+                let indent = block_indentation(&tree);
+                let separator = if source.is_empty() || source.ends_with('\n') {
+                    "\n"
+                } else {
+                    "\n\n"
+                };
+                let text = format!(
+                    "{}define {{\n{}{}({});\n}}\n",
+                    separator, indent, id, attribute
+                );
+                (source.len()..source.len(), text)
+            }
+        };
+
+        self.apply(vec![edit]);
+        Ok(())
+    }
+
+    /// Inserts `node` into the grid at `coord`, shifting the cells after it in
+    /// the same row to the right. Rows that are too short are padded with
+    /// empty cells and missing rows are added. Repeated and filled cells
+    /// count as the cells they stand for, so a fill in the row gets shorter
+    /// to make room for the node.
+    pub fn insert_node(&mut self, coord: GridCoord, node: &str) -> Result<(), EditError> {
+        ensure_valid(Node::parse, node)?;
+
+        if coord.row < 0 || coord.col < 0 {
+            return Err(EditError::OutOfBounds(coord));
+        }
+        let (x, y) = (coord.col as usize, coord.row as usize);
+
+        let tree = SyntaxTree::parse(&self.source);
+        let rows = tree
+            .root()
+            .child_nodes()
+            .find(|node| node.kind() == NodeKind::Grid)
+            .ok_or(EditError::NoGrid)?
+            .child_nodes()
+            .filter(|node| node.kind() == NodeKind::Row)
+            .collect::<Vec<_>>();
+
+        let last_row = rows.last().ok_or(EditError::NoGrid)?;
+        let padding = |n| ", _".repeat(n);
+
+        let edit = match rows.get(y) {
+            Some(row) => {
+                let cells = row_cells(&tree, row, y);
+                let width = cells.iter().map(|(_, width)| width).sum::<usize>();
+
+                let mut start = 0;
+                let cell = cells.iter().find(|(_, width)| {
+                    start += width;
+                    start > x
+                });
+
+                match (cell, cells.last()) {
+                    (Some((cell, width)), _) if start - width == x => {
+                        let start = cell.range().start;
+                        (start..start, format!("{}, ", node))
+                    }
+                    (Some(_), _) => return Err(EditError::InsideRepetition(coord)),
+                    (None, Some((last, _))) => {
+                        let end = last.range().end;
+                        let text = format!("{}, {}", padding(x - width), node);
+                        (end..end, text)
+                    }
+                    (None, None) => {
+                        let start = row.range().start;
+                        let text = format!("{}{}", "_, ".repeat(x), node);
+                        (start..start, text)
+                    }
+                }
+            }
+            None => {
+                let indent = indentation(tree.source(), last_row.range().start);
+                let mut text = String::new();
+                for _ in rows.len()..y {
+                    text.push_str(&format!("\n{}_;", indent));
+                }
+                text.push_str(&format!("\n{}{}{};", indent, "_, ".repeat(x), node));
+
+                let end = end_of_line(tree.source(), last_row.range().end);
+                (end..end, text)
+            }
+        };
+
+        self.apply(vec![edit]);
+        Ok(())
+    }
+
+    fn apply(&mut self, mut edits: Vec<TextEdit>) {
+        edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+
+        for (range, text) in edits {
+            self.source.replace_range(range, &text);
+        }
+    }
+}
+
+/// The cells of the `y`th row along with the number of grid cells each of
+/// them stands for.
+fn row_cells<'t>(tree: &SyntaxTree, row: &'t SyntaxNode, y: usize) -> Vec<(&'t SyntaxNode, usize)> {
+    let mut cells = row
+        .child_nodes()
+        .filter_map(|node| match node.kind() {
+            NodeKind::Node | NodeKind::Empty => Some((node, 1)),
+            NodeKind::Repeat => {
+                let count = node.children().iter().find_map(|element| match element {
+                    Element::Token(token) if token.kind() == TokenKind::Number => {
+                        tree.text(token.range()).parse().ok()
+                    }
+                    _ => None,
+                });
+                Some((node, count.unwrap_or(1)))
+            }
+            NodeKind::Fill => Some((node, 0)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // A fill's length depends on the rest of the grid, so it's taken from
+    // the parsed document. Fills in documents that don't parse are empty.
+    let len = Document::parse(tree.source())
+        .ok()
+        .and_then(|document| document.grid.0.get(y).map(Vec::len));
+    if let Some(len) = len {
+        let known = cells.iter().map(|(_, width)| width).sum::<usize>();
+        for (node, width) in &mut cells {
+            if node.kind() == NodeKind::Fill {
+                *width = len.saturating_sub(known);
+            }
+        }
+    }
+
+    cells
+}
+
+fn ensure_valid<'i, O>(
+    parser: impl FnMut(&'i str) -> crate::parse::Result<'i, O>,
+    text: &'i str,
+) -> Result<O, EditError> {
+    all_consuming(parser)(text)
+        .map(|(_, output)| output)
+        .map_err(|_| EditError::InvalidSyntax(text.to_string()))
+}
+
+fn descendants(node: &SyntaxNode) -> Vec<&SyntaxNode> {
+    let mut result = vec![node];
+    for child in node.child_nodes() {
+        result.extend(descendants(child));
+    }
+    result
+}
+
+fn identifier(node: &SyntaxNode) -> Option<&Token> {
+    node.children().iter().find_map(|child| match child {
+        Element::Token(token) if token.kind() == TokenKind::Identifier => Some(token),
+        _ => None,
+    })
+}
+
+fn is_label_destination(tree: &SyntaxTree, node: &SyntaxNode) -> bool {
+    tree.text(node.range()).starts_with('#')
+}
+
+fn closing_brace(node: &SyntaxNode) -> Option<usize> {
+    match node.children().last() {
+        Some(Element::Token(token)) if token.kind() == TokenKind::Punctuation => {
+            Some(token.range().start)
+        }
+        _ => None,
+    }
+}
+
+fn add_to_definition(
+    tree: &SyntaxTree,
+    definition: &SyntaxNode,
+    key: &str,
+    attribute: &str,
+) -> TextEdit {
+    let list = definition
+        .child_nodes()
+        .find(|node| node.kind() == NodeKind::AttributeList);
+
+    let list = match list {
+        Some(list) => list,
+        None => {
+            let end = identifier(definition).map_or(definition.range().end, |t| t.range().end);
+            return (end..end, format!("({})", attribute));
+        }
+    };
+
+    let attributes = list
+        .child_nodes()
+        .filter(|node| node.kind() == NodeKind::Attribute)
+        .collect::<Vec<_>>();
+
+    let existing = attributes.iter().find(|node| {
+        let text = tree.text(node.range());
+        match identifier(node) {
+            // The key runs up to the colon, so that `connect+` isn't
+            // mistaken for `connect`.
+            Some(_) => text
+                .split(':')
+                .next()
+                .unwrap_or_default()
+                .trim_end()
+                .eq_ignore_ascii_case(key),
+            None => key == "text" && text.starts_with('"'),
+        }
+    });
+
+    match (existing, attributes.last()) {
+        (Some(existing), _) => (existing.range(), attribute.to_string()),
+        (None, Some(last)) => (
+            last.range().end..last.range().end,
+            format!(", {}", attribute),
+        ),
+        (None, None) => {
+            let start = list.range().start + 1;
+            (start..start, attribute.to_string())
+        }
+    }
+}
+
+/// The whitespace at the start of the line containing `offset`, if
+/// nothing but whitespace precedes `offset` on that line.
+fn indentation(source: &str, offset: usize) -> &str {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let prefix = &source[line_start..offset];
+
+    if prefix.chars().all(char::is_whitespace) {
+        prefix
+    } else {
+        ""
+    }
+}
+
+/// The end of the line containing `offset`, if only whitespace or a
+/// comment follows `offset` on that line, or `offset` otherwise.
+fn end_of_line(source: &str, offset: usize) -> usize {
+    let rest = &source[offset..];
+    let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+    let line = line.trim_end_matches('\r');
+    let trimmed = line.trim_start();
+
+    if trimmed.is_empty() || trimmed.starts_with("//") {
+        offset + line.len()
+    } else {
+        offset
+    }
+}
+
+/// The indentation used for the contents of blocks, taken from the grid.
+fn block_indentation<'i>(tree: &SyntaxTree<'i>) -> &'i str {
+    let first_row = descendants(tree.root())
+        .into_iter()
+        .find(|node| node.kind() == NodeKind::Row);
+
+    match first_row.map(|row| indentation(tree.source(), row.range().start)) {
+        Some(indent) if !indent.is_empty() => indent,
+        _ => "    ",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse::ast::Destination, test::assert_eq};
+
+    const SOURCE: &str = r#"grid {
+    block#start("Start", connect: s:n@s);  // The first step
+    block#end("End", connect: n:s#start);
+}
+"#;
+
+    #[test]
+    fn renames_labels() {
+        let mut editor = Editor::new(SOURCE);
+        editor.rename_label("start", "begin").unwrap();
+
+        assert_eq!(
+            editor.source(),
+            r#"grid {
+    block#begin("Start", connect: s:n@s);  // The first step
+    block#end("End", connect: n:s#begin);
+}
+"#
+        );
+
+        assert_eq!(
+            editor.rename_label("begin", "end"),
+            Err(EditError::DuplicateLabel("end".into()))
+        );
+        assert_eq!(
+            editor.rename_label("start", "foo"),
+            Err(EditError::UnknownLabel("start".into()))
+        );
+        assert_eq!(
+            editor.rename_label("end", "not valid"),
+            Err(EditError::InvalidSyntax("not valid".into()))
+        );
+
+        let mut editor = Editor::new(r#"grid { a#start; } note #start "Begins here""#);
+        editor.rename_label("start", "begin").unwrap();
+        assert_eq!(
+            editor.source(),
+            r#"grid { a#begin; } note #begin "Begins here""#
+        );
+
+        let mut editor = Editor::new("grid { a(connect: s:n#exit%nearest); b#exit, c#exit; }");
+        editor.rename_label("exit", "out").unwrap();
+        assert_eq!(
+            editor.source(),
+            "grid { a(connect: s:n#out%nearest); b#out, c#out; }"
+        );
+    }
+
+    #[test]
+    fn adds_attributes_to_definitions() {
+        let mut editor = Editor::new(SOURCE);
+        editor.add_attribute("block", r#"class: "step""#).unwrap();
+        editor.add_attribute("block", "shape: circle").unwrap();
+        editor.add_attribute("block", r#"class: "other""#).unwrap();
+        editor.add_attribute("note", r#"text: "Note""#).unwrap();
+
+        assert_eq!(
+            editor.source(),
+            r#"grid {
+    block#start("Start", connect: s:n@s);  // The first step
+    block#end("End", connect: n:s#start);
+}
+
+define {
+    block(class: "other", shape: circle);
+    note(text: "Note");
+}
+"#
+        );
+
+        let mut editor = Editor::new(r#"grid { a; } define { a("A", class: "x"); }"#);
+        editor.add_attribute("a", r#"text: "B""#).unwrap();
+        assert_eq!(
+            editor.source(),
+            r#"grid { a; } define { a(text: "B", class: "x"); }"#
+        );
+
+        let mut editor = Editor::new("grid { a; } define { a(connect+: s:n@s); }");
+        editor.add_attribute("a", "connect: e:w@e").unwrap();
+        assert_eq!(
+            editor.source(),
+            "grid { a; } define { a(connect+: s:n@s, connect: e:w@e); }"
+        );
+
+        assert_eq!(
+            editor.add_attribute("block", "colour: red"),
+            Err(EditError::InvalidSyntax("colour: red".into()))
+        );
+    }
+
+    #[test]
+    fn inserts_nodes() {
+        let mut editor = Editor::new("grid {\n  a, b; // Comment\n}");
+        editor.insert_node(GridCoord::new(0, 1), "c").unwrap();
+        editor
+            .insert_node(GridCoord::new(0, 4), r#"d("D")"#)
+            .unwrap();
+        editor.insert_node(GridCoord::new(2, 1), "e").unwrap();
+
+        assert_eq!(
+            editor.source(),
+            "grid {\n  a, c, b, _, d(\"D\"); // Comment\n  _;\n  _, e;\n}"
+        );
+
+        assert_eq!(
+            editor.insert_node(GridCoord::new(0, -1), "f"),
+            Err(EditError::OutOfBounds(GridCoord::new(0, -1)))
+        );
+        assert_eq!(
+            Editor::new("define { a; }").insert_node(GridCoord::new(0, 0), "a"),
+            Err(EditError::NoGrid)
+        );
+    }
+
+    #[test]
+    fn inserts_nodes_around_repeated_cells() {
+        let mut editor = Editor::new("grid {\n  a*2, fill _, b;\n  c, c, c, c, c;\n}");
+        editor.insert_node(GridCoord::new(0, 2), "d").unwrap();
+        editor.insert_node(GridCoord::new(0, 4), "e").unwrap();
+        editor.insert_node(GridCoord::new(0, 7), "f").unwrap();
+
+        assert_eq!(
+            editor.source(),
+            "grid {\n  a*2, d, fill _, e, b, _, _, f;\n  c, c, c, c, c;\n}"
+        );
+
+        assert_eq!(
+            editor.insert_node(GridCoord::new(0, 1), "g"),
+            Err(EditError::InsideRepetition(GridCoord::new(0, 1)))
+        );
+    }
+
+    #[test]
+    fn inserts_nodes_that_positions_connect_to() {
+        let coord = GridCoord::new(1, 2);
+        let mut editor = Editor::new("grid {\n  a(connect: s:n@(2, 1));\n}");
+        editor.insert_node(coord, "b").unwrap();
+
+        let document = Document::parse(editor.source()).unwrap();
+        let nodes = document.grid.nodes().collect::<Vec<_>>();
+        let (_, a) = nodes[0];
+        let (b_coord, b) = nodes[1];
+
+        assert_eq!(b_coord, coord);
+        assert_eq!(b.id, Identifier("b"));
+        match &a.attrs[..] {
+            [NodeAttribute::Connect(connections)] => {
+                assert_eq!(connections[0].to, Destination::Position(coord));
+            }
+            attrs => panic!("unexpected attributes: {:?}", attrs),
+        }
+    }
+}
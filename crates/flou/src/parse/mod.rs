@@ -1,7 +1,12 @@
-pub(crate) mod ast;
+pub mod ast;
 mod combinators;
 mod constants;
+#[cfg(feature = "cst")]
+pub mod cst;
 mod parts;
+mod print;
 mod types;
 
+#[cfg(feature = "testing")]
+pub(crate) use print::quote;
 pub(crate) use types::*;
@@ -5,9 +5,20 @@ use nom::{
     combinator::{cut, map, opt, value},
     sequence::delimited,
 };
-use nom_supreme::tag::complete::tag;
+use nom_supreme::tag::complete::{tag, tag_no_case};
 
-use super::{Input, Result};
+use super::{
+    combinators::enclosed_list0,
+    constants::{ARRAY_DELIMITERS, LIST_SEPARATOR},
+    Input, Result,
+};
+
+pub(super) fn boolean(i: Input) -> Result<bool> {
+    alt((
+        value(true, tag_no_case("true")),
+        value(false, tag_no_case("false")),
+    ))(i)
+}
 
 pub(super) fn quoted_string(i: Input) -> Result<String> {
     let esc = escaped_transform(
@@ -26,6 +37,18 @@ pub(super) fn quoted_string(i: Input) -> Result<String> {
     )(i)
 }
 
+/// Parses one or more classes, either as a single space-separated string
+/// or as a list of strings such as `["warning", "db"]`.
+pub(super) fn class_list(i: Input) -> Result<String> {
+    alt((
+        quoted_string,
+        map(
+            enclosed_list0(ARRAY_DELIMITERS, quoted_string, LIST_SEPARATOR),
+            |classes| classes.join(" "),
+        ),
+    ))(i)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -45,6 +68,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn valid_boolean() {
+        assert_parsed_eq(boolean, "true", true);
+        assert_parsed_eq(boolean, "false", false);
+        assert_not_parsed(boolean, "yes");
+    }
+
+    #[test]
+    fn valid_class_list() {
+        assert_parsed_eq(class_list, r#""a b""#, "a b".into());
+        assert_parsed_eq(class_list, r#"["a", "b c",]"#, "a b c".into());
+        assert_parsed_eq(class_list, "[]", "".into());
+        assert_not_parsed(class_list, r#"["a" "b"]"#);
+    }
+
     #[test]
     fn invalid_quoted_string() {
         assert_not_parsed(quoted_string, r#""missing end quote"#);
@@ -0,0 +1,357 @@
+//! Prints a [`Document`] back into Flou source.
+
+use std::fmt::Write;
+
+use super::ast::{
+    Anchor, Anchors, ArrowheadType, ConnectionAttribute, ConnectionDescriptor, ConnectionRoute,
+    ConnectionWeight, Destination, Direction, Document, Fit, Headers, LegendEntry, Node,
+    NodeAttribute, Note,
+};
+
+const INDENT: &str = "    ";
+
+impl Document<'_> {
+    /// Prints the document as Flou source, which parses back into the same
+    /// document. Comments and formatting of a parsed document aren't kept;
+    /// use the [`cst`](crate::cst) module to edit a source in place instead.
+    ///
+    /// Since the syntax has no way to write them, empty grid rows are
+    /// printed as a single empty cell, and definitions without attributes
+    /// are left out.
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+
+        match self.width {
+            Some(width) => {
+                let _ = writeln!(out, "grid(width: {}) {{", width);
+            }
+            None => out.push_str("grid {\n"),
+        }
+        print_headers(&mut out, &self.headers);
+        if self.ragged {
+            let _ = writeln!(out, "{}ragged: true;", INDENT);
+        }
+        for row in &self.grid.0 {
+            let cells = row.iter().map(|cell| match cell {
+                Some(node) => print_node(node),
+                None => String::from("_"),
+            });
+            let cells = cells.collect::<Vec<_>>();
+            let cells = match cells.is_empty() {
+                true => String::from("_"),
+                false => cells.join(", "),
+            };
+            let _ = writeln!(out, "{}{};", INDENT, cells);
+        }
+        out.push_str("}\n");
+
+        let definitions = self
+            .definitions
+            .iter()
+            .filter(|(_, attrs)| !attrs.is_empty())
+            .collect::<Vec<_>>();
+        if !definitions.is_empty() {
+            out.push_str("\ndefine {\n");
+            for (id, attrs) in definitions {
+                let _ = writeln!(out, "{}{}({});", INDENT, id, print_node_attributes(attrs));
+            }
+            out.push_str("}\n");
+        }
+
+        if !self.legend.is_empty() {
+            out.push_str("\nlegend {\n");
+            for LegendEntry { class, text } in &self.legend {
+                let _ = writeln!(out, "{}{} -> {};", INDENT, quote(class), quote(text));
+            }
+            out.push_str("}\n");
+        }
+
+        if !self.notes.is_empty() {
+            out.push('\n');
+            for note in &self.notes {
+                let _ = writeln!(out, "{};", print_note(note));
+            }
+        }
+
+        out
+    }
+}
+
+fn print_headers(out: &mut String, headers: &Headers) {
+    for (key, names) in [("rows", &headers.rows), ("cols", &headers.cols)] {
+        if !names.is_empty() {
+            let names = names.iter().map(|x| quote(x)).collect::<Vec<_>>();
+            let _ = writeln!(out, "{}{}: {};", INDENT, key, names.join(", "));
+        }
+    }
+}
+
+fn print_node(node: &Node) -> String {
+    let mut out = node.id.to_string();
+
+    if let Some(label) = node.label {
+        let _ = write!(out, "#{}", label);
+    }
+
+    if !node.attrs.is_empty() {
+        let _ = write!(out, "({})", print_node_attributes(&node.attrs));
+    }
+
+    out
+}
+
+fn print_node_attributes(attrs: &[NodeAttribute]) -> String {
+    let attrs = attrs.iter().map(|attr| {
+        let value = match attr {
+            NodeAttribute::Text(text) => quote(text),
+            NodeAttribute::Class(class) => quote(class),
+            NodeAttribute::Shape(shape) => shape.name().to_string(),
+            NodeAttribute::Connect(connections) | NodeAttribute::ConnectAppend(connections) => {
+                print_connections(connections)
+            }
+            NodeAttribute::Start(x) | NodeAttribute::End(x) | NodeAttribute::Hidden(x) => {
+                x.to_string()
+            }
+            NodeAttribute::Opacity(opacity) => opacity.value().to_string(),
+            NodeAttribute::Step(step) => step.to_string(),
+            NodeAttribute::Anchors(anchors) => print_anchors(anchors),
+            NodeAttribute::Radius(length) | NodeAttribute::Padding(length) => {
+                length.value().to_string()
+            }
+            NodeAttribute::Fit(fit) => fit.name().to_string(),
+            NodeAttribute::Aspect(aspect) => aspect.value().to_string(),
+            NodeAttribute::Palette(palette) => palette.name().to_string(),
+        };
+
+        format!("{}: {}", attr.as_key(), value)
+    });
+
+    attrs.collect::<Vec<_>>().join(", ")
+}
+
+fn print_connections(connections: &[ConnectionDescriptor]) -> String {
+    match connections {
+        [connection] => print_connection(connection),
+        _ => {
+            let connections = connections.iter().map(print_connection);
+            format!("{{{}}}", connections.collect::<Vec<_>>().join("; "))
+        }
+    }
+}
+
+fn print_connection(connection: &ConnectionDescriptor) -> String {
+    let (from, to) = connection.sides;
+    let mut out = format!("{}:{}", side(from), side(to));
+
+    match connection.to {
+        Destination::Itself => out.push('@'),
+        Destination::Relative(dir) => {
+            let _ = write!(out, "@{}", side(dir));
+        }
+        Destination::Label(label) => {
+            let _ = write!(out, "#{}", label);
+        }
+        Destination::NearestLabel(label) => {
+            let _ = write!(out, "#{}%nearest", label);
+        }
+        Destination::Position(pos) => {
+            let _ = write!(out, "@({}, {})", pos.col, pos.row);
+        }
+    }
+
+    if !connection.attrs.is_empty() {
+        let attrs = connection.attrs.iter().map(|attr| {
+            let value = match attr {
+                ConnectionAttribute::Text(text) => quote(text),
+                ConnectionAttribute::Class(class) => quote(class),
+                ConnectionAttribute::Arrowheads(arrowheads) => arrowheads.name().to_string(),
+                ConnectionAttribute::Id(id) => quote(id),
+                ConnectionAttribute::Order(order) => order.to_string(),
+                ConnectionAttribute::Weight(weight) => weight.name().to_string(),
+                ConnectionAttribute::Opacity(opacity) => opacity.value().to_string(),
+                ConnectionAttribute::Hidden(hidden) => hidden.to_string(),
+                ConnectionAttribute::Step(step) => step.to_string(),
+                ConnectionAttribute::Route(route) => route.name().to_string(),
+            };
+
+            format!("{}: {}", attr.as_key(), value)
+        });
+
+        let _ = write!(out, "({})", attrs.collect::<Vec<_>>().join(", "));
+    }
+
+    out
+}
+
+fn print_anchors(anchors: &Anchors) -> String {
+    let sides = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+    let anchors = sides.iter().filter_map(|&dir| {
+        let anchor = anchors.get(dir)?;
+        Some(format!("{}: {}%", side(dir), percentage(anchor)))
+    });
+
+    format!("{{{}}}", anchors.collect::<Vec<_>>().join("; "))
+}
+
+/// Anchors are stored as fractions, so the percentage they were written as
+/// is the shortest one that gives back the same fraction.
+fn percentage(anchor: Anchor) -> String {
+    let percentage = anchor.value() * 100.0;
+    (0..17)
+        .map(|precision| format!("{:.*}", precision, percentage))
+        .find(|x| x.parse::<f64>().map(|x| x / 100.0) == Ok(anchor.value()))
+        .unwrap_or_else(|| percentage.to_string())
+}
+
+fn print_note(note: &Note) -> String {
+    let mut out = format!("note #{} {}", note.label, quote(&note.text));
+
+    if let Some(dir) = note.side {
+        let _ = write!(out, " (side: {})", side(dir));
+    }
+
+    out
+}
+
+fn side(dir: Direction) -> char {
+    match dir {
+        Direction::North => 'n',
+        Direction::South => 's',
+        Direction::West => 'w',
+        Direction::East => 'e',
+    }
+}
+
+/// Quotes `text` as a string literal of the DSL, escaping it as needed.
+pub(crate) fn quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+impl Fit {
+    /// The name the fit is referred to by in the DSL.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Contain => "contain",
+            Self::Cover => "cover",
+            Self::Stretch => "stretch",
+        }
+    }
+}
+
+impl ArrowheadType {
+    /// The name the arrowheads are referred to by in the DSL.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Start => "start",
+            Self::End => "end",
+            Self::Both => "both",
+        }
+    }
+}
+
+impl ConnectionWeight {
+    /// The name the weight is referred to by in the DSL.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Thin => "thin",
+            Self::Normal => "normal",
+            Self::Thick => "thick",
+        }
+    }
+}
+
+impl ConnectionRoute {
+    /// The name the route is referred to by in the DSL.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Straight => "straight",
+            Self::Elbow => "elbow",
+            Self::Curve => "curve",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse::ast::{Grid, Identifier},
+        pos::GridCoord,
+    };
+
+    fn assert_round_trip(source: &str) {
+        let document = Document::parse(source).unwrap();
+        let printed = document.to_source();
+        assert_eq!(Document::parse(&printed).unwrap(), document, "{}", printed);
+    }
+
+    #[test]
+    fn round_trips_every_block() {
+        assert_round_trip(
+            r#"
+grid {
+    ragged: true;
+    rows: "First", "";
+    cols: "A";
+    a#start("Start \"here\"\n", class: ["x", "y"], connect: s:n@s("Yes", arrowheads: both)), _, b;
+    a(shape: diamond, connect: {e:w#start(id: "back", order: -2, weight: thick, route: curve); w:w@(3, 0); n:n@; s:s#start%nearest(opacity: 0.25, hidden: true, step: 1)});
+}
+
+define {
+    a(connect+: e:e@e, start: true, end: false, opacity: 0.5, hidden: false, step: 3);
+    b(anchors: {n: 30%; e: 12.5%}, radius: 4, padding: 0.75, fit: cover, aspect: 1.5, palette: green);
+}
+
+legend {
+    "x" -> "Some \\ text";
+}
+
+note #start "Hello" (side: w)
+note #start "World"
+"#,
+        );
+    }
+
+    #[test]
+    fn round_trips_minimal_document() {
+        assert_round_trip("grid { a; }");
+        assert_round_trip("grid(width: 2) { a; }");
+    }
+
+    #[test]
+    fn prints_built_documents() {
+        let a = Identifier("a");
+        let document = Document {
+            grid: Grid(vec![
+                vec![Some(Node {
+                    id: a,
+                    label: None,
+                    attrs: vec![NodeAttribute::Connect(vec![ConnectionDescriptor {
+                        to: Destination::Position(GridCoord::new(1, 0)),
+                        sides: (Direction::South, Direction::North),
+                        attrs: vec![],
+                    }])],
+                })],
+                vec![],
+                vec![None, None],
+            ]),
+            definitions: vec![(a, vec![])],
+            ..Document::default()
+        };
+
+        assert_eq!(
+            document.to_source(),
+            "grid {\n    a(connect: s:n@(0, 1));\n    _;\n    _, _;\n}\n"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::fmt::Display;
+
+use crate::{
+    pos::{pos, PixelPos},
+    render_svg::EmbeddedFont,
+    text_metrics::{FontFamily, TextMetrics, DEFAULT_FONT_SIZE},
+};
+
+use super::{
+    flou::Flou,
+    limits::{Limit, RenderLimits},
+};
+
+const DEFAULT_STROKE_WIDTH: f64 = 1.0;
+const PRINT_STROKE_WIDTH: f64 = 2.0;
+const DEFAULT_DPI: f64 = 96.0;
+
+/// The corner of the flowchart that the `legend` block is drawn in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LegendPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// How connections are drawn along their route.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionStyle {
+    /// Straight lines with right-angled turns.
+    #[default]
+    Orthogonal,
+    /// A smooth curve through the turns of the route.
+    Curved,
+}
+
+/// An absolute unit that the size of the flowchart can be given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// CSS pixels, of which there are 96 in an inch.
+    Px,
+    Mm,
+    Cm,
+    In,
+    Pt,
+}
+
+impl Unit {
+    /// The suffix of lengths in this unit, such as `mm`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Self::Px => "px",
+            Self::Mm => "mm",
+            Self::Cm => "cm",
+            Self::In => "in",
+            Self::Pt => "pt",
+        }
+    }
+
+    fn per_inch(self) -> f64 {
+        match self {
+            Self::Px => 96.0,
+            Self::Mm => 25.4,
+            Self::Cm => 2.54,
+            Self::In => 1.0,
+            Self::Pt => 72.0,
+        }
+    }
+}
+
+/// How the background of the flowchart is filled.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum Background {
+    /// Filled by the `.background` CSS class.
+    #[default]
+    Default,
+    /// Not drawn at all, so the flowchart can be put on a page of any color.
+    Transparent,
+    /// Filled with a CSS color, taking precedence over any CSS.
+    Color(String),
+}
+
+/// A grid drawn over the background, aligned to the cells of the flowchart.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackgroundGrid {
+    #[default]
+    None,
+    /// A dot where the corners of four cells meet.
+    Dots,
+    /// Lines along the edges of the cells.
+    Lines,
+}
+
+pub struct RenderConfig {
+    /// Precede the SVG with an XML prolog and DOCTYPE declaration.
+    pub standalone: bool,
+    pub default_css: bool,
+    /// Define the colors and font size of the default CSS as CSS custom
+    /// properties, such as `--flou-node-stroke`, on the root element. Host
+    /// pages can override them to theme the flowchart. Off by default,
+    /// since not every SVG consumer supports custom properties, in which
+    /// case their values are written into the default CSS instead.
+    pub css_variables: bool,
+    /// Render for printing in black and white: without a background fill,
+    /// with thicker lines, with patterns instead of palette colors and with
+    /// the size given in absolute units.
+    pub print_mode: bool,
+    pub css: Vec<String>,
+    /// Leave out the classes and connection ids written in the flowchart.
+    /// Text and attribute values are always escaped, but when the SVG of
+    /// an untrusted flowchart is inlined into a host page, its classes can
+    /// still match the page's CSS selectors and its ids can clash with or
+    /// shadow the page's own. Classes added by Flou itself are kept, except
+    /// for the ones [`Flou::diff`] marks changes with, which are written as
+    /// classes of the flowchart.
+    pub strip_user_attributes: bool,
+
+    // Element sizes
+    /// Size of arrowheads on connections with a stroke width of 1.
+    pub arrowhead: PixelPos,
+    /// Width of connection lines in pixels, set on every connection's path.
+    /// Arrowheads are scaled along with it.
+    pub stroke_width: Option<f64>,
+    pub node: PixelPos,
+    pub grid_gap: PixelPos,
+    /// Corner radius of rectangle and square nodes that don't set their
+    /// own `radius`.
+    pub corner_radius: f64,
+    /// Space between the outline of nodes that don't set their own
+    /// `padding` and their text.
+    pub node_padding: f64,
+    /// How connections are drawn along their route.
+    pub connection_style: ConnectionStyle,
+    /// If set, connections stop short of the outline of the nodes they
+    /// attach to by half the stroke width plus this margin, in pixels, so
+    /// that their arrowheads aren't hidden under thick node borders.
+    /// Otherwise, they end right on the outline.
+    pub endpoint_margin: Option<f64>,
+    /// Corner of the flowchart that the legend is drawn in.
+    pub legend_position: LegendPosition,
+    pub background: Background,
+    /// Unit that the width and height of the flowchart are given in. If
+    /// `None`, they are unitless, which is the same as pixels. Everything
+    /// inside the flowchart is laid out in pixels either way.
+    pub unit: Option<Unit>,
+    /// How many pixels make up an inch when converting the size of the
+    /// flowchart into `unit`.
+    pub dpi: f64,
+    /// Grid drawn over the background, such as to judge alignment in drafts.
+    pub background_grid: BackgroundGrid,
+
+    // Text layout
+    /// CSS `font-family` set on the root element.
+    pub font_family: Option<String>,
+    /// Font size in pixels set on the root element.
+    pub font_size: Option<f64>,
+    /// Fonts embedded into the SVG as `@font-face` rules.
+    pub fonts: Vec<EmbeddedFont>,
+    /// Wrap node text that is wider than the node.
+    pub wrap_text: bool,
+    /// Used to measure text. If `None`, widths are approximated from
+    /// the average glyph widths of `font_family`.
+    pub text_metrics: Option<Box<dyn TextMetrics>>,
+
+    /// Upper bounds on what a flowchart may render into, for when it comes
+    /// from an untrusted source. Unlimited by default.
+    pub limits: RenderLimits,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            standalone: false,
+            default_css: true,
+            css_variables: false,
+            print_mode: false,
+            css: Vec::new(),
+            strip_user_attributes: false,
+            arrowhead: pos(10.0, 10.0),
+            stroke_width: None,
+            node: pos(200.0, 100.0),
+            grid_gap: pos(50.0, 50.0),
+            corner_radius: 0.0,
+            node_padding: 0.0,
+            connection_style: ConnectionStyle::default(),
+            endpoint_margin: None,
+            legend_position: LegendPosition::default(),
+            background: Background::default(),
+            unit: None,
+            dpi: DEFAULT_DPI,
+            background_grid: BackgroundGrid::default(),
+            font_family: None,
+            font_size: None,
+            fonts: Vec::new(),
+            wrap_text: true,
+            text_metrics: None,
+            limits: RenderLimits::default(),
+        }
+    }
+}
+
+impl RenderConfig {
+    /// Checks that `flou` stays within [`RenderConfig::limits`] when
+    /// rendered with this config, without rendering it.
+    pub fn check_limits(&self, flou: &Flou) -> Result<(), Limit> {
+        self.limits.check(flou, self)
+    }
+
+    pub(crate) fn metrics(&self) -> &dyn TextMetrics {
+        match &self.text_metrics {
+            Some(metrics) => metrics.as_ref(),
+            None => match &self.font_family {
+                Some(font_family) => FontFamily::from_css(font_family).average_metrics(),
+                None => FontFamily::default().average_metrics(),
+            },
+        }
+    }
+
+    pub(crate) fn font_size(&self) -> f64 {
+        self.font_size.unwrap_or(DEFAULT_FONT_SIZE)
+    }
+
+    /// The unit of the flowchart's size. Printed flowcharts are always
+    /// given one, so that they print at a predictable size.
+    pub(crate) fn unit(&self) -> Option<Unit> {
+        match self.unit {
+            None if self.print_mode => Some(Unit::Px),
+            unit => unit,
+        }
+    }
+
+    /// Converts a length in pixels into `unit`.
+    pub(crate) fn to_unit(&self, pixels: f64, unit: Unit) -> f64 {
+        pixels / self.dpi * unit.per_inch()
+    }
+
+    /// Whether connections are drawn with a stroke width other than 1,
+    /// which has to be set on them since the CSS doesn't know about it.
+    pub(crate) fn has_custom_stroke_width(&self) -> bool {
+        self.stroke_width.is_some() || self.print_mode
+    }
+
+    /// How far connections stop short of the outline of their nodes.
+    pub(crate) fn endpoint_inset(&self) -> f64 {
+        match self.endpoint_margin {
+            Some(margin) => self.stroke_width() / 2.0 + margin,
+            None => 0.0,
+        }
+    }
+
+    pub(crate) fn stroke_width(&self) -> f64 {
+        let default = match self.print_mode {
+            true => PRINT_STROKE_WIDTH,
+            false => DEFAULT_STROKE_WIDTH,
+        };
+
+        self.stroke_width.unwrap_or(default)
+    }
+}
+
+/// Renders a [`Flou`] into an output format, such as SVG.
+///
+/// `render` takes `&self`, so that renderers can carry settings of their
+/// own, such as the hooks of [`SvgRenderer`](crate::SvgRenderer). This is a
+/// breaking change from when it was an associated function: implementations
+/// need to take `&self`, and callers need a renderer to call it on, as in
+/// `SvgRenderer::new().render(&flou, &config)` instead of
+/// `SvgRenderer::render(&flou, &config)`.
+pub trait Renderer {
+    fn render<'i>(&self, flou: &'i Flou<'i>, config: &'i RenderConfig) -> Box<dyn Display + 'i>;
+
+    /// Renders `flou` like [`Renderer::render`], but fails without rendering
+    /// it if it exceeds any of the config's [`limits`](RenderConfig::limits).
+    fn try_render<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+    ) -> Result<Box<dyn Display + 'i>, Limit> {
+        config.check_limits(flou)?;
+        Ok(self.render(flou, config))
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use crate::pos::pos;
+
+use super::{
+    flou::{Connection, Flou, NodeAttributes},
+    grid::Grid,
+};
+
+const ADDED: &str = "diff-added";
+const REMOVED: &str = "diff-removed";
+const CHANGED: &str = "diff-changed";
+
+impl<'i> Flou<'i> {
+    /// Creates a flowchart showing the changes between `old` and `new`.
+    ///
+    /// Nodes are matched by their position in the grid and connections by
+    /// their endpoints. The result contains everything in `new`, plus the
+    /// nodes and connections that were removed from `old`. Nodes and
+    /// connections that were added, removed or changed get the
+    /// `diff-added`, `diff-removed` or `diff-changed` class respectively,
+    /// which the default CSS highlights. Labels refer to the nodes in `new`.
+    pub fn diff(old: &Flou<'i>, new: &Flou<'i>) -> Flou<'i> {
+        let size = pos(
+            old.grid.size.x.max(new.grid.size.x),
+            old.grid.size.y.max(new.grid.size.y),
+        );
+
+        let mut nodes = new.grid.nodes().collect::<Vec<_>>();
+        let mut node_attributes = HashMap::new();
+
+        for &(pos, id) in &nodes {
+            let attrs = new.node_attributes.get(&pos).cloned().unwrap_or_default();
+
+            let diff_class = match old.grid.id_at(pos) {
+                None => Some(ADDED),
+                Some(old_id) => {
+                    let old_attrs = old.node_attributes.get(&pos).cloned().unwrap_or_default();
+                    (old_id != id || old_attrs != attrs).then_some(CHANGED)
+                }
+            };
+
+            node_attributes.insert(pos, diff_attributes(attrs, diff_class));
+        }
+
+        for (pos, id) in old.grid.nodes() {
+            if new.grid.id_at(pos).is_some() {
+                continue;
+            }
+
+            let attrs = old.node_attributes.get(&pos).cloned().unwrap_or_default();
+            nodes.push((pos, id));
+            node_attributes.insert(pos, diff_attributes(attrs, Some(REMOVED)));
+        }
+
+        let key = |c: &Connection| (c.from, c.to);
+
+        let old_connections: HashMap<_, _> = old.connections.iter().map(|c| (key(c), c)).collect();
+        let new_connections: HashMap<_, _> = new.connections.iter().map(|c| (key(c), c)).collect();
+
+        let mut connections = Vec::new();
+
+        for connection in &new.connections {
+            let diff_class = match old_connections.get(&key(connection)) {
+                None => Some(ADDED),
+                Some(old) => (old.attrs != connection.attrs).then_some(CHANGED),
+            };
+
+            connections.push(diff_connection(connection, diff_class));
+        }
+
+        for connection in &old.connections {
+            if !new_connections.contains_key(&key(connection)) {
+                connections.push(diff_connection(connection, Some(REMOVED)));
+            }
+        }
+
+        Flou {
+            grid: Grid::new(size, nodes),
+            connections,
+            node_attributes,
+            labels: new.labels.clone(),
+            shared_labels: new.shared_labels.clone(),
+            headers: new.headers.clone(),
+            legend: new.legend.clone(),
+            notes: new.notes.clone(),
+        }
+    }
+}
+
+fn diff_attributes(mut attrs: NodeAttributes, diff_class: Option<&str>) -> NodeAttributes {
+    if let Some(diff_class) = diff_class {
+        attrs.add_class(diff_class);
+    }
+
+    attrs
+}
+
+fn diff_connection(connection: &Connection, diff_class: Option<&str>) -> Connection {
+    let mut connection = connection.clone();
+
+    if let Some(diff_class) = diff_class {
+        connection.attrs.add_class(diff_class);
+    }
+
+    connection
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::{pos::IndexPos, test::assert_eq};
+
+    fn node_class(flou: &Flou, pos: IndexPos) -> Option<String> {
+        flou.node_attributes[&pos].class.clone()
+    }
+
+    #[test]
+    fn marks_added_removed_and_changed_nodes() {
+        let old = Flou::try_from(r#"grid { a("A"), b, c; }"#).unwrap();
+        let new = Flou::try_from(r#"grid { a("A"), b("B"); d; }"#).unwrap();
+
+        let diff = Flou::diff(&old, &new);
+
+        assert_eq!(diff.grid.size, pos(3, 2));
+        assert_eq!(node_class(&diff, pos(0, 0)), None);
+        assert_eq!(node_class(&diff, pos(1, 0)), Some("diff-changed".into()));
+        assert_eq!(node_class(&diff, pos(2, 0)), Some("diff-removed".into()));
+        assert_eq!(node_class(&diff, pos(0, 1)), Some("diff-added".into()));
+    }
+
+    #[test]
+    fn marks_connections() {
+        let old = Flou::try_from(
+            r#"grid { a(connect: e:w@e), b(connect: e:w@e), c(connect: s:n@s); _, _, d; }"#,
+        )
+        .unwrap();
+        let new = Flou::try_from(
+            r#"grid { a(connect: e:w@e), b(class: "x", connect: e:w@e("Yes")), c; d, _, _; }"#,
+        )
+        .unwrap();
+
+        let diff = Flou::diff(&old, &new);
+
+        let mut classes = diff
+            .connections
+            .iter()
+            .map(|c| (c.from.0, c.attrs.class.clone()))
+            .collect::<Vec<_>>();
+        classes.sort_by_key(|(pos, _)| *pos);
+
+        assert_eq!(
+            classes,
+            vec![
+                (pos(0, 0), None),
+                (pos(1, 0), Some("diff-changed".into())),
+                (pos(2, 0), Some("diff-removed".into())),
+            ]
+        );
+        assert_eq!(node_class(&diff, pos(1, 0)), Some("x diff-changed".into()));
+    }
+}
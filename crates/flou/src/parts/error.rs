@@ -1,32 +1,356 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt,
+};
 
-use crate::{parse::ast::Identifier, pos::IndexPos};
+use crate::{
+    parse::ast::Identifier,
+    pos::{CellPosition, GridCoord},
+};
 
 use super::grid::ResolutionError;
 
-type MapPos<T> = HashMap<IndexPos, T>;
-type MapId<'i, T> = HashMap<Identifier<'i>, T>;
+// Errors are kept in ordered collections so that they are always reported
+// in the same order: by position in the grid or by identifier.
+type MapPos<T> = BTreeMap<GridCoord, T>;
+type MapId<'i, T> = BTreeMap<Identifier<'i>, T>;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum LogicError<'i> {
+    /// More than one node takes up the same cell of the grid. Cells are
+    /// mapped to the identifiers of the nodes in them, in the order they
+    /// were declared.
+    OverlappingNodes(MapPos<Vec<Identifier<'i>>>),
+
     /// A label was used more than once.
-    DuplicateLabels(MapId<'i, HashSet<IndexPos>>),
+    DuplicateLabels(MapId<'i, BTreeSet<GridCoord>>),
 
     /// There is more than one definition for one identifier.
-    DuplicateDefinitions(HashSet<Identifier<'i>>),
+    DuplicateDefinitions(BTreeSet<Identifier<'i>>),
 
     /// Some definitions contain duplicate node attributes.
-    DuplicateNodeAttributesInDefinitions(MapId<'i, HashSet<&'static str>>),
+    DuplicateNodeAttributesInDefinitions(MapId<'i, BTreeSet<&'static str>>),
 
     /// Some nodes inside the grid have duplicate node attributes.
-    DuplicateNodeAttributesInGrid(MapPos<HashSet<&'static str>>),
+    DuplicateNodeAttributesInGrid(MapPos<BTreeSet<&'static str>>),
 
     /// Some connections inside the `define` block contain duplicate attributes.
-    DuplicateConnectionAttributesInDefinitions(MapId<'i, HashMap<usize, HashSet<&'static str>>>),
+    DuplicateConnectionAttributesInDefinitions(MapId<'i, BTreeMap<usize, BTreeSet<&'static str>>>),
 
     /// Some connections inside the `grid` block contain duplicate attributes.
-    DuplicateConnectionAttributesInGrid(MapPos<HashMap<usize, HashSet<&'static str>>>),
+    DuplicateConnectionAttributesInGrid(MapPos<BTreeMap<usize, BTreeSet<&'static str>>>),
 
     /// One or more connections have destinations that couldn't be resolved.
-    InvalidDestination(MapPos<HashMap<usize, ResolutionError<'i>>>),
+    InvalidDestination(MapPos<BTreeMap<usize, ResolutionError<'i>>>),
+
+    /// Some nodes have more than one connection with the same destination
+    /// and sides, mapped to the positions of those destinations.
+    DuplicateConnections(MapPos<BTreeSet<GridCoord>>),
+
+    /// Some notes are attached to labels that no node has.
+    UnknownNoteLabels(BTreeSet<Identifier<'i>>),
+
+    /// The grid declares its width with `grid(width: ...)`, but some rows
+    /// have a different number of cells. The rows are mapped from their
+    /// index to their length.
+    RowWidthMismatch {
+        width: usize,
+        rows: BTreeMap<usize, usize>,
+    },
+}
+
+impl LogicError<'_> {
+    /// Like the [`Display`](fmt::Display) output, but grid positions also
+    /// name the line of the source that their row is written on.
+    /// `row_lines` are the lines, counting from 1, that the rows of the
+    /// grid start on, in the order of the rows, such as the ones returned
+    /// by `SyntaxTree::row_lines` with the `cst` feature.
+    pub fn display_with_lines<'a>(&'a self, row_lines: &'a [usize]) -> impl fmt::Display + 'a {
+        WithLines {
+            error: self,
+            cells: Cells { lines: row_lines },
+        }
+    }
+}
+
+impl fmt::Display for LogicError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display_with_lines(&[]).fmt(f)
+    }
+}
+
+struct WithLines<'a, 'i> {
+    error: &'a LogicError<'i>,
+    cells: Cells<'a>,
+}
+
+impl fmt::Display for WithLines<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&logic_error_to_string(self.error, &self.cells))
+    }
+}
+
+/// Turns grid positions into the ones shown to users, along with the line
+/// of the source each row is written on, if known.
+struct Cells<'a> {
+    lines: &'a [usize],
+}
+
+impl Cells<'_> {
+    fn line(&self, row: isize) -> Option<usize> {
+        usize::try_from(row)
+            .ok()
+            .and_then(|row| self.lines.get(row).copied())
+    }
+
+    fn at(&self, coord: GridCoord) -> CellPosition {
+        CellPosition::from(coord).with_line(self.line(coord.row))
+    }
+}
+
+fn logic_error_to_string(e: &LogicError, cells: &Cells) -> String {
+    match e {
+        LogicError::OverlappingNodes(overlapping) => {
+            let overlapping = print_map(overlapping, "\n", |&pos, ids| {
+                let ids = print_sequence(ids, ", ", quote);
+                format!("  - {} is taken by: {}", cells.at(pos), ids)
+            });
+
+            format!(
+                "Some cells of the grid are taken by more than one node:\n\n{}",
+                overlapping
+            )
+        }
+        LogicError::DuplicateLabels(labels) => {
+            let labels = print_map(labels, "\n", |label, locations| {
+                let locations = print_sequence(locations, "; ", |&x| cells.at(x).to_string());
+                format!("  - \"{}\" at: {}", label, locations)
+            });
+
+            format!("Some labels are used more than once:\n\n{}", labels)
+        }
+        LogicError::DuplicateDefinitions(ids) => {
+            let ids = print_sequence(ids, "\n", |id| format!("  - \"{}\"", id));
+            format!("Some identifiers have multiple definitions:\n\n{}", ids)
+        }
+        LogicError::DuplicateNodeAttributesInDefinitions(attrs) => {
+            let attrs = print_map(attrs, "\n", |id, attrs| {
+                let attrs = print_sequence(attrs, ", ", quote);
+                format!("  - \"{}\" has duplicate(s): {}", id, attrs)
+            });
+
+            format!(
+                "Some node definitions have duplicate attributes:\n\n{}",
+                attrs
+            )
+        }
+        LogicError::DuplicateNodeAttributesInGrid(attrs) => {
+            let attrs = print_map(attrs, "\n", |&pos, attrs| {
+                let attrs = print_sequence(attrs, ", ", quote);
+                format!("  - Node at {} has duplicate(s): {}", cells.at(pos), attrs)
+            });
+
+            format!(
+                "Some nodes declared in the grid have duplicate attributes:\n\n{}",
+                attrs
+            )
+        }
+        LogicError::DuplicateConnectionAttributesInDefinitions(attrs) => {
+            let attrs = print_map(attrs, "\n", |id, index_map| {
+                let indexes = print_map(index_map, "\n", |index, attrs| {
+                    format!(
+                        "    - At index {}: {}",
+                        index,
+                        print_sequence(attrs, ", ", quote)
+                    )
+                });
+
+                format!("  - At definition \"{}\":\n{}", id, indexes)
+            });
+
+            format!(
+                "Some connections in node definitions have duplicate attributes:\n\n{}",
+                attrs
+            )
+        }
+        LogicError::DuplicateConnectionAttributesInGrid(attrs) => {
+            let attrs = print_map(attrs, "\n", |&pos, index_map| {
+                let index_map = print_map(index_map, "\n", |index, attrs| {
+                    format!(
+                        "    - For connection at index {}: {}",
+                        index,
+                        print_sequence(attrs, ", ", quote)
+                    )
+                });
+
+                format!("  - At {}:\n{}", cells.at(pos), index_map)
+            });
+
+            format!(
+                "Some connections declared in the grid have duplicate attributes:\n\n{}",
+                attrs
+            )
+        }
+        LogicError::InvalidDestination(errors) => {
+            let errors = print_map(errors, "\n", |&pos, index_map| {
+                let index_map = print_map(index_map, "\n", |index, error| {
+                    format!(
+                        "    - For connection at index {}: {}",
+                        index,
+                        resolution_error_to_string(error, cells)
+                    )
+                });
+
+                format!("  - For node at {}:\n{}", cells.at(pos), index_map)
+            });
+
+            format!(
+                "Could not resolve destination for some node's connections:\n\n{}",
+                errors
+            )
+        }
+        LogicError::DuplicateConnections(connections) => {
+            let connections = print_map(connections, "\n", |&pos, destinations| {
+                let destinations =
+                    print_sequence(destinations, "; ", |&pos| cells.at(pos).to_string());
+                format!("  - Node at {} to: {}", cells.at(pos), destinations)
+            });
+
+            format!(
+                "Some nodes have the same connection more than once:\n\n{}",
+                connections
+            )
+        }
+        LogicError::UnknownNoteLabels(labels) => {
+            let labels = print_sequence(labels, "\n", |label| format!("  - \"{}\"", label));
+            format!(
+                "Some notes are attached to labels that no node has:\n\n{}",
+                labels
+            )
+        }
+        LogicError::RowWidthMismatch { width, rows } => {
+            let rows = print_map(rows, "\n", |&row, len| {
+                let row = row as isize;
+                match cells.line(row) {
+                    Some(line) => format!("  - Row {} (line {}) has {} cells", row + 1, line, len),
+                    None => format!("  - Row {} has {} cells", row + 1, len),
+                }
+            });
+
+            format!(
+                "Some rows don't have the {} cells the grid is declared with:\n\n{}",
+                width, rows
+            )
+        }
+    }
+}
+
+fn resolution_error_to_string(e: &ResolutionError, cells: &Cells) -> String {
+    match e {
+        ResolutionError::InvalidDirection {
+            dir,
+            empty_cells,
+            candidates,
+        } => {
+            let reason = match empty_cells {
+                0 => "the node is at the edge of the grid".to_string(),
+                1 => "there is only 1 empty cell before the edge of the grid".to_string(),
+                n => format!(
+                    "there are only {} empty cells before the edge of the grid",
+                    n
+                ),
+            };
+            let mut message = format!("No destination found in direction {}: {}", dir, reason);
+            if !candidates.is_empty() {
+                let candidates = print_sequence(candidates, "; ", |&x| cells.at(x).to_string());
+                message.push_str(&format!(
+                    ". Closest nodes in that direction: {}",
+                    candidates
+                ));
+            }
+            message
+        }
+        ResolutionError::UnknownLabel {
+            label,
+            nodes_with_id,
+            ..
+        } => {
+            let mut message = format!("No destination with label: \"{}\"", label);
+            if let Some(closest) = e.closest_label() {
+                message.push_str(&format!(". Did you mean \"{}\"?", closest));
+            }
+            if !nodes_with_id.is_empty() {
+                let nodes = print_sequence(nodes_with_id, "; ", |&x| cells.at(x).to_string());
+                message.push_str(&format!(
+                    concat!(
+                        ". \"{}\" is the identifier of the node(s) at: {}, ",
+                        "but connections go to labels; write it as {}#{} to label the node"
+                    ),
+                    label, nodes, label, label
+                ));
+            }
+            message
+        }
+        ResolutionError::OutOfBounds(pos) => {
+            format!("Destination {} is outside the grid", cells.at(*pos))
+        }
+    }
+}
+
+fn quote<T: fmt::Display>(item: T) -> String {
+    format!("\"{}\"", item)
+}
+
+fn print_sequence<T, I: IntoIterator<Item = T>>(
+    seq: I,
+    delimiter: &str,
+    print: impl Fn(T) -> String,
+) -> String {
+    seq.into_iter()
+        .map(print)
+        .collect::<Vec<_>>()
+        .join(delimiter)
+}
+
+fn print_map<K, V, I: IntoIterator<Item = (K, V)>>(
+    map: I,
+    delimiter: &str,
+    print: impl Fn(K, V) -> String,
+) -> String {
+    map.into_iter()
+        .map(|(k, v)| print(k, v))
+        .collect::<Vec<_>>()
+        .join(delimiter)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use crate::{test::assert_eq, Flou, Limits};
+
+    #[test]
+    fn displays_errors_with_cell_positions() {
+        let e = Flou::try_from("grid {\n    a;\n    b(connect: s:n#x);\n}").unwrap_err();
+        let message = concat!(
+            "Error in Flou logic:\n",
+            "Could not resolve destination for some node's connections:\n\n",
+            "  - For node at column 1, row 2{}:\n",
+            "    - For connection at index 0: No destination with label: \"x\"",
+        );
+
+        assert_eq!(e.to_string(), message.replace("{}", ""));
+        assert_eq!(
+            e.display_with_lines(&[2, 3]).to_string(),
+            message.replace("{}", " (line 3)")
+        );
+
+        let limits = Limits {
+            max_input_size: 4,
+            ..Limits::unlimited()
+        };
+        let e = Flou::parse_with_limits("grid { a; }", &limits).unwrap_err();
+        assert_eq!(e.to_string(), "Input is too large (11 bytes).");
+    }
 }
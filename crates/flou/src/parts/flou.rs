@@ -1,60 +1,174 @@
 use std::{
-    collections::{hash_map, HashMap, HashSet},
+    borrow::Cow,
+    collections::{hash_map, BTreeMap, BTreeSet, HashMap},
     convert::TryFrom,
-    fmt::Display,
+    fmt,
 };
 
 use crate::{
     parse::ast::{
-        ArrowheadType, ConnectionAttribute, ConnectionDescriptor, Destination, Direction, Document,
-        Grid as ASTGrid, Identifier, NodeAttribute, NodeShape,
+        Anchors, ArrowheadType, Aspect, ConnectionAttribute, ConnectionDescriptor, ConnectionRoute,
+        ConnectionWeight, Destination, Direction, Document, Fit, Grid as ASTGrid, Headers,
+        Identifier, LegendEntry, Length, NodeAttribute, NodeShape, Note as ASTNote, Opacity,
+        Palette, Placement,
     },
     parse::Error as AstError,
-    pos::{pos, IndexPos, PixelPos},
+    pos::{GridCoord, IndexPos},
 };
 
 use super::{
     error::LogicError,
-    grid::{Grid, ResolutionError},
+    grid::{Grid, GridView, ResolutionError},
+    limits::Limit,
+    options::{ConvertOptions, Strictness},
 };
 
 type MapPos<T> = HashMap<IndexPos, T>;
 type MapId<'i, T> = HashMap<Identifier<'i>, T>;
+// Errors are collected into ordered maps so they are reported by position
+// or identifier rather than in hashing order.
+type ErrorsPos<T> = BTreeMap<GridCoord, T>;
+type ErrorsId<'i, T> = BTreeMap<Identifier<'i>, T>;
 type TwoMapId<'i, T1, T2> = (MapId<'i, T1>, MapId<'i, T2>);
 type TwoMapPos<T1, T2> = (MapPos<T1>, MapPos<T2>);
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub(crate) struct NodeAttributes {
     pub(crate) text: Option<String>,
     pub(crate) class: Option<String>,
     pub(crate) shape: Option<NodeShape>,
+    pub(crate) start: Option<bool>,
+    pub(crate) end: Option<bool>,
+    pub(crate) opacity: Option<Opacity>,
+    pub(crate) hidden: Option<bool>,
+    pub(crate) step: Option<u32>,
+    pub(crate) anchors: Option<Anchors>,
+    pub(crate) radius: Option<Length>,
+    pub(crate) padding: Option<Length>,
+    pub(crate) fit: Option<Fit>,
+    pub(crate) aspect: Option<Aspect>,
+    pub(crate) palette: Option<Palette>,
 }
 
-#[derive(Debug, Default, Clone)]
+impl NodeAttributes {
+    /// Appends `class` to the node's classes.
+    pub(crate) fn add_class(&mut self, class: &str) {
+        self.class = Some(match self.class.take() {
+            Some(existing) if !class.is_empty() => format!("{} {}", existing, class),
+            Some(existing) => existing,
+            None => class.to_string(),
+        });
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub(crate) struct ConnectionAttributes {
     pub(crate) text: Option<String>,
     pub(crate) class: Option<String>,
     pub(crate) arrowheads: Option<ArrowheadType>,
+    pub(crate) id: Option<String>,
+    pub(crate) order: Option<i32>,
+    pub(crate) weight: Option<ConnectionWeight>,
+    pub(crate) opacity: Option<Opacity>,
+    pub(crate) hidden: Option<bool>,
+    pub(crate) step: Option<u32>,
+    pub(crate) route: Option<ConnectionRoute>,
 }
 
-#[derive(Debug)]
+impl ConnectionAttributes {
+    /// Appends `class` to the connection's classes.
+    pub(crate) fn add_class(&mut self, class: &str) {
+        self.class = Some(match self.class.take() {
+            Some(existing) if !class.is_empty() => format!("{} {}", existing, class),
+            Some(existing) => existing,
+            None => class.to_string(),
+        });
+    }
+}
+
+/// A note attached to the side of the node at `pos`.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "render"), allow(dead_code))]
+pub(crate) struct Note {
+    pub(crate) pos: IndexPos,
+    pub(crate) side: Direction,
+    pub(crate) text: String,
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct Connection {
     pub(crate) from: (IndexPos, Direction),
     pub(crate) to: (IndexPos, Direction),
     pub(crate) attrs: ConnectionAttributes,
 }
 
-#[derive(Debug)]
+impl Connection {
+    /// The connection's endpoints as the public API reports them.
+    #[cfg_attr(not(feature = "render"), allow(dead_code))]
+    pub(crate) fn coords(&self) -> ((GridCoord, Direction), (GridCoord, Direction)) {
+        (
+            (self.from.0.into(), self.from.1),
+            (self.to.0.into(), self.to.1),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Flou<'i> {
     pub(crate) grid: Grid<'i>,
     pub(crate) connections: Vec<Connection>,
     pub(crate) node_attributes: MapPos<NodeAttributes>,
+    pub(crate) labels: MapId<'i, IndexPos>,
+    pub(crate) shared_labels: ErrorsId<'i, BTreeSet<IndexPos>>,
+    pub(crate) headers: Headers,
+    pub(crate) legend: Vec<LegendEntry>,
+    pub(crate) notes: Vec<Note>,
 }
 
 #[derive(Debug)]
 pub enum FlouError<'i> {
     Parse(AstError<'i>),
     Logic(LogicError<'i>),
+    LimitExceeded(Limit),
+}
+
+impl FlouError<'_> {
+    /// Like the [`Display`](fmt::Display) output, but grid positions in
+    /// logic errors also name the line of the source that their row is
+    /// written on. See [`LogicError::display_with_lines`].
+    pub fn display_with_lines<'a>(&'a self, row_lines: &'a [usize]) -> impl fmt::Display + 'a {
+        WithLines {
+            error: self,
+            row_lines,
+        }
+    }
+}
+
+impl fmt::Display for FlouError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display_with_lines(&[]).fmt(f)
+    }
+}
+
+impl std::error::Error for FlouError<'_> {}
+
+struct WithLines<'a, 'i> {
+    error: &'a FlouError<'i>,
+    row_lines: &'a [usize],
+}
+
+impl fmt::Display for WithLines<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error {
+            FlouError::Parse(e) => write!(f, "Error parsing Flou:\n\n{}", e),
+            FlouError::Logic(e) => write!(
+                f,
+                "Error in Flou logic:\n{}",
+                e.display_with_lines(self.row_lines)
+            ),
+            FlouError::LimitExceeded(limit) => limit.fmt(f),
+        }
+    }
 }
 
 impl<'i> TryFrom<&'i str> for Flou<'i> {
@@ -67,37 +181,55 @@ impl<'i> TryFrom<&'i str> for Flou<'i> {
     }
 }
 
-pub struct RenderConfig {
-    pub default_css: bool,
-    pub css: Vec<String>,
+impl<'i> TryFrom<Document<'i>> for Flou<'i> {
+    type Error = LogicError<'i>;
 
-    // Element sizes
-    pub arrowhead: PixelPos,
-    pub node: PixelPos,
-    pub grid_gap: PixelPos,
+    fn try_from(document: Document<'i>) -> Result<Self, Self::Error> {
+        Self::try_from_with_options(document, &ConvertOptions::default())
+    }
 }
 
-impl Default for RenderConfig {
-    fn default() -> Self {
-        Self {
-            default_css: true,
-            css: Vec::new(),
-            arrowhead: pos(10, 10),
-            node: pos(200, 100),
-            grid_gap: pos(50, 50),
+impl<'i> Flou<'i> {
+    /// Parses each of `sources` and combines them into a single flowchart.
+    /// Each document's grid is placed relative to the documents before it
+    /// according to `placement`, and their definitions are combined.
+    ///
+    /// Labels have to be unique across all documents, and an identifier
+    /// can only be defined differently in one of them.
+    pub fn compose<I>(sources: I, placement: Placement) -> Result<Self, FlouError<'i>>
+    where
+        I: IntoIterator<Item = &'i str>,
+    {
+        let mut document: Option<Document<'i>> = None;
+
+        for source in sources {
+            let other = Document::parse(source).map_err(FlouError::Parse)?;
+            // The merged document has no declared width, so each document
+            // is checked against its own before merging.
+            check_row_widths(&other).map_err(FlouError::Logic)?;
+            document = Some(match document {
+                Some(document) => document.merge(other, placement),
+                None => other,
+            });
         }
+
+        let document = document.unwrap_or_default();
+        Flou::try_from(document).map_err(FlouError::Logic)
     }
-}
 
-pub trait Renderer {
-    fn render<'i>(flou: &'i Flou<'i>, config: &'i RenderConfig) -> Box<dyn Display + 'i>;
-}
+    /// Like `Flou::try_from`, but with control over how the document is
+    /// interpreted, such as whether duplicate attributes are an error.
+    pub fn try_from_with_options(
+        document: Document<'i>,
+        options: &ConvertOptions,
+    ) -> Result<Self, LogicError<'i>> {
+        check_row_widths(&document)?;
 
-impl<'i> TryFrom<Document<'i>> for Flou<'i> {
-    type Error = LogicError<'i>;
+        let grid = Grid::try_from(&document.grid).map_err(LogicError::OverlappingNodes)?;
 
-    fn try_from(document: Document<'i>) -> Result<Self, Self::Error> {
-        let grid = Grid::from(&document.grid);
+        // Collected before the grid is consumed below, but reported after
+        // the other errors.
+        let (labels, duplicate_labels) = into_label_map(&document.grid);
 
         let definitions = ensure_definitions_are_unique(document.definitions)
             .map_err(LogicError::DuplicateDefinitions)?;
@@ -105,11 +237,13 @@ impl<'i> TryFrom<Document<'i>> for Flou<'i> {
         // TODO: Warn if a definition doesn't map to any nodes in the grid.
 
         let (def_attrs, def_connections) = {
-            let (def_attrs, def_connection_desc_map) = get_attributes_from_definitions(definitions)
-                .map_err(LogicError::DuplicateNodeAttributesInDefinitions)?;
+            let (def_attrs, def_connection_desc_map) =
+                get_attributes_from_definitions(definitions, options.strictness)
+                    .map_err(LogicError::DuplicateNodeAttributesInDefinitions)?;
 
-            let def_connections = parse_connection_desc_map(def_connection_desc_map)
-                .map_err(LogicError::DuplicateConnectionAttributesInDefinitions)?;
+            let def_connections =
+                parse_connection_desc_map(def_connection_desc_map, options.strictness)
+                    .map_err(LogicError::DuplicateConnectionAttributesInDefinitions)?;
 
             (
                 resolve_id_map(&grid, def_attrs),
@@ -118,11 +252,14 @@ impl<'i> TryFrom<Document<'i>> for Flou<'i> {
         };
 
         let (grid_attrs, grid_connections) = {
-            let (grid_attrs, grid_conn_desc_map) = get_attributes_from_grid(&document.grid)
-                .map_err(LogicError::DuplicateNodeAttributesInGrid)?;
+            let (grid_attrs, grid_conn_desc_map) =
+                get_attributes_from_grid(document.grid, options.strictness)
+                    .map_err(LogicError::DuplicateNodeAttributesInGrid)?;
 
-            let grid_connections = parse_connection_desc_map(grid_conn_desc_map)
-                .map_err(LogicError::DuplicateConnectionAttributesInGrid)?;
+            let grid_connections =
+                parse_connection_desc_map(grid_conn_desc_map, options.strictness).map_err(
+                    |errors| LogicError::DuplicateConnectionAttributesInGrid(by_coord(errors)),
+                )?;
 
             (grid_attrs, grid_connections)
         };
@@ -130,17 +267,76 @@ impl<'i> TryFrom<Document<'i>> for Flou<'i> {
         let node_attributes = Overwrite::overwrite(def_attrs, grid_attrs);
         let connections = Overwrite::overwrite(def_connections, grid_connections);
 
-        let labels = try_into_label_map(&document.grid).map_err(LogicError::DuplicateLabels)?;
-
-        let connections = resolve_connections_map(&grid, &labels, connections)
+        let shared_labels =
+            share_duplicate_labels(duplicate_labels, &connections).map_err(|duplicates| {
+                let duplicates = duplicates.into_iter().map(|(label, positions)| {
+                    (label, positions.into_iter().map(GridCoord::from).collect())
+                });
+                LogicError::DuplicateLabels(duplicates.collect())
+            })?;
+
+        let targets = match options.ids_as_labels {
+            true => Cow::Owned(with_unique_ids(&grid, &labels, &shared_labels)),
+            false => Cow::Borrowed(&labels),
+        };
+        let connections = resolve_connections_map(&grid, &targets, &shared_labels, connections)
             .map_err(LogicError::InvalidDestination)?;
 
+        let connections = remove_duplicate_connections(connections, options.strictness)
+            .map_err(LogicError::DuplicateConnections)?;
+
+        let notes =
+            resolve_notes(&labels, document.notes).map_err(LogicError::UnknownNoteLabels)?;
+
         Ok(Self {
             grid,
             connections,
             node_attributes,
+            labels,
+            shared_labels,
+            headers: document.headers,
+            legend: document.legend,
+            notes,
         })
     }
+
+    /// A read-only view of the flowchart's grid, for querying where nodes
+    /// are and where connections lead.
+    pub fn grid(&self) -> GridView<'_, 'i> {
+        GridView::new(&self.grid, &self.labels, &self.shared_labels)
+    }
+}
+
+/// Makes sure that every row of the grid has as many cells as the grid's
+/// declared width, if it declares one.
+fn check_row_widths<'i>(document: &Document) -> Result<(), LogicError<'i>> {
+    let width = match document.width {
+        Some(width) => width,
+        None => return Ok(()),
+    };
+
+    let rows = document
+        .grid
+        .0
+        .iter()
+        .map(Vec::len)
+        .enumerate()
+        .filter(|&(_, len)| len != width)
+        .collect::<BTreeMap<_, _>>();
+
+    match rows.is_empty() {
+        true => Ok(()),
+        false => Err(LogicError::RowWidthMismatch { width, rows }),
+    }
+}
+
+/// Errors are collected by the positions used internally, but reported by
+/// their [`GridCoord`].
+fn by_coord<T>(errors: BTreeMap<IndexPos, T>) -> ErrorsPos<T> {
+    errors
+        .into_iter()
+        .map(|(pos, value)| (pos.into(), value))
+        .collect()
 }
 
 /// Tries to assemble `NodeAttributes` from the vector of individual attributes.
@@ -148,25 +344,71 @@ impl<'i> TryFrom<Document<'i>> for Flou<'i> {
 /// vector of connections can be created later down the line.
 fn parse_node_attributes<'i>(
     attributes: Vec<NodeAttribute<'i>>,
-) -> Result<(NodeAttributes, Option<Vec<ConnectionDescriptor<'i>>>), HashSet<&'static str>> {
+    strictness: Strictness,
+) -> Result<
+    (
+        NodeAttributes,
+        Option<ConnectionList<ConnectionDescriptor<'i>>>,
+    ),
+    BTreeSet<&'static str>,
+> {
     let mut res = NodeAttributes::default();
-    let mut duplicates = HashSet::new();
+    let mut duplicates = BTreeSet::new();
     let mut conn_descriptors = None;
+    let mut appended_descriptors = Vec::new();
 
     for attribute in attributes {
-        match attribute {
-            NodeAttribute::Text(text) if res.text.is_none() => res.text = Some(text),
-            NodeAttribute::Class(class) if res.class.is_none() => res.class = Some(class),
-            NodeAttribute::Shape(shape) if res.shape.is_none() => res.shape = Some(shape),
-            NodeAttribute::Connect(descriptors) if conn_descriptors.is_none() => {
-                conn_descriptors = Some(descriptors)
+        let key = attribute.as_key();
+        let assigned = match attribute {
+            NodeAttribute::Text(text) => strictness.assign(&mut res.text, text),
+            // Classes accumulate instead of replacing each other.
+            NodeAttribute::Class(class) => {
+                res.add_class(&class);
+                true
             }
-            _ => {
-                duplicates.insert(attribute.as_key());
+            NodeAttribute::Shape(shape) => strictness.assign(&mut res.shape, shape),
+            NodeAttribute::Start(start) => strictness.assign(&mut res.start, start),
+            NodeAttribute::End(end) => strictness.assign(&mut res.end, end),
+            NodeAttribute::Opacity(opacity) => strictness.assign(&mut res.opacity, opacity),
+            NodeAttribute::Hidden(hidden) => strictness.assign(&mut res.hidden, hidden),
+            NodeAttribute::Step(step) => strictness.assign(&mut res.step, step),
+            NodeAttribute::Anchors(anchors) => strictness.assign(&mut res.anchors, anchors),
+            NodeAttribute::Radius(radius) => strictness.assign(&mut res.radius, radius),
+            NodeAttribute::Padding(padding) => strictness.assign(&mut res.padding, padding),
+            NodeAttribute::Fit(fit) => strictness.assign(&mut res.fit, fit),
+            NodeAttribute::Aspect(aspect) => strictness.assign(&mut res.aspect, aspect),
+            NodeAttribute::Palette(palette) => strictness.assign(&mut res.palette, palette),
+            NodeAttribute::Connect(descriptors) => {
+                strictness.assign(&mut conn_descriptors, descriptors)
             }
+            NodeAttribute::ConnectAppend(descriptors) => {
+                appended_descriptors.extend(descriptors);
+                true
+            }
+        };
+
+        if !assigned {
+            duplicates.insert(key);
         }
     }
 
+    // `connect+` adds to `connect` in the same list, and only adds to the
+    // definition's connections if there is no `connect` to replace them.
+    let conn_descriptors = match conn_descriptors {
+        Some(mut items) => {
+            items.extend(appended_descriptors);
+            Some(ConnectionList {
+                items,
+                append: false,
+            })
+        }
+        None if !appended_descriptors.is_empty() => Some(ConnectionList {
+            items: appended_descriptors,
+            append: true,
+        }),
+        None => None,
+    };
+
     if duplicates.is_empty() {
         Ok((res, conn_descriptors))
     } else {
@@ -174,79 +416,114 @@ fn parse_node_attributes<'i>(
     }
 }
 
-impl TryFrom<Vec<ConnectionAttribute>> for ConnectionAttributes {
-    type Error = HashSet<&'static str>;
+fn parse_connection_attributes(
+    attributes: Vec<ConnectionAttribute>,
+    strictness: Strictness,
+) -> Result<ConnectionAttributes, BTreeSet<&'static str>> {
+    let mut res = ConnectionAttributes::default();
+    let mut duplicates = BTreeSet::new();
 
-    fn try_from(attributes: Vec<ConnectionAttribute>) -> Result<Self, Self::Error> {
-        let mut res = Self::default();
-        let mut duplicates = HashSet::new();
-
-        for attribute in attributes {
-            match attribute {
-                ConnectionAttribute::Text(text) if res.text.is_none() => res.text = Some(text),
-                ConnectionAttribute::Class(class) if res.class.is_none() => res.class = Some(class),
-                ConnectionAttribute::Arrowheads(arrowheads) if res.arrowheads.is_none() => {
-                    res.arrowheads = Some(arrowheads)
-                }
-                _ => {
-                    duplicates.insert(attribute.as_key());
-                }
+    for attribute in attributes {
+        let key = attribute.as_key();
+        let assigned = match attribute {
+            ConnectionAttribute::Text(text) => strictness.assign(&mut res.text, text),
+            ConnectionAttribute::Class(class) => {
+                res.add_class(&class);
+                true
             }
-        }
+            ConnectionAttribute::Arrowheads(arrowheads) => {
+                strictness.assign(&mut res.arrowheads, arrowheads)
+            }
+            ConnectionAttribute::Id(id) => strictness.assign(&mut res.id, id),
+            ConnectionAttribute::Order(order) => strictness.assign(&mut res.order, order),
+            ConnectionAttribute::Weight(weight) => strictness.assign(&mut res.weight, weight),
+            ConnectionAttribute::Opacity(opacity) => strictness.assign(&mut res.opacity, opacity),
+            ConnectionAttribute::Hidden(hidden) => strictness.assign(&mut res.hidden, hidden),
+            ConnectionAttribute::Step(step) => strictness.assign(&mut res.step, step),
+            ConnectionAttribute::Route(route) => strictness.assign(&mut res.route, route),
+        };
 
-        if duplicates.is_empty() {
-            Ok(res)
-        } else {
-            Err(duplicates)
+        if !assigned {
+            duplicates.insert(key);
         }
     }
+
+    if duplicates.is_empty() {
+        Ok(res)
+    } else {
+        Err(duplicates)
+    }
 }
 
 /// Tries to map a label to the position of the node it's attached to.
 /// Labels are supposed to be unique, so encountering duplicates is an error.
-fn try_into_label_map<'i>(
+/// Maps each label used by a single node to its position. Labels used by
+/// more than one node are returned separately, along with their positions.
+fn into_label_map<'i>(
     grid: &ASTGrid<'i>,
-) -> Result<MapId<'i, IndexPos>, MapId<'i, HashSet<IndexPos>>> {
-    let mut positions: HashMap<Identifier, HashSet<IndexPos>> = HashMap::new();
+) -> (MapId<'i, IndexPos>, ErrorsId<'i, BTreeSet<IndexPos>>) {
+    let mut positions: ErrorsId<BTreeSet<IndexPos>> = BTreeMap::new();
 
     for (pos, node) in grid.nodes() {
         if let Some(label) = node.label {
-            positions.entry(label).or_default().insert(pos);
+            positions.entry(label).or_default().insert(pos.into());
         }
     }
 
     let mut labels = HashMap::new();
-    let mut unique_labels = HashSet::new();
-
-    for (&label, positions) in &positions {
-        if positions.len() == 1 {
+    positions.retain(|&label, positions| match positions.len() {
+        1 => {
             labels.insert(label, *positions.iter().next().unwrap());
-            unique_labels.insert(label);
+            false
         }
+        _ => true,
+    });
+
+    (labels, positions)
+}
+
+/// A label can be used by more than one node if every connection to it
+/// goes to the nearest of them with `#label%nearest`. Other duplicate
+/// labels are an error, including ones that nothing connects to.
+fn share_duplicate_labels<'i>(
+    duplicates: ErrorsId<'i, BTreeSet<IndexPos>>,
+    connections: &MapPos<ConnectionList<UnresolvedConnection<'i>>>,
+) -> Result<ErrorsId<'i, BTreeSet<IndexPos>>, ErrorsId<'i, BTreeSet<IndexPos>>> {
+    let mut nearest = BTreeSet::new();
+    let mut exact = BTreeSet::new();
+
+    for connection in connections.values().flat_map(|list| &list.items) {
+        match connection.to {
+            Destination::NearestLabel(label) => nearest.insert(label),
+            Destination::Label(label) => exact.insert(label),
+            _ => false,
+        };
     }
 
-    if unique_labels.len() < positions.len() {
-        for label in unique_labels {
-            positions.remove(&label);
-        }
-        Err(positions)
+    let (shared, errors): (ErrorsId<_>, ErrorsId<_>) = duplicates
+        .into_iter()
+        .partition(|(label, _)| nearest.contains(label) && !exact.contains(label));
+
+    if errors.is_empty() {
+        Ok(shared)
     } else {
-        Ok(labels)
+        Err(errors)
     }
 }
 
 fn get_attributes_from_definitions<'i>(
     definitions: MapId<'i, Vec<NodeAttribute<'i>>>,
+    strictness: Strictness,
 ) -> Result<
-    TwoMapId<'i, NodeAttributes, Vec<ConnectionDescriptor<'i>>>,
-    MapId<'i, HashSet<&'static str>>,
+    TwoMapId<'i, NodeAttributes, ConnectionList<ConnectionDescriptor<'i>>>,
+    ErrorsId<'i, BTreeSet<&'static str>>,
 > {
-    let mut errors = HashMap::new();
+    let mut errors = BTreeMap::new();
     let mut map_node_attrs = HashMap::new();
     let mut map_connection_descriptors = HashMap::new();
 
     for (id, attrs) in definitions {
-        match parse_node_attributes(attrs) {
+        match parse_node_attributes(attrs, strictness) {
             Ok((node_attrs, connection_descriptors)) => {
                 map_node_attrs.insert(id, node_attrs);
                 if let Some(descriptors) = connection_descriptors {
@@ -267,15 +544,19 @@ fn get_attributes_from_definitions<'i>(
 }
 
 fn get_attributes_from_grid<'i>(
-    grid: &ASTGrid<'i>,
-) -> Result<TwoMapPos<NodeAttributes, Vec<ConnectionDescriptor<'i>>>, MapPos<HashSet<&'static str>>>
-{
-    let mut errors = HashMap::new();
+    grid: ASTGrid<'i>,
+    strictness: Strictness,
+) -> Result<
+    TwoMapPos<NodeAttributes, ConnectionList<ConnectionDescriptor<'i>>>,
+    ErrorsPos<BTreeSet<&'static str>>,
+> {
+    let mut errors = BTreeMap::new();
     let mut map_node_attrs = HashMap::new();
     let mut map_connection_descriptors = HashMap::new();
 
-    for (pos, node) in grid.nodes() {
-        match parse_node_attributes(node.attrs.clone()) {
+    for (coord, node) in grid.into_nodes() {
+        let pos = IndexPos::from(coord);
+        match parse_node_attributes(node.attrs, strictness) {
             Ok((node_attrs, connection_descriptors)) => {
                 map_node_attrs.insert(pos, node_attrs);
                 if let Some(descriptors) = connection_descriptors {
@@ -283,7 +564,7 @@ fn get_attributes_from_grid<'i>(
                 }
             }
             Err(duplicate_attrs) => {
-                errors.insert(pos, duplicate_attrs);
+                errors.insert(coord, duplicate_attrs);
             }
         }
     }
@@ -297,8 +578,8 @@ fn get_attributes_from_grid<'i>(
 
 fn ensure_definitions_are_unique<'i>(
     definitions: Vec<(Identifier<'i>, Vec<NodeAttribute<'i>>)>,
-) -> Result<MapId<Vec<NodeAttribute<'i>>>, HashSet<Identifier<'i>>> {
-    let mut duplicates = HashSet::new();
+) -> Result<MapId<'i, Vec<NodeAttribute<'i>>>, BTreeSet<Identifier<'i>>> {
+    let mut duplicates = BTreeSet::new();
     let mut res = HashMap::new();
 
     for (id, attrs) in definitions {
@@ -316,6 +597,14 @@ fn ensure_definitions_are_unique<'i>(
     }
 }
 
+/// The connections a node has, either replacing or added to the ones from
+/// the node's definition.
+#[derive(Debug, Clone)]
+struct ConnectionList<T> {
+    items: Vec<T>,
+    append: bool,
+}
+
 #[derive(Debug, Clone)]
 struct UnresolvedConnection<'i> {
     to: Destination<'i>,
@@ -323,19 +612,20 @@ struct UnresolvedConnection<'i> {
     attrs: ConnectionAttributes,
 }
 
-type MapToUnresolvedConnection<'i, T> = HashMap<T, Vec<UnresolvedConnection<'i>>>;
-type MapToDuplicateAttrs<'i, T> = HashMap<T, HashMap<usize, HashSet<&'static str>>>;
+type MapToUnresolvedConnection<'i, T> = HashMap<T, ConnectionList<UnresolvedConnection<'i>>>;
+type MapToDuplicateAttrs<'i, T> = BTreeMap<T, BTreeMap<usize, BTreeSet<&'static str>>>;
 
-fn parse_connection_desc_map<T: Eq + std::hash::Hash + Copy>(
-    def_connection_desc_map: HashMap<T, Vec<ConnectionDescriptor>>,
+fn parse_connection_desc_map<T: Ord + std::hash::Hash + Copy>(
+    def_connection_desc_map: HashMap<T, ConnectionList<ConnectionDescriptor>>,
+    strictness: Strictness,
 ) -> Result<MapToUnresolvedConnection<T>, MapToDuplicateAttrs<T>> {
-    let mut errors = HashMap::new();
+    let mut errors = BTreeMap::new();
     let mut res = HashMap::new();
 
     for (id, descriptors) in def_connection_desc_map {
         let mut value = Vec::new();
-        for (i, descriptor) in descriptors.into_iter().enumerate() {
-            match ConnectionAttributes::try_from(descriptor.attrs) {
+        for (i, descriptor) in descriptors.items.into_iter().enumerate() {
+            match parse_connection_attributes(descriptor.attrs, strictness) {
                 Ok(attrs) => {
                     value.push(UnresolvedConnection {
                         to: descriptor.to,
@@ -346,13 +636,19 @@ fn parse_connection_desc_map<T: Eq + std::hash::Hash + Copy>(
                 Err(duplicate_attrs) => {
                     errors
                         .entry(id)
-                        .or_insert_with(HashMap::new)
+                        .or_insert_with(BTreeMap::new)
                         .insert(i, duplicate_attrs);
                 }
             };
         }
 
-        res.insert(id, value);
+        res.insert(
+            id,
+            ConnectionList {
+                items: value,
+                append: descriptors.append,
+            },
+        );
     }
 
     if errors.is_empty() {
@@ -362,25 +658,109 @@ fn parse_connection_desc_map<T: Eq + std::hash::Hash + Copy>(
     }
 }
 
+/// Adds the identifiers used by only one node to `labels`, so that they can
+/// be connected to like labels. Labels take precedence over identifiers.
+fn with_unique_ids<'i>(
+    grid: &Grid<'i>,
+    labels: &MapId<'i, IndexPos>,
+    shared_labels: &ErrorsId<'i, BTreeSet<IndexPos>>,
+) -> MapId<'i, IndexPos> {
+    let mut res = labels.clone();
+    for (id, pos) in grid.unique_ids() {
+        if !shared_labels.contains_key(&id) {
+            res.entry(id).or_insert(pos);
+        }
+    }
+    res
+}
+
 fn resolve_connections_map<'i>(
     grid: &Grid<'i>,
     labels: &MapId<'i, IndexPos>,
-    connections_map: MapPos<Vec<UnresolvedConnection<'i>>>,
-) -> Result<Vec<Connection>, MapPos<HashMap<usize, ResolutionError<'i>>>> {
-    let mut errors: MapPos<HashMap<usize, ResolutionError>> = HashMap::new();
+    shared_labels: &ErrorsId<'i, BTreeSet<IndexPos>>,
+    connections_map: MapPos<ConnectionList<UnresolvedConnection<'i>>>,
+) -> Result<Vec<Connection>, ErrorsPos<BTreeMap<usize, ResolutionError<'i>>>> {
+    let mut errors: ErrorsPos<BTreeMap<usize, ResolutionError>> = BTreeMap::new();
     let mut res = Vec::new();
 
     for (from, connections) in connections_map {
-        for (i, unresolved) in connections.into_iter().enumerate() {
-            match grid.normalize_destination(from, unresolved.to, labels) {
+        for (i, unresolved) in connections.items.into_iter().enumerate() {
+            match grid.normalize_destination(from, unresolved.to, labels, shared_labels) {
                 Ok(to) => res.push(Connection {
                     from: (from, unresolved.sides.0),
                     to: (to, unresolved.sides.1),
                     attrs: unresolved.attrs,
                 }),
                 Err(resolution_error) => {
-                    errors.entry(from).or_default().insert(i, resolution_error);
+                    errors
+                        .entry(from.into())
+                        .or_default()
+                        .insert(i, resolution_error);
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(res)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Finds connections with the same endpoints and sides, which would be drawn
+/// on top of each other. Unless they are an error, only the first or the last
+/// of them is kept, in the place of the first.
+fn remove_duplicate_connections(
+    connections: Vec<Connection>,
+    strictness: Strictness,
+) -> Result<Vec<Connection>, ErrorsPos<BTreeSet<GridCoord>>> {
+    let mut errors: ErrorsPos<BTreeSet<GridCoord>> = BTreeMap::new();
+    let mut indexes = HashMap::new();
+    let mut res: Vec<Connection> = Vec::new();
+
+    for connection in connections {
+        match indexes.entry((connection.from, connection.to)) {
+            hash_map::Entry::Vacant(e) => {
+                e.insert(res.len());
+                res.push(connection);
+            }
+            hash_map::Entry::Occupied(e) => match strictness {
+                Strictness::Error => {
+                    errors
+                        .entry(connection.from.0.into())
+                        .or_default()
+                        .insert(connection.to.0.into());
                 }
+                Strictness::FirstWins => {}
+                Strictness::LastWins => res[*e.get()] = connection,
+            },
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(res)
+    } else {
+        Err(errors)
+    }
+}
+
+fn resolve_notes<'i>(
+    labels: &MapId<'i, IndexPos>,
+    notes: Vec<ASTNote<'i>>,
+) -> Result<Vec<Note>, BTreeSet<Identifier<'i>>> {
+    let mut errors = BTreeSet::new();
+    let mut res = Vec::new();
+
+    for note in notes {
+        match labels.get(&note.label) {
+            Some(&pos) => res.push(Note {
+                pos,
+                side: note.side.unwrap_or(Direction::East),
+                text: note.text,
+            }),
+            None => {
+                errors.insert(note.label);
             }
         }
     }
@@ -396,10 +776,12 @@ fn resolve_id_map<'i, T: Clone>(grid: &Grid<'i>, map_id: MapId<T>) -> MapPos<T>
     let mut res = HashMap::new();
 
     for (id, value) in map_id {
-        if let Some(positions) = grid.get_positions(&id) {
-            for &pos in positions {
+        if let Some((&last, rest)) = grid.get_positions(&id).and_then(|p| p.split_last()) {
+            for &pos in rest {
                 res.insert(pos, value.clone());
             }
+            // The last node can take the value itself instead of a copy.
+            res.insert(last, value);
         }
     }
 
@@ -416,6 +798,17 @@ impl Overwrite for NodeAttributes {
             text: new.text.or(old.text),
             class: new.class.or(old.class),
             shape: new.shape.or(old.shape),
+            start: new.start.or(old.start),
+            end: new.end.or(old.end),
+            opacity: new.opacity.or(old.opacity),
+            hidden: new.hidden.or(old.hidden),
+            step: new.step.or(old.step),
+            anchors: new.anchors.or(old.anchors),
+            radius: new.radius.or(old.radius),
+            padding: new.padding.or(old.padding),
+            fit: new.fit.or(old.fit),
+            aspect: new.aspect.or(old.aspect),
+            palette: new.palette.or(old.palette),
         }
     }
 }
@@ -426,13 +819,29 @@ impl Overwrite for ConnectionAttributes {
             text: new.text.or(old.text),
             class: new.class.or(old.class),
             arrowheads: new.arrowheads.or(old.arrowheads),
+            id: new.id.or(old.id),
+            order: new.order.or(old.order),
+            weight: new.weight.or(old.weight),
+            opacity: new.opacity.or(old.opacity),
+            hidden: new.hidden.or(old.hidden),
+            step: new.step.or(old.step),
+            route: new.route.or(old.route),
         }
     }
 }
 
-impl<T> Overwrite for Vec<T> {
-    fn overwrite(_old: Self, new: Self) -> Self {
-        new
+impl<T> Overwrite for ConnectionList<T> {
+    fn overwrite(old: Self, new: Self) -> Self {
+        if new.append {
+            let mut items = old.items;
+            items.extend(new.items);
+            Self {
+                items,
+                append: old.append,
+            }
+        } else {
+            new
+        }
     }
 }
 
@@ -466,14 +875,17 @@ mod tests {
     use std::convert::TryFrom;
 
     use crate::{
-        parse::ast::{Direction, Document},
+        parse::ast::{Direction, Document, NodeShape, Placement},
         pos::pos,
-        test::{assert_eq, id, map, set},
+        test::{assert_eq, coord, id, map, set},
     };
 
     use super::{
-        super::grid::ResolutionError,
-        {Flou, LogicError},
+        super::{
+            grid::ResolutionError,
+            options::{ConvertOptions, Strictness},
+        },
+        {Flou, FlouError, LogicError},
     };
 
     macro_rules! parse_flou {
@@ -499,8 +911,8 @@ mod tests {
         assert_eq!(
             flou.unwrap_err(),
             LogicError::DuplicateLabels(map([
-                (id("foo"), set([pos(0, 0), pos(0, 2)])),
-                (id("bar"), set([pos(0, 1), pos(1, 3)])),
+                (id("foo"), set([coord(0, 0), coord(2, 0)])),
+                (id("bar"), set([coord(1, 0), coord(3, 1)])),
             ]))
         );
     }
@@ -530,7 +942,7 @@ mod tests {
             grid: "foo; bar;",
             define: r#"
                 foo(connect: n:n@s(text: "hi", text: "hello"));
-                bar(connect: {n:n@n(text: "hey", class: "hello"); n:n@e(class: "hi", class: "hello")});
+                bar(connect: {n:n@n(text: "hey", class: "hello"); n:n@e(arrowheads: none, class: "hi", arrowheads: both)});
             "#,
         };
 
@@ -538,7 +950,7 @@ mod tests {
             flou.unwrap_err(),
             LogicError::DuplicateConnectionAttributesInDefinitions(map([
                 (id("foo"), map([(0, set(["text"]))])),
-                (id("bar"), map([(1, set(["class"]))])),
+                (id("bar"), map([(1, set(["arrowheads"]))])),
             ]))
         );
     }
@@ -555,8 +967,8 @@ mod tests {
         assert_eq!(
             flou.unwrap_err(),
             LogicError::DuplicateNodeAttributesInGrid(map([
-                (pos(0, 0), set(["shape"])),
-                (pos(0, 1), set(["connect", "shape"])),
+                (coord(0, 0), set(["shape"])),
+                (coord(1, 0), set(["connect", "shape"])),
             ]))
         );
     }
@@ -566,19 +978,195 @@ mod tests {
         let flou = parse_flou! {
             grid: r#"
                 foo(connect: n:n@s(text: "hi", text: "hello"));
-                _, bar(connect: {n:n@n(text: "hey", class: "hello"); n:n@e(class: "hi", class: "hello")});
+                _, bar(connect: {n:n@n(text: "hey", class: "hello"); n:n@e(arrowheads: none, class: "hi", arrowheads: both)});
             "#,
         };
 
         assert_eq!(
             flou.unwrap_err(),
             LogicError::DuplicateConnectionAttributesInGrid(map([
-                (pos(0, 0), map([(0, set(["text"]))])),
-                (pos(1, 1), map([(1, set(["class"]))])),
+                (coord(0, 0), map([(0, set(["text"]))])),
+                (coord(1, 1), map([(1, set(["arrowheads"]))])),
             ]))
         );
     }
 
+    #[test]
+    fn classes_accumulate() {
+        let flou = parse_flou! {
+            grid: r#"foo(class: "a", class: ["b", "c"], connect: s:n@s(class: "d", class: "e")); bar;"#,
+        }
+        .unwrap();
+
+        assert_eq!(
+            flou.node_attributes[&pos(0, 0)].class.as_deref(),
+            Some("a b c")
+        );
+        assert_eq!(flou.connections[0].attrs.class.as_deref(), Some("d e"));
+    }
+
+    #[test]
+    fn appends_connections_to_definition() {
+        let flou = parse_flou! {
+            grid: r#"
+                a(connect+: e:w@e), b;
+                a(connect: s:n@e, connect+: e:w@e), b;
+            "#,
+            define: "a(connect: n:s@s);",
+        }
+        .unwrap();
+
+        let mut connections = flou
+            .connections
+            .iter()
+            .map(|c| (c.from.0, c.to.0))
+            .collect::<Vec<_>>();
+        connections.sort();
+
+        assert_eq!(
+            connections,
+            vec![
+                (pos(0, 0), pos(1, 0)),
+                (pos(0, 0), pos(0, 1)),
+                (pos(0, 1), pos(1, 1)),
+                (pos(0, 1), pos(1, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_attributes_with_strictness() {
+        let input = r#"
+            grid { foo(text: "a", text: "b", connect: s:n@s(text: "c", text: "d")); bar; }
+            define { bar(shape: rect, shape: diamond); }
+        "#;
+
+        let convert = |strictness| {
+            let document = Document::parse(input).unwrap();
+            let options = ConvertOptions {
+                strictness,
+                ..ConvertOptions::default()
+            };
+            Flou::try_from_with_options(document, &options)
+        };
+
+        assert!(matches!(
+            convert(Strictness::Error).unwrap_err(),
+            LogicError::DuplicateNodeAttributesInDefinitions(_)
+        ));
+
+        let flou = convert(Strictness::FirstWins).unwrap();
+        assert_eq!(flou.node_attributes[&pos(0, 0)].text.as_deref(), Some("a"));
+        assert_eq!(
+            flou.node_attributes[&pos(0, 1)].shape,
+            Some(NodeShape::Rectangle)
+        );
+        assert_eq!(flou.connections[0].attrs.text.as_deref(), Some("c"));
+
+        let flou = convert(Strictness::LastWins).unwrap();
+        assert_eq!(flou.node_attributes[&pos(0, 0)].text.as_deref(), Some("b"));
+        assert_eq!(
+            flou.node_attributes[&pos(0, 1)].shape,
+            Some(NodeShape::Diamond)
+        );
+        assert_eq!(flou.connections[0].attrs.text.as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn connects_to_ids_as_labels() {
+        let input = "grid { a(connect: {s:n#b; s:n#c}), b; c, c#x, d#b; }";
+        let convert = |ids_as_labels| {
+            let document = Document::parse(input).unwrap();
+            let options = ConvertOptions {
+                ids_as_labels,
+                ..ConvertOptions::default()
+            };
+            Flou::try_from_with_options(document, &options)
+        };
+
+        // `#b` is a label, so it wins over the identifier.
+        assert_eq!(
+            convert(true).unwrap_err(),
+            LogicError::InvalidDestination(map([(
+                coord(0, 0),
+                map([(
+                    1,
+                    ResolutionError::UnknownLabel {
+                        label: id("c"),
+                        known: set([id("a"), id("b"), id("d"), id("x")]),
+                        nodes_with_id: vec![coord(1, 0), coord(1, 1)],
+                    }
+                )])
+            )]))
+        );
+
+        assert_eq!(
+            convert(false).unwrap_err(),
+            LogicError::InvalidDestination(map([(
+                coord(0, 0),
+                map([(
+                    1,
+                    ResolutionError::UnknownLabel {
+                        label: id("c"),
+                        known: set([id("b"), id("x")]),
+                        nodes_with_id: vec![coord(1, 0), coord(1, 1)],
+                    }
+                )])
+            )]))
+        );
+
+        let document = Document::parse("grid { a(connect: s:n#d), _; b#d, d; }").unwrap();
+        let options = ConvertOptions {
+            ids_as_labels: true,
+            ..ConvertOptions::default()
+        };
+        let flou = Flou::try_from_with_options(document, &options).unwrap();
+        assert_eq!(flou.connections[0].to.0, pos(0, 1));
+
+        let flou = parse_flou! { grid: "a(connect: s:n#b); b;" };
+        assert!(flou.is_err());
+        let document = Document::parse("grid { a(connect: s:n#b); b; }").unwrap();
+        let flou = Flou::try_from_with_options(document, &options).unwrap();
+        assert_eq!(flou.connections[0].to.0, pos(0, 1));
+    }
+
+    #[test]
+    fn duplicate_connections() {
+        let input = r#"
+            grid { a(connect+: {s:n@s("first"); e:s@s; s:n#b("last")}), c; b#b; }
+            define { a(connect: s:n@s); c(connect: s:n@(1, 1)); }
+        "#;
+
+        let convert = |strictness| {
+            let document = Document::parse(input).unwrap();
+            let options = ConvertOptions {
+                strictness,
+                ..ConvertOptions::default()
+            };
+            Flou::try_from_with_options(document, &options)
+        };
+
+        assert_eq!(
+            convert(Strictness::Error).unwrap_err(),
+            LogicError::DuplicateConnections(map([(coord(0, 0), set([coord(1, 0)]))]))
+        );
+
+        let texts = |flou: Flou| {
+            let mut connections = flou.connections;
+            connections.sort_by_key(|c| c.from.0);
+            connections
+                .into_iter()
+                .map(|c| c.attrs.text)
+                .collect::<Vec<_>>()
+        };
+
+        let first = convert(Strictness::FirstWins).unwrap();
+        assert_eq!(texts(first), vec![None, None, None]);
+
+        let last = convert(Strictness::LastWins).unwrap();
+        assert_eq!(texts(last), vec![Some("last".to_string()), None, None]);
+    }
+
     #[test]
     fn invalid_destination() {
         let flou = parse_flou! {
@@ -594,17 +1182,187 @@ mod tests {
             flou.unwrap_err(),
             LogicError::InvalidDestination(map([
                 (
-                    pos(0, 0),
-                    map([(0, ResolutionError::InvalidDirection(Direction::North))])
+                    coord(0, 0),
+                    map([(
+                        0,
+                        ResolutionError::InvalidDirection {
+                            dir: Direction::North,
+                            empty_cells: 0,
+                            candidates: vec![],
+                        }
+                    )])
                 ),
                 (
-                    pos(0, 2),
+                    coord(2, 0),
                     map([
-                        (0, ResolutionError::UnknownLabel(id("foo"))),
-                        (1, ResolutionError::InvalidDirection(Direction::East)),
+                        (
+                            0,
+                            ResolutionError::UnknownLabel {
+                                label: id("foo"),
+                                known: set([]),
+                                nodes_with_id: vec![],
+                            }
+                        ),
+                        (
+                            1,
+                            ResolutionError::InvalidDirection {
+                                dir: Direction::East,
+                                empty_cells: 0,
+                                candidates: vec![],
+                            }
+                        ),
                     ])
                 )
             ]))
         )
     }
+
+    #[test]
+    fn connects_to_positions() {
+        let flou = parse_flou! {
+            grid: "a(connect: {s:n@(1, 1); s:n@(2, 0)}), _; _;",
+        };
+
+        assert_eq!(
+            flou.unwrap_err(),
+            LogicError::InvalidDestination(map([(
+                coord(0, 0),
+                map([(1, ResolutionError::OutOfBounds(coord(0, 2)))])
+            )]))
+        );
+
+        let flou = parse_flou! { grid: "a(connect: s:n@(1, 1)), _; _;" }.unwrap();
+        assert_eq!(flou.connections[0].to, (pos(1, 1), Direction::North));
+    }
+
+    #[test]
+    fn connects_to_nearest_shared_label() {
+        let flou = parse_flou! {
+            grid: "a(connect: s:n#exit%nearest), b#exit; _, c(connect: w:e#exit%nearest); d#exit;",
+        }
+        .unwrap();
+
+        let mut targets = flou
+            .connections
+            .iter()
+            .map(|connection| (connection.from.0, connection.to.0))
+            .collect::<Vec<_>>();
+        targets.sort_by_key(|&(from, _)| (from.y, from.x));
+        assert_eq!(
+            targets,
+            vec![(pos(0, 0), pos(1, 0)), (pos(1, 1), pos(1, 0))]
+        );
+
+        let flou = parse_flou! {
+            grid: "a(connect: {s:n#exit%nearest; e:w#exit}), b#exit; c#exit, d#other; e#other;",
+        };
+        assert_eq!(
+            flou.unwrap_err(),
+            LogicError::DuplicateLabels(map([
+                (id("exit"), set([coord(0, 1), coord(1, 0)])),
+                (id("other"), set([coord(1, 1), coord(2, 0)])),
+            ]))
+        );
+
+        let flou = parse_flou! { grid: "a(connect: s:n#b%nearest); b#b;" }.unwrap();
+        assert_eq!(flou.connections[0].to, (pos(0, 1), Direction::North));
+    }
+
+    #[test]
+    fn attaches_notes_to_labels() {
+        let flou = Flou::try_from(
+            r#"grid { a#validate, b; } note #validate "Retries" note #validate "Logs" (side: s);"#,
+        )
+        .unwrap();
+
+        let notes = flou
+            .notes
+            .iter()
+            .map(|note| (note.pos, note.side, note.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notes,
+            vec![
+                (pos(0, 0), Direction::East, "Retries"),
+                (pos(0, 0), Direction::South, "Logs"),
+            ]
+        );
+
+        let flou = Flou::try_from(r#"grid { a#a; } note #b "B" note #c "C" note #a "A""#);
+        match flou.unwrap_err() {
+            FlouError::Logic(e) => {
+                assert_eq!(e, LogicError::UnknownNoteLabels(set([id("b"), id("c")])))
+            }
+            e => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn errors_are_ordered_by_position() {
+        let flou = parse_flou! {
+            grid: r#"
+                a(connect: n:s@n), b(connect: n:s@n);
+                c(connect: s:n#x), d(connect: s:n#y);
+            "#,
+        };
+
+        match flou.unwrap_err() {
+            LogicError::InvalidDestination(errors) => assert_eq!(
+                errors.keys().copied().collect::<Vec<_>>(),
+                vec![coord(0, 0), coord(0, 1), coord(1, 0), coord(1, 1)]
+            ),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn compose() {
+        let flou = Flou::compose(
+            vec!["grid { a#start(connect: s:n#end); }", "grid { b#end; }"],
+            Placement::Below { offset: 0 },
+        )
+        .unwrap();
+
+        assert_eq!(flou.grid.size, pos(1, 2));
+        assert_eq!(flou.connections[0].to.0, pos(0, 1));
+
+        let flou = Flou::compose(
+            vec!["grid { a#foo; }", "grid { b#foo; }"],
+            Placement::Right { offset: 0 },
+        );
+
+        match flou.unwrap_err() {
+            FlouError::Logic(e) => assert_eq!(
+                e,
+                LogicError::DuplicateLabels(map([(id("foo"), set([coord(0, 0), coord(0, 1)]))]))
+            ),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+
+        // Each document is checked against its own width, even though
+        // together they are ragged.
+        let flou = Flou::compose(
+            vec!["grid(width: 2) { a, b; }", "grid(width: 1) { c; }"],
+            Placement::Below { offset: 0 },
+        );
+        assert!(flou.is_ok());
+    }
+
+    #[test]
+    fn checks_declared_width() {
+        let flou = Flou::try_from("grid(width: 3) { a, b, c; d, fill _; e; a*4; }");
+        match flou.unwrap_err() {
+            FlouError::Logic(e) => assert_eq!(
+                e,
+                LogicError::RowWidthMismatch {
+                    width: 3,
+                    rows: map([(2, 1), (3, 4)]),
+                }
+            ),
+            e => panic!("Unexpected error: {:?}", e),
+        }
+
+        let flou = Flou::try_from("grid(width: 3) { a, b, _; fill _, c; }").unwrap();
+        assert_eq!(flou.grid.size, pos(3, 2));
+    }
 }
@@ -1,41 +1,354 @@
-use std::collections::HashMap;
+// Some queries are only needed by the renderer.
+#![cfg_attr(not(feature = "render"), allow(dead_code))]
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+    convert::TryFrom,
+    ops::Index,
+};
 
 use crate::{
     parse::ast::{Destination, Direction, Grid as ASTGrid, Identifier},
-    pos::IndexPos,
+    pos::{pos, GridCoord, IndexPos},
 };
 
+/// How many nodes [`ResolutionError::InvalidDirection`] suggests at most.
+const MAX_CANDIDATES: usize = 3;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum ResolutionError<'i> {
-    InvalidDirection(Direction),
-    UnknownLabel(Identifier<'i>),
+    /// There is no node in direction `dir`. The cells between the node
+    /// and the edge of the grid, if any, are all empty. `candidates` are
+    /// the closest nodes in that direction found in other rows or columns,
+    /// nearest first.
+    InvalidDirection {
+        dir: Direction,
+        empty_cells: usize,
+        candidates: Vec<GridCoord>,
+    },
+    /// No node has `label`. `known` are the labels that the destination
+    /// could have referred to instead, and `nodes_with_id` are the nodes
+    /// whose identifier is `label`, in case one of them was meant.
+    UnknownLabel {
+        label: Identifier<'i>,
+        known: BTreeSet<Identifier<'i>>,
+        nodes_with_id: Vec<GridCoord>,
+    },
+    OutOfBounds(GridCoord),
+}
+
+impl<'i> ResolutionError<'i> {
+    /// For an unknown label, the known label closest to it in spelling, if
+    /// any is close enough to likely be what was meant.
+    pub fn closest_label(&self) -> Option<Identifier<'i>> {
+        let (label, known) = match self {
+            Self::UnknownLabel { label, known, .. } => (label, known),
+            _ => return None,
+        };
+
+        let max_distance = (label.0.chars().count() / 3).max(1);
+        known
+            .iter()
+            .map(|&other| (edit_distance(label.0, other.0), other))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, other)| other)
+    }
+}
+
+/// The number of characters that have to be inserted, removed or replaced
+/// to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, x) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(x != y);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Values stored by position, where only occupied cells take up space.
+/// Unlike a `HashMap`, cells can be iterated in row-major order and
+/// searched by direction without visiting empty cells.
+#[derive(Debug, Clone)]
+pub(crate) struct SparseGrid<T> {
+    rows: BTreeMap<isize, BTreeMap<isize, T>>,
+    columns: BTreeMap<isize, BTreeSet<isize>>,
+    len: usize,
+}
+
+impl<T> Default for SparseGrid<T> {
+    fn default() -> Self {
+        Self {
+            rows: BTreeMap::new(),
+            columns: BTreeMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> SparseGrid<T> {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn insert(&mut self, pos: IndexPos, value: T) -> Option<T> {
+        let old = self.rows.entry(pos.y).or_default().insert(pos.x, value);
+        if old.is_none() {
+            self.columns.entry(pos.x).or_default().insert(pos.y);
+            self.len += 1;
+        }
+        old
+    }
+
+    pub(crate) fn remove(&mut self, pos: IndexPos) -> Option<T> {
+        let row = self.rows.get_mut(&pos.y)?;
+        let old = row.remove(&pos.x)?;
+
+        if row.is_empty() {
+            self.rows.remove(&pos.y);
+        }
+
+        let column = self
+            .columns
+            .get_mut(&pos.x)
+            .expect("column index is in sync");
+        column.remove(&pos.y);
+        if column.is_empty() {
+            self.columns.remove(&pos.x);
+        }
+
+        self.len -= 1;
+        Some(old)
+    }
+
+    pub(crate) fn get(&self, pos: IndexPos) -> Option<&T> {
+        self.rows.get(&pos.y)?.get(&pos.x)
+    }
+
+    /// Iterates over the occupied cells in row-major order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (IndexPos, &T)> + '_ {
+        self.rows
+            .iter()
+            .flat_map(|(&y, row)| row.iter().map(move |(&x, value)| (pos(x, y), value)))
+    }
+
+    /// Returns the top-left and bottom-right occupied corners of the
+    /// smallest rectangle containing every occupied cell.
+    pub(crate) fn bounding_box(&self) -> Option<(IndexPos, IndexPos)> {
+        let (&min_y, _) = self.rows.iter().next()?;
+        let (&max_y, _) = self.rows.iter().next_back()?;
+        let (&min_x, _) = self.columns.iter().next()?;
+        let (&max_x, _) = self.columns.iter().next_back()?;
+
+        Some((pos(min_x, min_y), pos(max_x, max_y)))
+    }
+
+    /// Returns the closest occupied cell when moving from `start` in
+    /// direction `dir`, not including `start` itself.
+    pub(crate) fn next_in_direction(&self, start: IndexPos, dir: Direction) -> Option<IndexPos> {
+        match dir {
+            Direction::East => self
+                .rows
+                .get(&start.y)?
+                .range(start.x + 1..)
+                .next()
+                .map(|(&x, _)| pos(x, start.y)),
+            Direction::West => self
+                .rows
+                .get(&start.y)?
+                .range(..start.x)
+                .next_back()
+                .map(|(&x, _)| pos(x, start.y)),
+            Direction::South => self
+                .columns
+                .get(&start.x)?
+                .range(start.y + 1..)
+                .next()
+                .map(|&y| pos(start.x, y)),
+            Direction::North => self
+                .columns
+                .get(&start.x)?
+                .range(..start.y)
+                .next_back()
+                .map(|&y| pos(start.x, y)),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Grid<'i> {
     pub(crate) size: IndexPos,
-    pub(crate) position_to_id: HashMap<IndexPos, Identifier<'i>>,
+    cells: SparseGrid<Identifier<'i>>,
     id_to_positions: HashMap<Identifier<'i>, Vec<IndexPos>>,
 }
 
 impl<'i> Grid<'i> {
+    /// Creates a grid with the given nodes. If more than one node is at the
+    /// same position, the last one replaces the ones before it.
+    pub(crate) fn new(
+        size: IndexPos,
+        nodes: impl IntoIterator<Item = (IndexPos, Identifier<'i>)>,
+    ) -> Self {
+        let mut cells = SparseGrid::new();
+        for (pos, id) in nodes {
+            cells.insert(pos, id);
+        }
+
+        let mut id_to_positions: HashMap<Identifier, Vec<_>> = HashMap::new();
+        for (pos, &id) in cells.iter() {
+            id_to_positions.entry(id).or_default().push(pos);
+        }
+
+        Self {
+            size,
+            cells,
+            id_to_positions,
+        }
+    }
+
+    /// Like [`Grid::new`], but with nodes that haven't been checked for
+    /// overlaps yet. Instead of the last node placed in a cell replacing the
+    /// ones before it, cells with more than one node are returned along
+    /// with the identifiers of those nodes, in the order they were given.
+    pub(crate) fn from_nodes(
+        size: IndexPos,
+        nodes: impl IntoIterator<Item = (IndexPos, Identifier<'i>)>,
+    ) -> Result<Self, BTreeMap<GridCoord, Vec<Identifier<'i>>>> {
+        let mut placed = HashMap::new();
+        let mut overlapping: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+        for (pos, id) in nodes {
+            match placed.entry(pos) {
+                Entry::Vacant(e) => {
+                    e.insert(id);
+                }
+                Entry::Occupied(e) => overlapping
+                    .entry(pos.into())
+                    .or_insert_with(|| vec![*e.get()])
+                    .push(id),
+            }
+        }
+
+        match overlapping.is_empty() {
+            true => Ok(Self::new(size, placed)),
+            false => Err(overlapping),
+        }
+    }
+
+    /// Resolves `to` into the position a connection from `from` leads to.
+    /// `shared_labels` are labels used by more than one node, which can only
+    /// be reached with `#label%nearest`.
     pub(crate) fn normalize_destination(
         &self,
         from: IndexPos,
         to: Destination<'i>,
         labels: &HashMap<Identifier<'i>, IndexPos>,
+        shared_labels: &BTreeMap<Identifier<'i>, BTreeSet<IndexPos>>,
     ) -> Result<IndexPos, ResolutionError<'i>> {
         match to {
-            Destination::Itself => Ok(from),
-            Destination::Relative(dir) => {
-                let step = IndexPos::from(dir);
-                self.walk(from, None, step)
-                    .ok_or(ResolutionError::InvalidDirection(dir))
+            Destination::NearestLabel(label) if shared_labels.contains_key(&label) => {
+                nearest(from, &shared_labels[&label]).ok_or_else(|| {
+                    self.unknown_label(label, labels.keys().chain(shared_labels.keys()))
+                })
             }
+            Destination::Itself => Ok(from),
+            Destination::Relative(dir) => self
+                .walk(from, dir)
+                .ok_or_else(|| self.invalid_direction(from, dir)),
             Destination::Label(label) => labels
                 .get(&label)
                 .copied()
-                .ok_or(ResolutionError::UnknownLabel(label)),
+                .ok_or_else(|| self.unknown_label(label, labels.keys())),
+            Destination::NearestLabel(label) => labels.get(&label).copied().ok_or_else(|| {
+                self.unknown_label(label, labels.keys().chain(shared_labels.keys()))
+            }),
+            Destination::Position(coord) => {
+                let pos = IndexPos::from(coord);
+                match pos.in_bounds(self.size) {
+                    true => Ok(pos),
+                    false => Err(ResolutionError::OutOfBounds(coord)),
+                }
+            }
+        }
+    }
+
+    /// `known` are the labels the destination could use: `#label` can only
+    /// refer to labels of a single node, while `#label%nearest` can also
+    /// refer to ones shared by several nodes.
+    fn unknown_label<'a>(
+        &self,
+        label: Identifier<'i>,
+        known: impl Iterator<Item = &'a Identifier<'i>>,
+    ) -> ResolutionError<'i>
+    where
+        'i: 'a,
+    {
+        let nodes_with_id = self
+            .id_to_positions
+            .get(&label)
+            .map_or(&[][..], Vec::as_slice);
+
+        ResolutionError::UnknownLabel {
+            label,
+            known: known.copied().collect(),
+            nodes_with_id: nodes_with_id.iter().map(|&pos| pos.into()).collect(),
+        }
+    }
+
+    /// Identifiers used by only one node, along with the node's position.
+    pub(crate) fn unique_ids(&self) -> impl Iterator<Item = (Identifier<'i>, IndexPos)> + '_ {
+        self.id_to_positions
+            .iter()
+            .filter_map(|(&id, positions)| match positions.as_slice() {
+                &[pos] => Some((id, pos)),
+                _ => None,
+            })
+    }
+
+    /// Explains why there's no node in direction `dir` from `from`.
+    fn invalid_direction(&self, from: IndexPos, dir: Direction) -> ResolutionError<'i> {
+        let empty_cells = match dir {
+            Direction::North => from.y,
+            Direction::South => self.size.y - from.y - 1,
+            Direction::West => from.x,
+            Direction::East => self.size.x - from.x - 1,
+        };
+
+        // Nodes in the same direction, but one or more rows or columns over.
+        let lines: Vec<_> = match dir {
+            Direction::North | Direction::South => self.cells.columns.keys().copied().collect(),
+            Direction::West | Direction::East => self.cells.rows.keys().copied().collect(),
+        };
+        let mut candidates = lines
+            .into_iter()
+            .filter_map(|line| {
+                let start = match dir {
+                    Direction::North | Direction::South => pos(line, from.y),
+                    Direction::West | Direction::East => pos(from.x, line),
+                };
+                self.walk(start, dir)
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|&pos| ((pos.x - from.x).abs() + (pos.y - from.y).abs(), pos));
+        candidates.truncate(MAX_CANDIDATES);
+
+        ResolutionError::InvalidDirection {
+            dir,
+            empty_cells: usize::try_from(empty_cells).unwrap_or(0),
+            candidates: candidates.into_iter().map(GridCoord::from).collect(),
         }
     }
 
@@ -43,54 +356,323 @@ impl<'i> Grid<'i> {
         self.id_to_positions.get(id)
     }
 
-    pub(crate) fn walk(
-        &self,
-        start: IndexPos,
-        end: Option<IndexPos>,
-        step: IndexPos,
-    ) -> Option<IndexPos> {
-        let mut current = start;
-        loop {
-            current += step;
-
-            if let Some(end) = end {
-                if current == end {
-                    return match self.get_id(current) {
-                        None => None,                   // Out of bounds
-                        Some(Some(_)) => Some(current), // Ran into something
-                        Some(None) => None,             // Empty space
-                    };
-                }
-            }
+    /// Returns the position of the first node found when moving from
+    /// `start` in direction `dir`.
+    pub(crate) fn walk(&self, start: IndexPos, dir: Direction) -> Option<IndexPos> {
+        self.cells.next_in_direction(start, dir)
+    }
+
+    /// Returns the top-left and bottom-right corners of the area
+    /// containing all nodes, or `None` if the grid is empty.
+    pub(crate) fn bounding_box(&self) -> Option<(IndexPos, IndexPos)> {
+        self.cells.bounding_box()
+    }
+
+    /// Number of nodes in the grid.
+    pub(crate) fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Positions of all nodes, in row-major order.
+    pub(crate) fn positions(&self) -> impl Iterator<Item = IndexPos> + '_ {
+        self.cells.iter().map(|(pos, _)| pos)
+    }
+
+    /// All nodes along with their positions, in row-major order.
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = (IndexPos, Identifier<'i>)> + '_ {
+        self.cells.iter().map(|(pos, &id)| (pos, id))
+    }
+
+    /// Identifier of the node at `pos`, if there is one.
+    pub(crate) fn id_at(&self, pos: IndexPos) -> Option<Identifier<'i>> {
+        self.cells.get(pos).copied()
+    }
+
+    /// Places a node at `pos`, growing the grid if needed.
+    pub(crate) fn insert(&mut self, pos: IndexPos, id: Identifier<'i>) {
+        self.remove(pos);
+
+        self.size = self::pos(self.size.x.max(pos.x + 1), self.size.y.max(pos.y + 1));
+        self.cells.insert(pos, id);
 
-            break match self.get_id(current) {
-                None => None,                   // Out of bounds
-                Some(Some(_)) => Some(current), // Ran into something; stop immediately
-                Some(None) => continue,         // Empty space; keep moving
-            };
+        let positions = self.id_to_positions.entry(id).or_default();
+        positions.push(pos);
+        positions.sort_unstable_by(|a, b| a.y.cmp(&b.y).then(a.x.cmp(&b.x)));
+    }
+
+    /// Removes the node at `pos`, returning its identifier.
+    pub(crate) fn remove(&mut self, pos: IndexPos) -> Option<Identifier<'i>> {
+        let id = self.cells.remove(pos)?;
+
+        if let Some(positions) = self.id_to_positions.get_mut(&id) {
+            positions.retain(|&p| p != pos);
+            if positions.is_empty() {
+                self.id_to_positions.remove(&id);
+            }
         }
+
+        Some(id)
     }
 
-    pub(crate) fn get_id(&self, pos: IndexPos) -> Option<Option<&Identifier>> {
-        pos.in_bounds(self.size)
-            .then(|| self.position_to_id.get(&pos))
+    pub(crate) fn get_id(&self, pos: IndexPos) -> Option<Option<&Identifier<'_>>> {
+        pos.in_bounds(self.size).then(|| self.cells.get(pos))
     }
 }
 
-impl<'i> From<&ASTGrid<'i>> for Grid<'i> {
-    fn from(grid: &ASTGrid<'i>) -> Self {
-        let mut position_to_id = HashMap::new();
-        let mut id_to_positions: HashMap<Identifier, Vec<_>> = HashMap::new();
+impl<'i> Index<IndexPos> for Grid<'i> {
+    type Output = Identifier<'i>;
 
-        for (pos, node) in grid.nodes() {
-            position_to_id.insert(pos, node.id);
-            id_to_positions.entry(node.id).or_default().push(pos);
-        }
+    /// Identifier of the node at `pos`. Panics if there is no node there.
+    fn index(&self, pos: IndexPos) -> &Self::Output {
+        self.cells.get(pos).expect("no node at position")
+    }
+}
 
+/// A read-only view of a flowchart's grid, for analysing the flowchart or
+/// rendering it in other ways. Returned by [`Flou::grid`](super::Flou::grid).
+#[derive(Debug, Clone, Copy)]
+pub struct GridView<'a, 'i> {
+    grid: &'a Grid<'i>,
+    labels: &'a HashMap<Identifier<'i>, IndexPos>,
+    shared_labels: &'a BTreeMap<Identifier<'i>, BTreeSet<IndexPos>>,
+}
+
+impl<'a, 'i> GridView<'a, 'i> {
+    pub(crate) fn new(
+        grid: &'a Grid<'i>,
+        labels: &'a HashMap<Identifier<'i>, IndexPos>,
+        shared_labels: &'a BTreeMap<Identifier<'i>, BTreeSet<IndexPos>>,
+    ) -> Self {
         Self {
-            size: grid.size(),
-            position_to_id,
-            id_to_positions,
+            grid,
+            labels,
+            shared_labels,
         }
     }
+
+    pub(crate) fn inner(&self) -> &'a Grid<'i> {
+        self.grid
+    }
+
+    /// Number of rows and columns in the grid.
+    pub fn size(&self) -> GridCoord {
+        self.grid.size.into()
+    }
+
+    /// Identifier of the node at `coord`, or `None` if the cell is empty or
+    /// outside the grid.
+    pub fn id_at(&self, coord: GridCoord) -> Option<&'i str> {
+        self.grid.cells.get(coord.into()).map(|id| id.0)
+    }
+
+    /// Positions of all nodes, in row-major order.
+    pub fn positions(&self) -> impl Iterator<Item = GridCoord> + 'a {
+        self.grid.positions().map(GridCoord::from)
+    }
+
+    /// Positions of the nodes with the identifier `id`, in row-major order.
+    pub fn get_positions(&self, id: &str) -> impl Iterator<Item = GridCoord> + 'a {
+        let positions = self.grid.id_to_positions.get(id);
+        positions.into_iter().flatten().map(|&pos| pos.into())
+    }
+
+    /// Returns the position of the first node found when moving from
+    /// `start` in direction `dir`.
+    pub fn walk(&self, start: GridCoord, dir: Direction) -> Option<GridCoord> {
+        self.grid.walk(start.into(), dir).map(GridCoord::from)
+    }
+
+    /// Resolves `to` into the position that a connection from `from` leads
+    /// to, the same way the flowchart's own connections are resolved.
+    pub fn normalize_destination(
+        &self,
+        from: GridCoord,
+        to: Destination<'i>,
+    ) -> Result<GridCoord, ResolutionError<'i>> {
+        self.grid
+            .normalize_destination(from.into(), to, self.labels, self.shared_labels)
+            .map(GridCoord::from)
+    }
+}
+
+/// The one of `positions` closest to `from`, counting the rows and columns
+/// between them. Ties go to the first in row-major order.
+fn nearest(from: IndexPos, positions: &BTreeSet<IndexPos>) -> Option<IndexPos> {
+    positions
+        .iter()
+        .min_by_key(|pos| (pos.x - from.x).abs() + (pos.y - from.y).abs())
+        .copied()
+}
+
+impl<'i> TryFrom<&ASTGrid<'i>> for Grid<'i> {
+    type Error = BTreeMap<GridCoord, Vec<Identifier<'i>>>;
+
+    fn try_from(grid: &ASTGrid<'i>) -> Result<Self, Self::Error> {
+        let nodes = grid.nodes().map(|(coord, node)| (coord.into(), node.id));
+        Self::from_nodes(grid.size().into(), nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{assert_eq, coord, map, set};
+    use crate::Flou;
+
+    #[test]
+    fn sparse_grid() {
+        let mut grid = SparseGrid::new();
+        grid.insert(pos(5, 0), 'a');
+        grid.insert(pos(0, 3), 'b');
+        grid.insert(pos(5, 1000), 'c');
+        grid.insert(pos(9000, 3), 'd');
+
+        assert_eq!(grid.len(), 4);
+        assert_eq!(grid.get(pos(0, 3)), Some(&'b'));
+        assert_eq!(grid.get(pos(1, 3)), None);
+        assert_eq!(
+            grid.iter().map(|(_, &v)| v).collect::<String>(),
+            "abdc".to_string()
+        );
+        assert_eq!(grid.bounding_box(), Some((pos(0, 0), pos(9000, 1000))));
+
+        assert_eq!(
+            grid.next_in_direction(pos(5, 0), Direction::South),
+            Some(pos(5, 1000))
+        );
+        assert_eq!(
+            grid.next_in_direction(pos(5, 1000), Direction::North),
+            Some(pos(5, 0))
+        );
+        assert_eq!(
+            grid.next_in_direction(pos(0, 3), Direction::East),
+            Some(pos(9000, 3))
+        );
+        assert_eq!(grid.next_in_direction(pos(0, 3), Direction::West), None);
+
+        assert_eq!(grid.remove(pos(5, 1000)), Some('c'));
+        assert_eq!(grid.remove(pos(5, 1000)), None);
+        assert_eq!(grid.next_in_direction(pos(5, 0), Direction::South), None);
+        assert_eq!(grid.bounding_box(), Some((pos(0, 0), pos(9000, 3))));
+    }
+
+    #[test]
+    fn reports_overlapping_nodes() {
+        let (a, b, c) = (Identifier("a"), Identifier("b"), Identifier("c"));
+        let nodes = vec![
+            (pos(0, 0), a),
+            (pos(1, 0), b),
+            (pos(0, 0), c),
+            (pos(1, 0), a),
+            (pos(0, 0), b),
+        ];
+
+        let overlapping = Grid::from_nodes(pos(2, 1), nodes).unwrap_err();
+        assert_eq!(
+            overlapping,
+            map([(coord(0, 0), vec![a, c, b]), (coord(0, 1), vec![b, a])])
+        );
+
+        let grid = Grid::from_nodes(pos(2, 1), vec![(pos(0, 0), a), (pos(1, 0), b)]).unwrap();
+        assert_eq!(grid.len(), 2);
+    }
+
+    #[test]
+    fn grid_view() {
+        let flou =
+            Flou::try_from("grid { a#start(connect: s:n#exit%nearest), _, b; _, b#exit; c#exit; }")
+                .unwrap();
+        let grid = flou.grid();
+
+        assert_eq!(grid.size(), coord(3, 3));
+        assert_eq!(grid.id_at(coord(0, 2)), Some("b"));
+        assert_eq!(grid.id_at(coord(0, 1)), None);
+        assert_eq!(grid.id_at(coord(5, 5)), None);
+        assert_eq!(
+            grid.positions().collect::<Vec<_>>(),
+            vec![coord(0, 0), coord(0, 2), coord(1, 1), coord(2, 0)]
+        );
+        assert_eq!(
+            grid.get_positions("b").collect::<Vec<_>>(),
+            vec![coord(0, 2), coord(1, 1)]
+        );
+        assert_eq!(grid.get_positions("missing").next(), None);
+
+        assert_eq!(grid.walk(coord(0, 0), Direction::East), Some(coord(0, 2)));
+        assert_eq!(grid.walk(coord(0, 0), Direction::North), None);
+
+        let resolve = |to| grid.normalize_destination(coord(0, 0), to);
+        assert_eq!(resolve(Destination::Itself), Ok(coord(0, 0)));
+        assert_eq!(
+            resolve(Destination::Label(Identifier("start"))),
+            Ok(coord(0, 0))
+        );
+        assert_eq!(
+            resolve(Destination::NearestLabel(Identifier("exit"))),
+            Ok(coord(1, 1))
+        );
+        assert_eq!(
+            resolve(Destination::Label(Identifier("exit"))),
+            Err(ResolutionError::UnknownLabel {
+                label: Identifier("exit"),
+                known: set([Identifier("start")]),
+                nodes_with_id: vec![],
+            })
+        );
+        assert_eq!(
+            resolve(Destination::Relative(Direction::West)),
+            Err(ResolutionError::InvalidDirection {
+                dir: Direction::West,
+                empty_cells: 0,
+                candidates: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn explains_invalid_directions() {
+        let flou = Flou::try_from("grid { a, _, _, _; _, _, _, b; c, _, d; _, e; }").unwrap();
+        let grid = flou.grid();
+
+        assert_eq!(
+            grid.normalize_destination(coord(0, 0), Destination::Relative(Direction::East)),
+            Err(ResolutionError::InvalidDirection {
+                dir: Direction::East,
+                empty_cells: 3,
+                candidates: vec![coord(1, 3), coord(2, 2), coord(3, 1)],
+            })
+        );
+        assert_eq!(
+            grid.normalize_destination(coord(2, 2), Destination::Relative(Direction::South)),
+            Err(ResolutionError::InvalidDirection {
+                dir: Direction::South,
+                empty_cells: 1,
+                candidates: vec![coord(3, 1)],
+            })
+        );
+    }
+
+    #[test]
+    fn suggests_close_labels() {
+        assert_eq!(edit_distance("approved", "approved"), 0);
+        assert_eq!(edit_distance("aproved", "approved"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+
+        let flou = Flou::try_from("grid { a#approved, b#rejected, c#start; }").unwrap();
+        let grid = flou.grid();
+        let closest = |label| {
+            grid.normalize_destination(coord(0, 0), Destination::Label(Identifier(label)))
+                .unwrap_err()
+                .closest_label()
+        };
+
+        assert_eq!(closest("aproved"), Some(Identifier("approved")));
+        assert_eq!(closest("rejectd"), Some(Identifier("rejected")));
+        assert_eq!(closest("stop"), None);
+        assert_eq!(
+            ResolutionError::OutOfBounds(coord(5, 5)).closest_label(),
+            None
+        );
+    }
 }
@@ -0,0 +1,362 @@
+use std::{convert::TryFrom, fmt};
+
+use crate::parse::ast::{Document, NodeAttribute};
+#[cfg(feature = "render")]
+use crate::render_svg::SvgRenderer;
+
+#[cfg(feature = "render")]
+use super::config::RenderConfig;
+use super::flou::{Flou, FlouError};
+
+/// Upper bounds on the size of a flowchart, for when it comes from an
+/// untrusted source.
+///
+/// The input size is checked before parsing starts, so an oversized input
+/// is rejected without being looked at. The rest are checked after parsing
+/// and before the grid is laid out, which is where most of the work happens.
+///
+/// The syntax has no recursive constructs, so nesting is bounded by the
+/// grammar itself and doesn't need a limit of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum length of the input in bytes.
+    pub max_input_size: usize,
+
+    /// Maximum number of columns in the grid.
+    pub max_grid_width: usize,
+
+    /// Maximum number of rows in the grid.
+    pub max_grid_height: usize,
+
+    /// Maximum number of attributes in a single attribute list, and of
+    /// connections in a single `connect` attribute.
+    pub max_attributes: usize,
+}
+
+impl Limits {
+    /// Limits that are never exceeded.
+    pub fn unlimited() -> Self {
+        Self {
+            max_input_size: usize::MAX,
+            max_grid_width: usize::MAX,
+            max_grid_height: usize::MAX,
+            max_attributes: usize::MAX,
+        }
+    }
+
+    fn check_document(&self, document: &Document) -> Result<(), Limit> {
+        let size = document.grid.size();
+
+        if size.col as usize > self.max_grid_width {
+            return Err(Limit::GridWidth(size.col as usize));
+        }
+
+        if size.row as usize > self.max_grid_height {
+            return Err(Limit::GridHeight(size.row as usize));
+        }
+
+        let attribute_lists = document
+            .grid
+            .nodes()
+            .map(|(_, node)| &node.attrs)
+            .chain(document.definitions.iter().map(|(_, attrs)| attrs));
+
+        for attrs in attribute_lists {
+            self.check_attributes(attrs)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_attributes(&self, attrs: &[NodeAttribute]) -> Result<(), Limit> {
+        self.check_attribute_count(attrs.len())?;
+
+        for attr in attrs {
+            if let NodeAttribute::Connect(descriptors) | NodeAttribute::ConnectAppend(descriptors) =
+                attr
+            {
+                self.check_attribute_count(descriptors.len())?;
+
+                for descriptor in descriptors {
+                    self.check_attribute_count(descriptor.attrs.len())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_attribute_count(&self, count: usize) -> Result<(), Limit> {
+        if count > self.max_attributes {
+            Err(Limit::Attributes(count))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// These are meant for rendering untrusted input. They are generous enough
+/// for any flowchart a person would write by hand.
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_input_size: 1024 * 1024,
+            max_grid_width: 1000,
+            max_grid_height: 1000,
+            max_attributes: 64,
+        }
+    }
+}
+
+/// Upper bounds on what a flowchart may render into, set in
+/// [`RenderConfig::limits`] and checked with [`RenderConfig::check_limits`].
+///
+/// Unlike [`Limits`], these are checked once the flowchart is built, since
+/// they depend on its nodes and connections, and on the sizes in the
+/// config. The default is never exceeded.
+#[cfg(feature = "render")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderLimits {
+    /// Maximum number of nodes in the grid.
+    pub max_nodes: usize,
+
+    /// Maximum number of connections between nodes.
+    pub max_connections: usize,
+
+    /// Maximum width times height of the rendered flowchart, in pixels.
+    pub max_pixel_area: u64,
+}
+
+#[cfg(feature = "render")]
+impl Default for RenderLimits {
+    fn default() -> Self {
+        Self {
+            max_nodes: usize::MAX,
+            max_connections: usize::MAX,
+            max_pixel_area: u64::MAX,
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl RenderLimits {
+    pub(crate) fn check(&self, flou: &Flou, config: &RenderConfig) -> Result<(), Limit> {
+        let nodes = flou.grid.len();
+        if nodes > self.max_nodes {
+            return Err(Limit::Nodes(nodes));
+        }
+
+        let connections = flou.connections.len();
+        if connections > self.max_connections {
+            return Err(Limit::Connections(connections));
+        }
+
+        let size = SvgRenderer::calculate_svg_size(config, flou.grid.size);
+        let area = (size.x * size.y).ceil() as u64;
+        if area > self.max_pixel_area {
+            return Err(Limit::PixelArea(area));
+        }
+
+        Ok(())
+    }
+}
+
+/// The limit that was exceeded, along with the size that exceeded it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// The input is this many bytes long.
+    InputSize(usize),
+
+    /// The grid has this many columns.
+    GridWidth(usize),
+
+    /// The grid has this many rows.
+    GridHeight(usize),
+
+    /// An attribute list or `connect` attribute has this many entries.
+    Attributes(usize),
+
+    /// The flowchart has this many nodes.
+    Nodes(usize),
+
+    /// The flowchart has this many connections.
+    Connections(usize),
+
+    /// The rendered flowchart would cover this many pixels.
+    PixelArea(u64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InputSize(size) => write!(f, "Input is too large ({} bytes).", size),
+            Self::GridWidth(width) => write!(f, "Grid is too wide ({} columns).", width),
+            Self::GridHeight(height) => write!(f, "Grid is too tall ({} rows).", height),
+            Self::Attributes(count) => {
+                write!(f, "Attribute list is too long ({} entries).", count)
+            }
+            Self::Nodes(count) => write!(f, "Flowchart has too many nodes ({}).", count),
+            Self::Connections(count) => {
+                write!(f, "Flowchart has too many connections ({}).", count)
+            }
+            Self::PixelArea(area) => {
+                write!(f, "Flowchart is too large to render ({} pixels).", area)
+            }
+        }
+    }
+}
+
+impl<'i> Flou<'i> {
+    /// Parses `i` like [`Flou::try_from`], but fails with
+    /// [`FlouError::LimitExceeded`] if the flowchart exceeds any of `limits`.
+    pub fn parse_with_limits(i: &'i str, limits: &Limits) -> Result<Self, FlouError<'i>> {
+        if i.len() > limits.max_input_size {
+            return Err(FlouError::LimitExceeded(Limit::InputSize(i.len())));
+        }
+
+        let document = Document::parse(i).map_err(FlouError::Parse)?;
+        limits
+            .check_document(&document)
+            .map_err(FlouError::LimitExceeded)?;
+
+        Flou::try_from(document).map_err(FlouError::Logic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::assert_eq;
+
+    fn limit(source: &str, limits: &Limits) -> Option<Limit> {
+        match Flou::parse_with_limits(source, limits) {
+            Err(FlouError::LimitExceeded(limit)) => Some(limit),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_input() {
+        let limits = Limits {
+            max_input_size: 16,
+            ..Limits::unlimited()
+        };
+
+        assert_eq!(limit("grid { a; }", &limits), None);
+        assert_eq!(
+            limit("grid { a, b, c; }", &limits),
+            Some(Limit::InputSize(17))
+        );
+    }
+
+    #[test]
+    fn rejects_large_grids() {
+        let limits = Limits {
+            max_grid_width: 2,
+            max_grid_height: 2,
+            ..Limits::unlimited()
+        };
+
+        assert_eq!(limit("grid { a, b; c; }", &limits), None);
+        assert_eq!(
+            limit("grid { a, b, c; }", &limits),
+            Some(Limit::GridWidth(3))
+        );
+        assert_eq!(
+            limit("grid { a; b; c; }", &limits),
+            Some(Limit::GridHeight(3))
+        );
+    }
+
+    #[test]
+    fn rejects_long_attribute_lists() {
+        let limits = Limits {
+            max_attributes: 2,
+            ..Limits::unlimited()
+        };
+
+        assert_eq!(limit(r#"grid { a("A", class: "x"); }"#, &limits), None);
+        assert_eq!(
+            limit(
+                r#"grid { a; } define { a("A", class: "x", shape: circle); }"#,
+                &limits
+            ),
+            Some(Limit::Attributes(3))
+        );
+        assert_eq!(
+            limit("grid { a(connect: {s:n@s; e:w@e; w:e@w}); }", &limits),
+            Some(Limit::Attributes(3))
+        );
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn rejects_flowcharts_that_render_too_large() {
+        let flou = Flou::try_from("grid { a(connect: e:w@e), b; c; }").unwrap();
+        let check = |limits| {
+            let config = RenderConfig {
+                limits,
+                ..RenderConfig::default()
+            };
+            config.check_limits(&flou)
+        };
+
+        assert_eq!(check(RenderLimits::default()), Ok(()));
+        assert_eq!(
+            check(RenderLimits {
+                max_nodes: 2,
+                ..RenderLimits::default()
+            }),
+            Err(Limit::Nodes(3))
+        );
+        assert_eq!(
+            check(RenderLimits {
+                max_connections: 0,
+                ..RenderLimits::default()
+            }),
+            Err(Limit::Connections(1))
+        );
+        assert_eq!(
+            check(RenderLimits {
+                max_pixel_area: 550 * 350 - 1,
+                ..RenderLimits::default()
+            }),
+            Err(Limit::PixelArea(550 * 350))
+        );
+    }
+
+    #[test]
+    fn survives_pathological_input() {
+        let limits = Limits::default();
+
+        let comments = format!("grid {{ a; }} //{}\n", "/".repeat(100_000));
+        assert!(Flou::parse_with_limits(&comments, &limits).is_ok());
+
+        let attributes = format!("grid {{ a({}); }}", r#"class: "x", "#.repeat(100));
+        assert_eq!(limit(&attributes, &limits), Some(Limit::Attributes(100)));
+
+        let unterminated = format!("grid {{ a(\"{}", "\\".repeat(10_000));
+        assert!(Flou::parse_with_limits(&unterminated, &limits).is_err());
+
+        // Repeated cells are counted before they're expanded, so even
+        // unlimited limits don't expand them without bound.
+        for repeated in [
+            "grid { a*4000000000; }",
+            "grid(width: 4000000000) { fill a; }",
+        ] {
+            for limits in [&limits, &Limits::unlimited()] {
+                assert!(matches!(
+                    Flou::parse_with_limits(repeated, limits),
+                    Err(FlouError::Parse(_))
+                ));
+            }
+        }
+        assert_eq!(
+            limit("grid { a*100000; }", &limits),
+            Some(Limit::GridWidth(100_000))
+        );
+
+        let huge = "_, ".repeat(400_000);
+        assert_eq!(limit(&huge, &limits), Some(Limit::InputSize(huge.len())));
+    }
+}
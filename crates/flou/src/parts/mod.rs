@@ -1,10 +1,31 @@
+#[cfg(feature = "render")]
+mod config;
+mod diff;
 mod error;
 mod flou;
 mod grid;
+mod limits;
+mod options;
+#[cfg(feature = "render")]
+mod stats;
+mod steps;
+mod transform;
 
 pub(crate) use self::flou::*;
+#[cfg(feature = "render")]
 pub(crate) use self::grid::*;
 
+#[cfg(feature = "render")]
+pub use self::config::{
+    Background, BackgroundGrid, ConnectionStyle, LegendPosition, RenderConfig, Renderer, Unit,
+};
 pub use self::error::LogicError;
-pub use self::flou::{Flou, FlouError, RenderConfig, Renderer};
-pub use self::grid::ResolutionError;
+pub use self::flou::{Flou, FlouError};
+pub use self::grid::{GridView, ResolutionError};
+#[cfg(feature = "render")]
+pub use self::limits::RenderLimits;
+pub use self::limits::{Limit, Limits};
+pub use self::options::{ConvertOptions, Strictness};
+#[cfg(feature = "render")]
+pub use self::stats::Stats;
+pub use self::transform::TransformError;
@@ -0,0 +1,45 @@
+/// How an attribute that is given more than once in the same attribute
+/// list is handled, and likewise a connection that a node has more than
+/// once, with the same destination and sides.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strictness {
+    /// Duplicates are reported as a [`LogicError`](super::LogicError).
+    #[default]
+    Error,
+
+    /// The first value of the attribute, or the first connection, is used
+    /// and the rest are ignored.
+    FirstWins,
+
+    /// The last value of the attribute, or the last connection, is used,
+    /// like in CSS.
+    LastWins,
+}
+
+impl Strictness {
+    /// Stores `value` in `slot` according to the strictness. Returns `false`
+    /// if `slot` was already set and the duplicate should be reported.
+    pub(crate) fn assign<T>(self, slot: &mut Option<T>, value: T) -> bool {
+        match (slot.is_some(), self) {
+            (false, _) | (true, Self::LastWins) => {
+                *slot = Some(value);
+                true
+            }
+            (true, Self::FirstWins) => true,
+            (true, Self::Error) => false,
+        }
+    }
+}
+
+/// Options for turning a parsed document into a [`Flou`](super::Flou).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConvertOptions {
+    /// How duplicate attributes and connections are handled.
+    pub strictness: Strictness,
+
+    /// Whether `#name` can refer to the node whose identifier is `name`,
+    /// when no node has that label and only one node has that identifier.
+    /// Only affects the flowchart's own connections, not
+    /// [`GridView::normalize_destination`](super::GridView::normalize_destination).
+    pub ids_as_labels: bool,
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    pos::{GridCoord, PixelPos},
+    render_svg::SvgRenderer,
+};
+
+use super::{config::RenderConfig, flou::Flou};
+
+/// Summary of a flowchart's contents and rendered size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Number of nodes in the grid.
+    pub nodes: usize,
+    /// Number of connections between nodes.
+    pub connections: usize,
+    /// Number of rows and columns in the grid.
+    pub grid_size: GridCoord,
+    /// Top-left and bottom-right grid positions of the area containing
+    /// all nodes, or `None` if there are no nodes.
+    pub node_area: Option<(GridCoord, GridCoord)>,
+    /// Width and height of the rendered flowchart, in pixels.
+    pub bounding_box: PixelPos,
+    /// Number of nodes of each shape, keyed by the shape's name in the DSL.
+    pub shapes: BTreeMap<&'static str, usize>,
+}
+
+impl<'i> Flou<'i> {
+    /// Collects statistics about the flowchart, as it would be rendered with `config`.
+    pub fn stats(&self, config: &RenderConfig) -> Stats {
+        let mut shapes = BTreeMap::new();
+
+        for pos in self.grid.positions() {
+            let shape = self
+                .node_attributes
+                .get(&pos)
+                .and_then(|attrs| attrs.shape)
+                .unwrap_or_default();
+
+            *shapes.entry(shape.name()).or_default() += 1;
+        }
+
+        Stats {
+            nodes: self.grid.len(),
+            connections: self.connections.len(),
+            grid_size: self.grid.size.into(),
+            node_area: self
+                .grid
+                .bounding_box()
+                .map(|(min, max)| (min.into(), max.into())),
+            bounding_box: SvgRenderer::calculate_svg_size(config, self.grid.size),
+            shapes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::{
+        pos::pos,
+        test::{assert_eq, coord},
+    };
+
+    #[test]
+    fn collects_stats() {
+        let flou = Flou::try_from(
+            r#"
+grid {
+    a, b("x"), _;
+    a, _, c;
+}
+
+define {
+    a(shape: diamond, connect: e:w@e);
+    c(shape: circle, connect: w:e@w);
+}
+"#,
+        )
+        .unwrap();
+
+        let config = RenderConfig {
+            node: pos(100.0, 50.0),
+            grid_gap: pos(10.0, 10.0),
+            ..Default::default()
+        };
+
+        let stats = flou.stats(&config);
+
+        assert_eq!(stats.nodes, 4);
+        assert_eq!(stats.connections, 3);
+        assert_eq!(stats.grid_size, coord(2, 3));
+        assert_eq!(stats.node_area, Some((coord(0, 0), coord(1, 2))));
+        assert_eq!(stats.bounding_box, pos(340.0, 130.0));
+        assert_eq!(
+            stats.shapes.into_iter().collect::<Vec<_>>(),
+            vec![("circle", 1), ("diamond", 2), ("rect", 1)]
+        );
+    }
+}
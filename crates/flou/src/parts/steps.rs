@@ -0,0 +1,82 @@
+use std::collections::BTreeSet;
+
+use super::flou::Flou;
+
+impl<'i> Flou<'i> {
+    /// The steps that nodes and connections are revealed in, from first
+    /// to last. Nodes and connections without a `step` attribute are part
+    /// of every step, so a flowchart without any has a single step, `0`.
+    pub fn steps(&self) -> Vec<u32> {
+        let node_steps = self.node_attributes.values().filter_map(|attrs| attrs.step);
+        let connection_steps = self.connections.iter().filter_map(|c| c.attrs.step);
+
+        let mut steps = node_steps.chain(connection_steps).collect::<BTreeSet<_>>();
+        if steps.is_empty() {
+            steps.insert(0);
+        }
+
+        steps.into_iter().collect()
+    }
+
+    /// Creates a copy of the flowchart as it looks at `step`, where nodes
+    /// and connections from later steps are hidden. The layout is the same
+    /// for every step.
+    pub fn at_step(&self, step: u32) -> Flou<'i> {
+        let mut flou = self.clone();
+
+        for attrs in flou.node_attributes.values_mut() {
+            if attrs.step > Some(step) {
+                attrs.hidden = Some(true);
+            }
+        }
+
+        for connection in &mut flou.connections {
+            if connection.attrs.step > Some(step) {
+                connection.attrs.hidden = Some(true);
+            }
+        }
+
+        flou
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use crate::{pos::pos, test::assert_eq};
+
+    use super::Flou;
+
+    #[test]
+    fn hides_later_steps() {
+        let flou = Flou::try_from(
+            "grid { a(connect: s:n@s(step: 2)); b(step: 2, connect: s:n@s(step: 3)); c(step: 3); }",
+        )
+        .unwrap();
+
+        assert_eq!(flou.steps(), vec![2, 3]);
+
+        let first = flou.at_step(2);
+        assert_eq!(first.node_attributes[&pos(0, 1)].hidden, None);
+        assert_eq!(first.node_attributes[&pos(0, 2)].hidden, Some(true));
+        assert_eq!(
+            first
+                .connections
+                .iter()
+                .filter(|c| c.attrs.hidden == Some(true))
+                .count(),
+            1
+        );
+
+        let last = flou.at_step(3);
+        assert!(last.node_attributes.values().all(|a| a.hidden.is_none()));
+        assert!(last.connections.iter().all(|c| c.attrs.hidden.is_none()));
+    }
+
+    #[test]
+    fn single_step_without_steps() {
+        let flou = Flou::try_from("grid { a; }").unwrap();
+        assert_eq!(flou.steps(), vec![0]);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{
+    parse::ast::{Direction, Identifier},
+    pos::{GridCoord, IndexPos},
+};
+
+use super::flou::{Connection, ConnectionAttributes, Flou};
+
+/// Error returned when a [`Flou`] can't be modified as requested.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransformError {
+    /// There is no node at the given position.
+    NoNode(GridCoord),
+
+    /// There is already a node at the given position.
+    Occupied(GridCoord),
+
+    /// The position has negative coordinates.
+    OutOfBounds(GridCoord),
+
+    /// There is no connection between the given endpoints.
+    NoConnection(Endpoint, Endpoint),
+
+    /// No node has the given label.
+    UnknownLabel(String),
+}
+
+/// A node's position and the side of the node a connection is attached to.
+type Endpoint = (GridCoord, Direction);
+
+fn index((coord, side): Endpoint) -> (IndexPos, Direction) {
+    (coord.into(), side)
+}
+
+/// Methods for modifying a flowchart after it has been parsed, e.g. to
+/// highlight a node before rendering. Nodes are referred to by their
+/// position in the grid and connections by their endpoints.
+impl<'i> Flou<'i> {
+    fn ensure_node(&self, pos: IndexPos) -> Result<(), TransformError> {
+        if self.grid.id_at(pos).is_some() {
+            Ok(())
+        } else {
+            Err(TransformError::NoNode(pos.into()))
+        }
+    }
+
+    fn find_connection(&mut self, from: Endpoint, to: Endpoint) -> Option<&mut Connection> {
+        let (from, to) = (index(from), index(to));
+        self.connections
+            .iter_mut()
+            .find(|c| c.from == from && c.to == to)
+    }
+
+    /// Adds a node with the given identifier at `coord`, growing the grid if needed.
+    pub fn add_node(&mut self, coord: GridCoord, id: &'i str) -> Result<(), TransformError> {
+        if coord.row < 0 || coord.col < 0 {
+            return Err(TransformError::OutOfBounds(coord));
+        }
+
+        let pos = IndexPos::from(coord);
+        if self.grid.id_at(pos).is_some() {
+            return Err(TransformError::Occupied(coord));
+        }
+
+        self.grid.insert(pos, Identifier(id));
+
+        Ok(())
+    }
+
+    /// Removes the node at `coord` along with all of its connections and notes.
+    pub fn remove_node(&mut self, coord: GridCoord) -> Result<(), TransformError> {
+        let pos = IndexPos::from(coord);
+        self.ensure_node(pos)?;
+
+        self.grid.remove(pos);
+
+        self.node_attributes.remove(&pos);
+        self.labels.retain(|_, &mut label_pos| label_pos != pos);
+        self.shared_labels.retain(|_, positions| {
+            positions.remove(&pos);
+            !positions.is_empty()
+        });
+        self.connections
+            .retain(|c| c.from.0 != pos && c.to.0 != pos);
+        self.notes.retain(|note| note.pos != pos);
+
+        Ok(())
+    }
+
+    /// Returns a copy of the flowchart with extra CSS classes attached to
+    /// some nodes, e.g. to highlight the current step of a process. Each
+    /// pair consists of a node's label and the class to attach to it.
+    pub fn with_classes(&self, classes: &[(&str, &str)]) -> Result<Self, TransformError> {
+        let mut result = self.clone();
+
+        for &(label, class) in classes {
+            let pos = *self
+                .labels
+                .get(&Identifier(label))
+                .ok_or_else(|| TransformError::UnknownLabel(label.to_string()))?;
+
+            result
+                .node_attributes
+                .entry(pos)
+                .or_default()
+                .add_class(class);
+        }
+
+        Ok(result)
+    }
+
+    /// Sets the text of the node at `coord`.
+    pub fn set_node_text<S: Into<String>>(
+        &mut self,
+        coord: GridCoord,
+        text: S,
+    ) -> Result<(), TransformError> {
+        let pos = IndexPos::from(coord);
+        self.ensure_node(pos)?;
+        self.node_attributes.entry(pos).or_default().text = Some(text.into());
+        Ok(())
+    }
+
+    /// Sets the class of the node at `coord`, replacing the one it was declared with.
+    pub fn set_class_at<S: Into<String>>(
+        &mut self,
+        coord: GridCoord,
+        class: S,
+    ) -> Result<(), TransformError> {
+        let pos = IndexPos::from(coord);
+        self.ensure_node(pos)?;
+        self.node_attributes.entry(pos).or_default().class = Some(class.into());
+        Ok(())
+    }
+
+    /// Adds a connection between two nodes. Endpoints consist of the node's
+    /// position and the side of the node the connection is attached to.
+    pub fn add_connection(&mut self, from: Endpoint, to: Endpoint) -> Result<(), TransformError> {
+        self.ensure_node(from.0.into())?;
+        self.ensure_node(to.0.into())?;
+
+        self.connections.push(Connection {
+            from: index(from),
+            to: index(to),
+            attrs: ConnectionAttributes::default(),
+        });
+
+        Ok(())
+    }
+
+    /// Removes the connections between the given endpoints.
+    pub fn remove_connection(
+        &mut self,
+        from: Endpoint,
+        to: Endpoint,
+    ) -> Result<(), TransformError> {
+        let count = self.connections.len();
+        let (index_from, index_to) = (index(from), index(to));
+        self.connections
+            .retain(|c| c.from != index_from || c.to != index_to);
+
+        if self.connections.len() == count {
+            Err(TransformError::NoConnection(from, to))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets the text of the connection between the given endpoints.
+    pub fn set_connection_text<S: Into<String>>(
+        &mut self,
+        from: Endpoint,
+        to: Endpoint,
+        text: S,
+    ) -> Result<(), TransformError> {
+        let connection = self
+            .find_connection(from, to)
+            .ok_or(TransformError::NoConnection(from, to))?;
+
+        connection.attrs.text = Some(text.into());
+        Ok(())
+    }
+
+    /// Sets the class of the connection between the given endpoints.
+    pub fn set_connection_class<S: Into<String>>(
+        &mut self,
+        from: Endpoint,
+        to: Endpoint,
+        class: S,
+    ) -> Result<(), TransformError> {
+        let connection = self
+            .find_connection(from, to)
+            .ok_or(TransformError::NoConnection(from, to))?;
+
+        connection.attrs.class = Some(class.into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::{
+        pos::pos,
+        test::{assert_eq, coord},
+    };
+
+    #[test]
+    fn modifies_nodes() {
+        let mut flou = Flou::try_from("grid { a(connect: s:n@s); b; }").unwrap();
+
+        flou.set_node_text(coord(0, 0), "Start").unwrap();
+        flou.set_class_at(coord(1, 0), "current").unwrap();
+        assert_eq!(
+            flou.set_node_text(coord(1, 1), "Nothing"),
+            Err(TransformError::NoNode(coord(1, 1)))
+        );
+
+        assert_eq!(
+            flou.node_attributes[&pos(0, 0)].text.as_deref(),
+            Some("Start")
+        );
+        assert_eq!(
+            flou.node_attributes[&pos(0, 1)].class.as_deref(),
+            Some("current")
+        );
+
+        flou.add_node(coord(2, 2), "c").unwrap();
+        assert_eq!(flou.grid.size, pos(3, 3));
+        assert_eq!(
+            flou.add_node(coord(2, 2), "d"),
+            Err(TransformError::Occupied(coord(2, 2)))
+        );
+
+        flou.remove_node(coord(1, 0)).unwrap();
+        assert!(flou.connections.is_empty());
+        assert_eq!(flou.grid.get_id(pos(0, 1)), Some(None));
+    }
+
+    #[test]
+    fn attaches_classes_by_label() {
+        let flou = Flou::try_from(
+            r#"grid { a#first(class: "step"), b#second; } define { b(class: "step"); }"#,
+        )
+        .unwrap();
+
+        let highlighted = flou
+            .with_classes(&[("first", "done"), ("second", "current")])
+            .unwrap();
+
+        assert_eq!(
+            highlighted.node_attributes[&pos(0, 0)].class.as_deref(),
+            Some("step done")
+        );
+        assert_eq!(
+            highlighted.node_attributes[&pos(1, 0)].class.as_deref(),
+            Some("step current")
+        );
+        assert_eq!(
+            flou.node_attributes[&pos(0, 0)].class.as_deref(),
+            Some("step")
+        );
+
+        assert_eq!(
+            flou.with_classes(&[("third", "current")]).unwrap_err(),
+            TransformError::UnknownLabel("third".into())
+        );
+    }
+
+    #[test]
+    fn modifies_connections() {
+        let mut flou = Flou::try_from("grid { a, b; }").unwrap();
+        let from = (coord(0, 0), Direction::East);
+        let to = (coord(0, 1), Direction::West);
+
+        flou.add_connection(from, to).unwrap();
+        flou.set_connection_text(from, to, "Yes").unwrap();
+        flou.set_connection_class(from, to, "taken").unwrap();
+
+        let attrs = &flou.connections[0].attrs;
+        assert_eq!(attrs.text.as_deref(), Some("Yes"));
+        assert_eq!(attrs.class.as_deref(), Some("taken"));
+
+        flou.remove_connection(from, to).unwrap();
+        assert_eq!(
+            flou.remove_connection(from, to),
+            Err(TransformError::NoConnection(from, to))
+        );
+    }
+}
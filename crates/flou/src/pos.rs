@@ -1,18 +1,18 @@
 use num_traits::{Num, Signed};
 
-use std::{fmt, marker::PhantomData, ops};
+use std::{cmp::Ordering, fmt, marker::PhantomData, ops};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub struct IndexSpace;
-pub type IndexPos = Position2D<isize, IndexSpace>;
+pub(crate) struct IndexSpace;
+pub(crate) type IndexPos = Position2D<isize, IndexSpace>;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct PixelSpace;
-pub type PixelPos = Position2D<i32, PixelSpace>;
+pub type PixelPos = Position2D<f64, PixelSpace>;
 
 impl_pos_from!(Position2D<usize, IndexSpace>, IndexPos, isize);
 impl_pos_from!(PixelPos, IndexPos, isize);
-impl_pos_from!(IndexPos, PixelPos, i32);
+impl_pos_from!(IndexPos, PixelPos, f64);
 
 #[derive(PartialEq, Eq, std::hash::Hash)]
 pub struct Position2D<T: Num, U> {
@@ -60,6 +60,19 @@ impl<T: Num, U> Position2D<T, U> {
     }
 }
 
+/// Positions are ordered row by row, top to bottom and then left to right.
+impl<T: Num + Ord, U: Eq> Ord for Position2D<T, U> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.y.cmp(&other.y).then_with(|| self.x.cmp(&other.x))
+    }
+}
+
+impl<T: Num + Ord, U: Eq> PartialOrd for Position2D<T, U> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl<T: Num + Signed, U> ops::Neg for Position2D<T, U> {
     type Output = Self;
 
@@ -172,3 +185,100 @@ impl<T: Num, U> From<(T, T)> for Position2D<T, U> {
         Self::new(x, y)
     }
 }
+
+/// A cell of the grid, named by its row and column. Both are counted from
+/// 0, starting at the top-left cell. This is how positions are passed to
+/// and returned from the public API, including the data of errors, so
+/// code using the library doesn't depend on how the renderer lays out its
+/// coordinates. Coordinates are ordered row by row, top to bottom and then
+/// left to right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GridCoord {
+    pub row: isize,
+    pub col: isize,
+}
+
+impl GridCoord {
+    pub fn new(row: isize, col: isize) -> Self {
+        Self { row, col }
+    }
+}
+
+impl From<IndexPos> for GridCoord {
+    fn from(pos: IndexPos) -> Self {
+        Self::new(pos.y, pos.x)
+    }
+}
+
+impl From<GridCoord> for IndexPos {
+    fn from(coord: GridCoord) -> Self {
+        Self::new(coord.col, coord.row)
+    }
+}
+
+impl fmt::Display for GridCoord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(row {}, col {})", self.row, self.col)
+    }
+}
+
+/// A cell of the grid the way it's shown to users, such as in error
+/// messages: columns and rows are counted from 1, and the line of the
+/// source that the cell's row is written on can be given along with them.
+/// [`GridCoord`] is for everything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CellPosition {
+    pub column: isize,
+    pub row: isize,
+    /// The line, counting from 1, that the row starts on.
+    pub line: Option<usize>,
+}
+
+impl CellPosition {
+    pub fn with_line(self, line: Option<usize>) -> Self {
+        Self { line, ..self }
+    }
+}
+
+impl From<GridCoord> for CellPosition {
+    fn from(coord: GridCoord) -> Self {
+        Self {
+            column: coord.col + 1,
+            row: coord.row + 1,
+            line: None,
+        }
+    }
+}
+
+impl fmt::Display for CellPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}, row {}", self.column, self.row)?;
+        match self.line {
+            Some(line) => write!(f, " (line {})", line),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_cell_positions() {
+        let cell = CellPosition::from(GridCoord::new(1, 2));
+        assert_eq!(cell.to_string(), "column 3, row 2");
+        assert_eq!(
+            cell.with_line(Some(5)).to_string(),
+            "column 3, row 2 (line 5)"
+        );
+    }
+
+    #[test]
+    fn converts_grid_coords() {
+        let coord = GridCoord::from(IndexPos::new(2, 1));
+        assert_eq!(coord, GridCoord::new(1, 2));
+        assert_eq!(IndexPos::from(coord), IndexPos::new(2, 1));
+        assert!(GridCoord::new(0, 5) < GridCoord::new(1, 0));
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt::{self, Display};
+
+use crate::{
+    parts::{Flou, RenderConfig, Renderer},
+    render_svg::{Layout, SvgRenderer},
+    svg::{escape, SVGElement},
+};
+
+const TEMPLATE: &str = include_str!("viewer.html");
+const TITLE_PLACEHOLDER: &str = "{{title}}";
+const SVG_PLACEHOLDER: &str = "{{svg}}";
+
+/// Renders a [`Flou`] into a self-contained HTML page that embeds the SVG
+/// and lets the viewer pan, zoom and fit the flowchart to the screen.
+pub struct HtmlRenderer {
+    svg: SvgRenderer,
+    title: String,
+}
+
+impl Default for HtmlRenderer {
+    fn default() -> Self {
+        Self::new(SvgRenderer::default())
+    }
+}
+
+impl HtmlRenderer {
+    /// Creates an HTML renderer which uses `svg` to render the embedded SVG.
+    pub fn new(svg: SvgRenderer) -> Self {
+        Self {
+            svg,
+            title: String::from("Flou"),
+        }
+    }
+
+    /// Sets the page's title.
+    pub fn title<S: Into<String>>(mut self, title: S) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Renders the page along with the layout of the embedded flowchart.
+    /// See [`SvgRenderer::render_with_layout`].
+    pub fn render_with_layout<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+    ) -> (Box<dyn Display + 'i>, Layout<'i>) {
+        let (svg, layout) = self.svg.render_root_with_layout(flou, config);
+        let page = HtmlPage {
+            title: self.title.clone(),
+            svg,
+        };
+
+        (Box::new(page), layout)
+    }
+}
+
+struct HtmlPage<'i> {
+    title: String,
+    svg: SVGElement<'i>,
+}
+
+impl Display for HtmlPage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (head, rest) = TEMPLATE.split_once(SVG_PLACEHOLDER).unwrap();
+        let head = head.replace(TITLE_PLACEHOLDER, &escape::text(self.title.as_str()));
+
+        f.write_str(&head)?;
+        self.svg.fmt(f)?;
+        f.write_str(rest)
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn render<'i>(&self, flou: &'i Flou<'i>, config: &'i RenderConfig) -> Box<dyn Display + 'i> {
+        Box::new(HtmlPage {
+            title: self.title.clone(),
+            svg: self.svg.render_root(flou, config),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::HtmlRenderer;
+    use crate::parts::{Flou, RenderConfig, Renderer};
+
+    #[test]
+    fn embeds_svg_into_page() {
+        let flou = Flou::try_from("grid { a; }").unwrap();
+        let config = RenderConfig {
+            standalone: true,
+            ..Default::default()
+        };
+
+        let output = HtmlRenderer::default()
+            .title("<Title>")
+            .render(&flou, &config)
+            .to_string();
+
+        assert!(output.starts_with("<!DOCTYPE html>"));
+        assert!(output.contains("<title>&lt;Title&gt;</title>"));
+        assert!(output.contains(r#"<svg class="flou" xmlns="http://www.w3.org/2000/svg""#));
+        assert!(!output.contains("<?xml"));
+        assert!(!output.contains("{{"));
+    }
+}
@@ -0,0 +1,529 @@
+use std::fmt::{self, Display};
+
+use crate::{
+    parse::ast::{Direction, NodeShape},
+    parts::{Flou, RenderConfig, Renderer, Stats},
+    pos::{pos, GridCoord, PixelPos},
+    render_svg::{ConnectionVisit, Layout, NodeVisit, PathSegment, RenderVisitor, SvgRenderer},
+    svg::format_number,
+};
+
+/// Renders a [`Flou`] into a flat list of draw commands, as JSON, for
+/// drawing it natively, such as on an HTML canvas or with a UI toolkit,
+/// instead of embedding an SVG.
+///
+/// The output is an object with the `width` and `height` of the flowchart
+/// and its `commands`, in the order they're drawn. Each command has a
+/// `type`, which is one of:
+///
+/// - `rect`, with `x`, `y`, `width`, `height` and the corner `radius`.
+/// - `ellipse`, with its center `cx`, `cy` and its radii `rx`, `ry`.
+/// - `polygon`, with its `points` as `[x, y]` pairs.
+/// - `path`, with its `segments`, each of which is an array of a command
+///   letter and its coordinates: `["M", x, y]`, `["L", x, y]` or
+///   `["C", x1, y1, x2, y2, x, y]`, the same as in SVG paths. Paths are
+///   stroked with `stroke_width` and not filled.
+/// - `text`, with its `lines`, which are centered on `x`, `y` as a block,
+///   and the `font_size` they're laid out with.
+///
+/// Each command also has a `role`, which is `node`, `connection`,
+/// `arrowhead` or `text`, and, if they're set, the `class`, `palette` and
+/// `opacity` of the node or connection it belongs to, for styling it.
+/// Hidden nodes and connections are left out. Coordinates are the same
+/// pixel coordinates as in the SVG.
+pub struct JsonRenderer {
+    svg: SvgRenderer,
+}
+
+impl Default for JsonRenderer {
+    fn default() -> Self {
+        Self::new(SvgRenderer::default())
+    }
+}
+
+impl JsonRenderer {
+    /// Creates a JSON renderer which lays out the flowchart with `svg`.
+    pub fn new(svg: SvgRenderer) -> Self {
+        Self { svg }
+    }
+
+    /// Renders the draw commands along with the layout they were made
+    /// from. See [`SvgRenderer::render_with_layout`].
+    pub fn render_with_layout<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+    ) -> (Box<dyn Display + 'i>, Layout<'i>) {
+        let mut commands = DrawCommands::new(config);
+        let layout = self.svg.visit_with_layout(flou, config, &mut commands);
+        commands.finish_connection();
+
+        (Box::new(commands), layout)
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn render<'i>(&self, flou: &'i Flou<'i>, config: &'i RenderConfig) -> Box<dyn Display + 'i> {
+        self.render_with_layout(flou, config).0
+    }
+}
+
+impl Layout<'_> {
+    /// The layout as JSON, for tools that need to know where nodes and
+    /// connections are without parsing the SVG. Nodes have their position
+    /// in the grid as `x` and `y`, their `id` and `label`, and the `rect`
+    /// their shape takes up. Connections have their `from` and `to`
+    /// endpoints, their `id` and the `points` they go through.
+    pub fn to_json(&self) -> String {
+        let cell = |coord: GridCoord| {
+            JsonObject::new()
+                .number("x", coord.col as f64)
+                .number("y", coord.row as f64)
+        };
+        let endpoint = |(coord, side): (GridCoord, Direction)| {
+            cell(coord)
+                .string("side", &side.to_string().to_lowercase())
+                .finish()
+        };
+
+        let nodes = self.nodes.iter().map(|node| {
+            let rect = JsonObject::new()
+                .number("x", node.origin.x)
+                .number("y", node.origin.y)
+                .number("width", node.size.x)
+                .number("height", node.size.y);
+
+            cell(node.position)
+                .string("id", node.id.as_str())
+                .string_or_null("label", node.label.as_ref().map(|label| label.as_str()))
+                .raw("rect", rect.finish())
+                .finish()
+        });
+
+        let connections = self.connections.iter().map(|connection| {
+            JsonObject::new()
+                .raw("from", endpoint(connection.from))
+                .raw("to", endpoint(connection.to))
+                .string_or_null("id", connection.id)
+                .points(&connection.points)
+                .finish()
+        });
+
+        JsonObject::new()
+            .number("width", self.size.x)
+            .number("height", self.size.y)
+            .raw("nodes", json_array(nodes))
+            .raw("connections", json_array(connections))
+            .finish()
+    }
+}
+
+impl Stats {
+    /// The statistics as JSON. The grid's and the flowchart's sizes are
+    /// given as a `width` and `height`, and so is the `node_area`, along
+    /// with the `x` and `y` of its top-left cell, or `null` if there are no
+    /// nodes. `shapes` maps the name of each shape to its number of nodes.
+    pub fn to_json(&self) -> String {
+        let size = |width: f64, height: f64| {
+            JsonObject::new()
+                .number("width", width)
+                .number("height", height)
+        };
+
+        let node_area = match self.node_area {
+            Some((min, max)) => JsonObject::new()
+                .number("x", min.col as f64)
+                .number("y", min.row as f64)
+                .number("width", (max.col - min.col + 1) as f64)
+                .number("height", (max.row - min.row + 1) as f64)
+                .finish(),
+            None => "null".to_string(),
+        };
+
+        let shapes = self
+            .shapes
+            .iter()
+            .fold(JsonObject::new(), |shapes, (shape, &count)| {
+                shapes.number(shape, count as f64)
+            });
+
+        JsonObject::new()
+            .number("nodes", self.nodes as f64)
+            .number("connections", self.connections as f64)
+            .raw(
+                "grid",
+                size(self.grid_size.col as f64, self.grid_size.row as f64).finish(),
+            )
+            .raw("node_area", node_area)
+            .raw(
+                "bounding_box",
+                size(self.bounding_box.x, self.bounding_box.y).finish(),
+            )
+            .raw("shapes", shapes.finish())
+            .finish()
+    }
+}
+
+/// Collects draw commands from a [`RenderVisitor`]. A connection's
+/// segments are passed after it, so it's only turned into commands once
+/// the next one starts or everything has been visited.
+struct DrawCommands<'i> {
+    font_size: f64,
+    size: PixelPos,
+    commands: Vec<String>,
+    connection: Option<(ConnectionVisit<'i>, Vec<PathSegment>)>,
+}
+
+impl<'i> DrawCommands<'i> {
+    fn new(config: &RenderConfig) -> Self {
+        Self {
+            font_size: config.font_size(),
+            size: pos(0.0, 0.0),
+            commands: Vec::new(),
+            connection: None,
+        }
+    }
+
+    fn text(&self, role: &str, lines: &str, center: PixelPos) -> JsonObject {
+        let lines = lines.lines().map(json_string);
+
+        JsonObject::command("text", role)
+            .number("x", center.x)
+            .number("y", center.y)
+            .raw("lines", json_array(lines))
+            .number("font_size", self.font_size)
+    }
+
+    fn finish_connection(&mut self) {
+        let (connection, segments) = match self.connection.take() {
+            Some((connection, _)) if connection.hidden => return,
+            Some(connection) => connection,
+            None => return,
+        };
+
+        let style = |object: JsonObject| {
+            object
+                .string_opt("class", connection.class)
+                .number_opt("opacity", connection.opacity)
+        };
+
+        let start = segments.first().map(|segment| match *segment {
+            PathSegment::Line { from, .. } | PathSegment::Curve { from, .. } => {
+                format!("[\"M\", {}]", numbers(&[from]))
+            }
+        });
+        let segments = start
+            .into_iter()
+            .chain(segments.iter().map(|segment| match *segment {
+                PathSegment::Line { to, .. } => format!("[\"L\", {}]", numbers(&[to])),
+                PathSegment::Curve { c1, c2, to, .. } => {
+                    format!("[\"C\", {}]", numbers(&[c1, c2, to]))
+                }
+            }));
+
+        let path = JsonObject::command("path", "connection")
+            .raw("segments", json_array(segments))
+            .number("stroke_width", connection.stroke_width)
+            .string_opt("id", connection.layout.id);
+        self.commands.push(style(path).finish());
+
+        for arrowhead in &connection.arrowheads {
+            let polygon = JsonObject::command("polygon", "arrowhead").points(arrowhead);
+            self.commands.push(style(polygon).finish());
+        }
+
+        if let Some((text, origin)) = connection.text {
+            let text = self.text("connection", text, origin);
+            self.commands.push(style(text).finish());
+        }
+    }
+}
+
+impl<'i> RenderVisitor<'i> for DrawCommands<'i> {
+    fn visit_flowchart(&mut self, size: PixelPos) {
+        self.size = size;
+    }
+
+    fn visit_node(&mut self, node: &NodeVisit<'i>) {
+        if node.hidden {
+            return;
+        }
+
+        let style = |object: JsonObject| {
+            object
+                .string_opt("class", node.class)
+                .string_opt("palette", node.palette.map(|palette| palette.name()))
+                .number_opt("opacity", node.opacity)
+        };
+
+        let (origin, size) = (node.layout.origin, node.layout.size);
+        let center = origin + size / 2.0;
+        let shape = match node.shape {
+            NodeShape::Rectangle | NodeShape::Square => JsonObject::command("rect", "node")
+                .number("x", origin.x)
+                .number("y", origin.y)
+                .number("width", size.x)
+                .number("height", size.y)
+                .number("radius", node.corner_radius),
+            NodeShape::Ellipse | NodeShape::Circle => {
+                let radius = match node.shape {
+                    NodeShape::Circle => {
+                        let radius = size.x.min(size.y) / 2.0;
+                        pos(radius, radius)
+                    }
+                    _ => size / 2.0,
+                };
+
+                JsonObject::command("ellipse", "node")
+                    .number("cx", center.x)
+                    .number("cy", center.y)
+                    .number("rx", radius.x)
+                    .number("ry", radius.y)
+            }
+            NodeShape::Diamond | NodeShape::AngledSquare => JsonObject::command("polygon", "node")
+                .points(&[
+                    pos(center.x, origin.y),
+                    pos(origin.x, center.y),
+                    pos(center.x, origin.y + size.y),
+                    pos(origin.x + size.x, center.y),
+                ]),
+            // Spacers aren't drawn, and have no text.
+            NodeShape::None => return,
+        };
+        self.commands.push(style(shape).finish());
+
+        if let Some(text) = &node.text {
+            let text = self.text("text", text, node.text_center);
+            self.commands.push(style(text).finish());
+        }
+    }
+
+    fn visit_connection(&mut self, connection: &ConnectionVisit<'i>) {
+        self.finish_connection();
+        self.connection = Some((connection.clone(), Vec::new()));
+    }
+
+    fn visit_path_segment(&mut self, segment: &PathSegment) {
+        if let Some((_, segments)) = &mut self.connection {
+            segments.push(*segment);
+        }
+    }
+}
+
+impl Display for DrawCommands<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{\"width\": {}, \"height\": {}, \"commands\": [",
+            format_number(self.size.x),
+            format_number(self.size.y)
+        )?;
+
+        for (i, command) in self.commands.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{}\n  {}", separator, command)?;
+        }
+
+        match self.commands.is_empty() {
+            true => f.write_str("]}"),
+            false => f.write_str("\n]}"),
+        }
+    }
+}
+
+/// Builder for a JSON object, which writes its keys in the order they're
+/// added. This is what the JSON output of the library is written with,
+/// for writing more of it in the same format.
+#[derive(Debug, Default)]
+pub struct JsonObject(Vec<String>);
+
+impl JsonObject {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A draw command of the given type and role.
+    fn command(kind: &str, role: &str) -> Self {
+        Self::new().string("type", kind).string("role", role)
+    }
+
+    /// Adds `value`, which has to be valid JSON already, such as an array
+    /// or another object.
+    pub fn raw(mut self, key: &str, value: String) -> Self {
+        self.0.push(format!("{}: {}", json_string(key), value));
+        self
+    }
+
+    pub fn number(self, key: &str, value: f64) -> Self {
+        self.raw(key, format_number(value))
+    }
+
+    fn number_opt(self, key: &str, value: Option<f64>) -> Self {
+        match value {
+            Some(value) => self.number(key, value),
+            None => self,
+        }
+    }
+
+    pub fn string(self, key: &str, value: &str) -> Self {
+        self.raw(key, json_string(value))
+    }
+
+    /// Like [`JsonObject::string`], but writes `null` if there's no value.
+    pub fn string_or_null(self, key: &str, value: Option<&str>) -> Self {
+        match value {
+            Some(value) => self.string(key, value),
+            None => self.raw(key, "null".to_string()),
+        }
+    }
+
+    fn string_opt(self, key: &str, value: Option<&str>) -> Self {
+        match value {
+            Some(value) => self.string(key, value),
+            None => self,
+        }
+    }
+
+    fn points(self, points: &[PixelPos]) -> Self {
+        let points = points
+            .iter()
+            .map(|point| format!("[{}]", numbers(&[*point])));
+        self.raw("points", json_array(points))
+    }
+
+    pub fn finish(self) -> String {
+        format!("{{{}}}", self.0.join(", "))
+    }
+}
+
+/// Writes `items`, which have to be valid JSON already, as a JSON array.
+pub fn json_array(items: impl IntoIterator<Item = String>) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(", "))
+}
+
+/// The coordinates of `points`, separated by commas.
+fn numbers(points: &[PixelPos]) -> String {
+    points
+        .iter()
+        .map(|point| format!("{}, {}", format_number(point.x), format_number(point.y)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Quotes `s` as a JSON string, escaping it as needed.
+pub fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{JsonObject, JsonRenderer};
+    use crate::{
+        parts::{Flou, RenderConfig, Renderer},
+        render_svg::SvgRenderer,
+        test::assert_eq,
+    };
+
+    #[test]
+    fn renders_draw_commands() {
+        let flou = Flou::try_from(
+            r#"grid { a("Say \"hi\"", class: "x", connect: s:n@s("Yes")); b(shape: diamond, palette: blue); c(hidden: true); }"#,
+        )
+        .unwrap();
+        let config = RenderConfig::default();
+        let output = JsonRenderer::default().render(&flou, &config).to_string();
+
+        assert_eq!(
+            output,
+            r#"
+{"width": 300, "height": 500, "commands": [
+  {"type": "rect", "role": "node", "x": 50, "y": 50, "width": 200, "height": 100, "radius": 0, "class": "x"},
+  {"type": "text", "role": "text", "x": 150, "y": 100, "lines": ["Say \"hi\""], "font_size": 16, "class": "x"},
+  {"type": "polygon", "role": "node", "points": [[150, 200], [50, 250], [150, 300], [250, 250]], "palette": "blue"},
+  {"type": "path", "role": "connection", "segments": [["M", 150, 150], ["L", 150, 200]], "stroke_width": 1},
+  {"type": "polygon", "role": "arrowhead", "points": [[150, 200], [155, 190], [150, 195], [145, 190], [150, 200]]},
+  {"type": "text", "role": "connection", "x": 126.56, "y": 175, "lines": ["Yes"], "font_size": 16}
+]}
+            "#
+            .trim()
+        );
+    }
+
+    #[test]
+    fn layout_to_json() {
+        let flou = Flou::try_from(r#"grid { a#start(connect: s:n@s(id: "q\"1")); b; }"#).unwrap();
+        let layout = SvgRenderer::default().layout(&flou, &RenderConfig::default());
+
+        assert_eq!(
+            layout.to_json(),
+            concat!(
+                r#"{"width": 300, "height": 350, "nodes": ["#,
+                r#"{"x": 0, "y": 0, "id": "a", "label": "start", "#,
+                r#""rect": {"x": 50, "y": 50, "width": 200, "height": 100}}, "#,
+                r#"{"x": 0, "y": 1, "id": "b", "label": null, "#,
+                r#""rect": {"x": 50, "y": 200, "width": 200, "height": 100}}], "#,
+                r#""connections": [{"from": {"x": 0, "y": 0, "side": "south"}, "#,
+                r#""to": {"x": 0, "y": 1, "side": "north"}, "id": "q\"1", "#,
+                r#""points": [[150, 150], [150, 200]]}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn escapes_quotes_and_backslashes() {
+        let flou =
+            Flou::try_from(r#"grid { a("C:\\tmp \"x\"", connect: s:n@s(id: "a\\b\"c")); b; }"#)
+                .unwrap();
+        let config = RenderConfig::default();
+
+        let output = JsonRenderer::default().render(&flou, &config).to_string();
+        assert!(output.contains(r#""lines": ["C:\\tmp \"x\""]"#));
+        assert!(output.contains(r#""id": "a\\b\"c""#));
+
+        let layout = SvgRenderer::default().layout(&flou, &config);
+        assert!(layout.to_json().contains(r#""id": "a\\b\"c""#));
+
+        assert_eq!(
+            JsonObject::new()
+                .string("file", "a\\b\"c.png")
+                .number("a\"b", 1.0)
+                .finish(),
+            r#"{"file": "a\\b\"c.png", "a\"b": 1}"#
+        );
+    }
+
+    #[test]
+    fn stats_to_json() {
+        let flou =
+            Flou::try_from("grid { _, a(connect: s:n@s), _; _, b(shape: diamond), c; }").unwrap();
+        let stats = flou.stats(&RenderConfig::default());
+
+        assert_eq!(
+            stats.to_json(),
+            concat!(
+                r#"{"nodes": 3, "connections": 1, "grid": {"width": 3, "height": 2}, "#,
+                r#""node_area": {"x": 1, "y": 0, "width": 2, "height": 2}, "#,
+                r#""bounding_box": {"width": 800, "height": 350}, "#,
+                r#""shapes": {"diamond": 1, "rect": 2}}"#
+            )
+        );
+
+        let flou = Flou::try_from("grid { _; }").unwrap();
+        let stats = flou.stats(&RenderConfig::default());
+        assert!(stats.to_json().contains(r#""node_area": null"#));
+    }
+}
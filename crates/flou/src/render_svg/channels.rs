@@ -0,0 +1,451 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::{
+    parse::ast::Direction,
+    parts::GridView,
+    pos::{pos, GridCoord},
+    render_svg::{
+        hooks::ConnectionContext,
+        path::get_path,
+        renderer::PaddedPos,
+        router::{Lane, PathRouter, Route},
+    },
+};
+
+/// How much a crossing with another connection costs, in the same units
+/// as the length of a route. A route is only allowed to cross another one
+/// if that saves it a detour of more than this.
+const CROSSING_COST: isize = 6;
+
+/// How much each turn of a route costs, so that of two routes with the
+/// same length, the straighter one is used.
+const TURN_COST: isize = 1;
+
+/// A router that routes all connections together, running them through
+/// the grid gaps between nodes like the channels of a circuit board.
+///
+/// Connections are routed one by one, shortest first. Each one takes the
+/// route that is the cheapest given the ones before it, where crossing an
+/// earlier connection counts as much as a detour of a few cells. After
+/// that, connections that run through the same grid gap are spread out
+/// into separate lanes so that they don't overlap. This makes flowcharts
+/// with many connections much easier to follow than with [`CornerRouter`],
+/// which routes each connection on its own.
+///
+/// [`CornerRouter`]: super::CornerRouter
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChannelRouter;
+
+impl PathRouter for ChannelRouter {
+    fn route(
+        &self,
+        grid: &GridView<'_, '_>,
+        from: (GridCoord, Direction),
+        to: (GridCoord, Direction),
+    ) -> Vec<PaddedPos> {
+        cheapest(candidates(grid, from, to), &[])
+    }
+
+    fn route_all(&self, grid: &GridView<'_, '_>, connections: &[ConnectionContext]) -> Vec<Route> {
+        let mut order = (0..connections.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| {
+            let (from, to) = (connections[i].from.0, connections[i].to.0);
+            ((from.row - to.row).abs() + (from.col - to.col).abs(), i)
+        });
+
+        let mut paths = vec![Vec::new(); connections.len()];
+        let mut placed = Vec::new();
+
+        for i in order {
+            let connection = &connections[i];
+            let path = cheapest(candidates(grid, connection.from, connection.to), &placed);
+            placed.extend(segments(&path));
+            paths[i] = path;
+        }
+
+        assign_lanes(paths)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// A straight part of a route. `at` is the row of a horizontal segment or
+/// the column of a vertical one, and `start..=end` is the span it covers.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    axis: Axis,
+    at: isize,
+    start: isize,
+    end: isize,
+}
+
+impl Segment {
+    fn new(a: PaddedPos, b: PaddedPos) -> Self {
+        if a.y == b.y {
+            Self {
+                axis: Axis::Horizontal,
+                at: a.y,
+                start: a.x.min(b.x),
+                end: a.x.max(b.x),
+            }
+        } else {
+            Self {
+                axis: Axis::Vertical,
+                at: a.x,
+                start: a.y.min(b.y),
+                end: a.y.max(b.y),
+            }
+        }
+    }
+
+    fn len(&self) -> isize {
+        self.end - self.start
+    }
+
+    /// Grid gaps are at even coordinates, and only segments running along
+    /// one can be moved into a separate lane.
+    fn in_gap(&self) -> bool {
+        self.at & 1 == 0
+    }
+
+    /// Whether the segments cross, or overlap somewhere they can't be put
+    /// into separate lanes.
+    fn crosses(&self, other: &Self) -> bool {
+        if self.axis == other.axis {
+            return self.at == other.at
+                && !self.in_gap()
+                && self.start.max(other.start) < self.end.min(other.end);
+        }
+
+        let within = |x: isize, segment: &Self| segment.start < x && x < segment.end;
+        within(other.at, self) && within(self.at, other)
+    }
+}
+
+fn segments(path: &[PaddedPos]) -> impl Iterator<Item = Segment> + '_ {
+    path.windows(2).map(|pair| Segment::new(pair[0], pair[1]))
+}
+
+fn cost(path: &[PaddedPos], placed: &[Segment]) -> isize {
+    let length: isize = segments(path).map(|segment| segment.len()).sum();
+    let turns = segments(path)
+        .zip(segments(path).skip(1))
+        .filter(|(a, b)| a.axis != b.axis)
+        .count() as isize;
+    let crossings = segments(path)
+        .map(|segment| placed.iter().filter(|other| segment.crosses(other)).count())
+        .sum::<usize>() as isize;
+
+    length + TURN_COST * turns + CROSSING_COST * crossings
+}
+
+/// The cheapest of `candidates`, preferring earlier ones on a tie.
+fn cheapest(candidates: Vec<Vec<PaddedPos>>, placed: &[Segment]) -> Vec<PaddedPos> {
+    candidates
+        .into_iter()
+        .min_by_key(|path| cost(path, placed))
+        .expect("the corner route is always a candidate")
+}
+
+/// Routes that a connection could take: the one [`CornerRouter`] would
+/// use, and ones that leave the source node into the grid gap next to it
+/// and follow the grid gaps to the destination node.
+///
+/// [`CornerRouter`]: super::CornerRouter
+fn candidates(
+    grid: &GridView<'_, '_>,
+    from: (GridCoord, Direction),
+    to: (GridCoord, Direction),
+) -> Vec<Vec<PaddedPos>> {
+    let mut res = vec![get_path(
+        grid.inner(),
+        (from.0.into(), from.1),
+        (to.0.into(), to.1),
+    )];
+
+    let start = PaddedPos::from(from.0);
+    let end = PaddedPos::from(to.0);
+    let a = start + PaddedPos::from(from.1);
+    let b = end + PaddedPos::from(to.1);
+
+    if start == end || a == b {
+        return res;
+    }
+
+    let max = PaddedPos::from(grid.size()) - 1;
+    let route = |mid: &[PaddedPos]| {
+        let mut path = vec![start, a];
+        path.extend_from_slice(mid);
+        path.extend_from_slice(&[b, end]);
+        simplify(path)
+    };
+
+    // Leaving a node to the north or south ends up in a horizontal gap.
+    let horizontal = |dir| matches!(dir, Direction::North | Direction::South);
+
+    match (horizontal(from.1), horizontal(to.1)) {
+        (true, true) => {
+            if a.y == b.y {
+                res.push(route(&[]));
+            }
+            for x in gaps_around(a.x, b.x, max.x) {
+                res.push(route(&[pos(x, a.y), pos(x, b.y)]));
+            }
+        }
+        (false, false) => {
+            if a.x == b.x {
+                res.push(route(&[]));
+            }
+            for y in gaps_around(a.y, b.y, max.y) {
+                res.push(route(&[pos(a.x, y), pos(b.x, y)]));
+            }
+        }
+        (true, false) => {
+            res.push(route(&[pos(b.x, a.y)]));
+            for x in gaps_around(a.x, b.x, max.x) {
+                for y in gaps_around(a.y, b.y, max.y) {
+                    res.push(route(&[pos(x, a.y), pos(x, y), pos(b.x, y)]));
+                }
+            }
+        }
+        (false, true) => {
+            res.push(route(&[pos(a.x, b.y)]));
+            for y in gaps_around(a.y, b.y, max.y) {
+                for x in gaps_around(a.x, b.x, max.x) {
+                    res.push(route(&[pos(a.x, y), pos(x, y), pos(x, b.y)]));
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Coordinates of the grid gaps between `a` and `b`, and of the ones
+/// right outside of them.
+fn gaps_around(a: isize, b: isize, max: isize) -> impl Iterator<Item = isize> {
+    let first = (a.min(b) - 2).max(0);
+    let first = first + (first & 1);
+    let last = (a.max(b) + 2).min(max);
+
+    (first..=last).step_by(2)
+}
+
+/// Removes repeated points, and points in the middle of a straight line.
+fn simplify(path: Vec<PaddedPos>) -> Vec<PaddedPos> {
+    let mut res: Vec<PaddedPos> = Vec::with_capacity(path.len());
+
+    for point in path {
+        match res[..] {
+            [.., last] if last == point => continue,
+            [.., before, last]
+                if PaddedPos::straight_line(before, last)
+                    == PaddedPos::straight_line(last, point) =>
+            {
+                res.pop();
+            }
+            _ => {}
+        }
+        res.push(point);
+    }
+
+    res
+}
+
+/// A stretch of consecutive segments of a route that run along the same
+/// grid gap.
+struct Run {
+    start: isize,
+    end: isize,
+    path: usize,
+    segments: Range<usize>,
+}
+
+/// Puts segments that run along the same grid gap into separate lanes,
+/// using the left-edge algorithm: going from one end of the gap to the
+/// other, each segment takes the first lane that is free by the point
+/// where it begins.
+fn assign_lanes(paths: Vec<Vec<PaddedPos>>) -> Vec<Route> {
+    let mut channels: BTreeMap<(Axis, isize), Vec<Run>> = BTreeMap::new();
+
+    for (i, path) in paths.iter().enumerate() {
+        let segments = segments(path).collect::<Vec<_>>();
+
+        // The first and last segments lead into the nodes themselves, so
+        // they are left in the middle.
+        let mut s = 1;
+        while s + 1 < segments.len() {
+            let segment = segments[s];
+            let mut e = s + 1;
+            if !segment.in_gap() {
+                s = e;
+                continue;
+            }
+
+            let (mut start, mut end) = (segment.start, segment.end);
+            while e + 1 < segments.len()
+                && (segments[e].axis, segments[e].at) == (segment.axis, segment.at)
+            {
+                start = start.min(segments[e].start);
+                end = end.max(segments[e].end);
+                e += 1;
+            }
+
+            channels
+                .entry((segment.axis, segment.at))
+                .or_default()
+                .push(Run {
+                    start,
+                    end,
+                    path: i,
+                    segments: s..e,
+                });
+            s = e;
+        }
+    }
+
+    let mut routes = paths
+        .into_iter()
+        .map(|points| Route {
+            lanes: vec![Lane::default(); points.len().saturating_sub(1)],
+            points,
+        })
+        .collect::<Vec<_>>();
+
+    for runs in channels.values_mut() {
+        runs.sort_by_key(|run| (run.start, run.end, run.path));
+
+        let mut lane_ends: Vec<isize> = Vec::new();
+        let indices = runs
+            .iter()
+            .map(
+                |run| match lane_ends.iter().position(|&end| end < run.start) {
+                    Some(index) => {
+                        lane_ends[index] = run.end;
+                        index
+                    }
+                    None => {
+                        lane_ends.push(run.end);
+                        lane_ends.len() - 1
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let count = lane_ends.len();
+        for (run, index) in runs.iter().zip(indices) {
+            for lane in &mut routes[run.path].lanes[run.segments.clone()] {
+                *lane = Lane { index, count };
+            }
+        }
+    }
+
+    routes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::{
+        parts::Flou,
+        render_svg::router::{is_valid_route, CornerRouter},
+        test::assert_eq,
+    };
+
+    fn connections(flou: &Flou) -> Vec<ConnectionContext> {
+        let mut connections = flou
+            .connections
+            .iter()
+            .map(|c| {
+                let (from, to) = c.coords();
+                ConnectionContext { from, to }
+            })
+            .collect::<Vec<_>>();
+        connections.sort_by_key(|c| (c.from.0, c.to.0));
+        connections
+    }
+
+    fn route_all(source: &str) -> Vec<Route> {
+        let flou = Flou::try_from(source).unwrap();
+        let connections = connections(&flou);
+
+        let routes = ChannelRouter.route_all(&flou.grid(), &connections);
+        for (route, c) in routes.iter().zip(&connections) {
+            assert!(
+                is_valid_route(&route.points, c.from.0.into(), c.to.0.into()),
+                "{:?}",
+                route
+            );
+            assert_eq!(route.lanes.len(), route.points.len() - 1);
+        }
+        routes
+    }
+
+    fn crossings(routes: &[Route]) -> usize {
+        let segments = routes
+            .iter()
+            .map(|route| segments(&route.points).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut res = 0;
+        for (i, a) in segments.iter().enumerate() {
+            for b in &segments[i + 1..] {
+                res += a
+                    .iter()
+                    .flat_map(|a| b.iter().filter(move |b| a.crosses(b)))
+                    .count();
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn routes_around_crossings() {
+        // Going straight from `a` to `f` would cross the connection from
+        // `b` to `e`, so it goes around `e` instead.
+        let source = "grid { a(connect: s:w#f), b(connect: s:n#e), c; d, e#e, f#f; }";
+        let flou = Flou::try_from(source).unwrap();
+
+        assert_eq!(
+            crossings(&CornerRouter.route_all(&flou.grid(), &connections(&flou))),
+            1
+        );
+        assert_eq!(crossings(&route_all(source)), 0);
+    }
+
+    #[test]
+    fn puts_shared_gaps_into_lanes() {
+        let routes = route_all("grid { a(connect: {e:e@s; w:w@s}), x; b, y(connect: w:w@n); }");
+
+        let lanes = routes
+            .iter()
+            .flat_map(|route| route.lanes.iter().filter(|lane| lane.count > 1))
+            .map(|lane| lane.index)
+            .collect::<Vec<_>>();
+        assert_eq!(lanes.len(), 2);
+        assert!(lanes.contains(&0) && lanes.contains(&1));
+    }
+
+    #[test]
+    fn simplifies_routes() {
+        assert_eq!(
+            simplify(vec![
+                pos(1, 1),
+                pos(1, 2),
+                pos(1, 2),
+                pos(3, 2),
+                pos(5, 2),
+                pos(5, 3)
+            ]),
+            vec![pos(1, 1), pos(1, 2), pos(5, 2), pos(5, 3)]
+        );
+        assert_eq!(gaps_around(3, 7, 8).collect::<Vec<_>>(), vec![2, 4, 6, 8]);
+        assert_eq!(gaps_around(1, 1, 8).collect::<Vec<_>>(), vec![0, 2]);
+    }
+}
@@ -0,0 +1,63 @@
+use base64::Engine;
+
+use crate::svg::escape;
+
+/// A font file to embed into the SVG's `<style>` block, so that the
+/// flowchart looks the same on machines that don't have it installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedFont {
+    /// Name the font can be referred to by in `font-family`.
+    pub family: String,
+    /// Contents of a TTF, OTF, WOFF or WOFF2 file.
+    pub data: Vec<u8>,
+}
+
+impl EmbeddedFont {
+    pub fn new<S: Into<String>>(family: S, data: Vec<u8>) -> Self {
+        Self {
+            family: family.into(),
+            data,
+        }
+    }
+
+    /// Returns the MIME type and CSS `format()` hint based on the
+    /// file's magic number.
+    fn format(&self) -> (&'static str, &'static str) {
+        match self.data.get(..4) {
+            Some(b"wOF2") => ("font/woff2", "woff2"),
+            Some(b"wOFF") => ("font/woff", "woff"),
+            Some(b"OTTO") => ("font/otf", "opentype"),
+            _ => ("font/ttf", "truetype"),
+        }
+    }
+
+    pub(crate) fn to_css(&self) -> String {
+        let (mime, format) = self.format();
+        let data = base64::engine::general_purpose::STANDARD.encode(&self.data);
+        let family = escape::css_string(&self.family);
+
+        format!(
+            "@font-face {{\n  font-family: \"{}\";\n  src: url(data:{};base64,{}) format(\"{}\");\n}}",
+            family, mime, data, format
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmbeddedFont;
+    use crate::test::assert_eq;
+
+    #[test]
+    fn generates_font_face() {
+        let font = EmbeddedFont::new("My \"Font\"", b"wOF2abc".to_vec());
+
+        assert_eq!(
+            font.to_css(),
+            r#"@font-face {
+  font-family: "My \"Font\"";
+  src: url(data:font/woff2;base64,d09GMmFiYw==) format("woff2");
+}"#
+        );
+    }
+}
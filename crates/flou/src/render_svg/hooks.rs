@@ -0,0 +1,93 @@
+use crate::{
+    parse::ast::{Direction, Identifier},
+    pos::{GridCoord, PixelPos},
+    svg::SVGElement,
+};
+
+/// Information about the node being rendered, passed to node hooks.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeContext<'i> {
+    /// Position of the node inside the grid.
+    pub position: GridCoord,
+    /// Identifier the node was declared with.
+    pub id: Identifier<'i>,
+    /// Top-left corner of the area the node is drawn in.
+    pub origin: PixelPos,
+    /// Size of the area the node is drawn in.
+    pub size: PixelPos,
+}
+
+/// Information about the connection being rendered, passed to connection hooks.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionContext {
+    /// Grid position and side of the source node.
+    pub from: (GridCoord, Direction),
+    /// Grid position and side of the destination node.
+    pub to: (GridCoord, Direction),
+}
+
+/// Bound on hooks. With the `parallel` feature, nodes and connections are
+/// rendered on multiple threads, so hooks have to be `Send + Sync`; without
+/// it, any hook is accepted, including ones that capture `Rc` or `RefCell`.
+#[cfg(feature = "parallel")]
+pub trait MaybeSync: Send + Sync {}
+
+#[cfg(feature = "parallel")]
+impl<T: Send + Sync + ?Sized> MaybeSync for T {}
+
+/// Bound on hooks. With the `parallel` feature, nodes and connections are
+/// rendered on multiple threads, so hooks have to be `Send + Sync`; without
+/// it, any hook is accepted, including ones that capture `Rc` or `RefCell`.
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSync {}
+
+#[cfg(not(feature = "parallel"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+#[cfg(feature = "parallel")]
+pub(crate) type NodeHook =
+    Box<dyn for<'i> Fn(&NodeContext<'i>, SVGElement<'i>) -> SVGElement<'i> + Send + Sync>;
+#[cfg(feature = "parallel")]
+pub(crate) type ConnectionHook =
+    Box<dyn for<'i> Fn(&ConnectionContext, SVGElement<'i>) -> SVGElement<'i> + Send + Sync>;
+#[cfg(feature = "parallel")]
+pub(crate) type RootHook = Box<dyn for<'i> Fn(SVGElement<'i>) -> SVGElement<'i> + Send + Sync>;
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) type NodeHook = Box<dyn for<'i> Fn(&NodeContext<'i>, SVGElement<'i>) -> SVGElement<'i>>;
+#[cfg(not(feature = "parallel"))]
+pub(crate) type ConnectionHook =
+    Box<dyn for<'i> Fn(&ConnectionContext, SVGElement<'i>) -> SVGElement<'i>>;
+#[cfg(not(feature = "parallel"))]
+pub(crate) type RootHook = Box<dyn for<'i> Fn(SVGElement<'i>) -> SVGElement<'i>>;
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub(crate) node: Vec<NodeHook>,
+    pub(crate) connection: Vec<ConnectionHook>,
+    pub(crate) root: Vec<RootHook>,
+}
+
+impl Hooks {
+    pub(crate) fn apply_node<'i>(
+        &self,
+        ctx: &NodeContext<'i>,
+        element: SVGElement<'i>,
+    ) -> SVGElement<'i> {
+        self.node.iter().fold(element, |el, hook| hook(ctx, el))
+    }
+
+    pub(crate) fn apply_connection<'i>(
+        &self,
+        ctx: &ConnectionContext,
+        element: SVGElement<'i>,
+    ) -> SVGElement<'i> {
+        self.connection
+            .iter()
+            .fold(element, |el, hook| hook(ctx, el))
+    }
+
+    pub(crate) fn apply_root<'i>(&self, element: SVGElement<'i>) -> SVGElement<'i> {
+        self.root.iter().fold(element, |el, hook| hook(el))
+    }
+}
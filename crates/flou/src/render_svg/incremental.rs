@@ -0,0 +1,215 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    parse::ast::{Anchors, Aspect, Direction, Fit, NodeShape},
+    parts::{ConnectionAttributes, Flou, FlouError, NodeAttributes, RenderConfig},
+    pos::IndexPos,
+    svg::{SVGDocument, SVGElement},
+};
+
+use super::{router::Route, SvgRenderer};
+
+#[derive(PartialEq, Eq, Hash)]
+struct NodeKey {
+    pos: IndexPos,
+    id: String,
+    attrs: Option<NodeAttributes>,
+}
+
+/// The node attributes that decide where connections attach to a node.
+#[derive(PartialEq, Eq, Hash)]
+struct Outline {
+    shape: Option<NodeShape>,
+    anchors: Option<Anchors>,
+    fit: Option<Fit>,
+    aspect: Option<Aspect>,
+}
+
+impl From<&NodeAttributes> for Outline {
+    fn from(attrs: &NodeAttributes) -> Self {
+        Self {
+            shape: attrs.shape,
+            anchors: attrs.anchors,
+            fit: attrs.fit,
+            aspect: attrs.aspect,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct ConnectionKey {
+    from: (IndexPos, Direction),
+    to: (IndexPos, Direction),
+    attrs: ConnectionAttributes,
+    // Connections attach to the outline of their nodes' shapes.
+    outlines: (Option<Outline>, Option<Outline>),
+    // Connections are routed around nodes and possibly each other, so
+    // the route is compared as well as the layout of the grid, which
+    // decides where the route meets empty cells.
+    route: Route,
+    grid: u64,
+}
+
+/// Renders successive versions of the same flowchart, such as while it is
+/// being edited, reusing the work done for the previous version.
+///
+/// The source is parsed in full every time, but nodes and connections that
+/// are unaffected by a change aren't laid out and rendered again. Changing
+/// the text of a node only re-renders that node, while adding or removing
+/// nodes re-renders all connections since they may need to be rerouted.
+pub struct IncrementalRenderer {
+    renderer: SvgRenderer,
+    config: RenderConfig,
+    source: Option<String>,
+    output: String,
+    nodes: HashMap<NodeKey, SVGElement<'static>>,
+    connections: HashMap<ConnectionKey, SVGElement<'static>>,
+}
+
+impl IncrementalRenderer {
+    pub fn new(renderer: SvgRenderer, config: RenderConfig) -> Self {
+        Self {
+            renderer,
+            config,
+            source: None,
+            output: String::new(),
+            nodes: HashMap::new(),
+            connections: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &RenderConfig {
+        &self.config
+    }
+
+    /// Replaces the configuration, which invalidates everything rendered so far.
+    pub fn set_config(&mut self, config: RenderConfig) {
+        self.config = config;
+        self.source = None;
+        self.nodes.clear();
+        self.connections.clear();
+    }
+
+    /// Renders `source` into an SVG document.
+    pub fn render<'s>(&mut self, source: &'s str) -> Result<&str, FlouError<'s>> {
+        if self.source.as_deref() == Some(source) {
+            return Ok(&self.output);
+        }
+
+        let flou = Flou::try_from(source)?;
+        let renderer = &self.renderer;
+        let config = &self.config;
+        let mut cached_nodes = std::mem::take(&mut self.nodes);
+        let mut cached_connections = std::mem::take(&mut self.connections);
+
+        let mut nodes = HashMap::new();
+        let node_elements = SvgRenderer::node_positions(&flou)
+            .into_iter()
+            .map(|pos| {
+                let key = NodeKey {
+                    pos,
+                    id: flou.grid[pos].to_string(),
+                    attrs: flou.node_attributes.get(&pos).cloned(),
+                };
+
+                let element = cached_nodes
+                    .remove(&key)
+                    .unwrap_or_else(|| renderer.render_node(config, &flou, pos).into_owned());
+
+                nodes.insert(key, element.clone());
+                element
+            })
+            .collect();
+
+        let grid = grid_fingerprint(&flou);
+        let outline = |pos| flou.node_attributes.get(&pos).map(Outline::from);
+
+        let sorted_connections = SvgRenderer::sorted_connections(&flou);
+        let routes = renderer.routes(&flou, &sorted_connections);
+
+        let mut connections = HashMap::new();
+        let connection_elements = sorted_connections
+            .into_iter()
+            .zip(routes)
+            .map(|(connection, route)| {
+                let key = ConnectionKey {
+                    from: connection.from,
+                    to: connection.to,
+                    attrs: connection.attrs.clone(),
+                    outlines: (outline(connection.from.0), outline(connection.to.0)),
+                    route,
+                    grid,
+                };
+
+                let element = cached_connections.remove(&key).unwrap_or_else(|| {
+                    renderer
+                        .render_hooked_connection(config, &flou, connection, &key.route)
+                        .into_owned()
+                });
+
+                connections.insert(key, element.clone());
+                element
+            })
+            .collect();
+
+        let root =
+            renderer.render_root_with(&flou, config, node_elements, connection_elements, None);
+        let output = SVGDocument::new(root)
+            .standalone(config.standalone)
+            .to_string();
+
+        // Only keep what was used for this version, so that the caches don't
+        // keep growing as the flowchart is edited.
+        self.nodes = nodes;
+        self.connections = connections;
+        self.source = Some(source.to_string());
+        self.output = output;
+
+        Ok(&self.output)
+    }
+}
+
+fn grid_fingerprint(flou: &Flou) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    flou.grid.size.hash(&mut hasher);
+    SvgRenderer::node_positions(flou).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::{parts::Renderer, test::assert_eq};
+
+    fn render(source: &str) -> String {
+        let flou = Flou::try_from(source).unwrap();
+        let config = RenderConfig::default();
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+        output
+    }
+
+    #[test]
+    fn matches_full_render() {
+        let mut renderer = IncrementalRenderer::new(SvgRenderer::new(), RenderConfig::default());
+
+        let versions = [
+            r#"grid { a("Start", connect: s:n@s); b("End"); }"#,
+            r#"grid { a("Begin", connect: s:n@s); b("End"); }"#,
+            r#"grid { a("Begin", connect: s:n@s); c; b("End"); }"#,
+            r#"grid { a("Begin", connect: s:n@s); c; b("End"); }"#,
+        ];
+
+        for source in &versions {
+            assert_eq!(renderer.render(source).unwrap(), render(source));
+        }
+
+        assert!(renderer.render("grid { a(connect: n:n@n); }").is_err());
+        assert_eq!(renderer.render(versions[0]).unwrap(), render(versions[0]));
+    }
+}
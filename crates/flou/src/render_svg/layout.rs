@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use crate::{
+    parse::ast::{Direction, Identifier},
+    pos::{pos, GridCoord, IndexPos, PixelPos},
+};
+
+/// Where everything in a rendered flowchart ended up, in the same pixel
+/// coordinates as the SVG. Useful for tools that need to know where nodes
+/// and connections are without parsing the SVG.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout<'i> {
+    /// Width and height of the flowchart.
+    pub size: PixelPos,
+    /// Every node, in the order they are rendered.
+    pub nodes: Vec<NodeLayout<'i>>,
+    /// Every connection, in the order they are rendered.
+    pub connections: Vec<ConnectionLayout<'i>>,
+}
+
+/// The position of a node in a [`Layout`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeLayout<'i> {
+    /// Position of the node inside the grid.
+    pub position: GridCoord,
+    /// Identifier the node was declared with.
+    pub id: Identifier<'i>,
+    /// Label of the node, if it has one.
+    pub label: Option<Identifier<'i>>,
+    /// Top-left corner of the node's shape.
+    pub origin: PixelPos,
+    /// Size of the node's shape, which can be smaller than the grid cell.
+    pub size: PixelPos,
+}
+
+/// The path of a connection in a [`Layout`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionLayout<'i> {
+    /// Grid position and side of the source node.
+    pub from: (GridCoord, Direction),
+    /// Grid position and side of the destination node.
+    pub to: (GridCoord, Direction),
+    /// The connection's `id` attribute, if it has one.
+    pub id: Option<&'i str>,
+    /// The points the connection goes through, from its source to its
+    /// destination. Curved connections are drawn as a smooth curve through
+    /// these points.
+    pub points: Vec<PixelPos>,
+}
+
+/// A rectangle in the pixel coordinates of the SVG.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    /// Top-left corner of the rectangle.
+    pub origin: PixelPos,
+    /// Width and height of the rectangle.
+    pub size: PixelPos,
+}
+
+impl Rect {
+    pub fn new(origin: PixelPos, size: PixelPos) -> Self {
+        Self { origin, size }
+    }
+
+    /// The smallest rectangle containing all of `points`, or [`None`] if
+    /// there aren't any.
+    pub fn bounding(points: &[PixelPos]) -> Option<Self> {
+        let (first, rest) = points.split_first()?;
+        let (min, max) = rest.iter().fold((*first, *first), |(min, max), point| {
+            (
+                pos(min.x.min(point.x), min.y.min(point.y)),
+                pos(max.x.max(point.x), max.y.max(point.y)),
+            )
+        });
+
+        Some(Self::new(min, max - min))
+    }
+
+    /// Whether the two rectangles overlap. Rectangles that only touch
+    /// count as overlapping, so that a straight line, whose bounding
+    /// rectangle has no width or height, can still overlap others.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        let (a_end, b_end) = (self.origin + self.size, other.origin + other.size);
+
+        self.origin.x <= b_end.x
+            && other.origin.x <= a_end.x
+            && self.origin.y <= b_end.y
+            && other.origin.y <= a_end.y
+    }
+}
+
+/// Maps each labeled node's position to its label.
+pub(crate) fn labels_by_position<'i>(
+    labels: &HashMap<Identifier<'i>, IndexPos>,
+    shared_labels: impl IntoIterator<Item = (Identifier<'i>, IndexPos)>,
+) -> HashMap<IndexPos, Identifier<'i>> {
+    labels
+        .iter()
+        .map(|(&label, &pos)| (pos, label))
+        .chain(shared_labels.into_iter().map(|(label, pos)| (pos, label)))
+        .collect()
+}
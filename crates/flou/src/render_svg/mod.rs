@@ -1,7 +1,21 @@
+mod channels;
+mod fonts;
+mod hooks;
+mod incremental;
+mod layout;
 mod node;
 mod path;
 mod renderer;
+mod router;
 mod viewport;
+mod visitor;
 
-pub use renderer::SvgRenderer;
+pub use channels::ChannelRouter;
+pub use fonts::EmbeddedFont;
+pub use hooks::{ConnectionContext, MaybeSync, NodeContext};
+pub use incremental::IncrementalRenderer;
+pub use layout::{ConnectionLayout, Layout, NodeLayout, Rect};
+pub use renderer::{render_svg, PaddedPos, PaddedSpace, SvgRenderer};
+pub use router::{CornerRouter, Lane, PathRouter, Route};
 pub(crate) use viewport::Viewport;
+pub use visitor::{ConnectionVisit, NodeVisit, PathSegment, RenderVisitor};
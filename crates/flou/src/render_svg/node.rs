@@ -1,35 +1,30 @@
+use std::borrow::Cow;
+
 use crate::{
-    parse::ast::{Direction, NodeShape},
-    parts::NodeAttributes,
+    parse::ast::{Direction, Fit, NodeShape},
+    parts::{NodeAttributes, RenderConfig},
     pos::{pos, PixelPos},
     svg::{SVGElement, SVGPath, SVGText},
+    text_metrics::wrap,
 };
 
-use super::viewport::{Midpoints, Viewport};
-
-impl Default for NodeShape {
-    fn default() -> Self {
-        Self::Rectangle
-    }
-}
+use super::{renderer::with_visibility, viewport::Viewport};
 
 impl NodeShape {
-    pub(crate) fn render(&self, viewport: Viewport) -> SVGElement<'static> {
-        match &self {
+    /// Renders the shape filling `viewport`, or nothing for
+    /// [`NodeShape::None`]. Rectangles and squares get rounded corners with
+    /// the given radius.
+    pub(crate) fn render(&self, viewport: Viewport, radius: f64) -> Option<SVGElement<'static>> {
+        let element = match &self {
             Self::Rectangle => SVGElement::new("rect")
                 .class("rect")
                 .pos(viewport.origin)
                 .size(viewport.size),
 
-            Self::Square => {
-                let size = std::cmp::min(viewport.size.x, viewport.size.y);
-                let origin = viewport.origin + (viewport.size - size) / 2;
-
-                SVGElement::new("rect")
-                    .class("square")
-                    .pos(origin)
-                    .size(size.into())
-            }
+            Self::Square => SVGElement::new("rect")
+                .class("square")
+                .pos(viewport.origin)
+                .size(viewport.size),
 
             Self::Diamond => {
                 let midpoints = viewport.midpoints();
@@ -45,9 +40,6 @@ impl NodeShape {
             }
 
             Self::AngledSquare => {
-                let size = std::cmp::min(viewport.size.x, viewport.size.y);
-                let origin = viewport.origin + (viewport.size - size) / 2;
-                let viewport = Viewport::new(origin, size.into());
                 let midpoints = viewport.midpoints();
 
                 SVGPath::new()
@@ -61,24 +53,55 @@ impl NodeShape {
             }
 
             Self::Ellipse => {
-                let size = viewport.size / 2;
+                let size = viewport.size / 2.0;
 
                 SVGElement::new("ellipse")
                     .class("ellipse")
                     .cpos(viewport.center())
-                    .attr("rx", size.x.to_string())
-                    .attr("ry", size.y.to_string())
+                    .attr_len("rx", size.x)
+                    .attr_len("ry", size.y)
             }
 
             Self::Circle => {
-                let diameter = std::cmp::min(viewport.size.x, viewport.size.y);
-                let radius = diameter / 2;
+                let radius = viewport.size.x.min(viewport.size.y) / 2.0;
 
                 SVGElement::new("circle")
                     .class("circle")
                     .cpos(viewport.center())
-                    .attr("r", radius.to_string())
+                    .attr_len("r", radius)
+            }
+
+            Self::None => return None,
+        };
+
+        let element = match self {
+            Self::Rectangle | Self::Square if radius > 0.0 => {
+                element.attr_len("rx", radius).attr_len("ry", radius)
             }
+            _ => element,
+        };
+
+        Some(element)
+    }
+
+    /// How much smaller than its bounding box the largest box that fits
+    /// inside the shape is. Used to keep text inside the outline.
+    fn inscribed_scale(&self) -> f64 {
+        match self {
+            Self::Diamond | Self::AngledSquare => 0.5,
+            Self::Ellipse | Self::Circle => std::f64::consts::FRAC_1_SQRT_2,
+            Self::Rectangle | Self::Square | Self::None => 1.0,
+        }
+    }
+
+    /// How far the outline is from the side of its bounding box, at a point
+    /// `t` along the side: -1 and 1 are the ends and 0 is the middle. The
+    /// result is a fraction of half the box's size across the side.
+    fn outline_depth(&self, t: f64) -> f64 {
+        match self {
+            Self::Diamond | Self::AngledSquare => t.abs(),
+            Self::Ellipse | Self::Circle => 1.0 - (1.0 - t * t).max(0.0).sqrt(),
+            Self::Rectangle | Self::Square | Self::None => 0.0,
         }
     }
 }
@@ -88,44 +111,131 @@ impl NodeAttributes {
         SVGElement::new("g").class("node-wrapper")
     }
 
-    pub(crate) fn render_default(viewport: Viewport) -> SVGElement<'static> {
-        let shape = NodeShape::default().render(viewport);
-        Self::wrapper().child(shape.class("node"))
+    pub(crate) fn render_default(viewport: Viewport, config: &RenderConfig) -> SVGElement<'static> {
+        let shape = NodeShape::default().render(viewport, config.corner_radius);
+        Self::wrapper().child_opt(shape.map(|shape| shape.class("node")))
     }
 
-    pub(crate) fn render(&self, viewport: Viewport) -> SVGElement {
-        let shape = self.shape.unwrap_or_default().render(viewport);
+    pub(crate) fn render(&self, viewport: Viewport, config: &RenderConfig) -> SVGElement<'_> {
+        let shape = self
+            .shape
+            .unwrap_or_default()
+            .render(self.shape_viewport(viewport), self.corner_radius(config));
 
         let text = self
-            .text
-            .as_ref()
+            .wrapped_text(viewport, config)
             .map(|text| SVGText::new(viewport.center()).render(text));
 
-        Self::wrapper()
-            .class_opt(self.class.as_ref())
-            .child(shape.class("node"))
+        let palette = self
+            .palette
+            .map(|palette| format!("palette-{}", palette.name()));
+        let wrapper = Self::wrapper().class_opt(palette).class_opt(
+            self.class
+                .as_ref()
+                .filter(|_| !config.strip_user_attributes),
+        );
+
+        with_visibility(wrapper, self.opacity, self.hidden)
+            .child_opt(shape.map(|shape| shape.class("node")))
             .child_opt(text)
     }
 
-    pub(crate) fn link_point(&self, viewport: Viewport, dir: Direction) -> PixelPos {
-        match &self.shape.unwrap_or_default() {
-            NodeShape::Circle | NodeShape::Square | NodeShape::AngledSquare => {
-                let radius = std::cmp::min(viewport.size.x, viewport.size.y) / 2;
-                let center = viewport.center();
-
-                // Calculate the midpoints as offsets from the center of the
-                // viewport because it's simpler, but then subtract the origin
-                // (top-left corner) because the offsets need to be relative to *it*.
-                let midpoints = Midpoints {
-                    top: center + pos(0, -radius) - viewport.origin,
-                    bottom: center + pos(0, radius) - viewport.origin,
-                    left: center + pos(-radius, 0) - viewport.origin,
-                    right: center + pos(radius, 0) - viewport.origin,
-                };
-
-                midpoints.get_from_direction(dir)
+    pub(crate) fn corner_radius(&self, config: &RenderConfig) -> f64 {
+        self.radius
+            .map_or(config.corner_radius, |radius| radius.value())
+    }
+
+    /// The node's text, wrapped to fit inside its shape. Spacers have no
+    /// shape to put the text in, so they have no text.
+    pub(crate) fn wrapped_text(
+        &self,
+        viewport: Viewport,
+        config: &RenderConfig,
+    ) -> Option<Cow<'_, str>> {
+        let text = self
+            .text
+            .as_ref()
+            .filter(|_| self.shape != Some(NodeShape::None))?;
+        let area = self.text_area(viewport, config);
+
+        Some(Self::layout_text(config, text, area.size.x))
+    }
+
+    /// The area the node's text is laid out in: the largest box inside the
+    /// shape's outline, inset by the node's padding.
+    pub(crate) fn text_area(&self, viewport: Viewport, config: &RenderConfig) -> Viewport {
+        let padding = self
+            .padding
+            .map_or(config.node_padding, |padding| padding.value());
+
+        let shape = self.shape.unwrap_or_default();
+        self.shape_viewport(viewport)
+            .scale(shape.inscribed_scale())
+            .inset(padding)
+    }
+
+    fn layout_text<'a>(config: &RenderConfig, text: &'a str, max_width: f64) -> Cow<'a, str> {
+        let metrics = config.metrics();
+
+        let font_size = config.font_size();
+
+        if !config.wrap_text || metrics.width(text, font_size) <= max_width {
+            return Cow::Borrowed(text);
+        }
+
+        Cow::Owned(wrap(metrics, text, font_size, max_width))
+    }
+
+    /// The box that the node's shape is drawn in, centered on the cell's
+    /// `viewport`. Squares, circles and angled squares always have an aspect
+    /// ratio of 1 and fit inside the cell by default, while other shapes
+    /// fill the cell unless they're given an `aspect`.
+    pub(crate) fn shape_viewport(&self, viewport: Viewport) -> Viewport {
+        let (aspect, fit) = match self.shape.unwrap_or_default() {
+            NodeShape::Square | NodeShape::Circle | NodeShape::AngledSquare => {
+                (Some(1.0), self.fit.filter(|&fit| fit != Fit::Stretch))
             }
-            _ => viewport.midpoints_relative().get_from_direction(dir),
+            _ => (self.aspect.map(|aspect| aspect.value()), self.fit),
+        };
+
+        let aspect = match aspect {
+            Some(aspect) => aspect,
+            None => return viewport,
+        };
+
+        let width = match fit.unwrap_or(Fit::Contain) {
+            Fit::Contain => viewport.size.x.min(viewport.size.y * aspect),
+            Fit::Cover => viewport.size.x.max(viewport.size.y * aspect),
+            Fit::Stretch => return viewport,
+        };
+        let size = pos(width, width / aspect);
+
+        Viewport::new(viewport.center() - size / 2.0, size)
+    }
+
+    pub(crate) fn link_point(&self, viewport: Viewport, dir: Direction) -> PixelPos {
+        let shape = self.shape.unwrap_or_default();
+        if shape == NodeShape::None {
+            return viewport.center() - viewport.origin;
         }
+        let outline = self.shape_viewport(viewport);
+
+        let anchor = self
+            .anchors
+            .and_then(|anchors| anchors.get(dir))
+            .map_or(0.5, |anchor| anchor.value());
+
+        // Connections meet the side head-on, so the point on the side of the
+        // bounding box is moved straight inwards until it touches the shape.
+        let half_across = match dir {
+            Direction::North | Direction::South => outline.size.y / 2.0,
+            Direction::West | Direction::East => outline.size.x / 2.0,
+        };
+        let depth = shape.outline_depth(anchor * 2.0 - 1.0) * half_across;
+        let inwards = PixelPos::from(dir) * -depth;
+
+        // The point is calculated on the outline, but the result needs to be
+        // relative to the viewport's origin (top-left corner).
+        outline.origin + outline.side_point_relative(dir, anchor) + inwards - viewport.origin
     }
 }
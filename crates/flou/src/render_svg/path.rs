@@ -134,10 +134,10 @@ impl From<PosSide> for PaddedPos {
 }
 
 impl Grid<'_> {
-    fn padded_get_id(&self, pos: PaddedPos) -> Option<Option<&Identifier>> {
+    fn padded_get_id(&self, pos: PaddedPos) -> Option<Option<&Identifier<'_>>> {
         pos.in_bounds(self.size.into())
             .then(|| match pos.grid_aligned() {
-                true => self.position_to_id.get(&pos.into()),
+                true => self.get_id(pos.into()).flatten(),
                 false => None,
             })
     }
@@ -181,17 +181,21 @@ fn get_best_corner(grid: &Grid, a: PosSide, b: PosSide) -> (PaddedPos, FreeAxisC
     }
 
     let corners = [pos(a_pos.x, b_pos.y), pos(b_pos.x, a_pos.y)];
-    let mut corners = vec![
+    let mut corners = [
         (corners[0], FreeAxisCount::from_pos(corners[0])),
         (corners[1], FreeAxisCount::from_pos(corners[1])),
     ];
 
-    corners.sort_unstable_by(|(_, a_lane_count), (_, b_lane_count)| a_lane_count.cmp(b_lane_count));
+    corners.sort_unstable_by_key(|&(_, lane_count)| lane_count);
 
     let (smaller, larger) = (corners[0], corners[1]);
 
+    // A corner on a node would take the connection through it, which for
+    // the nodes at either end means leaving or entering one through its
+    // opposite side.
     let can_make_direct_connection = |from: PosSide, to: PosSide, corner: PaddedPos| -> bool {
-        line_does_not_overlap_nodes(grid, from, corner)
+        !matches!(grid.padded_get_id(corner), Some(Some(_)))
+            && line_does_not_overlap_nodes(grid, from, corner)
             && line_does_not_overlap_nodes(grid, to, corner)
     };
 
@@ -1,23 +1,77 @@
 use std::{borrow::Cow, cmp::Ordering, convert::TryFrom, fmt::Display};
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
 use crate::{
-    parse::ast::{ArrowheadType, Direction},
-    parts::{Connection, Flou, NodeAttributes, RenderConfig, Renderer},
-    pos::{impl_pos_from, pos, IndexPos, PixelPos, Position2D},
-    svg::{ArrowHead, SVGElement, SVGPath, SVGText},
+    parse::ast::{
+        ArrowheadType, ConnectionRoute, Direction, Headers, LegendEntry, Opacity, Palette,
+    },
+    parts::{
+        Background, BackgroundGrid, Connection, ConnectionStyle, Flou, FlouError, LegendPosition,
+        NodeAttributes, RenderConfig, Renderer,
+    },
+    pos::{impl_pos_from, pos, GridCoord, IndexPos, PixelPos, Position2D},
+    svg::{format_number, ArrowHead, Defs, SVGDocument, SVGElement, SVGPath, SVGText, Transform},
+};
+
+use super::{
+    fonts::EmbeddedFont,
+    hooks::{ConnectionContext, Hooks, MaybeSync, NodeContext},
+    layout::{labels_by_position, ConnectionLayout, Layout, NodeLayout, Rect},
+    path::get_path,
+    router::{is_valid_route, CornerRouter, PathRouter, Route},
+    viewport::Viewport,
+    visitor::{ConnectionVisit, NodeVisit, PathSegment, RenderVisitor},
 };
 
-use super::{path::get_path, viewport::Viewport};
+const CONNECTION_TEXT_OFFSET: f64 = 20.0;
+
+const NOTE_DISTANCE: f64 = 20.0;
+const NOTE_PADDING: f64 = 5.0;
+
+const LEGEND_MARGIN: f64 = 10.0;
+const LEGEND_PADDING: f64 = 10.0;
+const LEGEND_SAMPLE_WIDTH: f64 = 40.0;
+const LEGEND_LINE_HEIGHT: f64 = 1.5;
+
+/// Colors of the default CSS, as CSS custom properties.
+const CSS_COLORS: &[(&str, &str)] = &[
+    ("--flou-background", "#eee"),
+    ("--flou-node-fill", "#fff"),
+    ("--flou-node-stroke", "#1e1e1e"),
+    ("--flou-text-color", "#1e1e1e"),
+    ("--flou-connection-stroke", "#1e1e1e"),
+    ("--flou-note-fill", "#fffbe6"),
+    ("--flou-grid-color", "#ccc"),
+];
+
+/// Applies the `opacity` and `hidden` attributes to an element. Hidden
+/// elements are still emitted so that they keep their place in the layout.
+pub(super) fn with_visibility<'a>(
+    mut element: SVGElement<'a>,
+    opacity: Option<Opacity>,
+    hidden: Option<bool>,
+) -> SVGElement<'a> {
+    if let Some(opacity) = opacity {
+        element = element.attr_number("opacity", opacity.value());
+    }
+
+    if hidden == Some(true) {
+        element = element.attr("visibility", "hidden");
+    }
 
-const ARROWHEAD_WIDTH: i32 = 10;
-const ARROWHEAD_HEIGHT: i32 = 10;
-const CONNECTION_TEXT_OFFSET: i32 = 20;
+    element
+}
 
+/// Coordinates that address the grid gaps as well as the cells. Cell
+/// `(x, y)` is at `(2x + 1, 2y + 1)` and the gaps are at the even
+/// coordinates around it, so `(0, 1)` is the gap left of cell `(0, 0)`.
 #[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
-pub(crate) struct PaddedSpace;
-pub(crate) type PaddedPos = Position2D<isize, PaddedSpace>;
+pub struct PaddedSpace;
+pub type PaddedPos = Position2D<isize, PaddedSpace>;
 
-impl_pos_from!(PaddedPos, PixelPos, i32);
+impl_pos_from!(PaddedPos, PixelPos, f64);
 
 impl PaddedPos {
     pub(crate) const PADDING: isize = 1;
@@ -57,9 +111,21 @@ impl From<PaddedPos> for IndexPos {
     }
 }
 
+impl From<GridCoord> for PaddedPos {
+    fn from(other: GridCoord) -> Self {
+        IndexPos::from(other).into()
+    }
+}
+
+impl From<PaddedPos> for GridCoord {
+    fn from(other: PaddedPos) -> Self {
+        IndexPos::from(other).into()
+    }
+}
+
 impl PixelPos {
     fn middle(a: Self, b: Self) -> Self {
-        Self::new((a.x + b.x) / 2, (a.y + b.y) / 2)
+        Self::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0)
     }
 }
 
@@ -81,24 +147,414 @@ impl Direction {
             Direction::East => Direction::South,
         }
     }
+}
 
-    pub(crate) fn rotate_counter_clockwise(&self) -> Self {
-        match self {
-            Direction::North => Direction::West,
-            Direction::South => Direction::East,
-            Direction::West => Direction::South,
-            Direction::East => Direction::North,
+/// Parses `input` and renders it into an SVG document with a default
+/// [`SvgRenderer`], which saves going through [`Flou`] for the common case.
+/// Fails with [`FlouError::LimitExceeded`] if the flowchart exceeds any of
+/// the config's [`limits`](RenderConfig::limits).
+///
+/// ```
+/// use flou::{render_svg, RenderConfig};
+///
+/// let svg = render_svg("grid { block(\"Hello\"); }", &RenderConfig::default()).unwrap();
+/// assert!(svg.starts_with("<svg"));
+/// ```
+pub fn render_svg<'i>(input: &'i str, config: &RenderConfig) -> Result<String, FlouError<'i>> {
+    let flou = Flou::try_from(input)?;
+    config
+        .check_limits(&flou)
+        .map_err(FlouError::LimitExceeded)?;
+    let output = SvgRenderer::new().render(&flou, config).to_string();
+    Ok(output)
+}
+
+/// Renders a [`Flou`] into an SVG document.
+///
+/// Hooks can be attached to customize the generated elements without
+/// having to post-process the output. Hooks of the same kind run in
+/// the order they were added, each receiving the previous one's result.
+pub struct SvgRenderer {
+    hooks: Hooks,
+    router: Box<dyn PathRouter>,
+}
+
+impl Default for SvgRenderer {
+    fn default() -> Self {
+        Self {
+            hooks: Hooks::default(),
+            router: Box::new(CornerRouter),
         }
     }
 }
 
-pub struct SvgRenderer;
+impl SvgRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the router that decides which way connections are drawn.
+    /// Defaults to [`CornerRouter`].
+    pub fn router<R: PathRouter + 'static>(mut self, router: R) -> Self {
+        self.router = Box::new(router);
+        self
+    }
+
+    /// Adds a hook that is called with every rendered node wrapper.
+    pub fn on_node<F>(mut self, hook: F) -> Self
+    where
+        F: for<'i> Fn(&NodeContext<'i>, SVGElement<'i>) -> SVGElement<'i> + MaybeSync + 'static,
+    {
+        self.hooks.node.push(Box::new(hook));
+        self
+    }
+
+    /// Adds a hook that is called with every rendered connection.
+    pub fn on_connection<F>(mut self, hook: F) -> Self
+    where
+        F: for<'i> Fn(&ConnectionContext, SVGElement<'i>) -> SVGElement<'i> + MaybeSync + 'static,
+    {
+        self.hooks.connection.push(Box::new(hook));
+        self
+    }
+
+    /// Adds a hook that is called with the root `<svg>` element once
+    /// everything else has been rendered.
+    pub fn on_root<F>(mut self, hook: F) -> Self
+    where
+        F: for<'i> Fn(SVGElement<'i>) -> SVGElement<'i> + MaybeSync + 'static,
+    {
+        self.hooks.root.push(Box::new(hook));
+        self
+    }
+}
 
 impl Renderer for SvgRenderer {
-    fn render<'i>(flou: &'i Flou<'i>, config: &'i RenderConfig) -> Box<dyn Display + 'i> {
+    fn render<'i>(&self, flou: &'i Flou<'i>, config: &'i RenderConfig) -> Box<dyn Display + 'i> {
+        let root = self.render_root(flou, config);
+        let document = SVGDocument::new(root).standalone(config.standalone);
+
+        Box::new(document)
+    }
+}
+
+impl SvgRenderer {
+    /// Lays out the flowchart as it would be rendered with `config`,
+    /// without rendering it.
+    pub fn layout<'i>(&self, flou: &'i Flou<'i>, config: &RenderConfig) -> Layout<'i> {
+        Self::layout_routed(config, flou, &self.routed_connections(flou))
+    }
+
+    /// Renders the flowchart along with its layout. Connections are only
+    /// routed once, which makes this faster than calling
+    /// [`Renderer::render`] and [`SvgRenderer::layout`] separately.
+    pub fn render_with_layout<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+    ) -> (Box<dyn Display + 'i>, Layout<'i>) {
+        let (root, layout) = self.render_root_with_layout(flou, config);
+        let document = SVGDocument::new(root).standalone(config.standalone);
+
+        (Box::new(document), layout)
+    }
+
+    /// Renders only the nodes and connections that overlap `region`, with
+    /// the SVG's view limited to it. Useful for showing parts of a large
+    /// flowchart, such as tiles of it, without rendering all of it.
+    ///
+    /// Connections overlap the region if the rectangle around the points
+    /// they go through does. Everything that isn't a node or a connection
+    /// is still rendered, but is cut off by the view.
+    pub fn render_region<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+        region: Rect,
+    ) -> Box<dyn Display + 'i> {
+        self.render_regions(flou, config, &[region]).remove(0)
+    }
+
+    /// Like [`SvgRenderer::render_region`], but for each of `regions`.
+    /// Connections are only routed once, which makes this faster than
+    /// rendering each region separately.
+    pub fn render_regions<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+        regions: &[Rect],
+    ) -> Vec<Box<dyn Display + 'i>> {
+        let connections = self.routed_connections(flou);
+        let layout = Self::layout_routed(config, flou, &connections);
+        let bounds = layout
+            .connections
+            .iter()
+            .map(|c| Rect::bounding(&c.points))
+            .collect::<Vec<_>>();
+
+        let render = |region: Rect| -> Box<dyn Display + 'i> {
+            let positions = layout
+                .nodes
+                .iter()
+                .filter(|node| region.intersects(&Rect::new(node.origin, node.size)))
+                .map(|node| node.position.into())
+                .collect();
+
+            let connections = connections
+                .iter()
+                .zip(&bounds)
+                .filter(|(_, bounds)| matches!(bounds, Some(b) if region.intersects(b)))
+                .map(|(connection, _)| connection.clone())
+                .collect::<Vec<_>>();
+
+            let root = self.render_root_routed(flou, config, positions, &connections, Some(region));
+            Box::new(SVGDocument::new(root).standalone(config.standalone))
+        };
+
+        regions.iter().map(|&region| render(region)).collect()
+    }
+
+    /// Renders the flowchart in a canonical form meant for snapshot tests,
+    /// so that they don't break on harmless changes to the output between
+    /// versions. Attributes and classes are sorted, whitespace in text is
+    /// trimmed, and the CSS and the XML declaration are left out.
+    pub fn render_normalized<'i>(&self, flou: &'i Flou<'i>, config: &'i RenderConfig) -> String {
+        self.render_root(flou, config).normalized().to_string()
+    }
+
+    /// Lays out the flowchart as it would be rendered with `config` and
+    /// passes its nodes and connections to `visitor`, for drawing it with
+    /// something other than SVG. Hooks and CSS don't apply, and headers,
+    /// notes and the legend aren't visited.
+    pub fn visit<'i, V>(&self, flou: &'i Flou<'i>, config: &RenderConfig, visitor: &mut V)
+    where
+        V: RenderVisitor<'i> + ?Sized,
+    {
+        self.visit_with_layout(flou, config, visitor);
+    }
+
+    /// Like [`SvgRenderer::visit`], but also returns the layout.
+    pub(crate) fn visit_with_layout<'i, V>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &RenderConfig,
+        visitor: &mut V,
+    ) -> Layout<'i>
+    where
+        V: RenderVisitor<'i> + ?Sized,
+    {
+        let connections = self.routed_connections(flou);
+        let layout = Self::layout_routed(config, flou, &connections);
+        visitor.visit_flowchart(layout.size);
+
+        let user_attr =
+            |attr: &'i Option<String>| attr.as_deref().filter(|_| !config.strip_user_attributes);
+
+        for node in &layout.nodes {
+            let pos = IndexPos::from(node.position);
+            let viewport = Viewport::new(Self::calculate_node_origin(config, pos), config.node);
+            let attrs = flou.node_attributes.get(&pos);
+
+            visitor.visit_node(&NodeVisit {
+                shape: attrs.and_then(|attrs| attrs.shape).unwrap_or_default(),
+                corner_radius: attrs
+                    .map_or(config.corner_radius, |attrs| attrs.corner_radius(config)),
+                text: attrs.and_then(|attrs| attrs.wrapped_text(viewport, config)),
+                text_center: viewport.center(),
+                class: attrs.and_then(|attrs| user_attr(&attrs.class)),
+                palette: attrs.and_then(|attrs| attrs.palette),
+                opacity: attrs.and_then(|attrs| attrs.opacity).map(Opacity::value),
+                hidden: attrs.and_then(|attrs| attrs.hidden) == Some(true),
+                layout: node.clone(),
+            });
+        }
+
+        for (connection, route) in &connections {
+            let (link_points, points) = Self::connection_points(config, flou, connection, route);
+            let segments = Self::path_segments(config, connection, &points);
+            let (from, to) = connection.coords();
+            let attrs = &connection.attrs;
+
+            let text = attrs.text.as_deref().map(|text| {
+                (
+                    text,
+                    Self::connection_text_origin(config, &link_points, text),
+                )
+            });
+            let arrowheads = Self::arrowheads(config, connection, &link_points, &points)
+                .into_iter()
+                .map(|(viewport, heading, _)| ArrowHead::outline(viewport, heading))
+                .collect();
+
+            visitor.visit_connection(&ConnectionVisit {
+                layout: ConnectionLayout {
+                    from,
+                    to,
+                    id: attrs.id.as_deref(),
+                    points,
+                },
+                text,
+                class: user_attr(&attrs.class),
+                stroke_width: Self::connection_stroke_width(config, connection),
+                arrowheads,
+                opacity: attrs.opacity.map(Opacity::value),
+                hidden: attrs.hidden == Some(true),
+            });
+
+            for segment in &segments {
+                visitor.visit_path_segment(segment);
+            }
+        }
+
+        layout
+    }
+
+    /// Renders the root `<svg>` element, with all hooks applied.
+    pub(crate) fn render_root<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+    ) -> SVGElement<'i> {
+        let connections = self.routed_connections(flou);
+        self.render_root_routed(flou, config, Self::node_positions(flou), &connections, None)
+    }
+
+    /// Like [`SvgRenderer::render_root`], but also returns the layout.
+    pub(crate) fn render_root_with_layout<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+    ) -> (SVGElement<'i>, Layout<'i>) {
+        let connections = self.routed_connections(flou);
+        let layout = Self::layout_routed(config, flou, &connections);
+        let positions = Self::node_positions(flou);
+
+        (
+            self.render_root_routed(flou, config, positions, &connections, None),
+            layout,
+        )
+    }
+
+    fn render_root_routed<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+        positions: Vec<IndexPos>,
+        connections: &[(&'i Connection, Route)],
+        region: Option<Rect>,
+    ) -> SVGElement<'i> {
+        // Indexed parallel iterators keep their order when collected, so
+        // the output is the same either way.
+        #[cfg(feature = "parallel")]
+        let (positions, connections) = (positions.into_par_iter(), connections.par_iter());
+        #[cfg(not(feature = "parallel"))]
+        let (positions, connections) = (positions.into_iter(), connections.iter());
+
+        let nodes = positions
+            .map(|pos| self.render_node(config, flou, pos))
+            .collect();
+
+        let connections = connections
+            .map(|(c, route)| self.render_hooked_connection(config, flou, c, route))
+            .collect();
+
+        self.render_root_with(flou, config, nodes, connections, region)
+    }
+
+    /// All connections in the order they are rendered, along with their routes.
+    fn routed_connections<'i>(&self, flou: &'i Flou<'i>) -> Vec<(&'i Connection, Route)> {
+        let connections = Self::sorted_connections(flou);
+        let routes = self.routes(flou, &connections);
+        connections.into_iter().zip(routes).collect()
+    }
+
+    fn layout_routed<'i>(
+        config: &RenderConfig,
+        flou: &'i Flou<'i>,
+        connections: &[(&'i Connection, Route)],
+    ) -> Layout<'i> {
+        let labels = labels_by_position(
+            &flou.labels,
+            flou.shared_labels
+                .iter()
+                .flat_map(|(&label, positions)| positions.iter().map(move |&pos| (label, pos))),
+        );
+
+        let default_attrs = NodeAttributes::default();
+        let nodes = Self::node_positions(flou)
+            .into_iter()
+            .map(|pos| {
+                let origin = Self::calculate_node_origin(config, pos);
+                let attrs = flou.node_attributes.get(&pos).unwrap_or(&default_attrs);
+                let shape = attrs.shape_viewport(Viewport::new(origin, config.node));
+
+                NodeLayout {
+                    position: pos.into(),
+                    id: flou.grid[pos],
+                    label: labels.get(&pos).copied(),
+                    origin: shape.origin,
+                    size: shape.size,
+                }
+            })
+            .collect();
+
+        let connections = connections
+            .iter()
+            .map(|(connection, route)| {
+                let (from, to) = connection.coords();
+                ConnectionLayout {
+                    from,
+                    to,
+                    id: connection.attrs.id.as_deref(),
+                    points: Self::connection_points(config, flou, connection, route).1,
+                }
+            })
+            .collect();
+
+        Layout {
+            size: Self::calculate_svg_size(config, flou.grid.size),
+            nodes,
+            connections,
+        }
+    }
+
+    /// Renders the root `<svg>` element around already rendered nodes and
+    /// connections. If a region is given, only it is shown.
+    pub(crate) fn render_root_with<'i>(
+        &self,
+        flou: &'i Flou<'i>,
+        config: &'i RenderConfig,
+        nodes: Vec<SVGElement<'i>>,
+        connections: Vec<SVGElement<'i>>,
+        region: Option<Rect>,
+    ) -> SVGElement<'i> {
         let mut styles: Vec<Cow<str>> = Vec::new();
+        if !config.fonts.is_empty() {
+            let font_faces = config.fonts.iter().map(EmbeddedFont::to_css);
+            styles.push(font_faces.collect::<Vec<_>>().join("\n\n").into());
+        }
+
+        let variables = Self::css_variables(config);
+        if config.css_variables {
+            let declarations = variables
+                .iter()
+                .map(|(name, value)| format!("  {}: {};", name, value))
+                .collect::<Vec<_>>();
+            // `:where` keeps the selector from being more specific than any
+            // host page style that overrides the variables.
+            styles.push(format!(":where(.flou) {{\n{}\n}}", declarations.join("\n")).into());
+        }
+
         if config.default_css {
-            styles.push(include_str!("../css/default.css").into());
+            let css = include_str!("../css/default.css");
+            styles.push(match config.css_variables {
+                true => css.into(),
+                false => Self::substitute_css_variables(css, &variables).into(),
+            });
+        }
+
+        if config.print_mode {
+            styles.push(include_str!("../css/print.css").into());
         }
 
         styles.extend(config.css.iter().map(Into::into));
@@ -109,34 +565,407 @@ impl Renderer for SvgRenderer {
 
         let size = Self::calculate_svg_size(config, flou.grid.size);
 
-        let svg = SVGElement::new("svg")
+        let mut svg = SVGElement::new("svg")
             .attr("xmlns", "http://www.w3.org/2000/svg")
-            .size(size)
-            .children(styles);
+            .class("flou");
+
+        let view = region.unwrap_or_else(|| Rect::new(pos(0.0, 0.0), size));
+        let view_box = format!(
+            "{} {} {} {}",
+            format_number(view.origin.x),
+            format_number(view.origin.y),
+            format_number(view.size.x),
+            format_number(view.size.y)
+        );
+
+        // With a unit, the viewBox keeps the pixel coordinates inside the
+        // flowchart while its size is given in that unit. A region needs
+        // one to move the view to it.
+        svg = match config.unit() {
+            Some(unit) => {
+                let length = |pixels| {
+                    let length = format_number(config.to_unit(pixels, unit));
+                    format!("{}{}", length, unit.suffix())
+                };
+
+                svg.attr("width", length(view.size.x))
+                    .attr("height", length(view.size.y))
+                    .attr("viewBox", view_box)
+            }
+            None if region.is_some() => svg.size(view.size).attr("viewBox", view_box),
+            None => svg.size(size),
+        };
+
+        if let Some(font_family) = &config.font_family {
+            svg = svg.attr("font-family", font_family.as_str());
+        }
+
+        if let Some(font_size) = config.font_size {
+            svg = svg.attr_len("font-size", font_size);
+        }
+
+        // Definitions shared by the rest of the flowchart, emitted once
+        // before anything that refers to them.
+        let mut defs = Defs::new();
+        if config.print_mode {
+            Self::define_print_patterns(&mut defs);
+        }
 
-        let nodes = SVGElement::new("g")
-            .class("nodes")
-            .children(Self::render_nodes(config, flou));
+        let nodes = SVGElement::new("g").class("nodes").children(nodes);
 
         let connections = SVGElement::new("g")
             .class("connections")
-            .children(Self::render_connections(config, flou));
+            .children(connections);
 
         let background = SVGElement::new("rect")
             .class("background")
-            .pos(pos(0, 0))
+            .pos(pos(0.0, 0.0))
             .size(size);
 
-        let result = svg.child(background).child(nodes).child(connections);
+        // An inline style is needed to take precedence over the CSS.
+        let background = match &config.background {
+            Background::Default => Some(background),
+            Background::Transparent => None,
+            Background::Color(color) => Some(background.attr("style", format!("fill: {}", color))),
+        };
 
-        Box::new(result)
+        let background_grid = Self::render_background_grid(config, size, &mut defs);
+        let headers = Self::render_headers(config, &flou.headers, flou.grid.size);
+        let notes = Self::render_notes(config, flou);
+        let legend = Self::render_legend(config, &flou.legend, size);
+
+        let result = svg
+            .children(styles)
+            .child_opt(defs.render())
+            .child_opt(background)
+            .child_opt(background_grid)
+            .child_opt(headers)
+            .child(nodes)
+            .child(connections)
+            .child_opt(notes)
+            .child_opt(legend);
+
+        self.hooks.apply_root(result)
+    }
+
+    /// The CSS custom properties used by the default CSS, along with their
+    /// values. Host pages can override them on the `.flou` class to theme
+    /// the flowchart.
+    fn css_variables(config: &RenderConfig) -> Vec<(&'static str, String)> {
+        let mut variables = vec![(
+            "--flou-font-size",
+            format!("{}px", format_number(config.font_size())),
+        )];
+
+        variables.extend(
+            CSS_COLORS
+                .iter()
+                .map(|&(name, color)| (name, color.to_string())),
+        );
+
+        variables
+    }
+
+    /// Replaces uses of the given CSS custom properties with their values,
+    /// for SVG consumers that don't support custom properties.
+    fn substitute_css_variables(css: &str, variables: &[(&str, String)]) -> String {
+        variables
+            .iter()
+            .fold(css.to_string(), |css, (name, value)| {
+                css.replace(&format!("var({})", name), value)
+            })
+    }
+
+    /// The patterns that nodes with a palette are filled with in print mode,
+    /// since palette colors don't survive black-and-white printing.
+    fn define_print_patterns(defs: &mut Defs) {
+        const SIZE: f64 = 8.0;
+
+        let line = |from: PixelPos, to: PixelPos| {
+            SVGElement::new("line")
+                .attr_len("x1", from.x)
+                .attr_len("y1", from.y)
+                .attr_len("x2", to.x)
+                .attr_len("y2", to.y)
+                .attr("stroke", "#000")
+        };
+
+        for palette in [Palette::Blue, Palette::Green, Palette::Red, Palette::Gray] {
+            let (transform, marks) = match palette {
+                Palette::Blue => (
+                    Some(Transform::Rotate(45.0, None)),
+                    vec![line(pos(0.0, 0.0), pos(0.0, SIZE))],
+                ),
+                Palette::Green => (
+                    None,
+                    vec![SVGElement::new("circle")
+                        .cpos(pos(SIZE, SIZE) / 2.0)
+                        .attr("r", "1.5")
+                        .attr("fill", "#000")],
+                ),
+                Palette::Red => (
+                    Some(Transform::Rotate(45.0, None)),
+                    vec![
+                        line(pos(0.0, 0.0), pos(0.0, SIZE)),
+                        line(pos(0.0, 0.0), pos(SIZE, 0.0)),
+                    ],
+                ),
+                Palette::Gray => (
+                    None,
+                    vec![line(pos(0.0, SIZE / 2.0), pos(SIZE, SIZE / 2.0))],
+                ),
+            };
+
+            let background = SVGElement::new("rect")
+                .size(pos(SIZE, SIZE))
+                .attr("fill", "#fff");
+
+            let pattern = SVGElement::new("pattern")
+                .size(pos(SIZE, SIZE))
+                .attr("patternUnits", "userSpaceOnUse");
+
+            let pattern = match transform {
+                Some(transform) => pattern.attr("patternTransform", transform.to_string()),
+                None => pattern,
+            };
+
+            let pattern = pattern.child(background).children(marks);
+            defs.define(format!("flou-print-{}", palette.name()), pattern);
+        }
+    }
+
+    /// Renders the background grid as a pattern repeated once per cell. Each
+    /// cell spans a node and half of the grid gap around it, so the marks
+    /// are in the middle of the grid gaps.
+    fn render_background_grid(
+        config: &RenderConfig,
+        size: PixelPos,
+        defs: &mut Defs,
+    ) -> Option<SVGElement<'static>> {
+        let cell = config.node + config.grid_gap;
+        let center = cell / 2.0;
+
+        let mark = match config.background_grid {
+            BackgroundGrid::None => return None,
+            BackgroundGrid::Dots => SVGElement::new("circle").cpos(center).attr("r", "1.5"),
+            BackgroundGrid::Lines => SVGPath::new()
+                .move_to(pos(center.x, 0.0))
+                .line_to(pos(center.x, cell.y))
+                .move_to(pos(0.0, center.y))
+                .line_to(pos(cell.x, center.y))
+                .render(),
+        };
+
+        // The pattern is shifted so that the middle of its tile, where the
+        // mark is, lands on the middle of the first grid gap.
+        let offset = config.grid_gap / 2.0 - center;
+        let pattern = SVGElement::new("pattern")
+            .pos(offset)
+            .size(cell)
+            .attr("patternUnits", "userSpaceOnUse")
+            .child(mark.class("background-grid-mark"));
+
+        let grid = SVGElement::new("rect")
+            .class("background-grid")
+            .pos(pos(0.0, 0.0))
+            .size(size)
+            .attr("fill", Defs::url("flou-background-grid"));
+        defs.define("flou-background-grid", pattern);
+
+        Some(grid)
+    }
+
+    /// Renders the row and column headers in the grid gap along the left
+    /// and top edges of the grid. Row headers are rotated to fit the gap.
+    /// Headers of rows and columns that are past the end of the grid are
+    /// left out.
+    fn render_headers<'i>(
+        config: &RenderConfig,
+        headers: &'i Headers,
+        grid_size: IndexPos,
+    ) -> Option<SVGElement<'i>> {
+        if headers.is_empty() {
+            return None;
+        }
+
+        let named = |names: &'i [String], len: isize| {
+            names
+                .iter()
+                .take(len as usize)
+                .enumerate()
+                .filter(|(_, name)| !name.is_empty())
+        };
+
+        let cols = named(&headers.cols, grid_size.x).map(|(x, name)| {
+            let origin = Self::calculate_node_origin(config, IndexPos::new(x as isize, 0));
+            let center = pos(origin.x + config.node.x / 2.0, config.grid_gap.y / 2.0);
+            SVGText::new(center)
+                .render(name.as_str())
+                .class("header")
+                .class("column-header")
+        });
+
+        let rows = named(&headers.rows, grid_size.y).map(|(y, name)| {
+            let origin = Self::calculate_node_origin(config, IndexPos::new(0, y as isize));
+            let center: PixelPos = pos(config.grid_gap.x / 2.0, origin.y + config.node.y / 2.0);
+            SVGText::new(center)
+                .render(name.as_str())
+                .class("header")
+                .class("row-header")
+                .rotate(-90.0, center)
+        });
+
+        Some(
+            SVGElement::new("g")
+                .class("headers")
+                .children(cols.chain(rows)),
+        )
+    }
+
+    /// Renders each note as a box next to its node, joined to the node by a
+    /// short connector. Notes aren't part of the grid, so connections are
+    /// routed as if they weren't there. A note is as visible as its node.
+    fn render_notes<'i>(config: &RenderConfig, flou: &'i Flou<'i>) -> Option<SVGElement<'i>> {
+        if flou.notes.is_empty() {
+            return None;
+        }
+
+        let font_size = config.font_size();
+        let default_attrs = NodeAttributes::default();
+
+        let notes = flou.notes.iter().map(|note| {
+            let attrs = flou
+                .node_attributes
+                .get(&note.pos)
+                .unwrap_or(&default_attrs);
+            let origin = Self::calculate_node_origin(config, note.pos);
+            let anchor = origin + attrs.link_point(Viewport::new(origin, config.node), note.side);
+
+            let metrics = config.metrics();
+            let size: PixelPos = pos(
+                metrics.width(&note.text, font_size) + NOTE_PADDING * 2.0,
+                metrics.height(&note.text, font_size) + NOTE_PADDING * 2.0,
+            );
+
+            let dir = PixelPos::from(note.side);
+            let half_extent = match note.side {
+                Direction::North | Direction::South => size.y / 2.0,
+                Direction::West | Direction::East => size.x / 2.0,
+            };
+            let connector_end = anchor + dir * NOTE_DISTANCE;
+            let center = connector_end + dir * half_extent;
+
+            let connector = SVGPath::new()
+                .line_to(anchor)
+                .line_to(connector_end)
+                .render()
+                .class("note-connector");
+
+            let background = SVGElement::new("rect")
+                .class("note-box")
+                .pos(center - size / 2.0)
+                .size(size);
+
+            let text = SVGText::new(center)
+                .render(note.text.as_str())
+                .class("note-text");
+
+            let element = SVGElement::new("g")
+                .class("note")
+                .child(connector)
+                .child(background)
+                .child(text);
+
+            with_visibility(element, attrs.opacity, attrs.hidden)
+        });
+
+        Some(SVGElement::new("g").class("notes").children(notes))
+    }
+
+    /// Renders the `legend` block as a key in the corner of an SVG of the
+    /// given size. Each entry's sample line is a `path` wrapped in the
+    /// entry's class, so it picks up the same styles as connections with
+    /// that class.
+    fn render_legend<'i>(
+        config: &RenderConfig,
+        legend: &'i [LegendEntry],
+        svg_size: PixelPos,
+    ) -> Option<SVGElement<'i>> {
+        if legend.is_empty() {
+            return None;
+        }
+
+        let font_size = config.font_size();
+        let line_height = font_size * LEGEND_LINE_HEIGHT;
+        let text_width = legend
+            .iter()
+            .map(|entry| config.metrics().width(&entry.text, font_size))
+            .fold(0.0, f64::max);
+
+        let size: PixelPos = pos(
+            LEGEND_PADDING * 3.0 + LEGEND_SAMPLE_WIDTH + text_width,
+            LEGEND_PADDING * 2.0 + line_height * legend.len() as f64,
+        );
+
+        let (left, top) = match config.legend_position {
+            LegendPosition::TopLeft => (true, true),
+            LegendPosition::TopRight => (false, true),
+            LegendPosition::BottomLeft => (true, false),
+            LegendPosition::BottomRight => (false, false),
+        };
+        let x = if left {
+            LEGEND_MARGIN
+        } else {
+            svg_size.x - LEGEND_MARGIN - size.x
+        };
+        let y = if top {
+            LEGEND_MARGIN
+        } else {
+            svg_size.y - LEGEND_MARGIN - size.y
+        };
+        let origin: PixelPos = pos(x, y);
+
+        let background = SVGElement::new("rect")
+            .class("legend-background")
+            .pos(origin)
+            .size(size);
+
+        let entries = legend.iter().enumerate().map(|(i, entry)| {
+            let y = origin.y + LEGEND_PADDING + line_height * (i as f64 + 0.5);
+            let sample_start = origin.x + LEGEND_PADDING;
+            let sample_end = sample_start + LEGEND_SAMPLE_WIDTH;
+
+            let mut sample = SVGPath::new()
+                .line_to(pos(sample_start, y))
+                .line_to(pos(sample_end, y))
+                .render()
+                .class("path");
+            if config.has_custom_stroke_width() {
+                sample = sample.attr_len("stroke-width", config.stroke_width());
+            }
+
+            let text = SVGText::new(pos(sample_end + LEGEND_PADDING, y))
+                .render(entry.text.as_str())
+                .class("legend-text");
+
+            SVGElement::new("g")
+                .class("legend-entry")
+                .class_opt((!config.strip_user_attributes).then_some(entry.class.as_str()))
+                .child(sample)
+                .child(text)
+        });
+
+        Some(
+            SVGElement::new("g")
+                .class("legend")
+                .child(background)
+                .children(entries),
+        )
     }
-}
 
-impl SvgRenderer {
     fn calculate_node_origin(config: &RenderConfig, pos: IndexPos) -> PixelPos {
         let node_offset: PixelPos = pos.into();
-        let num_grid_gaps = (node_offset + 1) * PaddedPos::PADDING as i32;
+        let num_grid_gaps = (node_offset + 1.0) * PaddedPos::PADDING as f64;
 
         node_offset * config.node + num_grid_gaps * config.grid_gap
     }
@@ -158,194 +987,1366 @@ impl SvgRenderer {
         node_offset + norm_distance + grid_offset
     }
 
-    fn calculate_svg_size(config: &RenderConfig, grid_size: IndexPos) -> PixelPos {
+    pub(crate) fn calculate_svg_size(config: &RenderConfig, grid_size: IndexPos) -> PixelPos {
         Self::calculate_origin(config, grid_size.into())
     }
 
-    fn render_nodes<'i>(config: &RenderConfig, flou: &'i Flou<'i>) -> Vec<SVGElement<'i>> {
-        let mut positions = flou
-            .grid
-            .position_to_id
-            .iter()
-            .map(|(&pos, _)| pos)
-            .collect::<Vec<_>>();
+    /// Positions of all nodes, in the order they are rendered.
+    pub(crate) fn node_positions(flou: &Flou) -> Vec<IndexPos> {
+        flou.grid.positions().collect()
+    }
 
-        positions.sort_unstable_by(|a, b| a.y.cmp(&b.y).then(a.x.cmp(&b.x)));
+    /// Renders the node at `pos`, with node hooks applied.
+    pub(crate) fn render_node<'i>(
+        &self,
+        config: &RenderConfig,
+        flou: &'i Flou<'i>,
+        pos: IndexPos,
+    ) -> SVGElement<'i> {
+        let origin = Self::calculate_node_origin(config, pos);
+        let viewport = Viewport::new(origin, config.node);
 
-        positions
-            .into_iter()
-            .map(|pos| {
-                let origin = Self::calculate_node_origin(config, pos);
-                let viewport = Viewport::new(origin, config.node);
+        let element = match flou.node_attributes.get(&pos) {
+            Some(node_attrs) => node_attrs.render(viewport, config),
+            None => NodeAttributes::render_default(viewport, config),
+        };
 
-                match flou.node_attributes.get(&pos) {
-                    Some(node_attrs) => node_attrs.render(viewport),
-                    None => NodeAttributes::render_default(viewport),
-                }
-            })
-            .collect()
+        let ctx = NodeContext {
+            position: pos.into(),
+            id: flou.grid[pos],
+            origin,
+            size: config.node,
+        };
+
+        self.hooks.apply_node(&ctx, element)
     }
 
-    fn render_connections<'i>(config: &RenderConfig, flou: &'i Flou<'i>) -> Vec<SVGElement<'i>> {
+    /// All connections, in the order they are rendered.
+    pub(crate) fn sorted_connections<'i>(flou: &'i Flou<'i>) -> Vec<&'i Connection> {
         let mut connections = flou.connections.iter().collect::<Vec<_>>();
 
-        connections.sort_unstable_by(|a, b| {
-            let a_pos = a.from.0;
-            let b_pos = b.from.0;
-
-            a_pos.y.cmp(&b_pos.y).then(a_pos.x.cmp(&b_pos.x))
-        });
+        // Later connections are drawn on top, so they're sorted by their
+        // `order` first and by the position of their source node second.
+        connections.sort_by_key(|c| (c.attrs.order.unwrap_or(0), c.from.0));
 
         connections
-            .into_iter()
-            .map(|c| Self::render_connection(config, flou, c))
-            .collect()
     }
 
-    fn render_connection<'i>(
+    /// Routes `connections` with the router. Routes that can't be drawn
+    /// are replaced with the ones [`CornerRouter`] would use.
+    pub(crate) fn routes(&self, flou: &Flou, connections: &[&Connection]) -> Vec<Route> {
+        let endpoints = connections
+            .iter()
+            .map(|c| {
+                let (from, to) = c.coords();
+                ConnectionContext { from, to }
+            })
+            .collect::<Vec<_>>();
+
+        let mut routes = self.router.route_all(&flou.grid(), &endpoints);
+        routes.resize_with(connections.len(), Route::default);
+
+        for (route, connection) in routes.iter_mut().zip(connections) {
+            if !is_valid_route(&route.points, connection.from.0, connection.to.0) {
+                *route = Route::new(get_path(&flou.grid, connection.from, connection.to));
+            }
+        }
+
+        routes
+    }
+
+    /// Renders a connection along `route`, with connection hooks applied.
+    pub(crate) fn render_hooked_connection<'i>(
+        &self,
         config: &RenderConfig,
         flou: &Flou<'i>,
         connection: &'i Connection,
+        route: &Route,
     ) -> SVGElement<'i> {
-        let path = get_path(&flou.grid, connection.from, connection.to);
-
-        // It is assumed that path always has at least 2 points.
-        let first_pair: &[PaddedPos] = &[path[1], path[0]];
+        let (from, to) = connection.coords();
+        let ctx = ConnectionContext { from, to };
 
-        let link_points: Vec<_> = std::iter::once(first_pair)
-            .chain(path.windows(2))
-            .flat_map(<&[_; 2]>::try_from)
-            .map(|&[from, to]| {
-                let dir = PaddedPos::straight_line(to, from).unwrap();
-                let link_point_offset = Self::get_link_point_offset(config, flou, to, dir);
-                let point = Self::calculate_origin(config, to) + link_point_offset;
-                (point, dir)
-            })
-            .collect();
+        self.hooks.apply_connection(
+            &ctx,
+            Self::render_connection(config, flou, connection, route),
+        )
+    }
 
-        let mut path_svg = SVGPath::new();
-        for (point, _) in &link_points {
-            path_svg = path_svg.line_to(*point);
-        }
+    fn render_connection<'i>(
+        config: &RenderConfig,
+        flou: &Flou<'i>,
+        connection: &'i Connection,
+        route: &Route,
+    ) -> SVGElement<'i> {
+        let (link_points, points) = Self::connection_points(config, flou, connection, route);
+        let segments = Self::path_segments(config, connection, &points);
+        let path_svg =
+            segments.iter().fold(
+                SVGPath::new().line_to(points[0]),
+                |path, segment| match *segment {
+                    PathSegment::Line { to, .. } => path.line_to(to),
+                    PathSegment::Curve { c1, c2, to, .. } => path.curve_to(c1, c2, to),
+                },
+            );
 
         let svg_text = connection.attrs.text.as_ref().map(|text| {
-            let text_origin = match &link_points[..2] {
-                [from, to] => {
-                    PixelPos::middle(from.0, to.0)
-                        + PixelPos::from(from.1.rotate_clockwise()) * CONNECTION_TEXT_OFFSET
-                }
-                // Again fine since it is assumed that path always has at least 2 points.
-                _ => unreachable!(),
-            };
-
-            SVGText::new(text_origin)
+            SVGText::new(Self::connection_text_origin(config, &link_points, text))
                 .render(text)
                 .class("connection-text")
         });
 
-        let path = path_svg.render().class("path");
+        let stroke_width = Self::connection_stroke_width(config, connection);
+
+        let mut path = path_svg.render().class("path");
+        if config.has_custom_stroke_width() || connection.attrs.weight.is_some() {
+            path = path.attr_len("stroke-width", stroke_width);
+        }
+
+        let user_attrs = |attr: &'i Option<String>| {
+            attr.as_ref()
+                .filter(|_| !config.strip_user_attributes)
+                .map(String::as_str)
+        };
 
         let mut result = SVGElement::new("g")
             .class("connection")
-            .class_opt(connection.attrs.class.as_ref())
-            .child(path)
-            .child_opt(svg_text);
+            .class_opt(user_attrs(&connection.attrs.class));
+        result = with_visibility(result, connection.attrs.opacity, connection.attrs.hidden);
 
-        fn create_arrowhead((link_point, dir): (PixelPos, Direction)) -> SVGElement<'static> {
-            let arrowhead_viewport =
-                Viewport::new(link_point, pos(ARROWHEAD_WIDTH, ARROWHEAD_HEIGHT));
-            ArrowHead::render(arrowhead_viewport, dir.reverse()).class("arrowhead")
+        if let Some(id) = user_attrs(&connection.attrs.id) {
+            result = result.attr("id", id);
         }
 
-        let arrowheads = connection.attrs.arrowheads.unwrap_or_default();
+        result = result.child(path).child_opt(svg_text);
 
-        if arrowheads == ArrowheadType::Start || arrowheads == ArrowheadType::Both {
-            result = result
-                .child(create_arrowhead(link_points.first().cloned().unwrap()).class("start"));
+        let arrowheads = Self::arrowheads(config, connection, &link_points, &points);
+        for (viewport, heading, class) in arrowheads {
+            let arrowhead = ArrowHead::render(viewport, heading).class("arrowhead");
+            result = result.child(arrowhead.class(class));
         }
 
-        if arrowheads == ArrowheadType::End || arrowheads == ArrowheadType::Both {
-            result =
-                result.child(create_arrowhead(link_points.last().cloned().unwrap()).class("end"));
+        result
+    }
+
+    /// Whether a connection is drawn as a curve instead of straight lines.
+    fn is_curved(config: &RenderConfig, connection: &Connection) -> bool {
+        match connection.attrs.route {
+            Some(ConnectionRoute::Straight | ConnectionRoute::Elbow) => false,
+            Some(ConnectionRoute::Curve) => true,
+            None => config.connection_style == ConnectionStyle::Curved,
         }
+    }
 
-        result
+    /// The segments of a connection through `points`.
+    fn path_segments(
+        config: &RenderConfig,
+        connection: &Connection,
+        points: &[PixelPos],
+    ) -> Vec<PathSegment> {
+        match Self::is_curved(config, connection) {
+            false => points
+                .windows(2)
+                .map(|pair| PathSegment::Line {
+                    from: pair[0],
+                    to: pair[1],
+                })
+                .collect(),
+            true => Self::curve_through(points),
+        }
     }
 
-    fn get_link_point_offset<'i>(
+    fn connection_stroke_width(config: &RenderConfig, connection: &Connection) -> f64 {
+        config.stroke_width() * connection.attrs.weight.unwrap_or_default().factor()
+    }
+
+    /// Where a connection's text is centered: next to the middle of the
+    /// connection's first segment.
+    fn connection_text_origin(
         config: &RenderConfig,
-        flou: &Flou<'i>,
-        point: PaddedPos,
-        dir: Direction,
+        link_points: &[(PixelPos, Direction)],
+        text: &str,
     ) -> PixelPos {
-        let empty_offset = {
-            let x = if point.grid_x_aligned() {
-                config.node.x / 2
-            } else {
-                config.grid_gap.x / 2
-            };
-            let y = if point.grid_y_aligned() {
-                config.node.y / 2
-            } else {
-                config.grid_gap.y / 2
+        match &link_points[..2] {
+            [from, to] => {
+                let offset = Self::connection_text_offset(config, text, from.1);
+                PixelPos::middle(from.0, to.0) + PixelPos::from(from.1.rotate_clockwise()) * offset
+            }
+            // Again fine since it is assumed that path always has at least 2 points.
+            _ => unreachable!(),
+        }
+    }
+
+    /// The area and direction of each of a connection's arrowheads, along
+    /// with the class telling which end it's on.
+    fn arrowheads(
+        config: &RenderConfig,
+        connection: &Connection,
+        link_points: &[(PixelPos, Direction)],
+        points: &[PixelPos],
+    ) -> Vec<(Viewport, PixelPos, &'static str)> {
+        // Arrowheads grow with the stroke so they don't look too small on
+        // thick connections.
+        let arrowhead_size = config.arrowhead * Self::connection_stroke_width(config, connection);
+        let straight = connection.attrs.route == Some(ConnectionRoute::Straight);
+        let arrowhead = |(link_point, dir): (PixelPos, Direction), end, before, class| {
+            // Straight connections can run at any angle, so their
+            // arrowheads point along the line instead of away from the
+            // side they're attached to.
+            let delta: PixelPos = points[end] - points[before];
+            let heading = match straight && (delta.x != 0.0 || delta.y != 0.0) {
+                true => delta,
+                false => PixelPos::from(dir.reverse()),
             };
 
-            pos(x, y)
+            (Viewport::new(link_point, arrowhead_size), heading, class)
         };
 
-        if !point.grid_aligned() || matches!(flou.grid.get_id(point.into()), Some(None)) {
-            return empty_offset;
-        }
-
-        let origin = Self::calculate_node_origin(config, point.into());
-        let viewport = Viewport::new(origin, config.node);
+        let arrowheads = connection.attrs.arrowheads.unwrap_or_default();
+        let last = points.len() - 1;
+        let mut result = Vec::new();
 
-        match flou.node_attributes.get(&IndexPos::from(point)) {
-            Some(attrs) => attrs.link_point(viewport, dir),
-            None => NodeAttributes::default().link_point(viewport, dir),
+        if arrowheads == ArrowheadType::Start || arrowheads == ArrowheadType::Both {
+            result.push(arrowhead(link_points[0], 0, 1, "start"));
         }
-    }
-}
+
+        if arrowheads == ArrowheadType::End || arrowheads == ArrowheadType::Both {
+            let link_point = link_points[link_points.len() - 1];
+            result.push(arrowhead(link_point, last, last - 1, "end"));
+        }
+
+        result
+    }
+
+    /// The points a connection goes through along `route`. Also returns the
+    /// link points between its segments before they're aligned to anchors,
+    /// each with the direction of the segment it's on. Straight connections
+    /// only keep the ends of the route.
+    fn connection_points(
+        config: &RenderConfig,
+        flou: &Flou,
+        connection: &Connection,
+        route: &Route,
+    ) -> (Vec<(PixelPos, Direction)>, Vec<PixelPos>) {
+        let path = &route.points;
+
+        // It is assumed that path always has at least 2 points.
+        let first_pair: &[PaddedPos] = &[path[1], path[0]];
+
+        let mut link_points: Vec<_> = std::iter::once(first_pair)
+            .chain(path.windows(2))
+            .flat_map(<&[_; 2]>::try_from)
+            .map(|&[from, to]| {
+                let dir = PaddedPos::straight_line(to, from).unwrap();
+                let link_point_offset = Self::get_link_point_offset(config, flou, to, dir);
+                let point = Self::calculate_origin(config, to) + link_point_offset;
+                (point, dir)
+            })
+            .collect();
+
+        // Segments in a lane other than the middle one are moved sideways,
+        // along with both of their ends.
+        for (i, pair) in path.windows(2).enumerate() {
+            let lane = match route.lanes.get(i) {
+                Some(lane) if lane.index < lane.count => *lane,
+                _ => continue,
+            };
+
+            if pair[0].x == pair[1].x && !pair[0].grid_x_aligned() {
+                let offset = lane.offset() * config.grid_gap.x;
+                link_points[i].0.x += offset;
+                link_points[i + 1].0.x += offset;
+            } else if pair[0].y == pair[1].y && !pair[0].grid_y_aligned() {
+                let offset = lane.offset() * config.grid_gap.y;
+                link_points[i].0.y += offset;
+                link_points[i + 1].0.y += offset;
+            }
+        }
+
+        let mut points = link_points
+            .iter()
+            .map(|&(point, _)| point)
+            .collect::<Vec<_>>();
+        Self::align_to_anchors(config, &mut points, path);
+
+        if connection.attrs.route == Some(ConnectionRoute::Straight) {
+            link_points = vec![link_points[0], link_points[link_points.len() - 1]];
+            points = vec![points[0], points[points.len() - 1]];
+        }
+
+        let inset = config.endpoint_inset();
+        if inset > 0.0 {
+            let (first, last) = (0, points.len() - 1);
+            points[first] = Self::move_towards(points[first], points[first + 1], inset);
+            points[last] = Self::move_towards(points[last], points[last - 1], inset);
+            link_points[0].0 = points[first];
+            let n = link_points.len();
+            link_points[n - 1].0 = points[last];
+        }
+
+        (link_points, points)
+    }
+
+    /// Moves `point` by `distance` towards `target`, but no further than
+    /// halfway, so that both ends of a segment can be moved without
+    /// crossing each other.
+    fn move_towards(point: PixelPos, target: PixelPos, distance: f64) -> PixelPos {
+        let delta = target - point;
+        let length = delta.x.hypot(delta.y);
+        if length == 0.0 {
+            return point;
+        }
+
+        point + delta * (distance.min(length / 2.0) / length)
+    }
+
+    /// A smooth curve through `points`, made of a cubic Bézier curve from
+    /// each point to the next. At each point, the curve goes in the
+    /// direction from the point before it to the one after it, and it
+    /// leaves the first point and enters the last one head-on, so that it
+    /// meets the arrowheads. The control points are a third of the way
+    /// along each segment, which keeps the curve from overshooting when a
+    /// short segment is next to a long one.
+    fn curve_through(points: &[PixelPos]) -> Vec<PathSegment> {
+        let length = |v: PixelPos| v.x.hypot(v.y);
+        let tangent = |i: usize| {
+            let before = points[i.saturating_sub(1)];
+            let after = points[(i + 1).min(points.len() - 1)];
+            let dir = after - before;
+            match length(dir) {
+                len if len > 0.0 => dir / len,
+                _ => PixelPos::new(0.0, 0.0),
+            }
+        };
+
+        (1..points.len())
+            .map(|i| {
+                let (from, to) = (points[i - 1], points[i]);
+                let handle = length(to - from) / 3.0;
+
+                PathSegment::Curve {
+                    from,
+                    c1: from + tangent(i - 1) * handle,
+                    c2: to - tangent(i) * handle,
+                    to,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns how far away the text should be from a connection segment
+    /// going in direction `dir`. Text next to a vertical segment is centered
+    /// horizontally, so it's moved further away the wider it is to avoid
+    /// overlapping the segment.
+    fn connection_text_offset(config: &RenderConfig, text: &str, dir: Direction) -> f64 {
+        match dir {
+            Direction::North | Direction::South => {
+                let half_width = config.metrics().width(text, config.font_size()) / 2.0;
+                CONNECTION_TEXT_OFFSET.max(half_width + CONNECTION_TEXT_OFFSET / 2.0)
+            }
+            Direction::West | Direction::East => CONNECTION_TEXT_OFFSET,
+        }
+    }
+
+    /// Anchored link points can be off the line that the connection leaves
+    /// or enters the node on. The turns next to them are moved in line with
+    /// them, and any segment that is still slanted gets a jog in the middle
+    /// of the grid cell halfway along it.
+    fn align_to_anchors(config: &RenderConfig, points: &mut Vec<PixelPos>, path: &[PaddedPos]) {
+        let n = points.len();
+        let vertical = |a: PaddedPos, b: PaddedPos| a.x == b.x;
+
+        if n > 2 {
+            if vertical(path[0], path[1]) {
+                points[1].x = points[0].x;
+            } else {
+                points[1].y = points[0].y;
+            }
+
+            if vertical(path[n - 2], path[n - 1]) {
+                points[n - 2].x = points[n - 1].x;
+            } else {
+                points[n - 2].y = points[n - 1].y;
+            }
+        }
+
+        let mut aligned = vec![points[0]];
+        for (i, pair) in points.windows(2).enumerate() {
+            let (from, to) = (pair[0], pair[1]);
+
+            if from.x != to.x && from.y != to.y {
+                let cell = (path[i] + path[i + 1]) / 2;
+                let middle = Self::calculate_origin(config, cell) + Self::cell_center(config, cell);
+                if vertical(path[i], path[i + 1]) {
+                    aligned.extend([pos(from.x, middle.y), pos(to.x, middle.y)]);
+                } else {
+                    aligned.extend([pos(middle.x, from.y), pos(middle.x, to.y)]);
+                }
+            }
+
+            aligned.push(to);
+        }
+
+        *points = aligned;
+    }
+
+    /// The center of a node's or gap's cell, relative to its origin.
+    fn cell_center(config: &RenderConfig, point: PaddedPos) -> PixelPos {
+        let x = if point.grid_x_aligned() {
+            config.node.x / 2.0
+        } else {
+            config.grid_gap.x / 2.0
+        };
+        let y = if point.grid_y_aligned() {
+            config.node.y / 2.0
+        } else {
+            config.grid_gap.y / 2.0
+        };
+
+        pos(x, y)
+    }
+
+    fn get_link_point_offset<'i>(
+        config: &RenderConfig,
+        flou: &Flou<'i>,
+        point: PaddedPos,
+        dir: Direction,
+    ) -> PixelPos {
+        let empty_offset = Self::cell_center(config, point);
+
+        if !point.grid_aligned() || matches!(flou.grid.get_id(point.into()), Some(None)) {
+            return empty_offset;
+        }
+
+        let origin = Self::calculate_node_origin(config, point.into());
+        let viewport = Viewport::new(origin, config.node);
+
+        match flou.node_attributes.get(&IndexPos::from(point)) {
+            Some(attrs) => attrs.link_point(viewport, dir),
+            None => NodeAttributes::default().link_point(viewport, dir),
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::{parts::RenderConfig, pos::pos, test::assert_eq};
-
-    use super::SvgRenderer;
+    use std::convert::TryFrom;
+
+    use crate::{
+        parse::ast::Direction,
+        parts::{
+            Background, BackgroundGrid, ConnectionStyle, Flou, FlouError, GridView, LegendPosition,
+            RenderConfig, Renderer, Unit,
+        },
+        pos::{pos, GridCoord, PixelPos},
+        render_svg::ChannelRouter,
+        test::assert_eq,
+    };
+
+    use super::{
+        render_svg, ConnectionVisit, NodeVisit, PaddedPos, PathRouter, PathSegment, Rect,
+        RenderVisitor, SvgRenderer,
+    };
 
     #[test]
     fn calculates_origin_without_grid_gap() {
         let config = &RenderConfig {
-            node: pos(50, 100),
-            grid_gap: pos(0, 0),
+            node: pos(50.0, 100.0),
+            grid_gap: pos(0.0, 0.0),
             ..Default::default()
         };
 
         let actual = SvgRenderer::calculate_node_origin(config, pos(0, 0));
-        assert_eq!(actual, pos(0, 0));
+        assert_eq!(actual, pos(0.0, 0.0));
 
         let actual = SvgRenderer::calculate_node_origin(config, pos(2, 0));
-        assert_eq!(actual, pos(100, 0));
+        assert_eq!(actual, pos(100.0, 0.0));
 
         let actual = SvgRenderer::calculate_node_origin(config, pos(1, 3));
-        assert_eq!(actual, pos(50, 300));
+        assert_eq!(actual, pos(50.0, 300.0));
     }
 
     #[test]
     fn calculates_origin_with_grid_gap() {
         let config = &RenderConfig {
-            node: pos(50, 100),
-            grid_gap: pos(10, 20),
+            node: pos(50.0, 100.0),
+            grid_gap: pos(10.0, 20.0),
             ..Default::default()
         };
 
         let actual = SvgRenderer::calculate_node_origin(config, pos(0, 0));
-        assert_eq!(actual, pos(10, 20));
+        assert_eq!(actual, pos(10.0, 20.0));
 
         let actual = SvgRenderer::calculate_node_origin(config, pos(2, 0));
-        assert_eq!(actual, pos(130, 20));
+        assert_eq!(actual, pos(130.0, 20.0));
 
         let actual = SvgRenderer::calculate_node_origin(config, pos(1, 3));
-        assert_eq!(actual, pos(70, 380));
+        assert_eq!(actual, pos(70.0, 380.0));
+    }
+
+    #[test]
+    fn hooks_modify_rendered_elements() {
+        let flou = Flou::try_from("grid { a(connect: s:n@s); b; }").unwrap();
+        let config = RenderConfig::default();
+
+        let renderer = SvgRenderer::new()
+            .on_node(|ctx, el| el.attr("data-id", ctx.id.to_string()))
+            .on_connection(|ctx, el| el.attr("data-from", ctx.from.0.to_string()))
+            .on_root(|el| el.attr("data-root", "yes"));
+
+        let output = renderer.render(&flou, &config).to_string();
+
+        assert!(output.contains(r#"data-id="a""#));
+        assert!(output.contains(r#"data-id="b""#));
+        assert!(output.contains(r#"data-from="(row 0, col 0)""#));
+        assert!(output.contains(r#"data-root="yes""#));
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    #[test]
+    fn hooks_dont_have_to_be_sync_without_parallel() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let flou = Flou::try_from("grid { a; b; }").unwrap();
+        let config = RenderConfig::default();
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let renderer = SvgRenderer::new().on_node({
+            let seen = Rc::clone(&seen);
+            move |ctx, el| {
+                seen.borrow_mut().push(ctx.id.to_string());
+                el
+            }
+        });
+        renderer.render(&flou, &config).to_string();
+
+        assert_eq!(*seen.borrow(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn connections_are_drawn_by_the_router() {
+        struct LeftGapRouter;
+
+        impl PathRouter for LeftGapRouter {
+            fn route(
+                &self,
+                _: &GridView,
+                from: (GridCoord, Direction),
+                to: (GridCoord, Direction),
+            ) -> Vec<PaddedPos> {
+                let (from, to) = (PaddedPos::from(from.0), PaddedPos::from(to.0));
+                vec![from, pos(0, from.y), pos(0, to.y), to]
+            }
+        }
+
+        struct BrokenRouter;
+
+        impl PathRouter for BrokenRouter {
+            fn route(
+                &self,
+                _: &GridView,
+                _: (GridCoord, Direction),
+                _: (GridCoord, Direction),
+            ) -> Vec<PaddedPos> {
+                vec![pos(0, 0), pos(5, 5)]
+            }
+        }
+
+        let flou = Flou::try_from("grid { a(connect: s:n@s); b; }").unwrap();
+        let config = RenderConfig::default();
+        let render = |renderer: SvgRenderer| renderer.render(&flou, &config).to_string();
+
+        let output = render(SvgRenderer::new().router(LeftGapRouter));
+        assert!(output.contains(r#"d="M 50 100 L 25 100 L 25 250 L 50 250""#));
+
+        assert_eq!(
+            render(SvgRenderer::new().router(BrokenRouter)),
+            render(SvgRenderer::new())
+        );
+    }
+
+    #[test]
+    fn lanes_are_drawn_side_by_side() {
+        let flou =
+            Flou::try_from("grid { a(connect: s:n#d), b(connect: s:n#c); c#c, d#d; }").unwrap();
+        let output = SvgRenderer::new()
+            .router(ChannelRouter)
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        assert!(output.contains(r#"d="M 150 150 L 150 166.667 L 400 166.667 L 400 200""#));
+        assert!(output.contains(r#"d="M 400 150 L 400 183.333 L 150 183.333 L 150 200""#));
+    }
+
+    #[test]
+    fn anchors_move_link_points() {
+        // Straight connections jog in the gap.
+        let render = |source| {
+            let flou = Flou::try_from(source).unwrap();
+            let output = SvgRenderer::new()
+                .render(&flou, &RenderConfig::default())
+                .to_string();
+            output
+        };
+
+        let output = render("grid { a(connect: s:n@s, anchors: {s: 25%}); b; }");
+        assert!(output.contains(r#"d="M 100 150 L 100 175 L 150 175 L 150 200""#));
+
+        // Turns are moved in line with the anchor.
+        let output = render("grid { a(connect: s:n#d, anchors: {s: 25%}), b; _, d#d; }");
+        assert!(output.contains(r#"d="M 100 150 L 100 175 L 400 175 L 400 200""#));
+    }
+
+    #[test]
+    fn render_svg_parses_and_renders() {
+        let config = RenderConfig::default();
+        let flou = Flou::try_from("grid { a; }").unwrap();
+
+        assert_eq!(
+            render_svg("grid { a; }", &config).unwrap(),
+            SvgRenderer::new().render(&flou, &config).to_string()
+        );
+        assert!(matches!(
+            render_svg("grid { a }", &config),
+            Err(FlouError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn normalized_output_leaves_out_volatile_content() {
+        let flou = Flou::try_from("grid { a(class: \"z\"); }").unwrap();
+        let config = RenderConfig {
+            standalone: true,
+            css: vec![String::from(".z { fill: red; }")],
+            ..Default::default()
+        };
+
+        let output = SvgRenderer::new().render_normalized(&flou, &config);
+        assert!(output.starts_with(r#"<svg class="flou" height="200" width="300" xmlns="#));
+        assert!(!output.contains("<style"));
+        assert!(!output.contains("<?xml"));
+    }
+
+    #[test]
+    fn layout_matches_rendered_output() {
+        let flou = Flou::try_from("grid { a#start(connect: s:n@s); b(shape: circle); }").unwrap();
+        let config = RenderConfig::default();
+        let renderer = SvgRenderer::new();
+
+        let (output, layout) = renderer.render_with_layout(&flou, &config);
+        assert_eq!(layout, renderer.layout(&flou, &config));
+
+        let nodes = layout
+            .nodes
+            .iter()
+            .map(|n| {
+                (
+                    n.position,
+                    n.id.as_str(),
+                    n.label.map(|l| l.as_str()),
+                    n.origin,
+                    n.size,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                (
+                    GridCoord::new(0, 0),
+                    "a",
+                    Some("start"),
+                    pos(50.0, 50.0),
+                    pos(200.0, 100.0)
+                ),
+                (
+                    GridCoord::new(1, 0),
+                    "b",
+                    None,
+                    pos(100.0, 200.0),
+                    pos(100.0, 100.0)
+                ),
+            ]
+        );
+
+        assert_eq!(layout.connections.len(), 1);
+        let connection = &layout.connections[0];
+        assert_eq!(connection.from, (GridCoord::new(0, 0), Direction::South));
+        assert_eq!(
+            connection.points,
+            vec![pos(150.0, 150.0), pos(150.0, 200.0)]
+        );
+        assert!(output.to_string().contains(r#"d="M 150 150 L 150 200""#));
+    }
+
+    #[test]
+    fn visitor_receives_what_would_be_rendered() {
+        #[derive(Default)]
+        struct Collect<'i> {
+            size: Option<PixelPos>,
+            nodes: Vec<NodeVisit<'i>>,
+            connections: Vec<ConnectionVisit<'i>>,
+            segments: Vec<PathSegment>,
+        }
+
+        impl<'i> RenderVisitor<'i> for Collect<'i> {
+            fn visit_flowchart(&mut self, size: PixelPos) {
+                self.size = Some(size);
+            }
+
+            fn visit_node(&mut self, node: &NodeVisit<'i>) {
+                self.nodes.push(node.clone());
+            }
+
+            fn visit_connection(&mut self, connection: &ConnectionVisit<'i>) {
+                self.connections.push(connection.clone());
+            }
+
+            fn visit_path_segment(&mut self, segment: &PathSegment) {
+                self.segments.push(*segment);
+            }
+        }
+
+        let flou = Flou::try_from(
+            r#"grid { a("Hello", class: "x", connect: s:n@s("Yes", route: curve)); b(shape: circle, hidden: true); }"#,
+        )
+        .unwrap();
+        let config = RenderConfig::default();
+        let renderer = SvgRenderer::new();
+        let layout = renderer.layout(&flou, &config);
+
+        let mut visitor = Collect::default();
+        renderer.visit(&flou, &config, &mut visitor);
+
+        assert_eq!(visitor.size, Some(layout.size));
+        let nodes = visitor
+            .nodes
+            .iter()
+            .map(|n| n.layout.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(nodes, layout.nodes);
+        assert_eq!(visitor.nodes[0].text.as_deref(), Some("Hello"));
+        assert_eq!(visitor.nodes[0].class, Some("x"));
+        assert_eq!(visitor.nodes[0].text_center, pos(150.0, 100.0));
+        assert!(visitor.nodes[1].hidden);
+
+        let connection = &visitor.connections[0];
+        assert_eq!(connection.layout, layout.connections[0]);
+        assert_eq!(connection.text.map(|(text, _)| text), Some("Yes"));
+        assert_eq!(connection.arrowheads.len(), 1);
+        assert_eq!(connection.arrowheads[0][0], pos(150.0, 200.0));
+        assert_eq!(
+            visitor.segments,
+            vec![PathSegment::Curve {
+                from: pos(150.0, 150.0),
+                c1: pos(150.0, 150.0 + 50.0 / 3.0),
+                c2: pos(150.0, 200.0 - 50.0 / 3.0),
+                to: pos(150.0, 200.0),
+            }]
+        );
+    }
+
+    #[test]
+    fn anchored_link_points_touch_the_outline() {
+        let render = |shape| {
+            let source = format!(
+                "grid {{ a(shape: {}, connect: s:n@s, anchors: {{s: 25%}}); b; }}",
+                shape
+            );
+            let flou = Flou::try_from(source.as_str()).unwrap();
+            let output = SvgRenderer::new()
+                .render(&flou, &RenderConfig::default())
+                .to_string();
+            output
+        };
+
+        assert!(render("rect").contains(r#"d="M 100 150 L 100 175 "#));
+        assert!(render("diamond").contains(r#"d="M 100 125 L 100 175 "#));
+        assert!(render("ellipse").contains(r#"d="M 100 143.301 L 100 175 "#));
+    }
+
+    #[test]
+    fn rectangles_can_have_rounded_corners() {
+        let flou =
+            Flou::try_from("grid { a(radius: 8), b(shape: diamond, radius: 8), c; }").unwrap();
+        let config = RenderConfig {
+            corner_radius: 4.0,
+            ..Default::default()
+        };
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+
+        assert_eq!(output.matches(r#"rx="8" ry="8""#).count(), 1);
+        assert_eq!(output.matches(r#"rx="4" ry="4""#).count(), 1);
+    }
+
+    #[test]
+    fn shapes_keep_their_aspect_ratio() {
+        let render = |attrs| {
+            let source = format!("grid {{ a({}); }}", attrs);
+            let flou = Flou::try_from(source.as_str()).unwrap();
+            let output = SvgRenderer::new()
+                .render(&flou, &RenderConfig::default())
+                .to_string();
+            output
+        };
+
+        // Cells are 200x100.
+        let output = render("shape: diamond, aspect: 1");
+        assert!(output.contains(r#"d="M 150 50 L 100 100 L 150 150 L 200 100 Z""#));
+
+        let output = render("shape: ellipse, aspect: 4, fit: cover");
+        assert!(output.contains(r#"rx="200" ry="50""#));
+
+        let output = render("shape: square, fit: cover");
+        assert!(output.contains(r#"x="50" y="0" width="200" height="200""#));
+
+        let output = render("shape: rect, aspect: 1, fit: stretch");
+        assert!(output.contains(r#"x="50" y="50" width="200" height="100""#));
+    }
+
+    #[test]
+    fn curved_connections_pass_through_their_route() {
+        let render = |source| {
+            let flou = Flou::try_from(source).unwrap();
+            let config = RenderConfig {
+                connection_style: ConnectionStyle::Curved,
+                ..Default::default()
+            };
+            let output = SvgRenderer::new().render(&flou, &config).to_string();
+            output
+        };
+
+        let output = render("grid { a(connect: s:n@s); b; }");
+        assert!(output.contains(r#"d="M 150 150 C 150 166.667, 150 183.333, 150 200""#));
+
+        // The curve starts and ends head-on, and passes through each turn.
+        let output = render("grid { a(connect: s:n#d), b; _, d#d; }");
+        assert!(output.contains(r#"d="M 150 150 C 150 158.333, "#));
+        assert!(output.contains(", 400 175 C "));
+        assert!(output.contains(r#", 400 191.667, 400 200""#));
+    }
+
+    #[test]
+    fn connections_can_override_how_they_are_drawn() {
+        let render = |source, connection_style| {
+            let flou = Flou::try_from(source).unwrap();
+            let config = RenderConfig {
+                connection_style,
+                ..Default::default()
+            };
+            let (output, layout) = SvgRenderer::new().render_with_layout(&flou, &config);
+            let points = layout.connections[0].points.clone();
+            (output.to_string(), points)
+        };
+
+        let (output, points) = render(
+            "grid { a(connect: s:n#d(route: straight)), b; _, d#d; }",
+            ConnectionStyle::Orthogonal,
+        );
+        assert!(output.contains(r#"d="M 150 150 L 400 200""#));
+        assert_eq!(points, vec![pos(150.0, 150.0), pos(400.0, 200.0)]);
+
+        let (output, _) = render(
+            "grid { a(connect: s:n@s(route: elbow)); b; }",
+            ConnectionStyle::Curved,
+        );
+        assert!(output.contains(r#"d="M 150 150 L 150 200""#));
+
+        let (output, _) = render(
+            "grid { a(connect: s:n@s(route: curve)); b; }",
+            ConnectionStyle::Orthogonal,
+        );
+        assert!(output.contains(r#"d="M 150 150 C 150 166.667, 150 183.333, 150 200""#));
+    }
+
+    #[test]
+    fn arrowheads_of_straight_connections_point_along_the_line() {
+        let flou =
+            Flou::try_from("grid { a(connect: s:n#d(route: straight)), b; _, d#d; }").unwrap();
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        // The line runs from (150, 150) to (400, 200), so the arrowhead's
+        // center is 5 pixels back along it from the tip and its corners are
+        // on either side of the line instead of straight above the tip.
+        assert!(output.contains(r#"d="M 150 150 L 400 200""#));
+        assert!(output.contains(
+            r#"d="M 400 200 L 391.175 193.136 L 395.097 199.019 L 389.214 202.942 L 400 200""#
+        ));
+    }
+
+    #[test]
+    fn connections_turn_around_their_nodes_instead_of_through_them() {
+        let points = |source| {
+            let flou = Flou::try_from(source).unwrap();
+            let config = RenderConfig::default();
+            let (_, layout) = SvgRenderer::new().render_with_layout(&flou, &config);
+            layout.connections[0].points.clone()
+        };
+
+        assert_eq!(
+            points("grid { a(shape: diamond, connect: e:n@s); b; }"),
+            vec![
+                pos(250.0, 100.0),
+                pos(275.0, 100.0),
+                pos(275.0, 175.0),
+                pos(150.0, 175.0),
+                pos(150.0, 200.0),
+            ]
+        );
+        assert_eq!(
+            points("grid { a(connect: e:n@); }"),
+            vec![
+                pos(250.0, 100.0),
+                pos(275.0, 100.0),
+                pos(275.0, 25.0),
+                pos(150.0, 25.0),
+                pos(150.0, 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn connections_stop_short_of_node_outlines() {
+        let flou = Flou::try_from("grid { a(connect: s:n@s); b; }").unwrap();
+        let config = RenderConfig {
+            stroke_width: Some(4.0),
+            endpoint_margin: Some(1.0),
+            ..Default::default()
+        };
+
+        let (output, layout) = SvgRenderer::new().render_with_layout(&flou, &config);
+
+        assert!(output.to_string().contains(r#"d="M 150 153 L 150 197""#));
+        assert_eq!(
+            layout.connections[0].points,
+            vec![pos(150.0, 153.0), pos(150.0, 197.0)]
+        );
+    }
+
+    #[test]
+    fn renders_only_what_overlaps_a_region() {
+        let flou = Flou::try_from(r#"grid { a(connect: s:n@s); b; _; c("Last"); }"#).unwrap();
+        let config = RenderConfig::default();
+        let render = |region| {
+            SvgRenderer::new()
+                .render_region(&flou, &config, region)
+                .to_string()
+        };
+
+        let output = render(Rect::new(pos(0.0, 120.0), pos(300.0, 50.0)));
+        assert!(output.contains(r#"width="300" height="50" viewBox="0 120 300 50""#));
+        assert_eq!(output.matches(r#"<g class="node-wrapper""#).count(), 1);
+        assert_eq!(output.matches(r#"<g class="connection""#).count(), 1);
+
+        let output = render(Rect::new(pos(0.0, 320.0), pos(300.0, 300.0)));
+        assert_eq!(output.matches(r#"<g class="node-wrapper""#).count(), 1);
+        assert!(output.contains("Last"));
+        assert_eq!(output.matches(r#"<g class="connection""#).count(), 0);
+    }
+
+    #[test]
+    fn untrusted_input_cannot_add_markup() {
+        let source = r#"
+grid {
+    a#a("<script>alert(1)</script>", class: "x\" onload=\"alert(1)", connect: s:n@s(id: "'><svg onload=alert(1)>"));
+    b("Bell: BELL");
+}
+legend {
+    "</g><script>" -> "<img src=x onerror=alert(1)>";
+}
+note #a "<style>* { display: none; }</style>";
+"#
+        .replace("BELL", "\u{7}");
+        let flou = Flou::try_from(source.as_str()).unwrap();
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        for markup in [
+            "<script",
+            "<svg onload",
+            "<img",
+            "<style>*",
+            "\" onload",
+            "\u{7}",
+        ] {
+            assert!(!output.contains(markup), "{}", markup);
+        }
+        assert!(output.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    }
+
+    #[test]
+    fn user_attributes_can_be_stripped() {
+        let source = r#"
+grid {
+    a(class: "hidden", connect: s:n@s(id: "main", class: "modal"));
+    b;
+}
+legend {
+    "modal" -> "Modal";
+}
+"#;
+        let flou = Flou::try_from(source).unwrap();
+        let config = RenderConfig {
+            strip_user_attributes: true,
+            ..Default::default()
+        };
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+
+        assert!(output.contains(r#"<g class="node-wrapper">"#));
+        assert!(output.contains(r#"<g class="connection">"#));
+        assert!(output.contains(r#"<g class="legend-entry">"#));
+        assert!(
+            !output.contains("hidden") && !output.contains("\"modal") && !output.contains("main")
+        );
+    }
+
+    #[test]
+    fn connection_ids_are_emitted() {
+        let flou = Flou::try_from(r#"grid { a(connect: s:n@s(id: "approve-edge")); b; }"#).unwrap();
+
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        assert!(output.contains(r#"<g class="connection" id="approve-edge">"#));
+    }
+
+    #[test]
+    fn connections_are_sorted_by_order() {
+        let flou = Flou::try_from(
+            r#"grid {
+                a(connect: s:n@s(id: "first", order: 1));
+                b(connect: {s:n@s(id: "second"); e:w@e(id: "third", order: -1)}), c;
+                d;
+            }"#,
+        )
+        .unwrap();
+
+        let ids = SvgRenderer::sorted_connections(&flou)
+            .into_iter()
+            .map(|c| c.attrs.id.as_deref().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(ids, vec!["third", "second", "first"]);
+    }
+
+    #[test]
+    fn arrowheads_scale_with_stroke_width() {
+        let flou = Flou::try_from("grid { a(connect: s:n@s); b; }").unwrap();
+
+        let render = |stroke_width| {
+            let config = RenderConfig {
+                stroke_width,
+                ..Default::default()
+            };
+            let output = SvgRenderer::new().render(&flou, &config).to_string();
+            output
+        };
+
+        // The arrowhead's tip is on the top edge of `b`, at (150, 200).
+        let output = render(None);
+        assert!(!output.contains(r#"stroke-width=""#));
+        assert!(output.contains(r#"d="M 150 200 L 155 190"#));
+
+        let output = render(Some(3.0));
+        assert!(output.contains(r#"stroke-width="3""#));
+        assert!(output.contains(r#"d="M 150 200 L 165 170"#));
+
+        let flou = Flou::try_from("grid { a(connect: s:n@s(weight: thick)); b; }").unwrap();
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+        assert!(output.contains(r#"stroke-width="2""#));
+        assert!(output.contains(r#"d="M 150 200 L 160 180"#));
+    }
+
+    #[test]
+    fn hidden_elements_keep_their_place() {
+        let flou = Flou::try_from(
+            "grid { a(hidden: true, connect: s:n@s(opacity: 0.5)); b(connect: s:n@s(hidden: true)); c; }",
+        )
+        .unwrap();
+
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        assert!(output.contains(r#"<g class="node-wrapper" visibility="hidden">"#));
+        assert!(output.contains(r#"<g class="connection" opacity="0.5">"#));
+        assert!(output.contains(r#"<g class="connection" visibility="hidden">"#));
+        assert_eq!(output.matches(r#"class="node-wrapper""#).count(), 3);
+    }
+
+    #[test]
+    fn headers_are_drawn_in_the_grid_gap() {
+        let flou = Flou::try_from(
+            r#"grid { rows: "Step 1", "Step 2", "Past the end"; cols: "", "Error path"; a, b; c; }"#,
+        )
+        .unwrap();
+
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        assert!(output
+            .contains(r#"<text class="header column-header" x="400" y="25">Error path</text>"#));
+        assert!(output.contains(
+            r#"<text class="header row-header" x="25" y="250" transform="rotate(-90 25 250)">Step 2</text>"#
+        ));
+        assert_eq!(output.matches("<text class=\"header").count(), 3);
+    }
+
+    #[test]
+    fn connections_to_empty_cells_end_in_their_middle() {
+        let flou = Flou::try_from("grid { a(connect: s:n@(0, 2)); _; _; }").unwrap();
+
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        assert!(output.contains(r#"d="M 150 150 L 150 175 L 150 325 L 150 400""#));
+    }
+
+    #[test]
+    fn spacers_are_not_drawn() {
+        let flou =
+            Flou::try_from(r#"grid { a(connect: s:n@s); gap("Hidden", shape: none); }"#).unwrap();
+
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        assert_eq!(output.matches(r#"class="rect node""#).count(), 1);
+        assert!(!output.contains("Hidden"));
+        // The connection ends in the middle of the spacer.
+        assert!(output.contains(r#"d="M 150 150 L 150 250""#));
+    }
+
+    #[test]
+    fn notes_are_drawn_next_to_their_node() {
+        let flou = Flou::try_from(
+            r#"grid { a#a(hidden: true); } note #a "Top" (side: n) note #a "Right""#,
+        )
+        .unwrap();
+
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        assert!(output.contains(r#"<path class="note-connector" d="M 150 50 L 150 30" />"#));
+        assert!(output.contains(r#"<path class="note-connector" d="M 250 100 L 270 100" />"#));
+        assert!(output.contains(r#">Right</text>"#));
+        assert_eq!(
+            output
+                .matches(r#"<g class="note" visibility="hidden">"#)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn legend_is_drawn_in_a_corner() {
+        let flou = Flou::try_from(
+            r#"grid { a; } legend { "solid" -> "sync call"; "dashed" -> "async"; }"#,
+        )
+        .unwrap();
+        let render = |legend_position| {
+            let config = RenderConfig {
+                legend_position,
+                ..Default::default()
+            };
+            let output = SvgRenderer::new().render(&flou, &config).to_string();
+            output
+        };
+
+        let output = render(LegendPosition::TopLeft);
+        assert!(output.contains(r#"<rect class="legend-background" x="10" y="10""#));
+        assert!(output.contains(r#"<g class="legend-entry dashed">"#));
+        assert!(output.contains(r#">sync call</text>"#));
+
+        let output = render(LegendPosition::BottomRight);
+        assert!(!output.contains(r#"x="10" y="10""#));
+        assert!(output.contains(r#">async</text>"#));
+    }
+
+    #[test]
+    fn odd_sizes_are_not_truncated() {
+        let flou = Flou::try_from(r#"grid { a("hi"); }"#).unwrap();
+        let config = RenderConfig {
+            node: pos(51.0, 101.0),
+            grid_gap: pos(0.0, 0.0),
+            ..Default::default()
+        };
+
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+
+        assert!(output.contains(r#"<text x="25.5" y="50.5">hi</text>"#));
+    }
+
+    #[test]
+    fn font_settings_are_emitted_and_used_for_wrapping() {
+        let flou = Flou::try_from(r#"grid { a("aaaa aaaa"); }"#).unwrap();
+        let config = RenderConfig {
+            node: pos(50.0, 50.0),
+            font_family: Some(String::from("monospace")),
+            font_size: Some(10.0),
+            ..Default::default()
+        };
+
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+
+        assert!(output.contains(r#"font-family="monospace" font-size="10""#));
+        assert!(output.contains(r#"<tspan x="75" dy="-0.5em">aaaa</tspan>"#));
+    }
+    #[test]
+    fn text_is_wrapped_inside_the_outline() {
+        let render = |shape| {
+            let source = format!(r#"grid {{ a("aaaa aaaa", shape: {}); }}"#, shape);
+            let flou = Flou::try_from(source.as_str()).unwrap();
+            let config = RenderConfig {
+                node: pos(100.0, 50.0),
+                font_family: Some(String::from("monospace")),
+                font_size: Some(10.0),
+                ..Default::default()
+            };
+            let output = SvgRenderer::new().render(&flou, &config).to_string();
+            output
+        };
+
+        // The text is 54 pixels wide.
+        assert!(render("rect").contains(">aaaa aaaa<"));
+        assert!(render("ellipse").contains(">aaaa aaaa<"));
+        assert!(render("diamond").contains(">aaaa</tspan>"));
+    }
+
+    #[test]
+    fn palettes_are_emitted_as_classes() {
+        let flou = Flou::try_from(r#"grid { a(palette: red, class: "x"); }"#).unwrap();
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+
+        assert!(output.contains(r#"<g class="node-wrapper palette-red x">"#));
+    }
+
+    #[test]
+    fn print_mode_is_black_and_white() {
+        let flou = Flou::try_from("grid { a(palette: blue, connect: s:n@s); b; }").unwrap();
+        let config = RenderConfig {
+            print_mode: true,
+            ..Default::default()
+        };
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+
+        assert!(output.contains(r#"width="300px" height="350px" viewBox="0 0 300 350""#));
+        assert!(output.contains("fill: url(#flou-print-blue);"));
+        assert!(output.contains(r#"id="flou-print-blue">"#));
+        assert!(output.contains(r#"stroke-width="2""#));
+    }
+
+    #[test]
+    fn background_grid_is_aligned_to_cells() {
+        let flou = Flou::try_from("grid { a; }").unwrap();
+        let config = RenderConfig {
+            background_grid: BackgroundGrid::Lines,
+            ..Default::default()
+        };
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+
+        // Cells are 250x150 and the first gap is centered at (25, 25).
+        assert!(output.contains(
+            r#"<pattern x="-100" y="-50" width="250" height="150" patternUnits="userSpaceOnUse" id="flou-background-grid">"#
+        ));
+        assert!(output.contains(r#"d="M 125 0 L 125 150 M 0 75 L 250 75""#));
+        assert!(output.contains(r#"fill="url(#flou-background-grid)""#));
+
+        let output = SvgRenderer::new()
+            .render(&flou, &RenderConfig::default())
+            .to_string();
+        assert!(!output.contains("<pattern"));
+    }
+
+    #[test]
+    fn background_can_be_transparent_or_colored() {
+        let flou = Flou::try_from("grid { a; }").unwrap();
+        let render = |background| {
+            let config = RenderConfig {
+                background,
+                ..Default::default()
+            };
+            let output = SvgRenderer::new().render(&flou, &config).to_string();
+            output
+        };
+
+        assert!(render(Background::Default).contains(r#"<rect class="background""#));
+        assert!(!render(Background::Transparent).contains(r#"<rect class="background""#));
+        assert!(render(Background::Color(String::from("#123")))
+            .contains(r#"width="300" height="200" style="fill: #123" />"#));
+    }
+
+    #[test]
+    fn size_can_be_given_in_physical_units() {
+        let flou = Flou::try_from("grid { a; }").unwrap();
+        let config = RenderConfig {
+            unit: Some(Unit::Mm),
+            dpi: 25.4,
+            ..Default::default()
+        };
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+
+        assert!(output.contains(r#"width="300mm" height="200mm" viewBox="0 0 300 200""#));
+    }
+
+    #[test]
+    fn css_variables_are_opt_in() {
+        let flou = Flou::try_from("grid { a; }").unwrap();
+
+        let config = RenderConfig {
+            font_size: Some(12.0),
+            ..Default::default()
+        };
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+        assert!(output.contains(r#"<svg class="flou""#));
+        assert!(!output.contains("--flou-"));
+        assert!(!output.contains("var("));
+        assert!(!output.contains(":where"));
+        assert!(output.contains("stroke: #1e1e1e;"));
+        assert!(output.contains("font-size: 12px;"));
+
+        let config = RenderConfig {
+            css_variables: true,
+            ..Default::default()
+        };
+        let output = SvgRenderer::new().render(&flou, &config).to_string();
+        assert!(output.contains(":where(.flou) {"));
+        assert!(output.contains("--flou-node-stroke: #1e1e1e;"));
+        assert!(output.contains("stroke: var(--flou-node-stroke);"));
+    }
+
+    #[test]
+    fn padding_narrows_the_text_area() {
+        let render = |source, node_padding| {
+            let flou = Flou::try_from(source).unwrap();
+            let config = RenderConfig {
+                node: pos(100.0, 50.0),
+                font_family: Some(String::from("monospace")),
+                font_size: Some(10.0),
+                node_padding,
+                ..Default::default()
+            };
+            let output = SvgRenderer::new().render(&flou, &config).to_string();
+            output
+        };
+
+        let output = render(r#"grid { a("aaaa aaaa"); }"#, 0.0);
+        assert!(output.contains(">aaaa aaaa<"));
+
+        let output = render(r#"grid { a("aaaa aaaa"); }"#, 30.0);
+        assert!(output.contains(">aaaa</tspan>"));
+
+        let output = render(r#"grid { a("aaaa aaaa", padding: 25); }"#, 0.0);
+        assert!(output.contains(">aaaa</tspan>"));
     }
 }
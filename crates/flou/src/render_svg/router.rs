@@ -0,0 +1,109 @@
+use crate::{
+    parse::ast::Direction,
+    parts::GridView,
+    pos::{GridCoord, IndexPos},
+    render_svg::{hooks::ConnectionContext, path::get_path, renderer::PaddedPos},
+};
+
+/// Decides which way connections are drawn through the grid.
+///
+/// A route is a list of points in [`PaddedPos`] coordinates. It starts at
+/// the center of the source node, ends at the center of the destination
+/// node and only turns at right angles, so every point has to be in a
+/// straight horizontal or vertical line with the one before it. Routes
+/// that don't follow these rules are drawn with [`CornerRouter`] instead.
+pub trait PathRouter: Send + Sync {
+    /// Returns the route of a connection leaving the `from` node on the
+    /// given side and entering the `to` node on the given side.
+    fn route(
+        &self,
+        grid: &GridView<'_, '_>,
+        from: (GridCoord, Direction),
+        to: (GridCoord, Direction),
+    ) -> Vec<PaddedPos>;
+
+    /// Routes all connections of a flowchart at once, returning one route
+    /// for each of `connections` in the same order. Routers that take the
+    /// other connections into account, such as to avoid crossings, do so
+    /// here. By default, each connection is routed on its own.
+    fn route_all(&self, grid: &GridView<'_, '_>, connections: &[ConnectionContext]) -> Vec<Route> {
+        connections
+            .iter()
+            .map(|c| Route::new(self.route(grid, c.from, c.to)))
+            .collect()
+    }
+}
+
+/// A routed connection.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Route {
+    /// Points of the route, as described in [`PathRouter`].
+    pub points: Vec<PaddedPos>,
+    /// The lane of each segment between two points. Segments that run
+    /// through the same grid gap can be put in different lanes to draw
+    /// them side by side instead of on top of each other. Segments without
+    /// a lane, including all of them if this is empty, are drawn in the
+    /// middle of the gap.
+    pub lanes: Vec<Lane>,
+}
+
+impl Route {
+    /// A route with every segment in the middle of its grid gap.
+    pub fn new(points: Vec<PaddedPos>) -> Self {
+        Self {
+            points,
+            lanes: Vec::new(),
+        }
+    }
+}
+
+/// One of the evenly spaced lanes that a grid gap is split into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Lane {
+    /// The lane, counting from the left or top of the gap.
+    pub index: usize,
+    /// Number of lanes in the gap.
+    pub count: usize,
+}
+
+impl Default for Lane {
+    fn default() -> Self {
+        Self { index: 0, count: 1 }
+    }
+}
+
+impl Lane {
+    /// How far from the middle of the gap the lane is, as a fraction of
+    /// the gap's width.
+    pub(crate) fn offset(&self) -> f64 {
+        (self.index + 1) as f64 / (self.count + 1) as f64 - 0.5
+    }
+}
+
+/// The default router. Each connection is routed on its own, going around
+/// nodes in the way through the grid gap next to them and turning at most
+/// a few times.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CornerRouter;
+
+impl PathRouter for CornerRouter {
+    fn route(
+        &self,
+        grid: &GridView<'_, '_>,
+        from: (GridCoord, Direction),
+        to: (GridCoord, Direction),
+    ) -> Vec<PaddedPos> {
+        get_path(grid.inner(), (from.0.into(), from.1), (to.0.into(), to.1))
+    }
+}
+
+/// Whether `path` can be drawn: it has at least two points, begins and
+/// ends at the given nodes and only has horizontal and vertical segments.
+pub(crate) fn is_valid_route(path: &[PaddedPos], from: IndexPos, to: IndexPos) -> bool {
+    path.len() >= 2
+        && path.first() == Some(&from.into())
+        && path.last() == Some(&to.into())
+        && path
+            .windows(2)
+            .all(|pair| PaddedPos::straight_line(pair[0], pair[1]).is_some())
+}
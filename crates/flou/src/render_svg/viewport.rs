@@ -16,24 +16,13 @@ pub(crate) struct Midpoints {
     pub(crate) right: PixelPos,
 }
 
-impl Midpoints {
-    pub(crate) fn get_from_direction(&self, dir: Direction) -> PixelPos {
-        match dir {
-            Direction::North => self.top,
-            Direction::East => self.right,
-            Direction::West => self.left,
-            Direction::South => self.bottom,
-        }
-    }
-}
-
 impl Viewport {
     pub(crate) fn new(origin: PixelPos, size: PixelPos) -> Self {
         Self { origin, size }
     }
 
     pub(crate) fn center(&self) -> PixelPos {
-        self.origin + self.size / 2
+        self.origin + self.size / 2.0
     }
 
     pub(crate) fn midpoints(&self) -> Midpoints {
@@ -48,13 +37,41 @@ impl Viewport {
     }
 
     pub(crate) fn midpoints_relative(&self) -> Midpoints {
-        let half = self.size / 2;
+        let half = self.size / 2.0;
 
         Midpoints {
-            top: pos(half.x, 0),
+            top: pos(half.x, 0.0),
             bottom: pos(half.x, self.size.y),
-            left: pos(0, half.y),
+            left: pos(0.0, half.y),
             right: pos(self.size.x, half.y),
         }
     }
+
+    /// The viewport scaled by `factor`, keeping its center.
+    pub(crate) fn scale(&self, factor: f64) -> Self {
+        let size = self.size * factor;
+        Self::new(self.center() - size / 2.0, size)
+    }
+
+    /// The viewport shrunk by `by` on every side, keeping its center. Never
+    /// shrinks below a size of 0.
+    pub(crate) fn inset(&self, by: f64) -> Self {
+        let size = pos(
+            (self.size.x - by * 2.0).max(0.0),
+            (self.size.y - by * 2.0).max(0.0),
+        );
+
+        Self::new(self.center() - size / 2.0, size)
+    }
+
+    /// The point on the `dir` side, `fraction` of the way along it from its
+    /// top or left end, relative to the origin.
+    pub(crate) fn side_point_relative(&self, dir: Direction, fraction: f64) -> PixelPos {
+        match dir {
+            Direction::North => pos(self.size.x * fraction, 0.0),
+            Direction::South => pos(self.size.x * fraction, self.size.y),
+            Direction::West => pos(0.0, self.size.y * fraction),
+            Direction::East => pos(self.size.x, self.size.y * fraction),
+        }
+    }
 }
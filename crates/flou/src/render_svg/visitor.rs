@@ -0,0 +1,84 @@
+use std::borrow::Cow;
+
+use crate::{
+    parse::ast::{NodeShape, Palette},
+    pos::PixelPos,
+};
+
+use super::layout::{ConnectionLayout, NodeLayout};
+
+/// Receives the laid out parts of a flowchart from [`SvgRenderer::visit`],
+/// in the order they're drawn, for drawing them without SVG. Every method
+/// does nothing by default.
+///
+/// [`SvgRenderer::visit`]: super::SvgRenderer::visit
+pub trait RenderVisitor<'i> {
+    /// Called first, with the width and height of the flowchart.
+    fn visit_flowchart(&mut self, _size: PixelPos) {}
+
+    /// Called for every node, including spacers, which have no shape.
+    fn visit_node(&mut self, _node: &NodeVisit<'i>) {}
+
+    /// Called for every connection, after all of the nodes.
+    fn visit_connection(&mut self, _connection: &ConnectionVisit<'i>) {}
+
+    /// Called for each segment of a connection's path, from its source to
+    /// its destination, right after the connection itself.
+    fn visit_path_segment(&mut self, _segment: &PathSegment) {}
+}
+
+/// A node, as passed to [`RenderVisitor::visit_node`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeVisit<'i> {
+    /// Where the node is. Its shape fills the box of the layout.
+    pub layout: NodeLayout<'i>,
+    pub shape: NodeShape,
+    /// Radius of the corners of rectangles and squares.
+    pub corner_radius: f64,
+    /// The node's text, already wrapped to fit inside its shape, with a
+    /// line break between lines.
+    pub text: Option<Cow<'i, str>>,
+    /// The point the text is centered on.
+    pub text_center: PixelPos,
+    /// The node's `class` attribute, unless user attributes are stripped.
+    pub class: Option<&'i str>,
+    pub palette: Option<Palette>,
+    pub opacity: Option<f64>,
+    /// Hidden nodes take up their place, but aren't drawn.
+    pub hidden: bool,
+}
+
+/// A connection, as passed to [`RenderVisitor::visit_connection`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionVisit<'i> {
+    /// Where the connection is. Its segments are passed separately.
+    pub layout: ConnectionLayout<'i>,
+    /// The connection's text, along with the point it's centered on.
+    pub text: Option<(&'i str, PixelPos)>,
+    /// The connection's `class` attribute, unless user attributes are
+    /// stripped.
+    pub class: Option<&'i str>,
+    pub stroke_width: f64,
+    /// The outline of each arrowhead, from its tip around to its tip again.
+    pub arrowheads: Vec<Vec<PixelPos>>,
+    pub opacity: Option<f64>,
+    /// Hidden connections aren't drawn.
+    pub hidden: bool,
+}
+
+/// A piece of a connection's path, as passed to
+/// [`RenderVisitor::visit_path_segment`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathSegment {
+    Line {
+        from: PixelPos,
+        to: PixelPos,
+    },
+    /// A cubic Bézier curve with the control points `c1` and `c2`.
+    Curve {
+        from: PixelPos,
+        c1: PixelPos,
+        c2: PixelPos,
+        to: PixelPos,
+    },
+}
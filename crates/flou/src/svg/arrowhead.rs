@@ -1,5 +1,4 @@
 use crate::{
-    parse::ast::Direction,
     pos::{pos, PixelPos},
     render_svg::Viewport,
     svg::{SVGElement, SVGPath},
@@ -31,26 +30,36 @@ impl ArrowHead {
     /// `viewport.origin` is the tip of the arrowhead.
     /// `viewport.size.x` is the wingspan of the arrowhead.
     /// `viewport.size.y` is the length of the arrowhead.
-    /// `dir` is the direction the arrowhead is facing.
-    pub(crate) fn render(viewport: Viewport, dir: Direction) -> SVGElement<'static> {
-        Self::get_points(viewport, dir).render()
+    /// `heading` is the direction the arrowhead is facing, at any angle and
+    /// of any length other than 0.
+    pub(crate) fn render(viewport: Viewport, heading: PixelPos) -> SVGElement<'static> {
+        Self::get_points(viewport, heading).render()
     }
 
-    fn get_points(viewport: Viewport, dir: Direction) -> ArrowheadPoints {
-        let dir = dir.reverse();
-
-        let size = match dir {
-            Direction::East | Direction::West => pos(viewport.size.y, viewport.size.x),
-            _ => viewport.size,
-        };
+    /// The outline of the arrowhead, from its tip around to its tip again.
+    pub(crate) fn outline(viewport: Viewport, heading: PixelPos) -> Vec<PixelPos> {
+        let points = Self::get_points(viewport, heading);
+        vec![
+            points.tip,
+            points.left_corner,
+            points.center,
+            points.right_corner,
+            points.tip,
+        ]
+    }
 
-        let center = viewport.origin + PixelPos::from(dir) * size / 2;
+    fn get_points(viewport: Viewport, heading: PixelPos) -> ArrowheadPoints {
+        // Unit vectors pointing from the tip back along the arrowhead, and
+        // across it to its left corner.
+        let back = -heading / heading.x.hypot(heading.y);
+        let across = pos(-back.y, back.x);
 
-        let left_corner = PixelPos::from(dir) + PixelPos::from(dir.rotate_clockwise());
-        let left_corner = center + left_corner * size / 2;
+        let wingspan = viewport.size.x / 2.0;
+        let length = viewport.size.y / 2.0;
 
-        let right_corner = PixelPos::from(dir) + PixelPos::from(dir.rotate_counter_clockwise());
-        let right_corner = center + right_corner * size / 2;
+        let center = viewport.origin + back * length;
+        let left_corner = center + back * length + across * wingspan;
+        let right_corner = center + back * length - across * wingspan;
 
         ArrowheadPoints {
             tip: viewport.origin,
@@ -69,29 +78,29 @@ mod tests {
 
     #[test]
     fn points_are_correct() {
-        let viewport = Viewport::new(pos(100, 100), pos(20, 40));
-        let actual = ArrowHead::get_points(viewport, Direction::North);
+        let viewport = Viewport::new(pos(100.0, 100.0), pos(20.0, 40.0));
+        let actual = ArrowHead::get_points(viewport, Direction::North.into());
 
         assert_eq!(
             actual,
             ArrowheadPoints {
-                tip: pos(100, 100),
-                center: pos(100, 120),
-                left_corner: pos(90, 140),
-                right_corner: pos(110, 140)
+                tip: pos(100.0, 100.0),
+                center: pos(100.0, 120.0),
+                left_corner: pos(90.0, 140.0),
+                right_corner: pos(110.0, 140.0)
             }
         );
 
-        let viewport = Viewport::new(pos(200, 200), pos(20, 40));
-        let actual = ArrowHead::get_points(viewport, Direction::East);
+        let viewport = Viewport::new(pos(200.0, 200.0), pos(20.0, 40.0));
+        let actual = ArrowHead::get_points(viewport, Direction::East.into());
 
         assert_eq!(
             actual,
             ArrowheadPoints {
-                tip: pos(200, 200),
-                center: pos(180, 200),
-                left_corner: pos(160, 190),
-                right_corner: pos(160, 210),
+                tip: pos(200.0, 200.0),
+                center: pos(180.0, 200.0),
+                left_corner: pos(160.0, 190.0),
+                right_corner: pos(160.0, 210.0),
             }
         )
     }
@@ -0,0 +1,94 @@
+use std::borrow::Cow;
+
+use crate::svg::SVGElement;
+
+/// Shared definitions, such as patterns, markers, gradients and symbols,
+/// that are emitted once in a `<defs>` element and referred to by id.
+///
+/// Defining an id again keeps the first definition, so a definition can
+/// be added by everything that uses it without checking whether it's
+/// already there.
+#[derive(Debug, Clone, Default)]
+pub struct Defs<'a> {
+    definitions: Vec<(Cow<'a, str>, SVGElement<'a>)>,
+}
+
+impl<'a> Defs<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `element` under the given id, unless the id is already defined.
+    pub fn define<I: Into<Cow<'a, str>>>(&mut self, id: I, element: SVGElement<'a>) {
+        let id = id.into();
+        if !self.contains(&id) {
+            self.definitions.push((id, element));
+        }
+    }
+
+    /// Whether something is defined under the given id.
+    pub fn contains(&self, id: &str) -> bool {
+        self.definitions.iter().any(|(x, _)| x == id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    /// A reference to a definition for use in presentation attributes and
+    /// CSS, such as `fill`.
+    pub fn url(id: &str) -> String {
+        format!("url(#{})", id)
+    }
+
+    /// A reference to a definition for use in `href` attributes, such as
+    /// that of a `<use>` element placing a symbol.
+    pub fn href(id: &str) -> String {
+        format!("#{}", id)
+    }
+
+    /// Builds the `<defs>` element, with each definition given its id. Does
+    /// nothing if there are no definitions.
+    pub fn render(self) -> Option<SVGElement<'a>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let definitions = self
+            .definitions
+            .into_iter()
+            .map(|(id, element)| element.attr("id", id));
+
+        Some(SVGElement::new("defs").children(definitions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Defs;
+    use crate::{svg::SVGElement, test::assert_eq};
+
+    #[test]
+    fn keeps_the_first_definition_of_an_id() {
+        assert!(Defs::new().render().is_none());
+
+        let mut defs = Defs::new();
+        defs.define("dot", SVGElement::new("circle").attr("r", "1"));
+        defs.define("mark", SVGElement::new("symbol"));
+        defs.define("dot", SVGElement::new("circle").attr("r", "2"));
+        assert!(defs.contains("mark"));
+
+        assert_eq!(
+            defs.render().unwrap().to_string(),
+            r#"
+<defs>
+  <circle r="1" id="dot" />
+  <symbol id="mark" />
+</defs>
+            "#
+            .trim(),
+        );
+        assert_eq!(Defs::url("dot"), "url(#dot)");
+        assert_eq!(Defs::href("mark"), "#mark");
+    }
+}
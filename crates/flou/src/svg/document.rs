@@ -0,0 +1,82 @@
+use std::fmt;
+
+use crate::svg::SVGElement;
+
+const XML_PROLOG: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#;
+const SVG_DOCTYPE: &str = r#"<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd">"#;
+
+/// A complete SVG document with an `<svg>` element at its root.
+///
+/// Standalone documents are preceded by an XML prolog and a DOCTYPE
+/// declaration, which some stricter consumers require.
+#[derive(Debug)]
+pub struct SVGDocument<'a> {
+    root: SVGElement<'a>,
+    standalone: bool,
+}
+
+impl<'a> SVGDocument<'a> {
+    pub fn new(root: SVGElement<'a>) -> Self {
+        Self {
+            root,
+            standalone: false,
+        }
+    }
+
+    /// Sets whether the document is standalone. The `xmlns:xlink`
+    /// namespace is declared automatically if any `xlink:` attributes
+    /// are used.
+    pub fn standalone(mut self, standalone: bool) -> Self {
+        self.standalone = standalone;
+        self
+    }
+}
+
+impl fmt::Display for SVGDocument<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.standalone {
+            return self.root.fmt(f);
+        }
+
+        writeln!(f, "{}", XML_PROLOG)?;
+        writeln!(f, "{}", SVG_DOCTYPE)?;
+
+        if self.root.has_attribute_prefix("xlink:") && !self.root.has_attribute("xmlns:xlink") {
+            self.root
+                .clone()
+                .attr("xmlns:xlink", "http://www.w3.org/1999/xlink")
+                .fmt(f)
+        } else {
+            self.root.fmt(f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SVGDocument;
+    use crate::{svg::SVGElement, test::assert_eq};
+
+    #[test]
+    fn standalone_document() {
+        let root = SVGElement::new("svg").child(SVGElement::new("a").attr("xlink:href", "#foo"));
+
+        assert_eq!(
+            SVGDocument::new(root).standalone(true).to_string(),
+            r##"
+<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd">
+<svg xmlns:xlink="http://www.w3.org/1999/xlink">
+  <a xlink:href="#foo" />
+</svg>
+            "##
+            .trim()
+        );
+    }
+
+    #[test]
+    fn non_standalone_document() {
+        let root = SVGElement::new("svg");
+        assert_eq!(SVGDocument::new(root).to_string(), "<svg />");
+    }
+}
@@ -3,68 +3,59 @@ use std::{
     fmt::{self},
 };
 
+use super::{escape, Transform};
 use crate::pos::PixelPos;
 
-fn escape(input: &str) -> Cow<str> {
-    fn should_escape(c: char) -> bool {
-        c == '<' || c == '>' || c == '&' || c == '"' || c == '\''
-    }
-
-    if input.contains(should_escape) {
-        let mut output = String::with_capacity(input.len());
-        for c in input.chars() {
-            match c {
-                '\'' => output.push_str("&apos;"),
-                '"' => output.push_str("&quot;"),
-                '<' => output.push_str("&lt;"),
-                '>' => output.push_str("&gt;"),
-                '&' => output.push_str("&amp;"),
-                _ => output.push(c),
-            }
-        }
-        Cow::Owned(output)
-    } else {
-        Cow::Borrowed(input)
-    }
+/// Whether `name` can be used as a tag or attribute name as is. Names are
+/// printed without escaping, so anything that could end the tag or start
+/// another attribute is rejected.
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first = chars.next();
+
+    matches!(first, Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
 }
 
-// This is a hacky workaround for lifetime issues in SVGElement::text().
-// There's probably a better way of resolving them without duplicating code.
-fn escape_cow(input: Cow<str>) -> Cow<str> {
-    fn should_escape(c: char) -> bool {
-        c == '<' || c == '>' || c == '&' || c == '"' || c == '\''
-    }
-
-    if input.contains(should_escape) {
-        let mut output = String::with_capacity(input.len());
-        for c in input.chars() {
-            match c {
-                '\'' => output.push_str("&apos;"),
-                '"' => output.push_str("&quot;"),
-                '<' => output.push_str("&lt;"),
-                '>' => output.push_str("&gt;"),
-                '&' => output.push_str("&amp;"),
-                _ => output.push(c),
-            }
-        }
-        Cow::Owned(output)
-    } else {
-        input
-    }
+/// Formats a coordinate or length, rounding away floating point noise
+/// so that e.g. `43.60000000000002` is printed as `43.6`.
+pub(crate) fn format_number(n: f64) -> String {
+    const PRECISION: f64 = 1000.0;
+
+    let rounded = (n * PRECISION).round() / PRECISION;
+    // Avoid printing "-0".
+    let rounded = if rounded == 0.0 { 0.0 } else { rounded };
+    rounded.to_string()
 }
 
-fn indent(depth: usize) -> String {
+fn write_indent(f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
     const SIZE: usize = 2;
-    " ".repeat(SIZE * depth)
+    const SPACES: &str = "                                ";
+
+    let mut remaining = SIZE * depth;
+    while remaining > 0 {
+        let n = remaining.min(SPACES.len());
+        f.write_str(&SPACES[..n])?;
+        remaining -= n;
+    }
+
+    Ok(())
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Node<'a> {
     Text(Cow<'a, str>),
     Element(SVGElement<'a>),
 }
 
 impl Node<'_> {
+    fn into_owned(self) -> Node<'static> {
+        match self {
+            Node::Text(text) => Node::Text(Cow::Owned(text.into_owned())),
+            Node::Element(el) => Node::Element(el.into_owned()),
+        }
+    }
+
     fn print(&self, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Node::Text(text) => {
@@ -72,7 +63,7 @@ impl Node<'_> {
                     if i != 0 {
                         writeln!(f)?;
                     }
-                    f.write_str(&indent(depth))?;
+                    write_indent(f, depth)?;
                     f.write_str(line)?;
                 }
             }
@@ -83,8 +74,20 @@ impl Node<'_> {
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct SVGElement<'a> {
+/// An SVG element built up through chained method calls.
+///
+/// Attributes keep the order they were first set in, and setting one again
+/// replaces its value. Classes are kept once each, in the order they were
+/// first added.
+///
+/// Attribute values, classes and text content are escaped when the
+/// element is printed through its [`Display`](fmt::Display) implementation,
+/// so they can hold any string, including ones from untrusted input,
+/// without being able to add elements or attributes of their own.
+/// Characters that aren't allowed in XML are left out of them. Tag and
+/// attribute names are printed as they are, so they are checked instead.
+#[derive(Debug, Clone)]
+pub struct SVGElement<'a> {
     tag: Cow<'a, str>,
     attributes: Vec<(Cow<'a, str>, Cow<'a, str>)>,
     classes: Vec<Cow<'a, str>>,
@@ -92,43 +95,76 @@ pub(crate) struct SVGElement<'a> {
 }
 
 impl<'a> SVGElement<'a> {
-    pub(crate) fn new<I: Into<Cow<'a, str>>>(tag: I) -> Self {
+    /// Creates an empty element with the given tag name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` isn't a valid tag name, such as one containing
+    /// spaces or quotes.
+    pub fn new<I: Into<Cow<'a, str>>>(tag: I) -> Self {
+        let tag = tag.into();
+        if !is_valid_name(&tag) {
+            panic!("Invalid tag name: {:?}", tag);
+        }
+
         Self {
-            tag: tag.into(),
+            tag,
             attributes: Vec::new(),
             classes: Vec::new(),
             children: Vec::new(),
         }
     }
 
-    pub(crate) fn pos(self, pos: PixelPos) -> Self {
-        self.attr("x", pos.x.to_string())
-            .attr("y", pos.y.to_string())
+    /// Sets the `x` and `y` attributes.
+    pub fn pos(self, pos: PixelPos) -> Self {
+        self.attr_len("x", pos.x).attr_len("y", pos.y)
     }
 
-    pub(crate) fn cpos(self, pos: PixelPos) -> Self {
-        self.attr("cx", pos.x.to_string())
-            .attr("cy", pos.y.to_string())
+    /// Sets the `cx` and `cy` attributes.
+    pub fn cpos(self, pos: PixelPos) -> Self {
+        self.attr_len("cx", pos.x).attr_len("cy", pos.y)
     }
 
-    pub(crate) fn size(self, size: PixelPos) -> Self {
-        self.attr("width", size.x.to_string())
-            .attr("height", size.y.to_string())
+    /// Sets the `width` and `height` attributes.
+    pub fn size(self, size: PixelPos) -> Self {
+        self.attr_len("width", size.x).attr_len("height", size.y)
     }
 
-    pub(crate) fn class<I: Into<Cow<'a, str>>>(mut self, s: I) -> Self {
-        self.classes.push(s.into());
+    /// Appends a CSS class to the element, unless it already has it. A
+    /// string of several classes separated by whitespace adds each of them.
+    pub fn class<I: Into<Cow<'a, str>>>(mut self, s: I) -> Self {
+        let classes: Vec<Cow<'a, str>> = match s.into() {
+            Cow::Borrowed(s) => s.split_whitespace().map(Cow::Borrowed).collect(),
+            Cow::Owned(s) if !s.contains(char::is_whitespace) => vec![Cow::Owned(s)],
+            Cow::Owned(s) => s.split_whitespace().map(|c| c.to_owned().into()).collect(),
+        };
+
+        for class in classes {
+            if !class.is_empty() && !self.classes.contains(&class) {
+                self.classes.push(class);
+            }
+        }
+
         self
     }
 
-    pub(crate) fn class_opt<I: Into<Cow<'a, str>>>(self, s: Option<I>) -> Self {
+    /// Appends a CSS class to the element if one is given.
+    pub fn class_opt<I: Into<Cow<'a, str>>>(self, s: Option<I>) -> Self {
         match s {
             Some(s) => self.class(s),
             None => self,
         }
     }
 
-    pub(crate) fn attr<K, V>(mut self, key: K, value: V) -> Self
+    /// Sets an attribute of the element. If it's already set, its value is
+    /// replaced, but it stays where it was among the other attributes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is `"class"`; use [`SVGElement::class`] instead. Also
+    /// panics if `key` isn't a valid attribute name, such as one containing
+    /// spaces or quotes.
+    pub fn attr<K, V>(mut self, key: K, value: V) -> Self
     where
         K: Into<Cow<'a, str>>,
         V: Into<Cow<'a, str>>,
@@ -137,31 +173,91 @@ impl<'a> SVGElement<'a> {
         if key == "class" {
             panic!("Use .class() instead.");
         }
+        if !is_valid_name(&key) {
+            panic!("Invalid attribute name: {:?}", key);
+        }
+
+        let value = value.into();
+        match self.attributes.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.attributes.push((key, value)),
+        }
 
-        self.attributes.push((key, value.into()));
         self
     }
 
-    pub(crate) fn child(mut self, child: SVGElement<'a>) -> Self {
+    /// Sets an attribute to a whole number.
+    pub fn attr_i32<K: Into<Cow<'a, str>>>(self, key: K, value: i32) -> Self {
+        self.attr(key, value.to_string())
+    }
+
+    /// Sets an attribute to a plain number, such as an opacity, rounded
+    /// like coordinates are.
+    pub fn attr_number<K: Into<Cow<'a, str>>>(self, key: K, value: f64) -> Self {
+        self.attr(key, format_number(value))
+    }
+
+    /// Sets an attribute to a coordinate or length in user units, which
+    /// are the SVG's pixels, rounded to get rid of floating point noise.
+    pub fn attr_len<K: Into<Cow<'a, str>>>(self, key: K, value: f64) -> Self {
+        self.attr(key, format_number(value))
+    }
+
+    /// Sets an attribute to a percentage, given as a fraction, so that
+    /// e.g. `0.5` is written as `50%`.
+    pub fn attr_percent<K: Into<Cow<'a, str>>>(self, key: K, fraction: f64) -> Self {
+        self.attr(key, format!("{}%", format_number(fraction * 100.0)))
+    }
+
+    /// Adds a transformation to the element. Transformations added
+    /// earlier are applied after the ones added later, the same as in the
+    /// `transform` attribute itself.
+    pub fn transform(self, transform: Transform) -> Self {
+        let value = match self.attributes.iter().find(|(k, _)| k == "transform") {
+            Some((_, existing)) => format!("{} {}", existing, transform),
+            None => transform.to_string(),
+        };
+
+        self.attr("transform", value)
+    }
+
+    /// Moves the element by `offset`.
+    pub fn translate(self, offset: PixelPos) -> Self {
+        self.transform(Transform::Translate(offset))
+    }
+
+    /// Rotates the element clockwise by `degrees` around `center`.
+    pub fn rotate(self, degrees: f64, center: PixelPos) -> Self {
+        self.transform(Transform::Rotate(degrees, Some(center)))
+    }
+
+    /// Scales the element by `factor`.
+    pub fn scale(self, factor: f64) -> Self {
+        self.transform(Transform::Scale(factor))
+    }
+
+    /// Appends a child element.
+    pub fn child(mut self, child: SVGElement<'a>) -> Self {
         self.children.push(Node::Element(child));
         self
     }
 
-    pub(crate) fn child_opt(self, child: Option<SVGElement<'a>>) -> Self {
+    /// Appends a child element if one is given.
+    pub fn child_opt(self, child: Option<SVGElement<'a>>) -> Self {
         match child {
             Some(child) => self.child(child),
             None => self,
         }
     }
 
-    pub(crate) fn text<I: Into<Cow<'a, str>>>(mut self, text: I) -> Self {
-        let text = text.into();
-        let text = escape_cow(text);
-        self.children.push(Node::Text(text));
+    /// Appends a text node.
+    pub fn text<I: Into<Cow<'a, str>>>(mut self, text: I) -> Self {
+        self.children.push(Node::Text(escape::text(text)));
         self
     }
 
-    pub(crate) fn children<T>(mut self, children: T) -> Self
+    /// Appends every element from the iterator as a child.
+    pub fn children<T>(mut self, children: T) -> Self
     where
         T: IntoIterator<Item = SVGElement<'a>>,
     {
@@ -170,29 +266,84 @@ impl<'a> SVGElement<'a> {
         self
     }
 
-    fn print(&self, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
-        let attributes = self
-            .attributes
-            .iter()
-            .map(|(key, value)| (key, escape(value)))
-            .collect::<Vec<_>>();
+    pub(crate) fn has_attribute(&self, key: &str) -> bool {
+        self.attributes.iter().any(|(k, _)| k == key)
+    }
 
-        let classes = self.classes.iter().map(|x| escape(x)).collect::<Vec<_>>();
+    /// Checks whether this element or any of its descendants has an
+    /// attribute whose name starts with `prefix`.
+    pub(crate) fn has_attribute_prefix(&self, prefix: &str) -> bool {
+        self.attributes.iter().any(|(k, _)| k.starts_with(prefix))
+            || self.children.iter().any(|child| match child {
+                Node::Element(el) => el.has_attribute_prefix(prefix),
+                Node::Text(_) => false,
+            })
+    }
 
-        f.write_str(&indent(depth))?;
+    /// Rewrites the element into a canonical form for comparing output
+    /// across versions: attributes and classes are sorted, surrounding
+    /// whitespace is trimmed from every line of text and `<style>` elements,
+    /// whose CSS changes from version to version, are left out.
+    pub(crate) fn normalized(mut self) -> Self {
+        self.attributes.sort();
+        self.classes.sort();
+
+        self.children = self
+            .children
+            .into_iter()
+            .filter_map(|child| match child {
+                Node::Element(el) if el.tag == "style" => None,
+                Node::Element(el) => Some(Node::Element(el.normalized())),
+                Node::Text(text) => {
+                    let lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+                    let text = lines.collect::<Vec<_>>().join("\n");
+                    (!text.is_empty()).then(|| Node::Text(text.into()))
+                }
+            })
+            .collect();
+
+        self
+    }
+
+    /// Copies all borrowed data so that the element no longer borrows from its source.
+    pub(crate) fn into_owned(self) -> SVGElement<'static> {
+        let owned = |s: Cow<str>| Cow::Owned(s.into_owned());
+
+        SVGElement {
+            tag: owned(self.tag),
+            attributes: self
+                .attributes
+                .into_iter()
+                .map(|(k, v)| (owned(k), owned(v)))
+                .collect(),
+            classes: self.classes.into_iter().map(owned).collect(),
+            children: self.children.into_iter().map(Node::into_owned).collect(),
+        }
+    }
+
+    fn print(&self, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+        write_indent(f, depth)?;
         f.write_str("<")?;
         f.write_str(&self.tag)?;
 
-        if !classes.is_empty() {
-            write!(f, " class=\"{}\"", classes.join(" "))?;
+        if !self.classes.is_empty() {
+            f.write_str(" class=\"")?;
+            for (i, class) in self.classes.iter().enumerate() {
+                if i != 0 {
+                    f.write_str(" ")?;
+                }
+                f.write_str(&escape::attribute(class.as_ref()))?;
+            }
+            f.write_str("\"")?;
         }
 
-        let attributes = attributes
-            .iter()
-            .map(|(k, v)| format!(" {}=\"{}\"", k, v))
-            .collect::<Vec<_>>()
-            .join("");
-        f.write_str(&attributes)?;
+        for (key, value) in &self.attributes {
+            f.write_str(" ")?;
+            f.write_str(key)?;
+            f.write_str("=\"")?;
+            f.write_str(&escape::attribute(value.as_ref()))?;
+            f.write_str("\"")?;
+        }
 
         if self.children.is_empty() {
             f.write_str(" />")?;
@@ -211,11 +362,13 @@ impl<'a> SVGElement<'a> {
                     child.print(depth + 1, f)?;
                 }
                 writeln!(f)?;
-                f.write_str(&indent(depth))?;
+                write_indent(f, depth)?;
             }
         }
 
-        write!(f, "</{}>", self.tag)?;
+        f.write_str("</")?;
+        f.write_str(&self.tag)?;
+        f.write_str(">")?;
         Ok(())
     }
 }
@@ -228,10 +381,27 @@ impl fmt::Display for SVGElement<'_> {
 
 #[cfg(test)]
 mod tests {
-    use super::{escape, SVGElement};
+    use super::{format_number, SVGElement};
+    use crate::pos::pos;
 
     use crate::test::assert_eq;
 
+    #[test]
+    fn normalized() {
+        let element = SVGElement::new("g")
+            .class("b")
+            .class("a")
+            .attr("y", "1")
+            .attr("x", "2")
+            .child(SVGElement::new("style").text("g { fill: red; }"))
+            .child(SVGElement::new("text").text("  Hello  \n\n  world "));
+
+        assert_eq!(
+            element.normalized().to_string(),
+            "<g class=\"a b\" x=\"2\" y=\"1\">\n  <text>Hello\nworld</text>\n</g>",
+        );
+    }
+
     #[test]
     fn tag_only() {
         assert_eq!(SVGElement::new("a").to_string(), "<a />");
@@ -253,6 +423,66 @@ mod tests {
         );
     }
 
+    #[test]
+    fn setting_an_attribute_again_replaces_it() {
+        assert_eq!(
+            SVGElement::new("a")
+                .attr("x", "1")
+                .attr("y", "2")
+                .attr("x", "3")
+                .to_string(),
+            r#"<a x="3" y="2" />"#,
+        );
+
+        assert_eq!(
+            SVGElement::new("rect")
+                .pos(pos(1.0, 2.0))
+                .pos(pos(3.0, 4.0))
+                .to_string(),
+            r#"<rect x="3" y="4" />"#,
+        );
+    }
+
+    #[test]
+    fn with_numeric_attributes() {
+        assert_eq!(
+            SVGElement::new("stop")
+                .attr_i32("n", -3)
+                .attr_number("opacity", 0.30000000000000004)
+                .attr_len("x", 43.60000000000002)
+                .attr_percent("offset", 0.125)
+                .to_string(),
+            r#"<stop n="-3" opacity="0.3" x="43.6" offset="12.5%" />"#,
+        );
+    }
+
+    #[test]
+    fn with_transforms() {
+        assert_eq!(
+            SVGElement::new("g")
+                .translate(pos(10.0, 20.5))
+                .rotate(-90.0, pos(5.0, 5.0))
+                .scale(0.5)
+                .to_string(),
+            r#"<g transform="translate(10 20.5) rotate(-90 5 5) scale(0.5)" />"#,
+        );
+    }
+
+    #[test]
+    fn classes_are_kept_once() {
+        assert_eq!(
+            SVGElement::new("g")
+                .class("node")
+                .class("a")
+                .class("node")
+                .class(String::from("a b"))
+                .class("  c  b ")
+                .class("")
+                .to_string(),
+            r#"<g class="node a b c" />"#,
+        );
+    }
+
     #[test]
     fn with_child() {
         assert_eq!(
@@ -269,12 +499,46 @@ mod tests {
     }
 
     #[test]
-    fn escape_attributes() {
-        assert_eq!(escape("\""), "&quot;");
-        assert_eq!(escape("'"), "&apos;");
-        assert_eq!(escape("<"), "&lt;");
-        assert_eq!(escape(">"), "&gt;");
-        assert_eq!(escape("&"), "&amp;");
+    fn deeply_nested() {
+        let depth = 20;
+        let element = (0..depth).fold(SVGElement::new("leaf"), |child, _| {
+            SVGElement::new("g").child(child)
+        });
+
+        let output = element.to_string();
+        let leaf = output.lines().find(|line| line.contains("<leaf")).unwrap();
+        assert_eq!(leaf, format!("{}<leaf />", " ".repeat(2 * depth)));
+    }
+
+    #[test]
+    fn formats_numbers() {
+        assert_eq!(format_number(100.0), "100");
+        assert_eq!(format_number(12.5), "12.5");
+        assert_eq!(format_number(43.60000000000002), "43.6");
+        assert_eq!(format_number(-0.0001), "0");
+    }
+
+    #[test]
+    fn escapes_text_and_attributes_differently() {
+        assert_eq!(
+            SVGElement::new("text")
+                .attr("data-note", "\"Hi\"\nthere")
+                .text("\u{8}\"Hi\" <b>")
+                .to_string(),
+            r#"<text data-note="&quot;Hi&quot;&#10;there">"Hi" &lt;b&gt;</text>"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid attribute name")]
+    fn rejects_invalid_attribute_names() {
+        let _ = SVGElement::new("g").attr("x onload=\"alert(1)\" y", "1");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid tag name")]
+    fn rejects_invalid_tag_names() {
+        let _ = SVGElement::new("g><script");
     }
 
     #[test]
@@ -0,0 +1,211 @@
+//! Escaping of strings for the places they end up in an SVG document.
+//!
+//! Every function here also leaves out the characters that aren't allowed
+//! in XML at all, such as most control characters, since no escape makes
+//! them valid.
+
+use std::borrow::Cow;
+
+/// Escapes text content, so that it can't start a tag or an entity.
+///
+/// `>` is escaped as well, so that text never contains `]]>`, which isn't
+/// allowed outside of CDATA sections.
+pub(crate) fn text<'a, I: Into<Cow<'a, str>>>(input: I) -> Cow<'a, str> {
+    escape_with(input.into(), |c| match c {
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '&' => Some("&amp;"),
+        _ => None,
+    })
+}
+
+/// Escapes a quoted attribute value, whichever quotes it's in.
+///
+/// Tabs and line breaks are escaped as character references, since XML
+/// parsers would otherwise turn them into spaces.
+pub(crate) fn attribute<'a, I: Into<Cow<'a, str>>>(input: I) -> Cow<'a, str> {
+    escape_with(input.into(), |c| match c {
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '&' => Some("&amp;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&apos;"),
+        '\t' => Some("&#9;"),
+        '\n' => Some("&#10;"),
+        '\r' => Some("&#13;"),
+        _ => None,
+    })
+}
+
+/// Escapes the contents of a double-quoted CSS string, such as a font
+/// family name in a `<style>` element.
+///
+/// Line breaks, `<` and `>` are written as CSS escapes, so that the
+/// string can't end the `<style>` element either, even in HTML pages that
+/// don't treat it as XML.
+pub(crate) fn css_string(input: &str) -> Cow<'_, str> {
+    escape_with(Cow::Borrowed(input), |c| match c {
+        '\\' => Some("\\\\"),
+        '"' => Some("\\\""),
+        '\n' => Some("\\a "),
+        '\r' => Some("\\d "),
+        '<' => Some("\\3c "),
+        '>' => Some("\\3e "),
+        _ => None,
+    })
+}
+
+/// Replaces each character that `rule` has a replacement for, and leaves
+/// out the ones that aren't allowed in XML. Borrows the input if there's
+/// nothing to replace.
+fn escape_with(input: Cow<str>, rule: fn(char) -> Option<&'static str>) -> Cow<str> {
+    let needs_escaping = |c| rule(c).is_some() || !is_xml_char(c);
+    if !input.contains(needs_escaping) {
+        return input;
+    }
+
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match rule(c) {
+            Some(replacement) => output.push_str(replacement),
+            None if is_xml_char(c) => output.push(c),
+            None => {}
+        }
+    }
+
+    Cow::Owned(output)
+}
+
+/// Whether `c` may appear in an XML 1.0 document.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}')
+        || c >= '\u{10000}'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::assert_eq;
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(text("a < b && c > d"), "a &lt; b &amp;&amp; c &gt; d");
+        assert_eq!(text("\"quoted\" and 'single'"), "\"quoted\" and 'single'");
+        assert_eq!(text("]]>"), "]]&gt;");
+    }
+
+    #[test]
+    fn escapes_attributes() {
+        assert_eq!(attribute("\""), "&quot;");
+        assert_eq!(attribute("'"), "&apos;");
+        assert_eq!(attribute("<"), "&lt;");
+        assert_eq!(attribute(">"), "&gt;");
+        assert_eq!(attribute("&"), "&amp;");
+        assert_eq!(attribute("a\tb\r\nc"), "a&#9;b&#13;&#10;c");
+    }
+
+    #[test]
+    fn escapes_css_strings() {
+        assert_eq!(css_string(r#"My "Font""#), r#"My \"Font\""#);
+        assert_eq!(css_string(r"back\slash"), r"back\\slash");
+        assert_eq!(css_string("</style>"), r"\3c /style\3e ");
+        assert_eq!(css_string("two\nlines"), r"two\a lines");
+    }
+
+    #[test]
+    fn leaves_out_characters_not_allowed_in_xml() {
+        assert_eq!(text("a\u{0}b\u{1b}c\u{FFFE}"), "abc");
+        assert_eq!(attribute("\u{8}x"), "x");
+        assert_eq!(css_string("\u{1}x"), "x");
+    }
+
+    #[test]
+    fn borrows_input_without_anything_to_escape() {
+        assert!(matches!(text("plain text"), Cow::Borrowed(_)));
+        assert!(matches!(attribute("plain"), Cow::Borrowed(_)));
+        assert!(matches!(css_string("Inter"), Cow::Borrowed(_)));
+    }
+
+    #[cfg(feature = "testing")]
+    mod properties {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        /// Reverses [`text`] and [`attribute`].
+        fn unescape_xml(input: &str) -> String {
+            [
+                ("&lt;", "<"),
+                ("&gt;", ">"),
+                ("&quot;", "\""),
+                ("&apos;", "'"),
+                ("&#9;", "\t"),
+                ("&#10;", "\n"),
+                ("&#13;", "\r"),
+                ("&amp;", "&"),
+            ]
+            .iter()
+            .fold(input.to_string(), |s, (from, to)| s.replace(from, to))
+        }
+
+        /// Reverses [`css_string`], following the CSS rules for escapes.
+        fn unescape_css(input: &str) -> String {
+            let mut output = String::new();
+            let mut chars = input.chars().peekable();
+
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    output.push(c);
+                    continue;
+                }
+
+                let mut hex = String::new();
+                while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_hexdigit()) {
+                    hex.push(digit);
+                    chars.next();
+                }
+
+                match u32::from_str_radix(&hex, 16) {
+                    Ok(code) => {
+                        output.extend(std::char::from_u32(code));
+                        chars.next_if_eq(&' ');
+                    }
+                    Err(_) => output.extend(chars.next()),
+                }
+            }
+
+            output
+        }
+
+        fn xml_chars(input: &str) -> String {
+            input.chars().filter(|&c| is_xml_char(c)).collect()
+        }
+
+        proptest! {
+            #[test]
+            fn text_cannot_start_markup(input in any::<String>()) {
+                let escaped = text(input.as_str());
+                prop_assert!(!escaped.contains(['<', '>']));
+                prop_assert!(escaped.chars().all(is_xml_char));
+                prop_assert_eq!(unescape_xml(&escaped), xml_chars(&input));
+            }
+
+            #[test]
+            fn attributes_cannot_end_their_quotes(input in any::<String>()) {
+                let escaped = attribute(input.as_str());
+                prop_assert!(!escaped.contains(['<', '>', '"', '\'', '\n', '\r', '\t']));
+                prop_assert!(escaped.chars().all(is_xml_char));
+                prop_assert_eq!(unescape_xml(&escaped), xml_chars(&input));
+            }
+
+            #[test]
+            fn css_strings_cannot_end_their_quotes(input in any::<String>()) {
+                let escaped = css_string(&input);
+                prop_assert!(!escaped.contains(['<', '>', '\n', '\r']));
+                prop_assert!(!escaped.replace("\\\\", "").replace("\\\"", "").contains('"'));
+                prop_assert!(escaped.chars().all(is_xml_char));
+                prop_assert_eq!(unescape_css(&escaped), xml_chars(&input));
+            }
+        }
+    }
+}
@@ -1,9 +1,22 @@
+//! A small builder for SVG documents.
+//!
+//! This is what [`SvgRenderer`](crate::SvgRenderer) uses to produce its
+//! output. It is exposed so that renderer hooks and custom renderers can
+//! build elements the same way.
+
 mod arrowhead;
+mod defs;
+mod document;
 mod element;
+pub(crate) mod escape;
 mod path;
 mod text;
+mod transform;
 
 pub(crate) use arrowhead::*;
-pub(crate) use element::*;
-pub(crate) use path::*;
-pub(crate) use text::*;
+pub use defs::*;
+pub use document::*;
+pub use element::*;
+pub use path::*;
+pub use text::*;
+pub use transform::*;
@@ -1,49 +1,191 @@
-use crate::{pos::PixelPos, svg::SVGElement};
+use std::fmt;
+
+use crate::{
+    pos::PixelPos,
+    svg::{format_number, SVGElement},
+};
 
 pub(crate) enum PathD {
     MoveTo(PixelPos),
     LineTo(PixelPos),
+    QuadTo(PixelPos, PixelPos),
+    CurveTo(PixelPos, PixelPos, PixelPos),
+    ArcTo(Arc, PixelPos),
     End,
 }
 
-impl ToString for PathD {
-    fn to_string(&self) -> String {
+/// The shape of an elliptical arc drawn by [`SVGPath::arc_to`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arc {
+    /// The x and y radius of the ellipse.
+    pub radius: PixelPos,
+    /// The rotation of the ellipse's x axis, in degrees.
+    pub rotation: f64,
+    /// Whether to take the longer of the two possible arcs.
+    pub large_arc: bool,
+    /// Whether to draw the arc clockwise.
+    pub sweep: bool,
+}
+
+impl Arc {
+    /// A short, counterclockwise arc of a circle with the given radius.
+    pub fn circle(radius: f64) -> Self {
+        Self {
+            radius: PixelPos::new(radius, radius),
+            rotation: 0.0,
+            large_arc: false,
+            sweep: false,
+        }
+    }
+}
+
+impl PathD {
+    fn letter(&self) -> char {
         match self {
-            PathD::MoveTo(pos) => format!("M {} {}", pos.x, pos.y),
-            PathD::LineTo(pos) => format!("L {} {}", pos.x, pos.y),
-            PathD::End => "Z".into(),
+            PathD::MoveTo(_) => 'M',
+            PathD::LineTo(_) => 'L',
+            PathD::QuadTo(..) => 'Q',
+            PathD::CurveTo(..) => 'C',
+            PathD::ArcTo(..) => 'A',
+            PathD::End => 'Z',
+        }
+    }
+}
+
+impl fmt::Display for PathD {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let point = |pos: &PixelPos| format!("{} {}", format_number(pos.x), format_number(pos.y));
+
+        match self {
+            PathD::MoveTo(pos) | PathD::LineTo(pos) => f.write_str(&point(pos)),
+            PathD::QuadTo(c, pos) => write!(f, "{}, {}", point(c), point(pos)),
+            PathD::CurveTo(c1, c2, pos) => {
+                write!(f, "{}, {}, {}", point(c1), point(c2), point(pos))
+            }
+            PathD::ArcTo(arc, pos) => write!(
+                f,
+                "{} {} {} {} {}",
+                point(&arc.radius),
+                format_number(arc.rotation),
+                arc.large_arc as u8,
+                arc.sweep as u8,
+                point(pos)
+            ),
+            PathD::End => Ok(()),
+        }
+    }
+}
+
+/// A path command, whose coordinates are either absolute or relative to
+/// where the previous command left the pen.
+struct Command {
+    d: PathD,
+    relative: bool,
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letter = match self.relative {
+            true => self.d.letter().to_ascii_lowercase(),
+            false => self.d.letter(),
+        };
+
+        match self.d {
+            PathD::End => write!(f, "{}", letter),
+            _ => write!(f, "{} {}", letter, self.d),
         }
     }
 }
 
-pub(crate) struct SVGPath {
-    d: Vec<PathD>,
+/// Builder for `<path>` elements.
+///
+/// Each drawing command has a relative variant ending in `_by`, whose
+/// coordinates are offsets from the current pen position instead.
+#[derive(Default)]
+pub struct SVGPath {
+    d: Vec<Command>,
 }
 
 impl SVGPath {
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         Self { d: Vec::new() }
     }
 
-    pub(crate) fn line_to(mut self, pos: PixelPos) -> Self {
-        let cmd = if self.d.is_empty() {
-            PathD::MoveTo(pos)
-        } else {
-            PathD::LineTo(pos)
-        };
-        self.d.push(cmd);
-        self
+    /// Moves the pen to `pos` without drawing.
+    pub fn move_to(self, pos: PixelPos) -> Self {
+        self.push(PathD::MoveTo(pos), false)
     }
 
-    pub(crate) fn end(mut self) -> Self {
-        self.d.push(PathD::End);
-        self
+    /// Moves the pen by `offset` without drawing.
+    pub fn move_by(self, offset: PixelPos) -> Self {
+        self.push(PathD::MoveTo(offset), true)
+    }
+
+    /// Draws a line to `pos`. The first call moves the pen instead.
+    pub fn line_to(self, pos: PixelPos) -> Self {
+        self.draw(PathD::LineTo(pos), pos, false)
+    }
+
+    /// Draws a line by `offset`. The first call moves the pen instead.
+    pub fn line_by(self, offset: PixelPos) -> Self {
+        self.draw(PathD::LineTo(offset), offset, true)
     }
 
-    pub(crate) fn render(self) -> SVGElement<'static> {
+    /// Draws a quadratic Bézier curve to `pos`, with the control point `c`.
+    /// The first call moves the pen instead.
+    pub fn quad_to(self, c: PixelPos, pos: PixelPos) -> Self {
+        self.draw(PathD::QuadTo(c, pos), pos, false)
+    }
+
+    /// Relative variant of [`quad_to`](Self::quad_to).
+    pub fn quad_by(self, c: PixelPos, offset: PixelPos) -> Self {
+        self.draw(PathD::QuadTo(c, offset), offset, true)
+    }
+
+    /// Draws a cubic Bézier curve to `pos`, with the control points `c1`
+    /// and `c2`. The first call moves the pen instead.
+    pub fn curve_to(self, c1: PixelPos, c2: PixelPos, pos: PixelPos) -> Self {
+        self.draw(PathD::CurveTo(c1, c2, pos), pos, false)
+    }
+
+    /// Relative variant of [`curve_to`](Self::curve_to).
+    pub fn curve_by(self, c1: PixelPos, c2: PixelPos, offset: PixelPos) -> Self {
+        self.draw(PathD::CurveTo(c1, c2, offset), offset, true)
+    }
+
+    /// Draws an elliptical arc to `pos`. The first call moves the pen
+    /// instead.
+    pub fn arc_to(self, arc: Arc, pos: PixelPos) -> Self {
+        self.draw(PathD::ArcTo(arc, pos), pos, false)
+    }
+
+    /// Relative variant of [`arc_to`](Self::arc_to).
+    pub fn arc_by(self, arc: Arc, offset: PixelPos) -> Self {
+        self.draw(PathD::ArcTo(arc, offset), offset, true)
+    }
+
+    /// Closes the path.
+    pub fn end(self) -> Self {
+        self.push(PathD::End, false)
+    }
+
+    /// Builds the `<path>` element.
+    pub fn render(self) -> SVGElement<'static> {
         SVGElement::new("path").attr("d", self.get_d())
     }
 
+    fn draw(self, d: PathD, pos: PixelPos, relative: bool) -> Self {
+        match self.d.is_empty() {
+            true => self.push(PathD::MoveTo(pos), relative),
+            false => self.push(d, relative),
+        }
+    }
+
+    fn push(mut self, d: PathD, relative: bool) -> Self {
+        self.d.push(Command { d, relative });
+        self
+    }
+
     fn get_d(&self) -> String {
         self.d
             .iter()
@@ -55,19 +197,63 @@ impl SVGPath {
 
 #[cfg(test)]
 mod tests {
-    use crate::{pos::pos, svg::SVGPath};
+    use crate::{
+        pos::pos,
+        svg::{Arc, SVGPath},
+    };
 
     use crate::test::assert_eq;
 
     #[test]
     fn create_path() {
-        let mut path = SVGPath::new().line_to(pos(10, 20));
+        let mut path = SVGPath::new().line_to(pos(10.0, 20.0));
         assert_eq!(path.get_d(), "M 10 20");
 
-        path = path.line_to(pos(30, 40));
+        path = path.line_to(pos(30.0, 40.0));
         assert_eq!(path.get_d(), "M 10 20 L 30 40");
 
+        path = path.curve_to(pos(30.0, 50.0), pos(40.0, 60.0), pos(50.0, 60.0));
+        assert_eq!(path.get_d(), "M 10 20 L 30 40 C 30 50, 40 60, 50 60");
+
         path = path.end();
-        assert_eq!(path.get_d(), "M 10 20 L 30 40 Z");
+        assert_eq!(path.get_d(), "M 10 20 L 30 40 C 30 50, 40 60, 50 60 Z");
+    }
+
+    #[test]
+    fn create_path_with_arcs_and_quadratic_curves() {
+        let path = SVGPath::new()
+            .move_to(pos(0.0, 10.0))
+            .arc_to(Arc::circle(10.0), pos(10.0, 0.0))
+            .quad_to(pos(20.0, 0.0), pos(20.0, 10.0))
+            .arc_to(
+                Arc {
+                    radius: pos(5.0, 2.5),
+                    rotation: 45.0,
+                    large_arc: true,
+                    sweep: true,
+                },
+                pos(30.0, 10.0),
+            )
+            .end();
+        assert_eq!(
+            path.get_d(),
+            "M 0 10 A 10 10 0 0 0 10 0 Q 20 0, 20 10 A 5 2.5 45 1 1 30 10 Z"
+        );
+    }
+
+    #[test]
+    fn create_relative_path() {
+        let path = SVGPath::new()
+            .line_by(pos(10.0, 20.0))
+            .line_by(pos(5.0, 0.0))
+            .quad_by(pos(5.0, 0.0), pos(5.0, 5.0))
+            .curve_by(pos(0.0, 5.0), pos(-5.0, 5.0), pos(-5.0, 5.0))
+            .arc_by(Arc::circle(2.0), pos(-2.0, -2.0))
+            .move_by(pos(1.0, 1.0))
+            .end();
+        assert_eq!(
+            path.get_d(),
+            "m 10 20 l 5 0 q 5 0, 5 5 c 0 5, -5 5, -5 5 a 2 2 0 0 0 -2 -2 m 1 1 Z"
+        );
     }
 }
@@ -1,27 +1,38 @@
+use std::borrow::Cow;
+
 use crate::{pos::PixelPos, svg::SVGElement};
 
-pub(crate) struct SVGText {
+/// Builder for `<text>` elements.
+///
+/// Multiline text is split into `<tspan>` elements which are vertically
+/// centered around the text's position.
+pub struct SVGText {
     pos: PixelPos,
 }
 
 impl SVGText {
-    pub(crate) fn new(pos: PixelPos) -> Self {
+    pub fn new(pos: PixelPos) -> Self {
         Self { pos }
     }
 
-    pub(crate) fn render(self, s: &str) -> SVGElement {
+    /// Builds the `<text>` element containing `s`.
+    pub fn render<'a, S: Into<Cow<'a, str>>>(self, s: S) -> SVGElement<'a> {
         let text = SVGElement::new("text").pos(self.pos);
-        let line_count = s.lines().count();
+        let lines: Vec<Cow<'a, str>> = match s.into() {
+            Cow::Borrowed(s) => s.lines().map(Cow::Borrowed).collect(),
+            Cow::Owned(s) => s.lines().map(|line| Cow::Owned(line.to_owned())).collect(),
+        };
+        let line_count = lines.len();
 
         if line_count == 1 {
-            return text.text(s);
+            return text.text(lines.into_iter().next().unwrap());
         }
 
-        let children = s.lines().enumerate().map(|(i, line)| {
+        let children = lines.into_iter().enumerate().map(|(i, line)| {
             let offset = Self::calculate_offset(i, line_count);
 
             SVGElement::new("tspan")
-                .attr("x", self.pos.x.to_string())
+                .attr_len("x", self.pos.x)
                 .attr("dy", format!("{}em", offset))
                 .text(line)
         });
@@ -0,0 +1,37 @@
+use std::fmt;
+
+use crate::{pos::PixelPos, svg::format_number};
+
+/// A transformation, as set by [`SVGElement::transform`](super::SVGElement::transform).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    /// Moves the element by the given offset.
+    Translate(PixelPos),
+    /// Rotates the element clockwise by the given number of degrees,
+    /// around the given point or the origin.
+    Rotate(f64, Option<PixelPos>),
+    /// Scales the element by the given factor.
+    Scale(f64),
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Transform::Translate(offset) => write!(
+                f,
+                "translate({} {})",
+                format_number(offset.x),
+                format_number(offset.y)
+            ),
+            Transform::Rotate(degrees, None) => write!(f, "rotate({})", format_number(degrees)),
+            Transform::Rotate(degrees, Some(center)) => write!(
+                f,
+                "rotate({} {} {})",
+                format_number(degrees),
+                format_number(center.x),
+                format_number(center.y)
+            ),
+            Transform::Scale(factor) => write!(f, "scale({})", format_number(factor)),
+        }
+    }
+}
@@ -1,9 +1,12 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet},
     iter::FromIterator,
 };
 
-use crate::parse::{ast, Input, Parser};
+use crate::{
+    parse::{ast, Input, Parser},
+    pos::GridCoord,
+};
 
 pub(crate) use pretty_assertions::assert_eq;
 
@@ -31,16 +34,18 @@ pub(crate) fn assert_not_parsed<'i, P: Parser<'i, O>, O: std::fmt::Debug + Parti
     assert!(actual.is_err(), "Unexpected success: {:?}", actual.unwrap());
 }
 
-pub(crate) fn id(s: &str) -> ast::Identifier {
+pub(crate) fn coord(row: isize, col: isize) -> GridCoord {
+    GridCoord::new(row, col)
+}
+
+pub(crate) fn id(s: &str) -> ast::Identifier<'_> {
     ast::Identifier(s)
 }
 
-pub(crate) fn map<K: Eq + std::hash::Hash, V, I: IntoIterator<Item = (K, V)>>(
-    xs: I,
-) -> HashMap<K, V> {
-    HashMap::from_iter(xs)
+pub(crate) fn map<K: Ord, V, I: IntoIterator<Item = (K, V)>>(xs: I) -> BTreeMap<K, V> {
+    BTreeMap::from_iter(xs)
 }
 
-pub(crate) fn set<T: Eq + std::hash::Hash, I: IntoIterator<Item = T>>(xs: I) -> HashSet<T> {
-    HashSet::from_iter(xs)
+pub(crate) fn set<T: Ord, I: IntoIterator<Item = T>>(xs: I) -> BTreeSet<T> {
+    BTreeSet::from_iter(xs)
 }
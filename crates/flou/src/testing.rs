@@ -0,0 +1,183 @@
+//! [`proptest`] strategies that generate Flou sources, so that tools built
+//! on flou, such as formatters and converters, can fuzz round trips against
+//! flou's own model. Only available with the `testing` feature.
+//!
+//! Sources are generated as text rather than as [`Document`]s, since
+//! documents borrow from the text they were parsed from. Every source
+//! generated by [`source`] parses into a [`Document`] and converts into a
+//! [`Flou`] without errors.
+//!
+//! [`Document`]: crate::ast::Document
+//! [`Flou`]: crate::Flou
+
+use std::collections::BTreeSet;
+
+use proptest::{collection, option, prelude::*, sample};
+
+use crate::parse::quote;
+
+const SHAPES: &[&str] = &[
+    "rect",
+    "square",
+    "ellipse",
+    "circle",
+    "diamond",
+    "angled_square",
+];
+
+const SIDES: &[&str] = &["n", "s", "w", "e"];
+
+/// Identifiers of nodes, such as `a` or `step_2`.
+pub fn identifier() -> impl Strategy<Value = String> {
+    "[a-z][a-z0-9_]{0,7}"
+}
+
+/// Quoted strings, with any characters that need it escaped.
+pub fn quoted_string() -> impl Strategy<Value = String> {
+    "(\\PC|\n){0,20}".prop_map(|text| quote(&text))
+}
+
+/// A grid of nodes, as rows of cells that are either empty or hold the
+/// index of one of `identifiers` kinds of nodes, with an optional label.
+pub fn grid(identifiers: usize) -> impl Strategy<Value = Vec<Vec<Option<(usize, bool)>>>> {
+    let cell = option::weighted(0.8, (0..identifiers.max(1), any::<bool>()));
+    collection::vec(collection::vec(cell, 1..5), 1..5)
+}
+
+/// Attributes of one node definition.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub text: Option<String>,
+    pub shape: Option<&'static str>,
+    pub class: Option<String>,
+    /// Connections as the index of the label they lead to, along with the
+    /// sides of the source and destination.
+    pub connections: BTreeSet<(usize, &'static str, &'static str)>,
+}
+
+/// The attributes of a node definition, with connections to any of up to
+/// `labels` labels.
+pub fn definition(labels: usize) -> impl Strategy<Value = Definition> {
+    let connection = (
+        0..labels.max(1),
+        sample::select(SIDES),
+        sample::select(SIDES),
+    );
+
+    (
+        option::of(quoted_string()),
+        option::of(sample::select(SHAPES)),
+        option::of(identifier()),
+        collection::btree_set(connection, 0..3),
+    )
+        .prop_map(|(text, shape, class, connections)| Definition {
+            text,
+            shape,
+            class,
+            connections,
+        })
+}
+
+/// Complete sources with a grid and a `define` block, which always convert
+/// into a [`Flou`](crate::Flou).
+pub fn source() -> impl Strategy<Value = String> {
+    collection::btree_set(identifier(), 1..6).prop_flat_map(|identifiers| {
+        let identifiers = identifiers.into_iter().collect::<Vec<_>>();
+        let count = identifiers.len();
+
+        // Labels are numbered in the order they appear in the grid, and
+        // connections to labels past the last one are left out.
+        let definitions = collection::vec(option::of(definition(8)), count);
+        (Just(identifiers), grid(count), definitions)
+            .prop_map(|(identifiers, grid, definitions)| print(&identifiers, &grid, &definitions))
+    })
+}
+
+fn print(
+    identifiers: &[String],
+    grid: &[Vec<Option<(usize, bool)>>],
+    definitions: &[Option<Definition>],
+) -> String {
+    let mut labels = 0;
+    let rows = grid.iter().map(|row| {
+        let cells = row.iter().map(|cell| match cell {
+            Some((id, true)) => {
+                labels += 1;
+                format!("{}#l{}", identifiers[*id], labels - 1)
+            }
+            Some((id, false)) => identifiers[*id].clone(),
+            None => String::from("_"),
+        });
+        format!("    {};", cells.collect::<Vec<_>>().join(", "))
+    });
+    let rows = rows.collect::<Vec<_>>();
+
+    let definitions = identifiers
+        .iter()
+        .zip(definitions)
+        .filter_map(|(id, definition)| {
+            let definition = definition.as_ref()?;
+
+            let mut attributes = Vec::new();
+            if let Some(text) = &definition.text {
+                attributes.push(format!("text: {}", text));
+            }
+            if let Some(shape) = definition.shape {
+                attributes.push(format!("shape: {}", shape));
+            }
+            if let Some(class) = &definition.class {
+                attributes.push(format!("class: {}", quote(class)));
+            }
+
+            let connections = definition
+                .connections
+                .iter()
+                .filter(|(label, ..)| *label < labels)
+                .map(|(label, from, to)| format!("{}:{}#l{}", from, to, label))
+                .collect::<Vec<_>>();
+            if !connections.is_empty() {
+                attributes.push(format!("connect: {{{}}}", connections.join("; ")));
+            }
+
+            // Attribute lists can't be empty.
+            if attributes.is_empty() {
+                return None;
+            }
+
+            Some(format!("    {}({});", id, attributes.join(", ")))
+        })
+        .collect::<Vec<_>>();
+
+    let grid = format!("grid {{\n{}\n}}\n", rows.join("\n"));
+
+    // The `define` block can't be empty either.
+    match definitions.is_empty() {
+        true => grid,
+        false => format!("{}\ndefine {{\n{}\n}}\n", grid, definitions.join("\n")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use proptest::prelude::*;
+
+    use super::source;
+    use crate::{ast::Document, Flou};
+
+    proptest! {
+        #[test]
+        fn sources_convert_into_flou(source in source()) {
+            prop_assert!(Document::parse(&source).is_ok());
+            prop_assert!(Flou::try_from(source.as_str()).is_ok());
+        }
+
+        #[test]
+        fn sources_round_trip_through_to_source(source in source()) {
+            let document = Document::parse(&source).unwrap();
+            let printed = document.to_source();
+            prop_assert_eq!(Document::parse(&printed).unwrap(), document);
+        }
+    }
+}
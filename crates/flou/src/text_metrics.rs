@@ -0,0 +1,329 @@
+//! Text measurement used for laying out text inside the diagram.
+//!
+//! SVG renderers lay out text themselves, so the widths computed here can
+//! only ever be estimates. By default every glyph is assigned an average
+//! width (as a fraction of the font size) depending on what kind of
+//! character it is and which generic font family is being used. With the
+//! `font-metrics` feature enabled, [`FontMetrics`] reads the exact glyph
+//! advances from a font file instead.
+
+/// The font size assumed when none is specified, in pixels.
+pub const DEFAULT_FONT_SIZE: f64 = 16.0;
+
+/// Measures how much horizontal space text takes up.
+pub trait TextMetrics: Send + Sync {
+    /// Width of a single character, relative to the font size.
+    fn char_width(&self, c: char) -> f64;
+
+    /// Width of a single line of text at the given font size.
+    fn line_width(&self, line: &str, font_size: f64) -> f64 {
+        line.chars().map(|c| self.char_width(c)).sum::<f64>() * font_size
+    }
+
+    /// Width of the widest line of `text` at the given font size.
+    fn width(&self, text: &str, font_size: f64) -> f64 {
+        text.lines()
+            .map(|line| self.line_width(line, font_size))
+            .fold(0.0, f64::max)
+    }
+
+    /// Height of `text` at the given font size. Lines are placed `1em` apart.
+    fn height(&self, text: &str, font_size: f64) -> f64 {
+        text.lines().count().max(1) as f64 * font_size
+    }
+}
+
+/// Generic font families that have their own average glyph widths.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FontFamily {
+    #[default]
+    SansSerif,
+    Serif,
+    Monospace,
+}
+
+impl FontFamily {
+    /// Guesses the generic family of a CSS `font-family` value by
+    /// looking at the first font in the list that it recognizes.
+    pub fn from_css(font_family: &str) -> Self {
+        font_family
+            .split(',')
+            .map(|name| {
+                name.trim()
+                    .trim_matches(|c| c == '"' || c == '\'')
+                    .to_lowercase()
+            })
+            .find_map(|name| Self::from_name(&name))
+            .unwrap_or_default()
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        const SERIF: &[&str] = &["serif", "times", "times new roman", "georgia", "garamond"];
+        const MONOSPACE: &[&str] = &[
+            "monospace",
+            "courier",
+            "courier new",
+            "consolas",
+            "menlo",
+            "monaco",
+            "fira mono",
+            "fira code",
+            "dejavu sans mono",
+        ];
+        const SANS_SERIF: &[&str] = &[
+            "sans-serif",
+            "arial",
+            "helvetica",
+            "verdana",
+            "tahoma",
+            "segoe ui",
+            "roboto",
+            "open sans",
+            "dejavu sans",
+            "system-ui",
+        ];
+
+        if SERIF.contains(&name) {
+            Some(Self::Serif)
+        } else if MONOSPACE.contains(&name) {
+            Some(Self::Monospace)
+        } else if SANS_SERIF.contains(&name) {
+            Some(Self::SansSerif)
+        } else {
+            None
+        }
+    }
+}
+
+/// Average glyph widths for each class of characters, relative to the font size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GlyphWidths {
+    narrow: f64,
+    space: f64,
+    lowercase: f64,
+    uppercase: f64,
+    digit: f64,
+    wide: f64,
+    other: f64,
+}
+
+const SANS_SERIF_WIDTHS: GlyphWidths = GlyphWidths {
+    narrow: 0.25,
+    space: 0.28,
+    lowercase: 0.51,
+    uppercase: 0.66,
+    digit: 0.56,
+    wide: 0.86,
+    other: 0.58,
+};
+
+const SERIF_WIDTHS: GlyphWidths = GlyphWidths {
+    narrow: 0.27,
+    space: 0.25,
+    lowercase: 0.47,
+    uppercase: 0.67,
+    digit: 0.5,
+    wide: 0.8,
+    other: 0.55,
+};
+
+const MONOSPACE_WIDTHS: GlyphWidths = GlyphWidths {
+    narrow: 0.6,
+    space: 0.6,
+    lowercase: 0.6,
+    uppercase: 0.6,
+    digit: 0.6,
+    wide: 0.6,
+    other: 0.6,
+};
+
+/// Estimates text width from per-family average glyph widths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AverageMetrics {
+    widths: GlyphWidths,
+}
+
+impl AverageMetrics {
+    pub const fn new(family: FontFamily) -> Self {
+        let widths = match family {
+            FontFamily::SansSerif => SANS_SERIF_WIDTHS,
+            FontFamily::Serif => SERIF_WIDTHS,
+            FontFamily::Monospace => MONOSPACE_WIDTHS,
+        };
+
+        Self { widths }
+    }
+
+    /// Creates metrics for the generic family of a CSS `font-family` value.
+    pub fn from_css(font_family: &str) -> Self {
+        Self::new(FontFamily::from_css(font_family))
+    }
+}
+
+impl Default for AverageMetrics {
+    fn default() -> Self {
+        Self::new(FontFamily::default())
+    }
+}
+
+impl TextMetrics for AverageMetrics {
+    fn char_width(&self, c: char) -> f64 {
+        let w = &self.widths;
+        match c {
+            ' ' => w.space,
+            'i' | 'j' | 'l' | 'I' | 'f' | 't' | 'r' | '.' | ',' | ':' | ';' | '\'' | '!' | '|' => {
+                w.narrow
+            }
+            'm' | 'w' | 'M' | 'W' | '@' | '%' => w.wide,
+            c if c.is_ascii_digit() => w.digit,
+            c if c.is_lowercase() => w.lowercase,
+            c if c.is_uppercase() => w.uppercase,
+            c if c.is_control() => 0.0,
+            _ => w.other,
+        }
+    }
+}
+
+impl FontFamily {
+    pub(crate) fn average_metrics(self) -> &'static AverageMetrics {
+        const SANS_SERIF: AverageMetrics = AverageMetrics::new(FontFamily::SansSerif);
+        const SERIF: AverageMetrics = AverageMetrics::new(FontFamily::Serif);
+        const MONOSPACE: AverageMetrics = AverageMetrics::new(FontFamily::Monospace);
+
+        match self {
+            Self::SansSerif => &SANS_SERIF,
+            Self::Serif => &SERIF,
+            Self::Monospace => &MONOSPACE,
+        }
+    }
+}
+
+/// Reads exact glyph advances from a TrueType or OpenType font.
+#[cfg(feature = "font-metrics")]
+pub struct FontMetrics {
+    data: Vec<u8>,
+    index: u32,
+    fallback: AverageMetrics,
+}
+
+#[cfg(feature = "font-metrics")]
+impl FontMetrics {
+    /// Loads the font at `index` inside the font file `data`. Returns
+    /// `None` if the data can't be parsed as a font.
+    pub fn from_data(data: Vec<u8>, index: u32) -> Option<Self> {
+        ttf_parser::Face::parse(&data, index).ok()?;
+
+        Some(Self {
+            data,
+            index,
+            fallback: AverageMetrics::default(),
+        })
+    }
+
+    fn face(&self) -> ttf_parser::Face<'_> {
+        // Already validated in `from_data`.
+        ttf_parser::Face::parse(&self.data, self.index).unwrap()
+    }
+}
+
+#[cfg(feature = "font-metrics")]
+impl TextMetrics for FontMetrics {
+    fn char_width(&self, c: char) -> f64 {
+        let face = self.face();
+        let advance = face
+            .glyph_index(c)
+            .and_then(|glyph| face.glyph_hor_advance(glyph));
+
+        match advance {
+            Some(advance) => advance as f64 / face.units_per_em() as f64,
+            None => self.fallback.char_width(c),
+        }
+    }
+
+    fn line_width(&self, line: &str, font_size: f64) -> f64 {
+        // Parse the face once per line instead of once per character.
+        let face = self.face();
+        let units_per_em = face.units_per_em() as f64;
+
+        let width: f64 = line
+            .chars()
+            .map(|c| {
+                face.glyph_index(c)
+                    .and_then(|glyph| face.glyph_hor_advance(glyph))
+                    .map(|advance| advance as f64 / units_per_em)
+                    .unwrap_or_else(|| self.fallback.char_width(c))
+            })
+            .sum();
+
+        width * font_size
+    }
+}
+
+/// Breaks `text` into lines no wider than `max_width`, splitting on
+/// whitespace. Existing line breaks are kept. Words that don't fit on a
+/// line of their own are left on a line by themselves rather than split.
+pub fn wrap(metrics: &dyn TextMetrics, text: &str, font_size: f64, max_width: f64) -> String {
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            if line.is_empty() {
+                line.push_str(word);
+                continue;
+            }
+
+            let candidate = format!("{} {}", line, word);
+            if metrics.line_width(&candidate, font_size) <= max_width {
+                line = candidate;
+            } else {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::assert_eq;
+
+    #[test]
+    fn recognizes_font_families() {
+        assert_eq!(FontFamily::from_css("Arial"), FontFamily::SansSerif);
+        assert_eq!(
+            FontFamily::from_css("'Times New Roman', serif"),
+            FontFamily::Serif
+        );
+        assert_eq!(
+            FontFamily::from_css("\"Unknown Font\", Consolas, monospace"),
+            FontFamily::Monospace
+        );
+        assert_eq!(FontFamily::from_css("Unknown"), FontFamily::SansSerif);
+    }
+
+    #[test]
+    fn measures_text() {
+        let metrics = AverageMetrics::new(FontFamily::Monospace);
+        assert_eq!(metrics.line_width("abcd", 10.0), 24.0);
+        assert_eq!(metrics.width("ab\nabcd", 10.0), 24.0);
+        assert_eq!(metrics.height("ab\nabcd", 10.0), 20.0);
+
+        let metrics = AverageMetrics::default();
+        assert!(metrics.line_width("WWW", 10.0) > metrics.line_width("iii", 10.0));
+    }
+
+    #[test]
+    fn wraps_text() {
+        let metrics = AverageMetrics::new(FontFamily::Monospace);
+
+        assert_eq!(wrap(&metrics, "aaa bbb ccc", 10.0, 50.0), "aaa bbb\nccc");
+        assert_eq!(wrap(&metrics, "aaa bbb\nccc", 10.0, 1000.0), "aaa bbb\nccc");
+        assert_eq!(wrap(&metrics, "aaaaaaaaaa b", 10.0, 30.0), "aaaaaaaaaa\nb");
+    }
+}
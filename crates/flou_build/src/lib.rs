@@ -0,0 +1,159 @@
+//! Renders Flou flowcharts from a build script, so that they can be kept
+//! as source and shipped as SVG.
+//!
+//! In `build.rs`:
+//!
+//! ```no_run
+//! let out_dir = std::env::var("OUT_DIR").unwrap();
+//! flou_build::compile_dir("diagrams", out_dir).unwrap();
+//! ```
+//!
+//! Each `diagrams/**/*.flou` file is rendered to the same path under
+//! `OUT_DIR`, with an `.svg` extension, ready to be included:
+//!
+//! ```ignore
+//! const EXAMPLE: &str = include_str!(concat!(env!("OUT_DIR"), "/example.svg"));
+//! ```
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use flou::{render_svg, RenderConfig};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading a flowchart or writing its output failed.
+    Io(PathBuf, io::Error),
+    /// A flowchart couldn't be rendered.
+    Flou(PathBuf, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Error::Flou(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) => Some(e),
+            Error::Flou(..) => None,
+        }
+    }
+}
+
+/// Renders every `.flou` file in `src`, including its subdirectories, to
+/// an SVG file in the same place under `out_dir`, with the default render
+/// config. Returns the paths of the written files.
+///
+/// Also tells Cargo to run the build script again when anything in `src`
+/// changes.
+pub fn compile_dir<S, O>(src: S, out_dir: O) -> Result<Vec<PathBuf>, Error>
+where
+    S: AsRef<Path>,
+    O: AsRef<Path>,
+{
+    compile_dir_with(src, out_dir, &RenderConfig::default())
+}
+
+/// Like [`compile_dir`], but renders with the given config.
+pub fn compile_dir_with<S, O>(
+    src: S,
+    out_dir: O,
+    config: &RenderConfig,
+) -> Result<Vec<PathBuf>, Error>
+where
+    S: AsRef<Path>,
+    O: AsRef<Path>,
+{
+    let src = src.as_ref();
+    println!("cargo:rerun-if-changed={}", src.display());
+
+    let mut written = Vec::new();
+    for input in find_flowcharts(src)? {
+        let relative = input.strip_prefix(src).unwrap_or(&input);
+        let output = out_dir.as_ref().join(relative).with_extension("svg");
+        compile_file(&input, &output, config)?;
+        written.push(output);
+    }
+
+    Ok(written)
+}
+
+/// Renders a single flowchart from `input` to `output`, creating the
+/// directories leading to `output` if needed.
+pub fn compile_file<I, O>(input: I, output: O, config: &RenderConfig) -> Result<(), Error>
+where
+    I: AsRef<Path>,
+    O: AsRef<Path>,
+{
+    let (input, output) = (input.as_ref(), output.as_ref());
+
+    let source = fs::read_to_string(input).map_err(|e| Error::Io(input.to_owned(), e))?;
+    let svg =
+        render_svg(&source, config).map_err(|e| Error::Flou(input.to_owned(), e.to_string()))?;
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::Io(parent.to_owned(), e))?;
+    }
+
+    fs::write(output, svg).map_err(|e| Error::Io(output.to_owned(), e))
+}
+
+/// All `.flou` files in `dir` and its subdirectories, in a stable order.
+fn find_flowcharts(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let entries = fs::read_dir(dir).map_err(|e| Error::Io(dir.to_owned(), e))?;
+    let mut paths = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::Io(dir.to_owned(), e))?;
+    paths.sort();
+
+    let mut flowcharts = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            flowcharts.extend(find_flowcharts(&path)?);
+        } else if matches!(path.extension(), Some(ext) if ext == "flou") {
+            flowcharts.push(path);
+        }
+    }
+
+    Ok(flowcharts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{compile_dir, Error};
+
+    #[test]
+    fn compiles_nested_flowcharts() {
+        let root = std::env::temp_dir().join(format!("flou_build_{}", std::process::id()));
+        let (src, out) = (root.join("diagrams"), root.join("out"));
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("a.flou"), "grid { a; }").unwrap();
+        fs::write(src.join("nested/b.flou"), "grid { b; }").unwrap();
+        fs::write(src.join("notes.txt"), "Not a flowchart").unwrap();
+
+        let written = compile_dir(&src, &out).unwrap();
+        assert_eq!(written, vec![out.join("a.svg"), out.join("nested/b.svg")]);
+        assert!(fs::read_to_string(out.join("nested/b.svg"))
+            .unwrap()
+            .starts_with("<svg"));
+
+        fs::write(src.join("nested/c.flou"), "grid { c(connect: s:n#x); }").unwrap();
+        match compile_dir(&src, &out) {
+            Err(Error::Flou(path, _)) => assert_eq!(path, src.join("nested/c.flou")),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
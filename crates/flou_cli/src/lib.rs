@@ -1,96 +1,773 @@
-use flou::{Flou, FlouError, LogicError, RenderConfig, Renderer, ResolutionError, SvgRenderer};
+use flou::{
+    ast,
+    cst::{EditError, Editor, SyntaxTree},
+    lint::{lint, Diagnostic, LintConfig, Rule, Severity},
+    Background, BackgroundGrid, CellPosition, ChannelRouter, ConnectionStyle, EmbeddedFont, Flou,
+    FlouError, GridCoord, HtmlRenderer, JsonRenderer, LegendPosition, Limit, RenderConfig,
+    RenderLimits, Renderer, SvgRenderer, Unit,
+};
+#[cfg(feature = "png")]
+use flou::{json_array, JsonObject, PixelPos, Rect};
+use logging::timed;
+use output_file::OutputFile;
 use std::convert::TryFrom;
-use std::fmt;
-use std::io::{BufWriter, Write};
+use std::io::{IsTerminal, Write};
+use std::str::FromStr;
 use std::{
     fs,
     io::{self, BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
+use structopt::clap::Shell;
 use structopt::StructOpt;
 
+mod logging;
+mod output_file;
+
 #[derive(Debug, StructOpt)]
 pub struct Opt {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+
     /// Input file; use "-" to read input from stdin.
     #[structopt(parse(from_os_str))]
-    input: PathBuf,
+    input: Option<PathBuf>,
 
     /// Output file; outputs to stdout if omitted.
-    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    #[structopt(short = "o", long = "output", global = true, parse(from_os_str))]
     output: Option<PathBuf>,
 
+    /// Output format: svg, html, json, pdf or png (if built with the "pdf"
+    /// or "png" feature).
+    #[structopt(short = "f", long = "format", default_value = "svg", possible_values = FORMATS, global = true)]
+    format: Format,
+
     /// Specifies the width and height of nodes in the grid (format: x,y).
-    #[structopt(short = "n", long = "node", parse(try_from_str = parse_size))]
-    node: Option<(i32, i32)>,
+    #[structopt(short = "n", long = "node", global = true, parse(try_from_str = parse_size))]
+    node: Option<(f64, f64)>,
 
     /// Specifies the width and height of the grid gaps (format: x,y).
-    #[structopt(short = "g", long = "gap", parse(try_from_str = parse_size))]
-    gap: Option<(i32, i32)>,
+    #[structopt(short = "g", long = "gap", global = true, parse(try_from_str = parse_size))]
+    gap: Option<(f64, f64)>,
 
     /// Injects the given CSS files into the generated SVG.
-    #[structopt(long = "css", parse(from_os_str))]
+    #[structopt(long = "css", global = true, parse(from_os_str))]
     css: Option<Vec<PathBuf>>,
 
     /// Don't inject the default CSS file.
-    #[structopt(long = "no-default-css")]
+    #[structopt(long = "no-default-css", global = true)]
     no_default_css: bool,
+
+    /// Define the colors and font size of the default CSS as CSS variables,
+    /// which host pages can override. Ignored for PDF and PNG output.
+    #[structopt(long = "css-variables", global = true)]
+    css_variables: bool,
+
+    /// Sets the font family of the text in the flowchart (CSS font-family syntax).
+    #[structopt(long = "font-family", global = true)]
+    font_family: Option<String>,
+
+    /// Sets the font size of the text in the flowchart, in pixels.
+    #[structopt(long = "font-size", global = true, parse(try_from_str = parse_font_size))]
+    font_size: Option<f64>,
+
+    /// Sets the width of connection lines, in pixels. Arrowheads are scaled along with it.
+    #[structopt(long = "stroke-width", global = true, parse(try_from_str = parse_stroke_width))]
+    stroke_width: Option<f64>,
+
+    /// Sets the corner radius of rectangle and square nodes, in pixels.
+    #[structopt(long = "corner-radius", global = true, parse(try_from_str = parse_corner_radius))]
+    corner_radius: Option<f64>,
+
+    /// Makes connections stop short of node outlines by half the stroke width plus this margin, in pixels.
+    #[structopt(long = "endpoint-margin", global = true, parse(try_from_str = parse_endpoint_margin))]
+    endpoint_margin: Option<f64>,
+
+    /// Sets the space between the outline of nodes and their text, in pixels.
+    #[structopt(long = "node-padding", global = true, parse(try_from_str = parse_node_padding))]
+    node_padding: Option<f64>,
+
+    /// How connections are routed: corner or channel.
+    #[structopt(long = "router", default_value = "corner", possible_values = ROUTERS, global = true)]
+    router: Router,
+
+    /// How connections are drawn: orthogonal or curved.
+    #[structopt(long = "connection-style", global = true, possible_values = CONNECTION_STYLES, parse(try_from_str = parse_connection_style))]
+    connection_style: Option<ConnectionStyle>,
+
+    /// Don't draw the background.
+    #[structopt(long = "transparent", global = true, conflicts_with = "background")]
+    transparent: bool,
+
+    /// Fills the background with the given CSS color.
+    #[structopt(long = "background", global = true)]
+    background: Option<String>,
+
+    /// Grid drawn over the background: none, dots or lines.
+    #[structopt(long = "background-grid", global = true, possible_values = BACKGROUND_GRIDS, parse(try_from_str = parse_background_grid))]
+    background_grid: Option<BackgroundGrid>,
+
+    /// Corner of the flowchart that the legend is drawn in.
+    #[structopt(long = "legend-position", global = true, possible_values = LEGEND_POSITIONS, parse(try_from_str = parse_legend_position))]
+    legend_position: Option<LegendPosition>,
+
+    /// Embeds a font file into the generated SVG (format: family=path).
+    #[structopt(long = "embed-font", global = true, parse(try_from_str = parse_embedded_font))]
+    embed_font: Option<Vec<(String, PathBuf)>>,
+
+    /// Precede the SVG with an XML declaration and DOCTYPE.
+    #[structopt(long = "standalone", global = true)]
+    standalone: bool,
+
+    /// Don't wrap node text that is wider than the node.
+    #[structopt(long = "no-wrap", global = true)]
+    no_wrap: bool,
+
+    /// Write binary output such as PDF even if stdout is a terminal.
+    #[structopt(long = "force", global = true)]
+    force: bool,
+
+    /// Log what is being done to standard error, including --timings.
+    #[structopt(short = "v", long = "verbose", global = true)]
+    verbose: bool,
+
+    /// Log how long each phase takes to standard error.
+    #[structopt(long = "timings", global = true)]
+    timings: bool,
+
+    /// Unit of the flowchart's width and height: px, mm, cm, in or pt.
+    #[structopt(long = "unit", global = true, possible_values = UNITS, parse(try_from_str = parse_unit))]
+    unit: Option<Unit>,
+
+    /// Pixels per inch, used to convert the flowchart's size into --unit.
+    #[structopt(long = "dpi", global = true, parse(try_from_str = parse_dpi))]
+    dpi: Option<f64>,
+
+    /// Fails instead of rendering flowcharts with more nodes than this.
+    #[structopt(long = "max-nodes", global = true)]
+    max_nodes: Option<usize>,
+
+    /// Fails instead of rendering flowcharts with more connections than this.
+    #[structopt(long = "max-connections", global = true)]
+    max_connections: Option<usize>,
+
+    /// Fails instead of rendering flowcharts whose width times height in
+    /// pixels is more than this.
+    #[structopt(long = "max-pixel-area", global = true)]
+    max_pixel_area: Option<u64>,
+
+    /// Render for black-and-white printing.
+    #[structopt(long = "print", global = true)]
+    print: bool,
+
+    /// Output statistics about the flowchart as JSON instead of rendering it.
+    #[structopt(long = "stats", global = true)]
+    stats: bool,
+
+    /// Render one file per step of a progressive reveal, named after the
+    /// output file (e.g. out-1.svg, out-2.svg). Requires --output.
+    #[structopt(long = "steps", global = true)]
+    steps: bool,
+
+    /// Outputs to write, separated by commas: svg (the flowchart, in
+    /// --format), layout (positions of nodes and connections as JSON) or
+    /// stats (as with --stats). More than one requires --output, and they're
+    /// written next to it (e.g. out.svg, out.layout.json, out.stats.json).
+    #[structopt(long = "emit", global = true, use_delimiter = true, possible_values = EMITS, conflicts_with_all = &["stats", "steps"])]
+    emit: Option<Vec<Emit>>,
+
+    /// Open the output with the system's default viewer once it's written.
+    /// Without --output, it's written to a temporary file first.
+    #[structopt(long = "open", global = true, conflicts_with_all = &["stats", "steps"])]
+    open: bool,
+
+    /// Split PNG output into square tiles of this many pixels, named after
+    /// the output file by row and column (e.g. out-0-1.png), along with an
+    /// index of them in out.tiles.json. Requires --output and --format png.
+    #[cfg(feature = "png")]
+    #[structopt(long = "tile", global = true, parse(try_from_str = parse_tile_size), conflicts_with_all = &["stats", "steps", "emit", "open"])]
+    tile: Option<u32>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Renders the changes between two versions of a flowchart.
+    ///
+    /// Added nodes and connections are highlighted in green, removed ones
+    /// in red and changed ones in amber.
+    Diff {
+        /// The old version of the flowchart.
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+
+        /// The new version of the flowchart.
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+
+    /// Renames a label along with every reference to it.
+    ///
+    /// The input is otherwise left untouched, including its formatting and
+    /// comments. Outputs the changed input instead of rendering it.
+    RenameLabel {
+        /// The label to rename.
+        old: String,
+
+        /// The new name of the label.
+        new: String,
+
+        /// Input file; use "-" to read input from stdin.
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Overwrite the input file instead of writing to the output.
+        #[structopt(short = "i", long = "in-place")]
+        in_place: bool,
+    },
+
+    /// Checks a flowchart for style problems.
+    ///
+    /// Rules are configured in the [lint] table of flou.toml in the current
+    /// directory, or of the file given with --config. Fails if any rule with
+    /// the "error" severity is broken.
+    Lint {
+        /// Input file; use "-" to read input from stdin.
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Configuration file to use instead of flou.toml.
+        #[structopt(long = "config", parse(from_os_str))]
+        config: Option<PathBuf>,
+
+        /// Fail if any rule is broken, including ones with the "warning"
+        /// severity.
+        #[structopt(long = "deny-warnings")]
+        deny_warnings: bool,
+    },
+
+    /// Outputs a completion script for the given shell.
+    ///
+    /// For example, to enable completions in the current bash session:
+    /// source <(flou completions bash)
+    Completions {
+        /// The shell to generate completions for.
+        #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+    },
 }
 
-fn parse_size(src: &str) -> Result<(i32, i32), &'static str> {
+const CONFIG_FILE: &str = "flou.toml";
+
+/// The name the CLI is installed under, which completion scripts are
+/// generated for.
+const BIN_NAME: &str = "flou";
+
+const FORMATS: &[&str] = &[
+    "svg",
+    "html",
+    "json",
+    #[cfg(feature = "pdf")]
+    "pdf",
+    #[cfg(feature = "png")]
+    "png",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Svg,
+    Html,
+    Json,
+    #[cfg(feature = "pdf")]
+    Pdf,
+    #[cfg(feature = "png")]
+    Png,
+}
+
+impl FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "svg" => Ok(Self::Svg),
+            "html" => Ok(Self::Html),
+            "json" => Ok(Self::Json),
+            #[cfg(feature = "pdf")]
+            "pdf" => Ok(Self::Pdf),
+            #[cfg(feature = "png")]
+            "png" => Ok(Self::Png),
+            _ => Err("Unsupported output format"),
+        }
+    }
+}
+
+impl Format {
+    /// The file extension used for output in this format.
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Html => "html",
+            Format::Json => "json",
+            #[cfg(feature = "pdf")]
+            Format::Pdf => "pdf",
+            #[cfg(feature = "png")]
+            Format::Png => "png",
+        }
+    }
+
+    /// Whether the output is binary data rather than text.
+    fn is_binary(self) -> bool {
+        match self {
+            Format::Svg | Format::Html | Format::Json => false,
+            #[cfg(feature = "pdf")]
+            Format::Pdf => true,
+            #[cfg(feature = "png")]
+            Format::Png => true,
+        }
+    }
+}
+
+const EMITS: &[&str] = &["svg", "layout", "stats"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Emit {
+    Svg,
+    Layout,
+    Stats,
+}
+
+impl FromStr for Emit {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "svg" => Ok(Self::Svg),
+            "layout" => Ok(Self::Layout),
+            "stats" => Ok(Self::Stats),
+            _ => Err("Unsupported output"),
+        }
+    }
+}
+
+const ROUTERS: &[&str] = &["corner", "channel"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Router {
+    Corner,
+    Channel,
+}
+
+impl FromStr for Router {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "corner" => Ok(Self::Corner),
+            "channel" => Ok(Self::Channel),
+            _ => Err("Unsupported router"),
+        }
+    }
+}
+
+fn parse_size(src: &str) -> Result<(f64, f64), &'static str> {
     let tokens = src.split(',').collect::<Vec<_>>();
     if tokens.len() != 2 {
         return Err("Size should have format: \"x,y\"");
     }
 
     let x = tokens[0]
-        .parse::<i32>()
+        .parse::<f64>()
         .map_err(|_| "Could not parse X coordinate")?;
     let y = tokens[1]
-        .parse::<i32>()
+        .parse::<f64>()
         .map_err(|_| "Could not parse Y coordinate")?;
 
-    if x < 0 || y < 0 {
+    if !x.is_finite() || !y.is_finite() {
+        return Err("X and Y must be finite numbers.");
+    }
+
+    if x < 0.0 || y < 0.0 {
         return Err("X and Y cannot be negative.");
     }
 
     Ok((x, y))
 }
 
+fn parse_font_size(src: &str) -> Result<f64, &'static str> {
+    match src.parse::<f64>() {
+        Ok(size) if size.is_finite() && size > 0.0 => Ok(size),
+        _ => Err("Font size should be a positive number."),
+    }
+}
+
+fn parse_corner_radius(src: &str) -> Result<f64, &'static str> {
+    match src.parse::<f64>() {
+        Ok(radius) if radius.is_finite() && radius >= 0.0 => Ok(radius),
+        _ => Err("Corner radius should be a non-negative number."),
+    }
+}
+
+fn parse_endpoint_margin(src: &str) -> Result<f64, &'static str> {
+    match src.parse::<f64>() {
+        Ok(margin) if margin.is_finite() && margin >= 0.0 => Ok(margin),
+        _ => Err("Endpoint margin should be a non-negative number."),
+    }
+}
+
+fn parse_node_padding(src: &str) -> Result<f64, &'static str> {
+    match src.parse::<f64>() {
+        Ok(padding) if padding.is_finite() && padding >= 0.0 => Ok(padding),
+        _ => Err("Node padding should be a non-negative number."),
+    }
+}
+
+fn parse_stroke_width(src: &str) -> Result<f64, &'static str> {
+    match src.parse::<f64>() {
+        Ok(width) if width.is_finite() && width > 0.0 => Ok(width),
+        _ => Err("Stroke width should be a positive number."),
+    }
+}
+
+const LEGEND_POSITIONS: &[&str] = &["top-left", "top-right", "bottom-left", "bottom-right"];
+
+fn parse_legend_position(src: &str) -> Result<LegendPosition, &'static str> {
+    match src {
+        "top-left" => Ok(LegendPosition::TopLeft),
+        "top-right" => Ok(LegendPosition::TopRight),
+        "bottom-left" => Ok(LegendPosition::BottomLeft),
+        "bottom-right" => Ok(LegendPosition::BottomRight),
+        _ => Err("Unsupported legend position"),
+    }
+}
+
+const UNITS: &[&str] = &["px", "mm", "cm", "in", "pt"];
+
+fn parse_unit(src: &str) -> Result<Unit, &'static str> {
+    match src {
+        "px" => Ok(Unit::Px),
+        "mm" => Ok(Unit::Mm),
+        "cm" => Ok(Unit::Cm),
+        "in" => Ok(Unit::In),
+        "pt" => Ok(Unit::Pt),
+        _ => Err("Unsupported unit"),
+    }
+}
+
+fn parse_dpi(src: &str) -> Result<f64, &'static str> {
+    match src.parse::<f64>() {
+        Ok(dpi) if dpi.is_finite() && dpi > 0.0 => Ok(dpi),
+        _ => Err("DPI should be a positive number."),
+    }
+}
+
+const BACKGROUND_GRIDS: &[&str] = &["none", "dots", "lines"];
+
+fn parse_background_grid(src: &str) -> Result<BackgroundGrid, &'static str> {
+    match src {
+        "none" => Ok(BackgroundGrid::None),
+        "dots" => Ok(BackgroundGrid::Dots),
+        "lines" => Ok(BackgroundGrid::Lines),
+        _ => Err("Unsupported background grid"),
+    }
+}
+
+const CONNECTION_STYLES: &[&str] = &["orthogonal", "curved"];
+
+fn parse_connection_style(src: &str) -> Result<ConnectionStyle, &'static str> {
+    match src {
+        "orthogonal" => Ok(ConnectionStyle::Orthogonal),
+        "curved" => Ok(ConnectionStyle::Curved),
+        _ => Err("Unsupported connection style"),
+    }
+}
+
+#[cfg(feature = "png")]
+fn parse_tile_size(src: &str) -> Result<u32, &'static str> {
+    match src.parse::<u32>() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err("Tile size should be a positive whole number."),
+    }
+}
+
+fn parse_embedded_font(src: &str) -> Result<(String, PathBuf), &'static str> {
+    match src.split_once('=') {
+        Some((family, path)) if !family.is_empty() && !path.is_empty() => {
+            Ok((family.to_string(), PathBuf::from(path)))
+        }
+        _ => Err("Embedded font should have format: \"family=path\""),
+    }
+}
+
+#[derive(Debug)]
 pub enum Error {
+    MissingInput,
+    MissingOutput(&'static str),
     InputOpen(io::Error),
     InputRead(io::Error),
+    InvalidUtf8(usize),
+    InvalidUtf16,
     OutputOpen(io::Error),
     OutputWrite(io::Error),
     CssRead(PathBuf, io::Error),
+    FontRead(PathBuf, io::Error),
     Parse(String),
+    Logic(String),
+    Edit(EditError),
+    ConfigRead(PathBuf, io::Error),
+    Config(PathBuf, String),
+    Lint(usize),
+    Pdf(String),
+    Png(String),
+    TileFormat,
+    BinaryToTerminal,
+    Open(PathBuf, io::Error),
 }
 
-pub fn run(opt: Opt) -> Result<(), Error> {
-    let mut reader: Box<dyn BufRead> = if opt.input != PathBuf::from("-") {
-        fs::File::open(opt.input)
+impl Error {
+    /// The code the process should exit with, so that scripts can tell
+    /// different kinds of failures apart:
+    ///
+    /// - 1 for invalid usage and anything not listed below,
+    /// - 2 for errors reading or writing files,
+    /// - 3 for input that can't be parsed or is over a limit,
+    /// - 4 for input that parses but is invalid, such as duplicate labels,
+    /// - 5 for `lint` finding errors, or warnings with `--deny-warnings`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InputOpen(_)
+            | Error::InputRead(_)
+            | Error::InvalidUtf8(_)
+            | Error::InvalidUtf16
+            | Error::OutputOpen(_)
+            | Error::OutputWrite(_)
+            | Error::CssRead(..)
+            | Error::FontRead(..)
+            | Error::ConfigRead(..) => 2,
+            Error::Parse(_) => 3,
+            Error::Logic(_) => 4,
+            Error::Lint(_) => 5,
+            Error::MissingInput
+            | Error::MissingOutput(_)
+            | Error::Edit(_)
+            | Error::Config(..)
+            | Error::Pdf(_)
+            | Error::Png(_)
+            | Error::TileFormat
+            | Error::BinaryToTerminal
+            | Error::Open(..) => 1,
+        }
+    }
+}
+
+pub fn run(mut opt: Opt) -> Result<(), Error> {
+    logging::init(opt.verbose, opt.timings);
+
+    match opt.command.take() {
+        Some(Command::Diff { old, new }) => {
+            let old_input = read_input(&old)?;
+            let new_input = read_input(&new)?;
+            let old = parse(&old_input)?;
+            let new = parse(&new_input)?;
+
+            output(opt, &Flou::diff(&old, &new))
+        }
+        Some(Command::RenameLabel {
+            old,
+            new,
+            input,
+            in_place,
+        }) => {
+            let mut editor = Editor::new(read_input(&input)?);
+            editor.rename_label(&old, &new).map_err(Error::Edit)?;
+
+            let output = if in_place { Some(input) } else { opt.output };
+            let mut writer = open_output(output.as_deref())?;
+            writer
+                .write_all(editor.source().as_bytes())
+                .map_err(Error::OutputWrite)?;
+            writer.finish().map_err(Error::OutputWrite)
+        }
+        Some(Command::Lint {
+            input,
+            config,
+            deny_warnings,
+        }) => {
+            let config = read_lint_config(config)?;
+            let input = read_input(&input)?;
+            let diagnostics = lint(&input, &config).map_err(|e| flou_error(e, &input))?;
+
+            let cells = Cells::new(&input);
+            let mut writer = open_output(opt.output.as_deref())?;
+            for diagnostic in &diagnostics {
+                writeln!(writer, "{}", diagnostic_to_string(diagnostic, &cells))
+                    .map_err(Error::OutputWrite)?;
+            }
+            writer.finish().map_err(Error::OutputWrite)?;
+
+            let errors = diagnostics
+                .iter()
+                .filter(|d| deny_warnings || d.severity == Severity::Error)
+                .count();
+
+            match errors {
+                0 => Ok(()),
+                n => Err(Error::Lint(n)),
+            }
+        }
+        Some(Command::Completions { shell }) => {
+            let mut writer = open_output(opt.output.as_deref())?;
+            Opt::clap().gen_completions_to(BIN_NAME, shell, &mut writer);
+            writer.finish().map_err(Error::OutputWrite)
+        }
+        None => {
+            let input = opt.input.as_deref().ok_or(Error::MissingInput)?;
+            let input = read_input(input)?;
+            let flou = parse(&input)?;
+
+            output(opt, &flou)
+        }
+    }
+}
+
+fn read_input(path: &Path) -> Result<String, Error> {
+    log::debug!("Reading {}", path.display());
+    let input = timed("read", || read_input_untimed(path))?;
+    log::debug!("Read {} bytes", input.len());
+
+    Ok(input)
+}
+
+fn read_input_untimed(path: &Path) -> Result<String, Error> {
+    let mut reader: Box<dyn BufRead> = if path.as_os_str() != "-" {
+        fs::File::open(path)
             .map(|x| -> Box<dyn BufRead> { Box::new(BufReader::new(x)) })
             .map_err(Error::InputOpen)?
     } else {
         Box::new(BufReader::new(io::stdin()))
     };
 
-    let mut writer: Box<dyn Write> = if let Some(filename) = opt.output {
-        fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(filename)
-            .map(|x| -> Box<dyn Write> { Box::new(BufWriter::new(x)) })
-            .map_err(Error::OutputOpen)?
-    } else {
-        Box::new(BufWriter::new(io::stdout()))
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input).map_err(Error::InputRead)?;
+
+    decode_input(input)
+}
+
+/// Decodes input as UTF-8, or as UTF-16 if it starts with a UTF-16 byte
+/// order mark. A UTF-8 byte order mark, as some editors on Windows add, is
+/// left out.
+fn decode_input(input: Vec<u8>) -> Result<String, Error> {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let pairs = bytes.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return Err(Error::InvalidUtf16);
+        }
+
+        let units = pairs
+            .map(|pair| from_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16(&units).map_err(|_| Error::InvalidUtf16)
+    };
+
+    match input.as_slice() {
+        [0xef, 0xbb, 0xbf, rest @ ..] => decode_utf8(rest.to_vec(), 3),
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        _ => decode_utf8(input, 0),
+    }
+}
+
+/// Decodes UTF-8 that starts `offset` bytes into the input.
+fn decode_utf8(input: Vec<u8>, offset: usize) -> Result<String, Error> {
+    String::from_utf8(input).map_err(|e| Error::InvalidUtf8(offset + e.utf8_error().valid_up_to()))
+}
+
+fn parse(input: &str) -> Result<Flou<'_>, Error> {
+    let document = timed("parse", || ast::Document::parse(input))
+        .map_err(|e| flou_error(FlouError::Parse(e), input))?;
+    let flou = timed("validate", || Flou::try_from(document))
+        .map_err(|e| flou_error(FlouError::Logic(e), input))?;
+
+    Ok(flou)
+}
+
+fn read_lint_config(path: Option<PathBuf>) -> Result<LintConfig, Error> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(CONFIG_FILE).exists() => PathBuf::from(CONFIG_FILE),
+        None => return Ok(LintConfig::default()),
     };
 
-    let mut input = String::new();
-    reader
-        .read_to_string(&mut input)
-        .map_err(Error::InputRead)?;
+    let contents = fs::read_to_string(&path).map_err(|e| Error::ConfigRead(path.clone(), e))?;
+    parse_lint_config(&contents).map_err(|e| Error::Config(path, e))
+}
 
+fn parse_lint_config(contents: &str) -> Result<LintConfig, String> {
+    let table = contents.parse::<toml::Table>().map_err(|e| e.to_string())?;
+    let mut config = LintConfig::default();
+
+    let lint = match table.get("lint") {
+        Some(toml::Value::Table(lint)) => lint,
+        Some(_) => return Err("\"lint\" should be a table.".into()),
+        None => return Ok(config),
+    };
+
+    for (key, value) in lint {
+        match (key.as_str(), value) {
+            ("max-grid-width", toml::Value::Integer(width)) if *width >= 0 => {
+                config.max_grid_width = *width as usize;
+            }
+            ("max-grid-width", _) => {
+                return Err("\"max-grid-width\" should be a non-negative integer.".into())
+            }
+            ("rules", toml::Value::Table(rules)) => {
+                for (name, severity) in rules {
+                    let rule = Rule::from_name(name)
+                        .ok_or_else(|| format!("Unknown rule \"{}\".", name))?;
+                    let severity =
+                        severity
+                            .as_str()
+                            .and_then(Severity::from_name)
+                            .ok_or_else(|| {
+                                format!(
+                                "Severity of \"{}\" should be \"allow\", \"warning\" or \"error\".",
+                                name
+                            )
+                            })?;
+
+                    config.set_severity(rule, severity);
+                }
+            }
+            ("rules", _) => return Err("\"rules\" should be a table.".into()),
+            (key, _) => return Err(format!("Unknown lint option \"{}\".", key)),
+        }
+    }
+
+    Ok(config)
+}
+
+fn diagnostic_to_string(diagnostic: &Diagnostic, cells: &Cells) -> String {
+    match diagnostic.pos {
+        Some(coord) => format!(
+            "{}[{}] at {}: {}",
+            diagnostic.severity,
+            diagnostic.rule,
+            cells.at(coord),
+            diagnostic.message
+        ),
+        None => format!(
+            "{}[{}]: {}",
+            diagnostic.severity, diagnostic.rule, diagnostic.message
+        ),
+    }
+}
+
+fn open_output(path: Option<&Path>) -> Result<OutputFile, Error> {
+    match path {
+        Some(filename) => OutputFile::create(filename).map_err(Error::OutputOpen),
+        None => Ok(OutputFile::stdout()),
+    }
+}
+
+fn output(opt: Opt, flou: &Flou<'_>) -> Result<(), Error> {
     let css = opt
         .css
         .unwrap_or_default()
@@ -98,11 +775,39 @@ pub fn run(opt: Opt) -> Result<(), Error> {
         .map(|filename| fs::read_to_string(&filename).map_err(|e| Error::CssRead(filename, e)))
         .collect::<Result<Vec<_>, _>>()?;
 
-    let flou = Flou::try_from(input.as_str()).map_err(|x| Error::Parse(flou_error_to_string(x)))?;
+    let fonts = opt
+        .embed_font
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(family, filename)| match fs::read(&filename) {
+            Ok(data) => Ok(EmbeddedFont::new(family, data)),
+            Err(e) => Err(Error::FontRead(filename, e)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     let mut config = RenderConfig {
         css,
+        standalone: opt.standalone,
         default_css: !opt.no_default_css,
+        css_variables: opt.css_variables,
+        font_family: opt.font_family,
+        font_size: opt.font_size,
+        stroke_width: opt.stroke_width,
+        corner_radius: opt.corner_radius.unwrap_or_default(),
+        node_padding: opt.node_padding.unwrap_or_default(),
+        connection_style: opt.connection_style.unwrap_or_default(),
+        endpoint_margin: opt.endpoint_margin,
+        legend_position: opt.legend_position.unwrap_or_default(),
+        background: match opt.background {
+            Some(color) => Background::Color(color),
+            None if opt.transparent => Background::Transparent,
+            None => Background::Default,
+        },
+        background_grid: opt.background_grid.unwrap_or_default(),
+        fonts,
+        wrap_text: !opt.no_wrap,
+        print_mode: opt.print,
+        unit: opt.unit,
         ..Default::default()
     };
 
@@ -110,152 +815,675 @@ pub fn run(opt: Opt) -> Result<(), Error> {
         config.node = node.into();
     }
 
+    // PDFs and PNGs are converted from SVG by libraries without support
+    // for CSS custom properties.
+    #[cfg(feature = "pdf")]
+    if let Format::Pdf = opt.format {
+        config.css_variables = false;
+    }
+    #[cfg(feature = "png")]
+    if let Format::Png = opt.format {
+        config.css_variables = false;
+    }
+
     if let Some(gap) = opt.gap {
         config.grid_gap = gap.into();
     }
 
-    let output = SvgRenderer::render(&flou, &config);
+    if let Some(dpi) = opt.dpi {
+        config.dpi = dpi;
+    }
 
-    write!(writer, "{}", output).map_err(Error::OutputWrite)?;
+    let unlimited = RenderLimits::default();
+    config.limits = RenderLimits {
+        max_nodes: opt.max_nodes.unwrap_or(unlimited.max_nodes),
+        max_connections: opt.max_connections.unwrap_or(unlimited.max_connections),
+        max_pixel_area: opt.max_pixel_area.unwrap_or(unlimited.max_pixel_area),
+    };
 
-    Ok(())
-}
+    #[cfg(feature = "png")]
+    if let Some(tile) = opt.tile {
+        if opt.format != Format::Png {
+            return Err(Error::TileFormat);
+        }
 
-fn flou_error_to_string(e: FlouError) -> String {
-    match e {
-        FlouError::Parse(e) => {
-            format!("Error parsing Flou:\n\n{}", e)
+        let output = opt
+            .output
+            .as_deref()
+            .ok_or(Error::MissingOutput("--tile"))?;
+        return emit_tiles(output, tile, opt.router, flou, &config);
+    }
+
+    if opt.steps && !opt.stats {
+        let output = opt
+            .output
+            .as_deref()
+            .ok_or(Error::MissingOutput("--steps"))?;
+
+        for step in flou.steps() {
+            let target = (Emit::Svg, Some(step_path(output, step)));
+            emit(
+                &[target],
+                None,
+                opt.format,
+                opt.router,
+                &flou.at_step(step),
+                &config,
+            )?;
         }
-        FlouError::Logic(e) => {
-            format!("Error in Flou logic:\n{}", logic_error_to_string(e))
+
+        return Ok(());
+    }
+
+    let mut kinds = match opt.emit {
+        Some(kinds) => kinds,
+        None if opt.stats => vec![Emit::Stats],
+        None => vec![Emit::Svg],
+    };
+    kinds.sort_unstable();
+    kinds.dedup();
+
+    // Without -o, --open writes to a new file in the temporary directory,
+    // which is only done for a single kind of output.
+    let mut temp_file = None;
+    let output = match opt.output {
+        None if opt.open && kinds.len() == 1 => {
+            let (file, path) = temp_output_file(opt.format)?;
+            temp_file = Some(file);
+            Some(path)
         }
+        output => output,
+    };
+
+    let targets = match (&kinds[..], output) {
+        (&[kind], output) => vec![(kind, output)],
+        (_, Some(output)) => kinds
+            .iter()
+            .map(|&kind| (kind, Some(emit_path(&output, kind))))
+            .collect(),
+        (_, None) => return Err(Error::MissingOutput("--emit with more than one output")),
+    };
+
+    // Binary output would garble the terminal it's written to.
+    let binary = opt.format.is_binary();
+    let binary_to_stdout = targets
+        .iter()
+        .any(|(kind, path)| *kind == Emit::Svg && path.is_none() && binary);
+    if binary_to_stdout && !opt.force && io::stdout().is_terminal() {
+        return Err(Error::BinaryToTerminal);
+    }
+
+    emit(&targets, temp_file, opt.format, opt.router, flou, &config)?;
+
+    let rendered = targets
+        .into_iter()
+        .find_map(|(kind, path)| path.filter(|_| kind == Emit::Svg));
+    match rendered {
+        Some(path) if opt.open => open_in_viewer(&path).map_err(|e| Error::Open(path, e)),
+        _ => Ok(()),
     }
 }
 
-fn logic_error_to_string(e: LogicError) -> String {
-    match e {
-        LogicError::DuplicateLabels(labels) => {
-            let labels = print_map(labels, "\n", |label, locations| {
-                let locations = print_sequence(locations, ", ", |x| x.to_string());
-                format!("  - \"{}\" at: {}", label, locations)
-            });
+/// The path that `kind` is written to when emitting more than one kind of
+/// output, such as `out.layout.json` for `out.svg`.
+fn emit_path(output: &Path, kind: Emit) -> PathBuf {
+    match kind {
+        Emit::Svg => output.to_path_buf(),
+        Emit::Layout => output.with_extension("layout.json"),
+        Emit::Stats => output.with_extension("stats.json"),
+    }
+}
 
-            format!("Some labels are used more than once:\n\n{}", labels)
-        }
-        LogicError::DuplicateDefinitions(ids) => {
-            let ids = print_sequence(ids, "\n", |id| format!("  - \"{}\"", id));
-            format!("Some identifiers have multiple definitions:\n\n{}", ids)
-        }
-        LogicError::DuplicateNodeAttributesInDefinitions(attrs) => {
-            let attrs = print_map(attrs, "\n", |id, attrs| {
-                let attrs = print_sequence(attrs, ", ", quote);
-                format!("  - \"{}\" has duplicate(s): {}", id, attrs)
-            });
-
-            format!(
-                "Some node definitions have duplicate attributes:\n\n{}",
-                attrs
-            )
-        }
-        LogicError::DuplicateNodeAttributesInGrid(attrs) => {
-            let attrs = print_map(attrs, "\n", |id, attrs| {
-                let attrs = print_sequence(attrs, ", ", quote);
-                format!("  - Node at {} has duplicate(s): {}", id, attrs)
-            });
-
-            format!(
-                "Some nodes declared in the grid have duplicate attributes:\n\n{}",
-                attrs
-            )
+/// A file in the temporary directory to write output to when it's only
+/// needed for `--open`. It gets a random name and is created only if no
+/// file by that name exists, so other users of the directory can't plant
+/// a file or symlink in its place. It's kept once the CLI exits, for the
+/// viewer to read.
+fn temp_output_file(format: Format) -> Result<(fs::File, PathBuf), Error> {
+    tempfile::Builder::new()
+        .prefix("flou-")
+        .suffix(&format!(".{}", format.extension()))
+        .tempfile()
+        .and_then(|file| file.keep().map_err(|e| e.error))
+        .map_err(Error::OutputOpen)
+}
+
+/// Opens `path` with the system's default application for its file type,
+/// without waiting for it to be closed.
+fn open_in_viewer(path: &Path) -> io::Result<()> {
+    log::debug!("Opening {}", path.display());
+
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+
+    command.arg(path).spawn().map(|_| ())
+}
+
+/// The path of the file that `step` is written to, such as `out-2.svg`
+/// for `out.svg`.
+fn step_path(output: &Path, step: u32) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, step, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, step),
+    };
+
+    output.with_file_name(name)
+}
+
+/// Renders `flou` and writes each kind of output in `targets` to its
+/// path, or to stdout if it has none. Connections are only routed once,
+/// even if both the flowchart and its layout are written. The rendered
+/// flowchart is written to `temp_file` instead, if it's given, which is
+/// already open at the flowchart's path.
+fn emit(
+    targets: &[(Emit, Option<PathBuf>)],
+    mut temp_file: Option<fs::File>,
+    format: Format,
+    router: Router,
+    flou: &Flou<'_>,
+    config: &RenderConfig,
+) -> Result<(), Error> {
+    let emits = |kind| targets.iter().any(|(k, _)| *k == kind);
+    let svg = svg_renderer(router);
+
+    log::debug!("Rendering as {:?}", format);
+    let rendered = timed("render", || -> Result<_, Error> {
+        Ok(match (emits(Emit::Svg), emits(Emit::Layout)) {
+            (true, true) => {
+                config.check_limits(flou).map_err(limit_error)?;
+                let (output, layout) = match format {
+                    Format::Html => HtmlRenderer::new(svg).render_with_layout(flou, config),
+                    Format::Json => JsonRenderer::new(svg).render_with_layout(flou, config),
+                    _ => svg.render_with_layout(flou, config),
+                };
+                (Some(output), Some(layout))
+            }
+            (true, false) => {
+                let renderer: Box<dyn Renderer> = match format {
+                    Format::Html => Box::new(HtmlRenderer::new(svg)),
+                    Format::Json => Box::new(JsonRenderer::new(svg)),
+                    _ => Box::new(svg),
+                };
+                let output = renderer.try_render(flou, config).map_err(limit_error)?;
+                (Some(output), None)
+            }
+            (false, true) => {
+                config.check_limits(flou).map_err(limit_error)?;
+                (None, Some(svg.layout(flou, config)))
+            }
+            (false, false) => (None, None),
+        })
+    });
+    let (output, layout) = rendered?;
+
+    timed("write", || {
+        for (kind, path) in targets {
+            let bytes = match (kind, &output, &layout) {
+                (Emit::Svg, Some(output), _) => encode(format, &output.to_string())?,
+                (Emit::Layout, _, Some(layout)) => format!("{}\n", layout.to_json()).into_bytes(),
+                (Emit::Stats, ..) => format!("{}\n", flou.stats(config).to_json()).into_bytes(),
+                _ => unreachable!("every kind of output in targets is rendered"),
+            };
+            log::debug!("Writing {} bytes of {:?}", bytes.len(), kind);
+
+            let temp_file = match kind {
+                Emit::Svg => temp_file.take(),
+                _ => None,
+            };
+            let mut writer = match temp_file {
+                Some(file) => OutputFile::direct(file),
+                None => open_output(path.as_deref())?,
+            };
+            writer.write_all(&bytes).map_err(Error::OutputWrite)?;
+            writer.finish().map_err(Error::OutputWrite)?;
         }
-        LogicError::DuplicateConnectionAttributesInDefinitions(attrs) => {
-            let attrs = print_map(attrs, "\n", |id, index_map| {
-                let indexes = print_map(index_map, "\n", |index, attrs| {
-                    format!(
-                        "    - At index {}: {}",
-                        index,
-                        print_sequence(attrs, ", ", quote)
-                    )
-                });
-
-                format!("  - At definition \"{}\":\n{}", id, indexes)
-            });
-
-            format!(
-                "Some connections in node definitions have duplicate attributes:\n\n{}",
-                attrs
-            )
+
+        Ok(())
+    })
+}
+
+fn svg_renderer(router: Router) -> SvgRenderer {
+    match router {
+        Router::Corner => SvgRenderer::new(),
+        Router::Channel => SvgRenderer::new().router(ChannelRouter),
+    }
+}
+
+/// Renders `flou` as PNG tiles of `size` pixels, each written next to
+/// `output` and named after its row and column, and writes an index of
+/// them to `output` with the `tiles.json` extension. Tiles in the last
+/// row and column are cut to the size of the flowchart.
+#[cfg(feature = "png")]
+fn emit_tiles(
+    output: &Path,
+    size: u32,
+    router: Router,
+    flou: &Flou<'_>,
+    config: &RenderConfig,
+) -> Result<(), Error> {
+    config.check_limits(flou).map_err(limit_error)?;
+
+    let bounds = flou.stats(config).bounding_box;
+    let tile = f64::from(size);
+    let (rows, cols) = ((bounds.y / tile).ceil(), (bounds.x / tile).ceil());
+
+    let mut tiles = Vec::new();
+    for row in 0..rows as u32 {
+        for col in 0..cols as u32 {
+            let origin = PixelPos::from((f64::from(col) * tile, f64::from(row) * tile));
+            let size =
+                PixelPos::from((tile.min(bounds.x - origin.x), tile.min(bounds.y - origin.y)));
+            tiles.push((row, col, Rect::new(origin, size)));
         }
-        LogicError::DuplicateConnectionAttributesInGrid(attrs) => {
-            let attrs = print_map(attrs, "\n", |pos, index_map| {
-                let index_map = print_map(index_map, "\n", |index, attrs| {
-                    format!(
-                        "    - For connection at index {}: {}",
-                        index,
-                        print_sequence(attrs, ", ", quote)
-                    )
-                });
-
-                format!("  - At grid position {}:\n{}", pos, index_map)
-            });
-
-            format!(
-                "Some connections declared in the grid have duplicate attributes:\n\n{}",
-                attrs
-            )
+    }
+
+    let regions = tiles.iter().map(|&(.., rect)| rect).collect::<Vec<_>>();
+    log::debug!("Rendering {} tiles", regions.len());
+    let rendered = timed("render", || {
+        svg_renderer(router).render_regions(flou, config, &regions)
+    });
+
+    timed("write", || {
+        let mut index = Vec::new();
+        for (&(row, col, rect), output_tile) in tiles.iter().zip(rendered) {
+            let path = tile_path(output, row, col);
+            let bytes = encode(Format::Png, &output_tile.to_string())?;
+
+            let mut writer = open_output(Some(&path))?;
+            writer.write_all(&bytes).map_err(Error::OutputWrite)?;
+            writer.finish().map_err(Error::OutputWrite)?;
+
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            index.push((row, col, rect, name.into_owned()));
         }
-        LogicError::InvalidDestination(errors) => {
-            let errors = print_map(errors, "\n", |pos, index_map| {
-                let index_map = print_map(index_map, "\n", |index, error| {
-                    format!(
-                        "    - For connection at index {}: {}",
-                        index,
-                        print_resolution_error(error)
-                    )
-                });
-
-                format!("  - For node at grid position {}:\n{}", pos, index_map)
-            });
-
-            format!(
-                "Could not resolve destination for some node's connections:\n\n{}",
-                errors
-            )
+
+        let index = format!("{}\n", tile_index(bounds, size, rows, cols, &index));
+
+        let mut writer = open_output(Some(&output.with_extension("tiles.json")))?;
+        writer
+            .write_all(index.as_bytes())
+            .map_err(Error::OutputWrite)?;
+        writer.finish().map_err(Error::OutputWrite)
+    })
+}
+
+/// The index of the tiles of a flowchart of size `bounds`, as JSON. Each
+/// tile is given as its row, column, area and file name.
+#[cfg(feature = "png")]
+fn tile_index(
+    bounds: PixelPos,
+    size: u32,
+    rows: f64,
+    cols: f64,
+    tiles: &[(u32, u32, Rect, String)],
+) -> String {
+    let tiles = tiles.iter().map(|(row, col, rect, name)| {
+        JsonObject::new()
+            .number("row", f64::from(*row))
+            .number("col", f64::from(*col))
+            .number("x", rect.origin.x)
+            .number("y", rect.origin.y)
+            .number("width", rect.size.x)
+            .number("height", rect.size.y)
+            .string("file", name)
+            .finish()
+    });
+
+    JsonObject::new()
+        .number("width", bounds.x)
+        .number("height", bounds.y)
+        .number("tile_size", f64::from(size))
+        .number("rows", rows)
+        .number("cols", cols)
+        .raw("tiles", json_array(tiles))
+        .finish()
+}
+
+/// The path of the tile at `row` and `col`, such as `out-0-1.png` for
+/// `out.png`.
+#[cfg(feature = "png")]
+fn tile_path(output: &Path, row: u32, col: u32) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}-{}-{}.png", stem, row, col))
+}
+
+/// Converts a rendered flowchart into the bytes that are written out for
+/// `format`.
+fn encode(format: Format, output: &str) -> Result<Vec<u8>, Error> {
+    match format {
+        Format::Svg | Format::Html | Format::Json => Ok(output.as_bytes().to_vec()),
+        #[cfg(feature = "pdf")]
+        Format::Pdf => svg_to_pdf(output).map_err(Error::Pdf),
+        #[cfg(feature = "png")]
+        Format::Png => svg_to_png(output).map_err(Error::Png),
+    }
+}
+
+#[cfg(feature = "pdf")]
+fn svg_to_pdf(svg: &str) -> Result<Vec<u8>, String> {
+    use svg2pdf::usvg::{self, fontdb, PostProcessingSteps, TreeParsing, TreePostProc};
+
+    let mut tree =
+        usvg::Tree::from_str(svg, &usvg::Options::default()).map_err(|e| e.to_string())?;
+
+    // Text is converted to paths, which requires the fonts to be available.
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    tree.postprocess(PostProcessingSteps::default(), &fonts);
+
+    Ok(svg2pdf::convert_tree(&tree, svg2pdf::Options::default()))
+}
+
+#[cfg(feature = "png")]
+fn svg_to_png(svg: &str) -> Result<Vec<u8>, String> {
+    use resvg::{
+        tiny_skia::{Pixmap, Transform},
+        usvg::{self, fontdb, PostProcessingSteps, TreeParsing, TreePostProc},
+    };
+
+    let mut tree =
+        usvg::Tree::from_str(svg, &usvg::Options::default()).map_err(|e| e.to_string())?;
+
+    // Text is converted to paths, which requires the fonts to be available.
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    tree.postprocess(PostProcessingSteps::default(), &fonts);
+
+    let size = tree.size.to_int_size();
+    let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or_else(|| {
+        format!(
+            "an image of {}x{} pixels is too large; split it up with --tile",
+            size.width(),
+            size.height()
+        )
+    })?;
+    resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+/// `source` is the input the error is about, for pointing to the lines
+/// that the grid positions in it are written on.
+fn flou_error(e: FlouError, source: &str) -> Error {
+    match e {
+        FlouError::Logic(_) => {
+            let lines = SyntaxTree::parse(source).row_lines();
+            let message = e.display_with_lines(&lines).to_string();
+            Error::Logic(message)
         }
+        FlouError::Parse(_) | FlouError::LimitExceeded(_) => Error::Parse(e.to_string()),
     }
 }
 
-fn quote<T: fmt::Display>(item: T) -> String {
-    format!("\"{}\"", item)
+/// Reports exceeding a render limit like any other input over a limit.
+fn limit_error(limit: Limit) -> Error {
+    flou_error(FlouError::LimitExceeded(limit), "")
 }
 
-fn print_sequence<T: fmt::Display, I: IntoIterator<Item = T>>(
-    seq: I,
-    delimiter: &str,
-    print: impl Fn(T) -> String,
-) -> String {
-    seq.into_iter()
-        .map(print)
-        .collect::<Vec<_>>()
-        .join(delimiter)
+/// Turns grid positions into the ones shown to users, along with the line
+/// of the input each row is written on.
+struct Cells {
+    lines: Vec<usize>,
 }
 
-fn print_map<K: fmt::Display, V, I: IntoIterator<Item = (K, V)>>(
-    map: I,
-    delimiter: &str,
-    print: impl Fn(K, V) -> String,
-) -> String {
-    map.into_iter()
-        .map(|(k, v)| print(k, v))
-        .collect::<Vec<_>>()
-        .join(delimiter)
+impl Cells {
+    fn new(source: &str) -> Self {
+        Self {
+            lines: SyntaxTree::parse(source).row_lines(),
+        }
+    }
+
+    fn line(&self, row: isize) -> Option<usize> {
+        usize::try_from(row)
+            .ok()
+            .and_then(|row| self.lines.get(row).copied())
+    }
+
+    fn at(&self, coord: GridCoord) -> CellPosition {
+        CellPosition::from(coord).with_line(self.line(coord.row))
+    }
 }
 
-fn print_resolution_error(e: ResolutionError) -> String {
-    match e {
-        ResolutionError::InvalidDirection(dir) => {
-            format!("No destination found in direction: {}", dir)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, to_bytes: fn(u16) -> [u8; 2]) -> Vec<u8> {
+        text.encode_utf16().flat_map(to_bytes).collect()
+    }
+
+    #[test]
+    fn maps_errors_to_exit_codes() {
+        let io_error = || io::Error::other("failed");
+        let path = PathBuf::from("flou.toml");
+
+        let usage = [
+            Error::MissingInput,
+            Error::MissingOutput("--steps"),
+            Error::Edit(EditError::NoGrid),
+            Error::Config(path.clone(), "invalid".into()),
+            Error::Pdf("failed".into()),
+            Error::Png("failed".into()),
+            Error::TileFormat,
+            Error::BinaryToTerminal,
+            Error::Open(path.clone(), io_error()),
+        ];
+        let io = [
+            Error::InputOpen(io_error()),
+            Error::InputRead(io_error()),
+            Error::InvalidUtf8(0),
+            Error::InvalidUtf16,
+            Error::OutputOpen(io_error()),
+            Error::OutputWrite(io_error()),
+            Error::CssRead(path.clone(), io_error()),
+            Error::FontRead(path.clone(), io_error()),
+            Error::ConfigRead(path, io_error()),
+        ];
+
+        for error in usage {
+            assert_eq!(error.exit_code(), 1, "{:?}", error);
+        }
+        for error in io {
+            assert_eq!(error.exit_code(), 2, "{:?}", error);
         }
-        ResolutionError::UnknownLabel(label) => format!("No destination with label: \"{}\"", label),
+        assert_eq!(Error::Parse("failed".into()).exit_code(), 3);
+        assert_eq!(Error::Logic("failed".into()).exit_code(), 4);
+        assert_eq!(Error::Lint(1).exit_code(), 5);
+    }
+
+    #[test]
+    fn maps_flou_errors_to_exit_codes() {
+        let exit_code = |source| parse(source).err().map(|e| e.exit_code());
+
+        assert_eq!(exit_code("grid { a; }"), None);
+        assert_eq!(exit_code("grid { a("), Some(3));
+        assert_eq!(exit_code("grid { a(connect: s:n@s); }"), Some(4));
+
+        let limit = FlouError::LimitExceeded(Limit::GridWidth(100_000));
+        assert_eq!(flou_error(limit, "").exit_code(), 3);
+    }
+
+    #[test]
+    fn decodes_utf8_with_a_byte_order_mark() {
+        let input = b"\xef\xbb\xbfgrid { a(\"\xc3\xa9\"); }".to_vec();
+        assert_eq!(decode_input(input).unwrap(), "grid { a(\"\u{e9}\"); }");
+
+        let input = b"grid { a; }".to_vec();
+        assert_eq!(decode_input(input).unwrap(), "grid { a; }");
+    }
+
+    #[test]
+    fn decodes_utf16_with_a_byte_order_mark() {
+        let source = "grid { a(\"\u{e9}\u{1f600}\"); }";
+
+        let input = [vec![0xff, 0xfe], utf16(source, u16::to_le_bytes)].concat();
+        assert_eq!(decode_input(input).unwrap(), source);
+
+        let input = [vec![0xfe, 0xff], utf16(source, u16::to_be_bytes)].concat();
+        assert_eq!(decode_input(input).unwrap(), source);
+
+        // An odd number of bytes, and a lone surrogate.
+        let input = [vec![0xff, 0xfe], utf16(source, u16::to_le_bytes), vec![0]].concat();
+        assert!(matches!(decode_input(input), Err(Error::InvalidUtf16)));
+        let input = vec![0xfe, 0xff, 0xd8, 0x00];
+        assert!(matches!(decode_input(input), Err(Error::InvalidUtf16)));
+    }
+
+    #[test]
+    fn reports_where_invalid_utf8_starts() {
+        let error = decode_input(b"grid { a; }\xff".to_vec()).unwrap_err();
+        assert!(matches!(error, Error::InvalidUtf8(11)));
+        assert_eq!(error.exit_code(), 2);
+
+        // The offset counts the byte order mark.
+        let error = decode_input(b"\xef\xbb\xbfa\xc3".to_vec()).unwrap_err();
+        assert!(matches!(error, Error::InvalidUtf8(4)));
+        assert_eq!(error.exit_code(), 2);
+    }
+
+    /// Runs `flou rename-label` with `args` on a file containing `source`,
+    /// returning the result along with the file.
+    fn rename_label(source: &str, args: &[&str]) -> (Result<(), Error>, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.flou");
+        fs::write(&input, source).unwrap();
+
+        let opt = Opt::from_iter_safe(
+            ["flou", "rename-label"]
+                .iter()
+                .copied()
+                .chain(args.iter().copied())
+                .chain([input.to_str().unwrap()]),
+        )
+        .unwrap();
+
+        (run(opt), dir)
+    }
+
+    const RENAME_SOURCE: &str = "grid {\n  a#start(connect: s:n#end); // Start\n  b#end;\n}\n";
+
+    #[test]
+    fn renames_labels_in_files() {
+        let (result, dir) = rename_label(RENAME_SOURCE, &["start", "begin", "--in-place"]);
+        result.unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("input.flou")).unwrap(),
+            "grid {\n  a#begin(connect: s:n#end); // Start\n  b#end;\n}\n"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output.flou");
+        let (result, input_dir) = rename_label(
+            RENAME_SOURCE,
+            &["end", "finish", "-o", output.to_str().unwrap()],
+        );
+        result.unwrap();
+        assert_eq!(
+            fs::read_to_string(output).unwrap(),
+            "grid {\n  a#start(connect: s:n#finish); // Start\n  b#finish;\n}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(input_dir.path().join("input.flou")).unwrap(),
+            RENAME_SOURCE
+        );
+    }
+
+    #[test]
+    fn fails_to_rename_unknown_and_conflicting_labels() {
+        let (result, dir) = rename_label(RENAME_SOURCE, &["middle", "center", "--in-place"]);
+        let error = result.unwrap_err();
+        assert!(matches!(&error, Error::Edit(EditError::UnknownLabel(label)) if label == "middle"));
+        assert_eq!(error.exit_code(), 1);
+
+        let (result, _) = rename_label(RENAME_SOURCE, &["start", "end", "--in-place"]);
+        let error = result.unwrap_err();
+        assert!(matches!(&error, Error::Edit(EditError::DuplicateLabel(label)) if label == "end"));
+        assert_eq!(error.exit_code(), 1);
+
+        // Failed renames leave the input as it was.
+        assert_eq!(
+            fs::read_to_string(dir.path().join("input.flou")).unwrap(),
+            RENAME_SOURCE
+        );
+    }
+
+    #[test]
+    fn opens_output_from_new_temp_files() {
+        let (_, first) = temp_output_file(Format::Svg).unwrap();
+        let (_, second) = temp_output_file(Format::Svg).unwrap();
+
+        assert_ne!(first, second);
+        for path in [first, second] {
+            assert!(path.starts_with(std::env::temp_dir()));
+            assert_eq!(path.extension(), Some("svg".as_ref()));
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn tile_index_escapes_file_names() {
+        let rect = Rect::new(PixelPos::from((0.0, 0.0)), PixelPos::from((100.0, 50.0)));
+        let tiles = [(0, 0, rect, r#"a\b"c-0-0.png"#.to_string())];
+
+        assert_eq!(
+            tile_index(PixelPos::from((100.0, 50.0)), 100, 1.0, 1.0, &tiles),
+            concat!(
+                r#"{"width": 100, "height": 50, "tile_size": 100, "rows": 1, "cols": 1, "#,
+                r#""tiles": [{"row": 0, "col": 0, "x": 0, "y": 0, "width": 100, "height": 50, "#,
+                r#""file": "a\\b\"c-0-0.png"}]}"#
+            )
+        );
+    }
+
+    /// Renders a file containing `source` to SVG with `args`, returning
+    /// the result along with the path it's rendered to.
+    fn render(source: &str, args: &[&str]) -> (Result<(), Error>, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.flou");
+        let output = dir.path().join("output.svg");
+        fs::write(&input, source).unwrap();
+
+        let opt = Opt::from_iter_safe(
+            [
+                "flou",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+            ]
+            .iter()
+            .copied()
+            .chain(args.iter().copied()),
+        )
+        .unwrap();
+
+        (run(opt), dir)
+    }
+
+    #[test]
+    fn fails_to_render_flowcharts_over_the_limits() {
+        let source = "grid { a(connect: s:n@s); b; }";
+        let exit_code = |args: &[&str]| {
+            let (result, dir) = render(source, args);
+            let rendered = dir.path().join("output.svg").exists();
+            (result.err().map(|e| e.exit_code()), rendered)
+        };
+
+        assert_eq!(exit_code(&["--max-nodes", "2"]), (None, true));
+        assert_eq!(exit_code(&["--max-nodes", "1"]), (Some(3), false));
+        assert_eq!(exit_code(&["--max-connections", "0"]), (Some(3), false));
+        assert_eq!(exit_code(&["--max-pixel-area", "1000"]), (Some(3), false));
+        assert_eq!(
+            exit_code(&["--max-nodes", "1", "--emit", "layout"]),
+            (Some(3), false)
+        );
     }
 }
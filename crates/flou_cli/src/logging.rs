@@ -0,0 +1,49 @@
+use std::time::Instant;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes log messages to standard error, keeping standard output free for
+/// the rendered flowchart.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        match record.level() {
+            Level::Info => eprintln!("{}", record.args()),
+            level => eprintln!("[{}] {}", level.as_str().to_lowercase(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Sets up logging: phase timings are logged with `timings`, and everything
+/// else with `verbose` as well.
+pub(crate) fn init(verbose: bool, timings: bool) {
+    // The logger can only be set once, so later calls only change the level.
+    let _ = log::set_logger(&LOGGER);
+
+    log::set_max_level(match (verbose, timings) {
+        (true, _) => LevelFilter::Debug,
+        (false, true) => LevelFilter::Info,
+        (false, false) => LevelFilter::Off,
+    });
+}
+
+/// Runs one phase of the CLI, logging how long it took.
+pub(crate) fn timed<T>(phase: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    log::info!("{}: {:.2?}", phase, start.elapsed());
+    result
+}
@@ -1,12 +1,23 @@
+use flou::cst::EditError;
 use flou_cli::{run, Error, Opt};
 use structopt::StructOpt;
 
 fn main() {
     let opt = Opt::from_args();
     run(opt).unwrap_or_else(|e| {
+        let code = e.exit_code();
+
         match e {
+            Error::MissingInput => eprintln!("No input file given; use --help for usage."),
+            Error::MissingOutput(option) => {
+                eprintln!("{} requires an output file given with -o.", option)
+            }
             Error::InputOpen(e) => eprintln!("Could not open input file: {}", e),
             Error::InputRead(e) => eprintln!("Could not read input: {}", e),
+            Error::InvalidUtf8(offset) => {
+                eprintln!("Input is not valid UTF-8 at byte {}.", offset)
+            }
+            Error::InvalidUtf16 => eprintln!("Input is not valid UTF-16."),
             Error::OutputOpen(e) => eprintln!("Could not open output file: {}", e),
             Error::OutputWrite(e) => eprintln!("Could not write output: {}", e),
             Error::CssRead(filename, e) => {
@@ -16,9 +27,53 @@ fn main() {
                     e
                 )
             }
-            Error::Parse(e) => eprintln!("{}", e),
+            Error::FontRead(filename, e) => {
+                eprintln!(
+                    "Could not read font file \"{}\": {}",
+                    filename.to_string_lossy(),
+                    e
+                )
+            }
+            Error::Parse(e) | Error::Logic(e) => eprintln!("{}", e),
+            Error::Edit(EditError::UnknownLabel(label)) => {
+                eprintln!("No node has the label \"{}\".", label)
+            }
+            Error::Edit(EditError::DuplicateLabel(label)) => {
+                eprintln!("A node already has the label \"{}\".", label)
+            }
+            Error::Edit(EditError::InvalidSyntax(text)) => {
+                eprintln!("\"{}\" is not a valid label.", text)
+            }
+            Error::Edit(e) => eprintln!("Could not edit input: {:?}", e),
+            Error::ConfigRead(filename, e) => {
+                eprintln!(
+                    "Could not read config file \"{}\": {}",
+                    filename.to_string_lossy(),
+                    e
+                )
+            }
+            Error::Config(filename, e) => {
+                eprintln!(
+                    "Invalid config file \"{}\": {}",
+                    filename.to_string_lossy(),
+                    e
+                )
+            }
+            Error::Lint(1) => eprintln!("Found 1 error."),
+            Error::Lint(n) => eprintln!("Found {} errors.", n),
+            Error::Pdf(e) => eprintln!("Could not convert to PDF: {}", e),
+            Error::Png(e) => eprintln!("Could not convert to PNG: {}", e),
+            Error::TileFormat => eprintln!("--tile requires --format png."),
+            Error::BinaryToTerminal => eprintln!(
+                "Refusing to write binary output to a terminal; use -o to write it to a file, or --force."
+            ),
+            Error::Open(filename, e) => eprintln!(
+                "Could not open \"{}\" in a viewer: {}",
+                filename.to_string_lossy(),
+                e
+            ),
         };
 
-        std::process::exit(1);
+        std::process::exit(code);
     });
 }
@@ -0,0 +1,121 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Where output is written: stdout, a regular file that is only replaced
+/// once all of the output has been written, or any other kind of file, such
+/// as a pipe or a device, which is written to directly.
+pub(crate) enum OutputFile {
+    Stdout(BufWriter<io::Stdout>),
+    Atomic(AtomicFile),
+    Direct(BufWriter<File>),
+}
+
+impl OutputFile {
+    pub(crate) fn stdout() -> Self {
+        Self::Stdout(BufWriter::new(io::stdout()))
+    }
+
+    /// Writes to `file` directly, without replacing it.
+    pub(crate) fn direct(file: File) -> Self {
+        Self::Direct(BufWriter::new(file))
+    }
+
+    pub(crate) fn create(target: &Path) -> io::Result<Self> {
+        match fs::metadata(target) {
+            Ok(metadata) if !metadata.is_file() => File::create(target).map(Self::direct),
+            _ => AtomicFile::create(target).map(Self::Atomic),
+        }
+    }
+
+    /// Flushes the output and, for files, moves it into place.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Stdout(mut writer) => writer.flush(),
+            Self::Atomic(file) => file.finish(),
+            Self::Direct(mut writer) => writer.flush(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdout(writer) => writer.write(buf),
+            Self::Atomic(file) => file.writer().write(buf),
+            Self::Direct(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stdout(writer) => writer.flush(),
+            Self::Atomic(file) => file.writer().flush(),
+            Self::Direct(writer) => writer.flush(),
+        }
+    }
+}
+
+/// A file that is written to a temporary file next to it first, which is
+/// renamed over it once finished. Readers of the file never see partially
+/// written output, and output that is never finished leaves it untouched.
+pub(crate) struct AtomicFile {
+    /// Taken when finishing, to close the file before it's renamed.
+    writer: Option<BufWriter<File>>,
+    temp: PathBuf,
+    target: PathBuf,
+    renamed: bool,
+}
+
+impl AtomicFile {
+    fn create(target: &Path) -> io::Result<Self> {
+        let temp = temp_path(target);
+        let file = File::create(&temp)?;
+
+        // Keep the permissions of the file being replaced, which matters
+        // when editing the input in place.
+        if let Ok(metadata) = fs::metadata(target) {
+            let _ = fs::set_permissions(&temp, metadata.permissions());
+        }
+
+        Ok(Self {
+            writer: Some(BufWriter::new(file)),
+            temp,
+            target: target.to_path_buf(),
+            renamed: false,
+        })
+    }
+
+    fn writer(&mut self) -> &mut BufWriter<File> {
+        self.writer
+            .as_mut()
+            .expect("the file is only closed when finishing")
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            let file = writer.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&self.temp, &self.target)?;
+        self.renamed = true;
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.renamed {
+            self.writer = None;
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// A hidden file next to `target`, so that renaming it over `target` stays
+/// on the same filesystem.
+fn temp_path(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
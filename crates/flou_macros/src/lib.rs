@@ -0,0 +1,188 @@
+//! Macros for embedding Flou flowcharts in Rust code.
+//!
+//! The flowchart is written as is inside the macro and checked while
+//! compiling, so a flowchart that doesn't parse, or has errors such as a
+//! connection to a missing label, fails the build instead of the program.
+//!
+//! ```
+//! use flou_macros::flou;
+//!
+//! const SOURCE: &str = flou! {
+//!     grid {
+//!         a#start("Start", connect: s:n@s);
+//!         b("End");
+//!     }
+//! };
+//!
+//! assert_eq!(
+//!     SOURCE,
+//!     "grid {\n    a#start(\"Start\", connect: s:n@s);\n    b(\"End\");\n}"
+//! );
+//! ```
+//!
+//! ```compile_fail
+//! use flou_macros::flou;
+//!
+//! const SOURCE: &str = flou! {
+//!     grid {
+//!         a(connect: s:n#missing);
+//!     }
+//! };
+//! ```
+//!
+//! The flowchart goes through the Rust tokenizer first, so it has to be
+//! made of valid Rust tokens, which Flou syntax is. Comments are left out
+//! of the expanded source.
+
+use std::convert::TryFrom;
+
+use flou::Flou;
+use proc_macro::{Delimiter, Literal, Span, TokenStream, TokenTree};
+
+/// Checks the flowchart and expands to its source, as a `&'static str`.
+#[proc_macro]
+pub fn flou(input: TokenStream) -> TokenStream {
+    match validated_source(input) {
+        Ok(source) => TokenTree::Literal(Literal::string(&source)).into(),
+        Err(error) => error,
+    }
+}
+
+/// Checks the flowchart and expands to a [`Flou`] built from it. Since the
+/// flowchart is known to be valid, building it can't fail.
+///
+/// ```
+/// use flou_macros::flou_model;
+///
+/// let flou = flou_model! {
+///     grid { a; b; }
+/// };
+///
+/// assert_eq!(flou.grid().size().row, 2);
+/// ```
+#[proc_macro]
+pub fn flou_model(input: TokenStream) -> TokenStream {
+    match validated_source(input) {
+        Ok(source) => format!(
+            "<::flou::Flou<'static> as ::core::convert::TryFrom<&'static str>>::try_from({})\
+             .expect(\"the flowchart was checked at compile time\")",
+            Literal::string(&source)
+        )
+        .parse()
+        .unwrap(),
+        Err(error) => error,
+    }
+}
+
+/// Rebuilds the flowchart's source from the macro's input and checks it,
+/// or returns a `compile_error!` invocation explaining what's wrong.
+fn validated_source(input: TokenStream) -> Result<String, TokenStream> {
+    let mut source = Source::default();
+    source.extend(input);
+
+    match check(&source.text) {
+        Ok(()) => Ok(source.text),
+        Err(message) => Err(compile_error(&message)),
+    }
+}
+
+/// Checks the flowchart, returning the message the build fails with if it
+/// isn't valid.
+fn check(source: &str) -> Result<(), String> {
+    Flou::try_from(source)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    format!("::core::compile_error!({})", Literal::string(message))
+        .parse()
+        .unwrap()
+}
+
+/// Source text rebuilt from tokens. Tokens are placed according to their
+/// spans, so tokens that were written together, such as the ones in
+/// `s:n#end`, stay together, and the line breaks are kept.
+#[derive(Default)]
+struct Source {
+    text: String,
+    /// The line and column of the end of the last token.
+    end: Option<(usize, usize)>,
+    /// The column of the first token, which the lines are indented from.
+    indent: usize,
+}
+
+impl Source {
+    fn extend(&mut self, tokens: TokenStream) {
+        for token in tokens {
+            match token {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => {
+                            self.extend(group.stream());
+                            continue;
+                        }
+                    };
+
+                    self.push(group.span_open(), open);
+                    self.extend(group.stream());
+                    self.push(group.span_close(), close);
+                }
+                TokenTree::Ident(ident) => self.push(ident.span(), &ident.to_string()),
+                TokenTree::Punct(punct) => self.push(punct.span(), &punct.to_string()),
+                TokenTree::Literal(literal) => self.push(literal.span(), &literal.to_string()),
+            }
+        }
+    }
+
+    fn push(&mut self, span: Span, text: &str) {
+        let (line, column) = (span.start().line(), span.start().column());
+
+        match self.end {
+            None => self.indent = column,
+            Some((end_line, _)) if line > end_line => {
+                self.text.extend(std::iter::repeat_n('\n', line - end_line));
+                let indent = column.saturating_sub(self.indent);
+                self.text.extend(std::iter::repeat_n(' ', indent));
+            }
+            Some((_, end_column)) => {
+                let gap = column.saturating_sub(end_column);
+                self.text.extend(std::iter::repeat_n(' ', gap));
+            }
+        }
+
+        self.text.push_str(text);
+        self.end = Some((span.end().line(), span.end().column()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+
+    #[test]
+    fn explains_errors_readably() {
+        assert_eq!(check("grid { a; }"), Ok(()));
+
+        assert_eq!(
+            check("grid { a(connect: s:n#missing); }"),
+            Err(concat!(
+                "Error in Flou logic:\n",
+                "Could not resolve destination for some node's connections:\n\n",
+                "  - For node at column 1, row 1:\n",
+                "    - For connection at index 0: No destination with label: \"missing\"",
+            )
+            .to_string())
+        );
+
+        let message = check("grid { a( }").unwrap_err();
+        assert!(
+            message.starts_with("Error parsing Flou:\n\n"),
+            "{}",
+            message
+        );
+    }
+}